@@ -1,4 +1,4 @@
-echo "Starting bank app"
+echo "Running scenarios"
 cargo build
-./target/debug/banking_app < commands.txt
-echo "Bank app finished." 
\ No newline at end of file
+./target/debug/scenario_runner scenarios/*.toml
+echo "Scenarios finished."
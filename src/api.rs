@@ -0,0 +1,926 @@
+//! HTTP/JSON API in front of the `Bank`, served by the `banking_server` binary.
+//!
+//! | Method | Path             | Body                                  | Role              |
+//! |--------|------------------|---------------------------------------|-------------------|
+//! | POST   | `/register`      | `username`, `password`, `role`        | - (staff: Manager)|
+//! | POST   | `/login`         | `username`, `password`                | -                 |
+//! | POST   | `/logout`        |                                       | any               |
+//! | POST   | `/deposit`       | `amount`, `reference`                 | Customer          |
+//! | POST   | `/withdraw`      | `amount`, `reference`                 | Customer          |
+//! | POST   | `/transfer`      | `to`, `amount`, `reference`           | Customer          |
+//! | GET    | `/balance`       |                                       | Customer          |
+//! | GET    | `/events`        | query `user` for one user's events    | any               |
+//! | GET    | `/report`        |                                       | Manager, Auditor  |
+//! | PUT    | `/interest-rate` | `rate`                                | Manager           |
+//! | POST   | `/interest`      |                                       | Manager           |
+//! | PUT    | `/tax-rate`      | `rate`                                | Auditor           |
+//! | POST   | `/tax`           |                                       | Auditor           |
+//!
+//! Bodies are flat JSON objects; `role` is `customer` (default), `manager` or `auditor`, and
+//! `reference` is optional. Anyone can register a customer, but only a logged in manager can
+//! register a manager or an auditor. The first manager is registered when the server starts,
+//! from `BANKING_MANAGER` and `BANKING_MANAGER_PASSWORD` (see `bootstrap_manager`). Login
+//! returns a token, which the other endpoints expect as an `Authorization: Bearer <token>`
+//! header. Errors are returned as `{"error":"<BankingError variant>","message":"<text>"}`, with
+//! the status code given by `status_code`.
+//!
+//! Tokens are 128 bits from the operating system's secure random generator, and expire
+//! `SESSION_LIFETIME` after login. The server speaks plain HTTP, so it is meant to listen on
+//! localhost only. Connections are read on threads of their own, and a connection that sends
+//! nothing for `REQUEST_TIMEOUT`, or headers larger than `MAX_HEADER_SIZE`, is answered with an
+//! error and closed.
+use crate::bank::Bank;
+use crate::primitives::*;
+use crate::report::{escape_json, render_events, render_report, ReportFormat};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::mpsc::{self, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Largest request body accepted, in bytes.
+pub const MAX_BODY_SIZE: usize = 64 * 1024;
+
+/// Largest request line and headers accepted, in bytes.
+pub const MAX_HEADER_SIZE: usize = 8 * 1024;
+
+/// How long reading a request, or writing a response, may block.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a session token is valid after login.
+pub const SESSION_LIFETIME: Duration = Duration::from_secs(60 * 60);
+
+/// Name of the environment variable with the username of the first manager.
+pub const MANAGER_ENV: &str = "BANKING_MANAGER";
+
+/// Name of the environment variable with the password of the first manager.
+pub const MANAGER_PASSWORD_ENV: &str = "BANKING_MANAGER_PASSWORD";
+
+/// An HTTP request, reduced to what the API needs.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Request {
+    pub method: String,
+    /// Path, including the query string.
+    pub path: String,
+    /// Bearer token from the `Authorization` header.
+    pub token: Option<String>,
+    pub body: String,
+}
+
+/// An HTTP response with a JSON body. The body is empty for `204 No Content`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: String,
+}
+
+impl Response {
+    fn json(status: u16, body: String) -> Self {
+        Self { status, body }
+    }
+
+    fn no_content() -> Self {
+        Self {
+            status: 204,
+            body: String::new(),
+        }
+    }
+
+    /// An error that is not a `BankingError`, such as a malformed request.
+    fn error(status: u16, error: &str, message: &str) -> Self {
+        Self::json(
+            status,
+            format!(
+                "{{\"error\":{},\"message\":{}}}",
+                escape_json(error),
+                escape_json(message)
+            ),
+        )
+    }
+
+    fn bad_request(message: &str) -> Self {
+        Self::error(400, "BadRequest", message)
+    }
+
+    fn unauthenticated() -> Self {
+        Self::error(401, "Unauthenticated", "Expected a valid bearer token.")
+    }
+}
+
+impl From<BankingError> for Response {
+    fn from(e: BankingError) -> Self {
+        Self::error(status_code(&e), &format!("{:?}", e), &e.to_string())
+    }
+}
+
+/// Returns the HTTP status code for a `BankingError`.
+pub fn status_code(e: &BankingError) -> u16 {
+    match e {
+        BankingError::FailedLogin => 401,
+        BankingError::Unauthorized
+        | BankingError::TransactionDenied
+        | BankingError::WatchListMatch
+        | BankingError::KycLimitExceeded
+        | BankingError::KycRequired => 403,
+        BankingError::NoUserFound
+        | BankingError::InvalidUserId
+        | BankingError::NoPayeeFound
+        | BankingError::NoTransferFound
+        | BankingError::NoFeeWaiverFound
+        | BankingError::NoLoanProductFound
+        | BankingError::NoLoanFound
+        | BankingError::NoTermDepositFound
+        | BankingError::NoFraudRuleFound
+        | BankingError::NoFraudReviewFound
+        | BankingError::NoReportFound
+        | BankingError::NoScreeningHitFound
+        | BankingError::NoProfileFound => 404,
+        BankingError::InsufficientBalance
+        | BankingError::UserAlreadyExist
+        | BankingError::PayeeAlreadyExist
+        | BankingError::InvalidTransferStatus
+        | BankingError::InvalidLoanStatus
+        | BankingError::InvalidTermDepositStatus
+        | BankingError::InvalidReviewStatus
+        | BankingError::InvalidReportStatus
+        | BankingError::InvalidScreeningHitStatus => 409,
+        BankingError::BatchTooLarge => 413,
+        BankingError::InvalidAmount
+        | BankingError::AmountTooSmall
+        | BankingError::InvalidTaxRate
+        | BankingError::InvalidInterestRate
+        | BankingError::InvalidNickname
+        | BankingError::InvalidBatchLine
+        | BankingError::InvalidReference
+        | BankingError::InvalidCreditorReference
+        | BankingError::InvalidFeeRule
+        | BankingError::InvalidLoanProduct
+        | BankingError::InvalidTerm
+        | BankingError::InvalidComplianceThresholds
+        | BankingError::InvalidScreeningThresholds
+        | BankingError::InvalidProfile
+        | BankingError::InvalidKycPolicy
+        | BankingError::InvalidTolerance => 422,
+        // The movement is not done yet, but may be once an auditor approves it.
+        BankingError::TransactionHeld => 202,
+        BankingError::FailedToReadFile
+        | BankingError::FailedToWriteFile
+        | BankingError::InvalidBankFile
+        | BankingError::UnsupportedBankFileVersion
+        | BankingError::OutdatedBankFile
+        | BankingError::TamperedBankFile => 500,
+        BankingError::ServiceStopped | BankingError::StorageFailure => 503,
+    }
+}
+
+// Helper function: Returns the reason phrase of a status code.
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+/// A value of a flat JSON object.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Null,
+}
+
+// Helper function: Parses a JSON number. Only the JSON syntax is accepted, so `NaN`, `inf` or
+// `+1` are not numbers, and numbers too large for an `f64` are rejected.
+fn parse_json_number(token: &str) -> Option<f64> {
+    let digits = |s: &str| s.bytes().take_while(u8::is_ascii_digit).count();
+    let mut rest = token.strip_prefix('-').unwrap_or(token);
+    let integer = digits(rest);
+    if integer == 0 || (integer > 1 && rest.starts_with('0')) {
+        return None;
+    }
+    rest = &rest[integer..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let count = digits(fraction);
+        if count == 0 {
+            return None;
+        }
+        rest = &fraction[count..];
+    }
+    if let Some(exponent) = rest.strip_prefix(['e', 'E']) {
+        let exponent = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        let count = digits(exponent);
+        if count == 0 {
+            return None;
+        }
+        rest = &exponent[count..];
+    }
+    if !rest.is_empty() {
+        return None;
+    }
+    token.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// Parses a flat JSON object. Nested objects and arrays are not supported.
+pub fn parse_json_object(input: &str) -> Option<HashMap<String, JsonValue>> {
+    let mut chars = input.trim().chars().peekable();
+    let mut fields = HashMap::new();
+    let skip_whitespace = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+    };
+    let parse_string = |chars: &mut std::iter::Peekable<std::str::Chars>| -> Option<String> {
+        if chars.next()? != '"' {
+            return None;
+        }
+        let mut s = String::new();
+        loop {
+            match chars.next()? {
+                '"' => return Some(s),
+                '\\' => match chars.next()? {
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'u' => {
+                        let code = (0..4).map(|_| chars.next()).collect::<Option<String>>()?;
+                        s.push(char::from_u32(u32::from_str_radix(&code, 16).ok()?)?);
+                    }
+                    c => s.push(c),
+                },
+                c => s.push(c),
+            }
+        }
+    };
+
+    if chars.next()? != '{' {
+        return None;
+    }
+    skip_whitespace(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+        return chars.next().is_none().then_some(fields);
+    }
+    loop {
+        skip_whitespace(&mut chars);
+        let key = parse_string(&mut chars)?;
+        skip_whitespace(&mut chars);
+        if chars.next()? != ':' {
+            return None;
+        }
+        skip_whitespace(&mut chars);
+        let value = match chars.peek()? {
+            '"' => JsonValue::String(parse_string(&mut chars)?),
+            _ => {
+                let mut token = String::new();
+                while chars
+                    .peek()
+                    .is_some_and(|c| c.is_alphanumeric() || "+-.".contains(*c))
+                {
+                    token.push(chars.next()?);
+                }
+                match token.as_str() {
+                    "true" => JsonValue::Bool(true),
+                    "false" => JsonValue::Bool(false),
+                    "null" => JsonValue::Null,
+                    _ => JsonValue::Number(parse_json_number(&token)?),
+                }
+            }
+        };
+        fields.insert(key, value);
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            ',' => continue,
+            '}' => return chars.next().is_none().then_some(fields),
+            _ => return None,
+        }
+    }
+}
+
+// Helper function: Returns a number field of the request body.
+fn number_field(fields: &HashMap<String, JsonValue>, key: &str) -> Result<f64, Response> {
+    match fields.get(key) {
+        Some(JsonValue::Number(n)) if n.is_finite() => Ok(*n),
+        _ => Err(Response::bad_request(&format!(
+            "expected a number `{}`",
+            key
+        ))),
+    }
+}
+
+// Helper function: Returns a string field of the request body, if present.
+fn string_field(
+    fields: &HashMap<String, JsonValue>,
+    key: &str,
+) -> Result<Option<String>, Response> {
+    match fields.get(key) {
+        Some(JsonValue::String(s)) => Ok(Some(s.clone())),
+        None | Some(JsonValue::Null) => Ok(None),
+        _ => Err(Response::bad_request(&format!(
+            "expected a string `{}`",
+            key
+        ))),
+    }
+}
+
+// Helper function: Reads the optional payment reference the same way as the CLI.
+fn reference_field(fields: &HashMap<String, JsonValue>) -> Result<PaymentReference, Response> {
+    match string_field(fields, "reference")? {
+        Some(r) => PaymentReference::parse(&r).map_err(Response::from),
+        None => Ok(PaymentReference::default()),
+    }
+}
+
+/// A logged in user.
+#[derive(Debug, Copy, Clone)]
+struct Session {
+    user: HashResult,
+    role: Role,
+    expires: Instant,
+}
+
+/// The API state: the bank and the logged in sessions.
+pub struct ApiServer {
+    bank: Bank,
+    sessions: HashMap<String, Session>,
+    session_lifetime: Duration,
+}
+
+impl ApiServer {
+    /// Serves `bank`, which stops printing to stdout.
+    pub fn new(mut bank: Bank) -> Self {
+        bank.set_verbose(false);
+        Self {
+            bank,
+            sessions: Default::default(),
+            session_lifetime: SESSION_LIFETIME,
+        }
+    }
+
+    pub fn bank(&self) -> &Bank {
+        &self.bank
+    }
+
+    /// Registers the first manager of the bank, who can then register the other staff over the
+    /// API. Does nothing if the bank already has a manager, e.g. one kept in its storage.
+    pub fn bootstrap_manager(&mut self, username: String, password: String) -> BankResult<()> {
+        if self.bank.has_role(Role::Manager) {
+            return Ok(());
+        }
+        self.bank.create_user(username, password, Role::Manager)
+    }
+
+    // Helper function: Generates a new session token, from the operating system's secure
+    // random generator.
+    fn generate_token() -> Result<String, Response> {
+        let mut bytes = [0u8; 16];
+        getrandom::getrandom(&mut bytes).map_err(|_| {
+            Response::error(
+                503,
+                "Unavailable",
+                "No secure random numbers are available.",
+            )
+        })?;
+        Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    // Helper function: Returns the session of the token, unless it expired.
+    fn session(&mut self, token: &str) -> Option<Session> {
+        match self.sessions.get(token) {
+            Some(session) if session.expires > Instant::now() => Some(*session),
+            Some(_) => {
+                self.sessions.remove(token);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Handles one request.
+    pub fn handle(&mut self, request: &Request) -> Response {
+        self.route(request).unwrap_or_else(|response| response)
+    }
+
+    // Helper function: Dispatches the request to its endpoint.
+    fn route(&mut self, request: &Request) -> Result<Response, Response> {
+        let (path, query) = match request.path.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (request.path.as_str(), ""),
+        };
+        let fields = || {
+            if request.body.trim().is_empty() {
+                return Ok(HashMap::new());
+            }
+            parse_json_object(&request.body)
+                .ok_or_else(|| Response::bad_request("expected a flat JSON object"))
+        };
+        let method = request.method.as_str();
+
+        match (method, path) {
+            ("POST", "/register") => {
+                let fields = fields()?;
+                let (username, password) = match (
+                    string_field(&fields, "username")?,
+                    string_field(&fields, "password")?,
+                ) {
+                    (Some(u), Some(p)) if !u.trim().is_empty() && !p.is_empty() => {
+                        (u.trim().to_string(), p)
+                    }
+                    _ => return Err(Response::bad_request("expected `username` and `password`")),
+                };
+                let role = match string_field(&fields, "role")?
+                    .map(|r| r.to_lowercase())
+                    .as_deref()
+                {
+                    None | Some("customer") => Role::Customer,
+                    Some("manager") => Role::Manager,
+                    Some("auditor") => Role::Auditor,
+                    Some(_) => return Err(Response::bad_request("unknown `role`")),
+                };
+                // Staff are registered by a manager.
+                if role != Role::Customer {
+                    match request.token.as_deref().and_then(|t| self.session(t)) {
+                        Some(Session {
+                            role: Role::Manager,
+                            ..
+                        }) => (),
+                        Some(_) => return Err(BankingError::Unauthorized.into()),
+                        None => return Err(Response::unauthenticated()),
+                    }
+                }
+                self.bank.create_user(username, password, role)?;
+                return Ok(Response::json(201, "{}".to_string()));
+            }
+            ("POST", "/login") => {
+                let fields = fields()?;
+                let (username, password) = match (
+                    string_field(&fields, "username")?,
+                    string_field(&fields, "password")?,
+                ) {
+                    (Some(u), Some(p)) => (u.trim().to_string(), p),
+                    _ => return Err(Response::bad_request("expected `username` and `password`")),
+                };
+                let (user, role) = self.bank.login(username, password)?;
+                let token = Self::generate_token()?;
+                let now = Instant::now();
+                self.sessions.retain(|_, session| session.expires > now);
+                let session = Session {
+                    user,
+                    role,
+                    expires: now + self.session_lifetime,
+                };
+                self.sessions.insert(token.clone(), session);
+                return Ok(Response::json(
+                    200,
+                    format!(
+                        "{{\"token\":{},\"role\":\"{:?}\"}}",
+                        escape_json(&token),
+                        role
+                    ),
+                ));
+            }
+            _ => (),
+        }
+
+        let token = request.token.clone().unwrap_or_default();
+        let Session { user, role, .. } = match self.session(&token) {
+            Some(session) => session,
+            None => return Err(Response::unauthenticated()),
+        };
+        let balance = |bank: &Bank| -> Result<Response, Response> {
+            Ok(Response::json(
+                200,
+                format!("{{\"balance\":{}}}", bank.check_balance(user)?),
+            ))
+        };
+
+        match (method, path) {
+            ("POST", "/logout") => {
+                self.sessions.remove(&token);
+                Ok(Response::no_content())
+            }
+            ("POST", "/deposit") | ("POST", "/withdraw") => {
+                let fields = fields()?;
+                let amount = number_field(&fields, "amount")?;
+                let reference = reference_field(&fields)?;
+                if path == "/deposit" {
+                    self.bank.deposit_with_reference(user, amount, reference)?;
+                } else {
+                    self.bank.withdraw_with_reference(user, amount, reference)?;
+                }
+                balance(&self.bank)
+            }
+            ("POST", "/transfer") => {
+                let fields = fields()?;
+                let to = number_field(&fields, "to")?;
+                let amount = number_field(&fields, "amount")?;
+                let reference = reference_field(&fields)?;
+                if to < 0f64 || to.fract() != 0f64 {
+                    return Err(Response::bad_request("expected a user ID `to`"));
+                }
+                self.bank
+                    .transfer_with_reference(user, amount, to as UserId, reference)?;
+                balance(&self.bank)
+            }
+            ("GET", "/balance") => balance(&self.bank),
+            ("GET", "/events") => {
+                let target = query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("user="))
+                    .map(|id| {
+                        id.parse::<UserId>()
+                            .map_err(|_| Response::bad_request("expected a user ID `user`"))
+                    })
+                    .transpose()?;
+                let rows = match (role, target) {
+                    (Role::Customer, _) => self.bank.my_events(user)?,
+                    (_, Some(id)) => self.bank.user_events(user, role, id)?,
+                    (_, None) => self.bank.all_events(user, role)?,
+                };
+                Ok(Response::json(
+                    200,
+                    render_events(&rows, ReportFormat::Json)
+                        .trim_end()
+                        .to_string(),
+                ))
+            }
+            ("GET", "/report") => Ok(Response::json(
+                200,
+                render_report(&self.bank.report(user)?, ReportFormat::Json)
+                    .trim_end()
+                    .to_string(),
+            )),
+            ("PUT", "/interest-rate") => {
+                let rate = number_field(&fields()?, "rate")?;
+                self.bank.set_interest_rate(user, rate)?;
+                Ok(Response::no_content())
+            }
+            ("POST", "/interest") => {
+                self.bank.pay_interest(user)?;
+                Ok(Response::no_content())
+            }
+            ("PUT", "/tax-rate") => {
+                let rate = number_field(&fields()?, "rate")?;
+                self.bank.set_tax_rate(user, rate)?;
+                Ok(Response::no_content())
+            }
+            ("POST", "/tax") => {
+                self.bank.take_tax(user)?;
+                Ok(Response::no_content())
+            }
+            (
+                _,
+                "/register" | "/login" | "/logout" | "/deposit" | "/withdraw" | "/transfer"
+                | "/balance" | "/events" | "/report" | "/interest-rate" | "/interest" | "/tax-rate"
+                | "/tax",
+            ) => Err(Response::error(
+                405,
+                "MethodNotAllowed",
+                "The method is not allowed for this path.",
+            )),
+            _ => Err(Response::error(404, "NotFound", "No such endpoint.")),
+        }
+    }
+
+    /// Serves requests on `listener`, forever. Every connection is read and answered on a
+    /// thread of its own, so a slow client does not hold up the others, and the requests are
+    /// applied to the bank one at a time, in the order they were read. Each connection carries
+    /// one request, which must arrive within `REQUEST_TIMEOUT`. A connection that cannot be
+    /// accepted or set up is reported on stderr, and the server carries on.
+    pub fn serve(&mut self, listener: &TcpListener) -> ! {
+        let (sender, requests) = mpsc::channel::<(Request, Sender<Response>)>();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                requests.into_iter().for_each(|(request, reply)| {
+                    // The client may have hung up already.
+                    let _ = reply.send(self.handle(&request));
+                })
+            });
+            loop {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let sender = sender.clone();
+                        thread::spawn(move || serve_connection(stream, &sender));
+                    }
+                    Err(e) => eprintln!("Failed to accept a connection: {}", e),
+                }
+            }
+        })
+    }
+}
+
+// Helper function: Reads the request of a connection, has it handled by the thread that owns
+// the bank, and writes the response.
+fn serve_connection(mut stream: TcpStream, requests: &Sender<(Request, Sender<Response>)>) {
+    let timeouts = stream
+        .set_read_timeout(Some(REQUEST_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(REQUEST_TIMEOUT)));
+    if let Err(e) = timeouts {
+        eprintln!("Failed to set up a connection: {}", e);
+        return;
+    }
+    let response = match read_request(&mut stream) {
+        Ok(request) => {
+            let (reply, response) = mpsc::channel();
+            match requests.send((request, reply)) {
+                Ok(()) => response.recv().ok(),
+                Err(_) => None,
+            }
+            // The thread that owns the bank stopped, on a panic.
+            .unwrap_or_else(|| BankingError::ServiceStopped.into())
+        }
+        Err(response) => response,
+    };
+    // A client that hung up does not stop the server.
+    let _ = write_response(&mut stream, &response);
+    // Closing with a request left unread would reset the connection before the client reads
+    // the response, so wait for the client to close first.
+    let _ = stream.shutdown(Shutdown::Write);
+    let _ = io::copy(&mut (&stream).take(MAX_BODY_SIZE as u64), &mut io::sink());
+}
+
+// Helper function: Maps an error reading the request to a response.
+fn read_error(e: io::Error) -> Response {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+            Response::error(408, "RequestTimeout", "The request did not arrive in time.")
+        }
+        _ => Response::bad_request("malformed HTTP request"),
+    }
+}
+
+// Helper function: Reads a line of the request line and headers, which take at most
+// `MAX_HEADER_SIZE` bytes together. `read` counts the bytes read so far.
+fn read_header_line<R: BufRead>(
+    reader: &mut R,
+    line: &mut String,
+    read: &mut usize,
+) -> Result<(), Response> {
+    line.clear();
+    let limit = (MAX_HEADER_SIZE - *read) as u64;
+    *read += reader.take(limit).read_line(line).map_err(read_error)?;
+    if line.ends_with('\n') {
+        Ok(())
+    } else if *read == MAX_HEADER_SIZE {
+        Err(Response::error(
+            431,
+            "HeadersTooLarge",
+            "The request headers are too large.",
+        ))
+    } else {
+        Err(Response::bad_request("malformed HTTP request"))
+    }
+}
+
+// Helper function: Reads an HTTP/1.1 request from the stream.
+fn read_request(stream: &mut TcpStream) -> Result<Request, Response> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    let mut read = 0;
+    read_header_line(&mut reader, &mut line, &mut read)?;
+    let mut parts = line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(Response::bad_request("malformed HTTP request")),
+    };
+
+    let mut content_length = 0;
+    let mut token = None;
+    loop {
+        read_header_line(&mut reader, &mut line, &mut read)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_lowercase().as_str() {
+                "content-length" => {
+                    content_length = value
+                        .parse()
+                        .map_err(|_| Response::bad_request("invalid Content-Length"))?
+                }
+                "authorization" => {
+                    token = value.strip_prefix("Bearer ").map(|t| t.trim().to_string())
+                }
+                _ => (),
+            }
+        }
+    }
+    if content_length > MAX_BODY_SIZE {
+        return Err(Response::error(
+            413,
+            "PayloadTooLarge",
+            "The request body is too large.",
+        ));
+    }
+
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).map_err(read_error)?;
+    let body =
+        String::from_utf8(body).map_err(|_| Response::bad_request("the body is not UTF-8"))?;
+    Ok(Request {
+        method,
+        path,
+        token,
+        body,
+    })
+}
+
+// Helper function: Writes the response and closes the connection.
+fn write_response(stream: &mut TcpStream, response: &Response) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason_phrase(response.status),
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_handle_api_requests() {
+        let mut server = ApiServer::new(Bank::default());
+        assert_eq!(
+            Ok(()),
+            server.bootstrap_manager("manager".to_string(), "pw".to_string())
+        );
+        // Once the bank has a manager, bootstrapping does nothing.
+        assert_eq!(
+            Ok(()),
+            server.bootstrap_manager("mallory".to_string(), "pw".to_string())
+        );
+        assert_eq!(
+            Err(BankingError::FailedLogin),
+            server.bank().login("mallory".to_string(), "pw".to_string())
+        );
+        let mut request = |method: &str, path: &str, token: Option<&str>, body: &str| {
+            server.handle(&Request {
+                method: method.to_string(),
+                path: path.to_string(),
+                token: token.map(str::to_string),
+                body: body.to_string(),
+            })
+        };
+        assert_eq!(
+            201,
+            request(
+                "POST",
+                "/register",
+                None,
+                "{\"username\":\"roy\",\"password\":\"pw\"}"
+            )
+            .status
+        );
+        assert_eq!(
+            409,
+            request(
+                "POST",
+                "/register",
+                None,
+                "{\"username\":\"roy\",\"password\":\"pw\"}"
+            )
+            .status
+        );
+        let login = request(
+            "POST",
+            "/login",
+            None,
+            "{\"username\":\"roy\",\"password\":\"pw\"}",
+        );
+        let token = login.body.split('"').nth(3).unwrap().to_string();
+        assert!(login.body.ends_with(",\"role\":\"Customer\"}"));
+
+        assert_eq!(
+            Response {
+                status: 200,
+                body: "{\"balance\":10}".to_string()
+            },
+            request("POST", "/deposit", Some(&token), "{\"amount\":10}")
+        );
+        assert_eq!(401, request("GET", "/balance", Some("guess"), "").status);
+        for amount in ["NaN", "inf", "-infinity", "1e999", "+1", "01", "1.", ".5"] {
+            let body = format!("{{\"amount\":{}}}", amount);
+            assert_eq!(
+                400,
+                request("POST", "/deposit", Some(&token), &body).status,
+                "{}",
+                amount
+            );
+        }
+
+        // Passwords are not trimmed.
+        assert_eq!(
+            401,
+            request(
+                "POST",
+                "/login",
+                None,
+                "{\"username\":\"roy\",\"password\":\" pw \"}"
+            )
+            .status
+        );
+
+        // Staff are registered by a manager, even the first one.
+        let staff = |name: &str, role: &str| {
+            format!(
+                "{{\"username\":\"{}\",\"password\":\"pw\",\"role\":\"{}\"}}",
+                name, role
+            )
+        };
+        assert_eq!(
+            401,
+            request("POST", "/register", None, &staff("mallory", "manager")).status
+        );
+        assert_eq!(
+            403,
+            request(
+                "POST",
+                "/register",
+                Some(&token),
+                &staff("mallory", "auditor")
+            )
+            .status
+        );
+        let login = request(
+            "POST",
+            "/login",
+            None,
+            "{\"username\":\"manager\",\"password\":\"pw\"}",
+        );
+        let manager = login.body.split('"').nth(3).unwrap().to_string();
+        assert_eq!(32, manager.len());
+        assert_eq!(
+            201,
+            request(
+                "POST",
+                "/register",
+                Some(&manager),
+                &staff("alice", "auditor")
+            )
+            .status
+        );
+
+        // Sessions expire.
+        server.session_lifetime = Duration::ZERO;
+        let request = |method: &str, path: &str, token: Option<&str>, body: &str| Request {
+            method: method.to_string(),
+            path: path.to_string(),
+            token: token.map(str::to_string),
+            body: body.to_string(),
+        };
+        let login = server.handle(&request(
+            "POST",
+            "/login",
+            None,
+            "{\"username\":\"roy\",\"password\":\"pw\"}",
+        ));
+        assert_eq!(200, login.status);
+        let token = login.body.split('"').nth(3).unwrap().to_string();
+        assert_eq!(
+            401,
+            server
+                .handle(&request("GET", "/balance", Some(&token), ""))
+                .status
+        );
+
+        assert_eq!(401, status_code(&BankingError::FailedLogin));
+        assert_eq!(403, status_code(&BankingError::Unauthorized));
+        assert_eq!(202, status_code(&BankingError::TransactionHeld));
+        assert_eq!(
+            Some(JsonValue::String("a\"b".to_string())),
+            parse_json_object("{ \"x\" : \"a\\\"b\", \"y\": -1.5e1 }")
+                .unwrap()
+                .remove("x")
+        );
+        assert_eq!(None, parse_json_object("{\"x\":1,}"));
+        assert_eq!(
+            Some(JsonValue::Number(-0.0005f64)),
+            parse_json_object("{\"x\":-0.5e-3}").unwrap().remove("x")
+        );
+        assert_eq!(None, parse_json_object("{\"x\":NaN}"));
+    }
+}
@@ -1,40 +1,294 @@
-/// This file contains the basic implementation of a banking system.
+//! This file contains the basic implementation of a banking system.
 
-/// Refer to this for a basic Hasher: https://doc.rust-lang.org/std/hash/trait.Hasher.html
+//! Refer to this for a basic Hasher: https://doc.rust-lang.org/std/hash/trait.Hasher.html
 use crate::primitives::*;
+use crate::storage::{MemoryStorage, Storage};
+use pbkdf2::pbkdf2_hmac;
+use sha2::Sha256;
 use std::{
-    collections::hash_map::{DefaultHasher, HashMap},
-    hash::{Hash, Hasher},
+    collections::{HashMap, HashSet},
+    mem,
+    panic::{self, AssertUnwindSafe},
 };
 
+mod compliance;
+mod fees;
+mod fraud;
+mod integrity;
+mod loans;
+mod profiles;
+mod reconciliation;
+mod screening;
+mod statements;
+mod summary;
+mod term_deposits;
+
+pub use compliance::ComplianceReport;
+pub use fraud::{
+    AmountAnomalyRule, FirstTimePayeeRule, FraudReview, FraudRule, Movement, RoundTripRule,
+    VelocityRule,
+};
+pub use integrity::{
+    entry_hash, sign_checkpoint, signing_key_from_env, CHECKPOINT_INTERVAL, SIGNING_KEY_ENV,
+};
+pub use loans::{amortization_schedule, Installment, Loan, LoanProduct};
+pub use profiles::{CustomerProfile, ProfileVersion};
+pub use screening::{
+    name_similarity, normalize_name, parse_watch_list, ScreeningHit, ScreeningThresholds,
+    WatchListEntry,
+};
+pub use term_deposits::TermDeposit;
+
+/// Number of PBKDF2 rounds of `Bank::hash` in the binaries.
+pub(crate) const FULL_HASH_ROUNDS: u32 = 10_000;
+/// Number of PBKDF2 rounds of `Bank::hash`. The unit tests, which hash thousands of
+/// credentials without optimizations, use one round.
+const HASH_ROUNDS: u32 = if cfg!(test) { 1 } else { FULL_HASH_ROUNDS };
+/// Prefix of the salt of `Bank::hash`, followed by the username.
+const HASH_SALT_PREFIX: &[u8] = b"banking_app:";
+
 pub struct Bank {
-    users: HashMap<HashResult, User>,
-    balances: HashMap<UserId, Balance>,
-    pub(crate) events: Vec<Event>,
+    /// Users, their credentials, the balances and the event log.
+    storage: Box<dyn Storage>,
+    payees: HashMap<UserId, Vec<Payee>>,
+    transfers: HashMap<TransferId, TransferRecord>,
     interest_rate: f64,
     tax_rate: f64,
     existential_deposit: Balance,
-    user_id_counter: UserId,
+    transfer_id_counter: TransferId,
+    loan_product_id_counter: LoanProductId,
+    loan_id_counter: LoanId,
+    term_deposit_id_counter: TermDepositId,
+    fee_rules: HashMap<FeeType, FeeRule>,
+    fee_waivers: HashSet<(UserId, Option<FeeType>)>,
+    minimum_balance: Balance,
+    fee_income: Balance,
+    loan_products: HashMap<LoanProductId, LoanProduct>,
+    loans: HashMap<LoanId, Loan>,
+    term_deposit_rate: f64,
+    term_deposits: HashMap<TermDepositId, TermDeposit>,
+    fraud_rules: Vec<Box<dyn FraudRule>>,
+    fraud_reviews: HashMap<ReviewId, FraudReview>,
+    compliance_thresholds: ComplianceThresholds,
+    compliance_reports: HashMap<ReportId, ComplianceReport>,
+    watch_list: Vec<WatchListEntry>,
+    screening_thresholds: ScreeningThresholds,
+    screening_hits: HashMap<HitId, ScreeningHit>,
+    /// Normalized names cleared against the listed name of a watch list entry.
+    cleared_matches: HashSet<(String, String)>,
+    profiles: HashMap<UserId, Vec<ProfileVersion>>,
+    kyc_statuses: HashMap<UserId, KycStatus>,
+    kyc_policy: KycPolicy,
+    reconciliation_tolerance: Balance,
+    /// Key the checkpoints of the event log are signed with.
+    signing_key: Vec<u8>,
+    now: Timestamp,
+    /// Print balance changes and logins to stdout.
+    verbose: bool,
+}
+
+/// Returns true if an operation failing with `error` logged why before failing: the screening
+/// hit of `WatchListMatch`, the fraud check of `TransactionHeld` and `TransactionDenied`, the
+/// overdraft fee of `InsufficientBalance`. The log keeps these events, the held movement its
+/// review and the bank its fee. Operations fail with these errors before changing anything
+/// else.
+fn is_logged(error: BankingError) -> bool {
+    matches!(
+        error,
+        BankingError::WatchListMatch
+            | BankingError::TransactionHeld
+            | BankingError::TransactionDenied
+            | BankingError::InsufficientBalance
+    )
 }
 
 impl Default for Bank {
     fn default() -> Self {
         Self {
-            users: Default::default(),
-            balances: Default::default(),
-            events: Default::default(),
+            storage: Box::new(MemoryStorage::new()),
+            payees: Default::default(),
+            transfers: Default::default(),
             interest_rate: INTEREST_RATE,
             tax_rate: TAX_RATE,
             existential_deposit: ED,
-            user_id_counter: Default::default(),
+            transfer_id_counter: Default::default(),
+            loan_product_id_counter: Default::default(),
+            loan_id_counter: Default::default(),
+            term_deposit_id_counter: Default::default(),
+            fee_rules: Default::default(),
+            fee_waivers: Default::default(),
+            minimum_balance: Default::default(),
+            fee_income: Default::default(),
+            loan_products: Default::default(),
+            loans: Default::default(),
+            term_deposit_rate: TERM_DEPOSIT_RATE,
+            term_deposits: Default::default(),
+            fraud_rules: Default::default(),
+            fraud_reviews: Default::default(),
+            compliance_thresholds: Default::default(),
+            compliance_reports: Default::default(),
+            watch_list: Default::default(),
+            screening_thresholds: Default::default(),
+            screening_hits: Default::default(),
+            cleared_matches: Default::default(),
+            profiles: Default::default(),
+            kyc_statuses: Default::default(),
+            kyc_policy: KycPolicy::Unrestricted,
+            reconciliation_tolerance: RECONCILIATION_TOLERANCE,
+            signing_key: signing_key_from_env(),
+            now: Default::default(),
+            verbose: true,
         }
     }
 }
 
 impl Bank {
+    /// Creates a bank keeping its users, balances and event log in `storage`. The storage may
+    /// already hold the data of a bank: new users get the IDs after the last user, and the
+    /// clock resumes at the time of the last event, and new events are chained to it. The rest
+    /// of the state of that bank was not stored, and starts over from the defaults (see
+    /// `storage`).
+    pub fn with_storage(storage: Box<dyn Storage>) -> Self {
+        let mut bank = Self {
+            storage,
+            ..Default::default()
+        };
+        bank.catch_up_clock();
+        bank
+    }
+
+    // Helper function: Runs an operation in a transaction of the storage, so its changes are
+    // saved together or not at all. They are saved if it succeeds, or fails with an error it
+    // logged an event for (see `is_logged`), and rolled back otherwise, also if it panics.
+    // Transactions nest: the changes of an inner one are saved with the outermost one. Fails
+    // with `StorageFailure`, after rolling back, if the storage failed.
+    //
+    // Other processes may have changed the storage since the last transaction, so whatever the
+    // bank derives from it is read anew inside it: the clock here, the next user ID and the head
+    // of the log when used. Nothing derived from the storage is cached, so nothing is stale
+    // after a rollback.
+    fn transaction<T>(
+        &mut self,
+        operation: impl FnOnce(&mut Self) -> BankResult<T>,
+    ) -> BankResult<T> {
+        self.transaction_keeping(is_logged, operation)
+    }
+
+    // Helper function: Runs an operation in a transaction, as `transaction` does, but saves its
+    // changes if it fails with an error only if `keep` returns true for it.
+    fn transaction_keeping<T>(
+        &mut self,
+        keep: fn(BankingError) -> bool,
+        operation: impl FnOnce(&mut Self) -> BankResult<T>,
+    ) -> BankResult<T> {
+        self.storage
+            .begin()
+            .map_err(|_| BankingError::StorageFailure)?;
+        self.catch_up_clock();
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| operation(&mut *self))) {
+            Ok(result) => result,
+            Err(panic) => {
+                let _ = self.storage.rollback();
+                panic::resume_unwind(panic)
+            }
+        };
+        let failed = self.storage.take_failure().is_some();
+        let ended = if failed || matches!(&result, Err(e) if !keep(*e)) {
+            self.storage.rollback()
+        } else {
+            self.storage.commit()
+        };
+        if failed || ended.is_err() {
+            return Err(BankingError::StorageFailure);
+        }
+        result
+    }
+
+    /// Runs `operation`, made of several operations of the bank, as a single one: its changes
+    /// are saved if it succeeds, and all undone if it fails. Unlike a single operation, this
+    /// also undoes the events logged by the operations that failed in it, and the changes to the
+    /// state the bank keeps in memory, such as transfer records and fraud reviews.
+    pub fn atomically<T>(
+        &mut self,
+        operation: impl FnOnce(&mut Self) -> BankResult<T>,
+    ) -> BankResult<T> {
+        let snapshot = self.snapshot();
+        let result = self.transaction_keeping(|_| false, operation);
+        if result.is_err() {
+            self.restore(snapshot);
+        }
+        result
+    }
+
+    // Helper function: Returns a copy of the state the bank keeps in memory, with no storage
+    // and no fraud rules, for `restore`.
+    fn snapshot(&self) -> Self {
+        Self {
+            storage: Box::new(MemoryStorage::new()),
+            payees: self.payees.clone(),
+            transfers: self.transfers.clone(),
+            interest_rate: self.interest_rate,
+            tax_rate: self.tax_rate,
+            existential_deposit: self.existential_deposit,
+            transfer_id_counter: self.transfer_id_counter,
+            loan_product_id_counter: self.loan_product_id_counter,
+            loan_id_counter: self.loan_id_counter,
+            term_deposit_id_counter: self.term_deposit_id_counter,
+            fee_rules: self.fee_rules.clone(),
+            fee_waivers: self.fee_waivers.clone(),
+            minimum_balance: self.minimum_balance,
+            fee_income: self.fee_income,
+            loan_products: self.loan_products.clone(),
+            loans: self.loans.clone(),
+            term_deposit_rate: self.term_deposit_rate,
+            term_deposits: self.term_deposits.clone(),
+            fraud_rules: Vec::new(),
+            fraud_reviews: self.fraud_reviews.clone(),
+            compliance_thresholds: self.compliance_thresholds,
+            compliance_reports: self.compliance_reports.clone(),
+            watch_list: self.watch_list.clone(),
+            screening_thresholds: self.screening_thresholds,
+            screening_hits: self.screening_hits.clone(),
+            cleared_matches: self.cleared_matches.clone(),
+            profiles: self.profiles.clone(),
+            kyc_statuses: self.kyc_statuses.clone(),
+            kyc_policy: self.kyc_policy,
+            reconciliation_tolerance: self.reconciliation_tolerance,
+            signing_key: self.signing_key.clone(),
+            now: self.now,
+            verbose: self.verbose,
+        }
+    }
+
+    // Helper function: Puts back the state the bank kept in memory when `snapshot` was taken,
+    // keeping its storage and fraud rules.
+    fn restore(&mut self, mut snapshot: Self) {
+        mem::swap(&mut snapshot.storage, &mut self.storage);
+        mem::swap(&mut snapshot.fraud_rules, &mut self.fraud_rules);
+        *self = snapshot;
+    }
+
+    // Helper function: Runs an operation that only reads. Fails with `StorageFailure` if the
+    // storage failed to read something, rather than answering without it.
+    fn read<T>(&self, operation: impl FnOnce(&Self) -> BankResult<T>) -> BankResult<T> {
+        let result = operation(self);
+        match self.storage.take_failure() {
+            Some(_) => Err(BankingError::StorageFailure),
+            None => result,
+        }
+    }
+
+    // Helper function: Moves the clock to the time of the last event, if it is behind it.
+    fn catch_up_clock(&mut self) {
+        if let Some(last) = self.event_count().checked_sub(1) {
+            let at = self.storage.event(last).map_or(0, |e| e.at);
+            self.now = self.now.max(at);
+        }
+    }
+
     /// Ensure the user is of a given role. If true, return the UserId. Error otherwise.
     fn assert_role(&self, user: HashResult, role: Role) -> BankResult<UserId> {
-        match self.users.get(&user) {
+        match self.storage.user(user) {
             Some(u) => {
                 if u.role == role {
                     Ok(u.id)
@@ -46,436 +300,1094 @@ impl Bank {
         }
     }
 
-    /// Log the events to the vec
-    fn deposit_event(&mut self, event: Event) {
-        self.events.push(event);
+    /// Set whether balance changes and logins are printed to stdout.
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
     }
 
-    /// Calculate the hash of username and password using a DefaultHasher
-    fn hash(username: &String, password: &String) -> HashResult {
-        let mut hasher = DefaultHasher::new();
-        username.hash(&mut hasher);
-        password.hash(&mut hasher);
-        hasher.finish()
+    // Helper function: Prints the message unless the bank is quiet.
+    fn notify(&self, message: String) {
+        if self.verbose {
+            println!("{}", message);
+        }
+    }
+
+    /// Log the events to the vec, stamped with the current time
+    pub(crate) fn deposit_event(&mut self, event: Event) {
+        self.chain_event(event);
+    }
+
+    /// Returns the number of events logged.
+    pub fn event_count(&self) -> usize {
+        self.storage.event_count()
+    }
+
+    // Helper function: Returns the balance of the user, zero if the account holds no funds.
+    fn balance(&self, id: UserId) -> Balance {
+        self.storage.balance(id).unwrap_or_default()
+    }
+
+    /// Calculate the hash of username and password: PBKDF2-HMAC-SHA256 of the password, salted
+    /// with the username, truncated to 64 bits. Unlike a `DefaultHasher`, it is the same in
+    /// every build, so the credentials stored in bank files and databases stay valid.
+    pub fn hash(username: &str, password: &str) -> HashResult {
+        Self::hash_with_rounds(username, password, HASH_ROUNDS)
+    }
+
+    /// Calculate the hash of username and password with the given number of PBKDF2 rounds.
+    /// Lets the tests compute the credentials the binaries store.
+    pub(crate) fn hash_with_rounds(username: &str, password: &str, rounds: u32) -> HashResult {
+        let salt = [HASH_SALT_PREFIX, username.as_bytes()].concat();
+        let mut hash = [0u8; 8];
+        pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, rounds, &mut hash);
+        HashResult::from_be_bytes(hash)
     }
 
     /// Function to generate the next user ID (auto-incrementing)
     fn generate_next_user_id(&mut self) -> UserId {
-        self.user_id_counter += 1u64;
-        self.user_id_counter
+        self.storage.last_user_id() + 1u64
+    }
+
+    /// Function to generate the next transfer ID (auto-incrementing)
+    fn generate_next_transfer_id(&mut self) -> TransferId {
+        self.transfer_id_counter += 1u64;
+        self.transfer_id_counter
+    }
+
+    /// Function to generate the next loan product ID (auto-incrementing)
+    fn generate_next_loan_product_id(&mut self) -> LoanProductId {
+        self.loan_product_id_counter += 1u64;
+        self.loan_product_id_counter
+    }
+
+    /// Function to generate the next loan ID (auto-incrementing)
+    fn generate_next_loan_id(&mut self) -> LoanId {
+        self.loan_id_counter += 1u64;
+        self.loan_id_counter
+    }
+
+    /// Function to generate the next term deposit ID (auto-incrementing)
+    fn generate_next_term_deposit_id(&mut self) -> TermDepositId {
+        self.term_deposit_id_counter += 1u64;
+        self.term_deposit_id_counter
+    }
+
+    /// Returns the part of the user's balance held by pending transfers.
+    fn held_balance(&self, id: UserId) -> Balance {
+        self.transfers
+            .values()
+            .filter(|t| t.from == id && t.status == TransferStatus::Pending)
+            .map(|t| t.amount + t.fee)
+            .sum::<Balance>()
+            + self.held_for_review(id)
+    }
+
+    /// Returns the user's balance that is not held by pending transfers or fraud reviews.
+    fn available_balance(&self, id: UserId) -> Balance {
+        self.balance(id) - self.held_balance(id)
+    }
+
+    /// Reduce the user's balance by `amount`. The caller must ensure the balance is sufficient.
+    /// If this brings the balance below ED and no funds are held, the account is reaped.
+    fn debit(&mut self, id: UserId, amount: Balance) {
+        let new_balance = self.balance(id) - amount;
+        if new_balance >= self.existential_deposit || self.held_balance(id) > 0f64 {
+            self.storage.set_balance(id, new_balance);
+            self.notify(format!("User: {}, current balance is {}.", id, new_balance));
+        } else {
+            self.storage.remove_balance(id);
+            self.deposit_event(Event::AccountReaped {
+                id,
+                dust: new_balance,
+            });
+            self.notify(format!(
+                "User: {}, balance is too low, account is reaped, current balance is 0.",
+                id
+            ));
+        }
+    }
+
+    /// Increase the user's balance by `amount`.
+    fn credit(&mut self, id: UserId, amount: Balance) {
+        self.storage.set_balance(id, self.balance(id) + amount);
     }
 
     /// Returns true if the given username is already registered.
     /// This function is used to check for duplicated usernames.
-    pub fn has_username(&self, username: &String) -> bool {
-        self.users
+    pub fn has_username(&self, username: &str) -> bool {
+        self.storage.user_by_username(username).is_some()
+    }
+
+    /// Returns the balance of every customer, zero if the account holds no funds, ordered by
+    /// user ID.
+    pub(crate) fn balances(&self) -> Vec<(UserId, Balance)> {
+        self.storage
+            .users()
             .iter()
-            .any(|(_, user)| user.username == *username)
+            .filter(|u| u.role == Role::Customer)
+            .map(|u| (u.id, self.balance(u.id)))
+            .collect()
+    }
+
+    /// Returns true if a user of the given role is registered.
+    pub fn has_role(&self, role: Role) -> bool {
+        self.storage.users().iter().any(|u| u.role == role)
+    }
+
+    /// Returns the `Customer` with the given username.
+    fn find_customer_by_username(&self, username: &str) -> BankResult<User> {
+        match self.storage.user_by_username(username) {
+            Some(user) if user.role == Role::Customer => Ok(user),
+            _ => Err(BankingError::NoUserFound),
+        }
+    }
+
+    /// Returns the `Customer` with the given user ID.
+    pub(crate) fn find_customer_by_id(&self, target: UserId) -> BankResult<User> {
+        match self.storage.user_by_id(target) {
+            Some(user) if user.role == Role::Customer => Ok(user),
+            _ => Err(BankingError::InvalidUserId),
+        }
     }
 
-    /// Add a new user to the `users` hashmap.
+    /// Returns the logged in user.
+    pub fn current_user(&self, user: HashResult) -> BankResult<User> {
+        self.read(|bank| match bank.storage.user(user) {
+            Some(u) => Ok(u),
+            None => Err(BankingError::NoUserFound),
+        })
+    }
+
+    /// Returns the minimum balance an account must hold.
+    pub fn existential_deposit(&self) -> Balance {
+        self.existential_deposit
+    }
+
+    /// Add a new user to the `users` hashmap. The username is screened against the watch list.
     pub fn create_user(
         &mut self,
         username: String,
         password: String,
         role: Role,
     ) -> BankResult<()> {
-        if self.has_username(&username) {
-            return Err(BankingError::UserAlreadyExist);
-        }
-        let hash_result = Self::hash(&username, &password);
-        let new_user = User {
-            id: self.generate_next_user_id(),
-            username,
-            role,
-        };
-        self.users.insert(hash_result, new_user);
-        Ok(())
+        self.transaction(|bank| {
+            let hash_result = Self::hash(&username, &password);
+            bank.create_user_with_hash(username, hash_result, role)
+        })
+    }
+
+    /// Add a new user whose username and password are already hashed, such as a user replayed
+    /// from a bank file.
+    pub fn create_user_with_hash(
+        &mut self,
+        username: String,
+        hash_result: HashResult,
+        role: Role,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            if bank.has_username(&username) {
+                return Err(BankingError::UserAlreadyExist);
+            }
+            bank.screen_name(&username, None)?;
+            let new_user = User {
+                id: bank.generate_next_user_id(),
+                username,
+                role,
+            };
+            bank.storage.insert_user(hash_result, new_user);
+            Ok(())
+        })
     }
 
     /// Tries to log in with the given username and password. If successful, return the "hash" and role of the
     /// user, which can be used to access other functions.
     pub fn login(&self, username: String, password: String) -> BankResult<(HashResult, Role)> {
-        let hash_result = Self::hash(&username, &password);
-        match self.users.get(&hash_result) {
-            Some(u) => {
-                println!("Login ID: {}, Role: {:?}", u.id, u.role);
-                Ok((hash_result, u.role))
+        self.read(|bank| {
+            let hash_result = Self::hash(&username, &password);
+            match bank.storage.user(hash_result) {
+                Some(u) => {
+                    bank.notify(format!("Login ID: {}, Role: {:?}", u.id, u.role));
+                    Ok((hash_result, u.role))
+                }
+                None => Err(BankingError::FailedLogin),
             }
-            None => Err(BankingError::FailedLogin),
-        }
+        })
     }
 
     /// Allows the user to set a new password. Rehashes the user and stores the user under the new hash.
     pub fn change_password(&mut self, user: HashResult, new_password: String) -> BankResult<()> {
-        let user_data = match self.users.remove(&user) {
-            Some(u) => Ok(u),
-            None => Err(BankingError::NoUserFound),
-        }?;
-        let name = user_data.username.clone();
-        let new_hash = Self::hash(&name, &new_password);
-        self.users.insert(new_hash, user_data);
-        Ok(())
+        self.transaction(|bank| {
+            let name = bank.current_user(user)?.username;
+            let new_hash = Self::hash(&name, &new_password);
+            bank.storage.set_credentials(user, new_hash);
+            Ok(())
+        })
     }
 
-    /// Report all the users information and print them into the console.
-    /// Requires `manager` or `auditor` role.
-    pub fn report(&self, user: HashResult) -> BankResult<()> {
-        match self.users.get(&user) {
-            Some(u) => {
-                if u.role != Role::Customer {
-                    self.users.iter().for_each(|(_, user)| {
-                        println!("User ID: {}", user.id);
-                        println!("Username: {}", user.username);
-                        match user.role {
-                            Role::Customer => println!("Role: Customer"),
-                            Role::Manager => println!("Role: Manager"),
-                            Role::Auditor => println!("Role: Auditor"),
-                        }
-                        if user.role == Role::Customer {
-                            let balance = self.balances.get(&user.id).copied().unwrap_or_default();
-                            println!("Blance: {}", balance);
-                        }
-                        println!("------------------------");
-                    });
-                    Ok(())
-                } else {
-                    Err(BankingError::Unauthorized)
-                }
+    /// Returns a row for every user, ordered by user ID, and the bank income account.
+    /// Requires `Manager` or `Auditor` role.
+    pub fn report(&self, user: HashResult) -> BankResult<BankReport> {
+        self.read(|bank| {
+            if bank.current_user(user)?.role == Role::Customer {
+                return Err(BankingError::Unauthorized);
             }
-            None => Err(BankingError::NoUserFound),
-        }
+            let rows = bank
+                .storage
+                .users()
+                .into_iter()
+                .map(|u| {
+                    let balance = bank.storage.balance(u.id);
+                    let status = match (u.role, balance) {
+                        (Role::Customer, Some(_)) => AccountStatus::Active,
+                        (Role::Customer, None) => AccountStatus::Empty,
+                        _ => AccountStatus::Staff,
+                    };
+                    ReportRow {
+                        id: u.id,
+                        username: u.username.clone(),
+                        role: u.role,
+                        balance: balance.unwrap_or_default(),
+                        term_deposits: bank.locked_in_term_deposits(u.id),
+                        kyc: Some(bank.kyc_status(u.id)).filter(|_| u.role == Role::Customer),
+                        status,
+                    }
+                })
+                .collect::<Vec<_>>();
+            Ok(BankReport {
+                rows,
+                fee_income: bank.fee_income,
+            })
+        })
+    }
+
+    // Helper function: Validates the reference given by the user. An empty reference is stored as `None`.
+    fn validate_reference(reference: PaymentReference) -> BankResult<Option<PaymentReference>> {
+        let reference = PaymentReference::new(reference.memo, reference.creditor_reference)?;
+        Ok(Some(reference).filter(|r| !r.is_empty()))
     }
 
     /// Deposits the given `amount` into the user's account.
     /// Requires `Customer` role.
     pub fn deposit(&mut self, user: HashResult, amount: Balance) -> BankResult<()> {
-        if amount <= 0f64 {
-            return Err(BankingError::InvalidAmount);
-        }
+        self.deposit_with_reference(user, amount, PaymentReference::default())
+    }
 
-        let id = self.assert_role(user, Role::Customer)?;
-        let new_balance = match self.balances.get(&id) {
-            Some(balance) => Ok(balance + amount),
-            None => {
-                if amount < self.existential_deposit {
-                    Err(BankingError::AmountTooSmall)
-                } else {
-                    Ok(amount)
-                }
+    /// Deposits the given `amount` into the user's account, and stores `reference` with the event.
+    /// Requires `Customer` role.
+    pub fn deposit_with_reference(
+        &mut self,
+        user: HashResult,
+        amount: Balance,
+        reference: PaymentReference,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            let reference = Self::validate_reference(reference)?;
+            if amount <= 0f64 || !amount.is_finite() {
+                return Err(BankingError::InvalidAmount);
             }
-        }?;
 
-        self.balances.insert(id, new_balance);
-        println!("User: {}, current balance is {}.", id, new_balance);
-        // Deposits the balance into the account.
-        self.deposit_event(Event::Deposit { id, amount });
+            let id = bank.assert_role(user, Role::Customer)?;
+            let new_balance = match bank.storage.balance(id) {
+                Some(balance) => Ok(balance + amount),
+                None => {
+                    if amount < bank.existential_deposit {
+                        Err(BankingError::AmountTooSmall)
+                    } else {
+                        Ok(amount)
+                    }
+                }
+            }?;
+
+            bank.storage.set_balance(id, new_balance);
+            bank.notify(format!("User: {}, current balance is {}.", id, new_balance));
+            // Deposits the balance into the account.
+            bank.deposit_event(Event::Deposit {
+                id,
+                amount,
+                reference,
+            });
 
-        Ok(())
+            Ok(())
+        })
     }
 
-    /// Withdraw `amount` funds from a user's account. If this brings the user's balance
-    /// to below ED, the account is reaped.
+    /// Withdraw `amount` funds from a user's account, plus the withdrawal fee. If this brings
+    /// the user's balance to below ED, the account is reaped.
     /// Requires `Customer` role.
     pub fn withdraw(&mut self, user: HashResult, amount: Balance) -> BankResult<()> {
-        if amount <= 0f64 {
-            return Err(BankingError::InvalidAmount);
-        }
+        self.withdraw_with_reference(user, amount, PaymentReference::default())
+    }
 
-        let id = self.assert_role(user, Role::Customer)?;
-        let new_balance = match self.balances.get(&id) {
-            Some(balance) => {
-                if *balance >= amount {
-                    Ok(balance - amount)
-                } else {
-                    Err(BankingError::InsufficientBalance)
-                }
+    /// Withdraw `amount` funds from a user's account, and stores `reference` with the event.
+    /// Follows the same rules as `withdraw`.
+    pub fn withdraw_with_reference(
+        &mut self,
+        user: HashResult,
+        amount: Balance,
+        reference: PaymentReference,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            let reference = Self::validate_reference(reference)?;
+            if amount <= 0f64 || !amount.is_finite() {
+                return Err(BankingError::InvalidAmount);
             }
-            None => Err(BankingError::InsufficientBalance),
-        }?;
-        self.deposit_event(Event::Withdrawal { id, amount });
-        if new_balance >= self.existential_deposit {
-            self.balances.insert(id, new_balance);
-            println!("User: {}, current balance is {}.", id, new_balance);
-        } else {
-            self.balances.remove(&id);
-            self.deposit_event(Event::AccountReaped {
-                id,
-                dust: new_balance,
-            });
-            println!(
-                "User: {}, balance is too low, account is reaped, current balance is 0.",
-                id
-            );
-        }
 
-        Ok(())
+            let id = bank.assert_role(user, Role::Customer)?;
+            bank.check_kyc_policy(id, amount, false)?;
+            let fee = bank.compute_fee(id, FeeType::Withdrawal, amount);
+            if bank.available_balance(id) < amount + fee {
+                bank.charge_overdraft_fee(id, amount);
+                return Err(BankingError::InsufficientBalance);
+            }
+            bank.screen_movement(id, None, amount, fee, reference.clone(), None)?;
+            bank.execute_withdrawal(id, amount, fee, reference);
+
+            Ok(())
+        })
     }
 
-    /// Transfer `amount` of fund from the current user to another user.
-    /// If the transfer brings the account's balance below ED, the account will be reaped.
+    // Helper function: Withdraws `amount` and charges `fee`. The caller must ensure the
+    // balance is sufficient.
+    fn execute_withdrawal(
+        &mut self,
+        id: UserId,
+        amount: Balance,
+        fee: Balance,
+        reference: Option<PaymentReference>,
+    ) {
+        self.deposit_event(Event::Withdrawal {
+            id,
+            amount,
+            reference,
+        });
+        self.post_fee(id, FeeType::Withdrawal, fee);
+        self.debit(id, amount + fee);
+    }
+
+    /// Transfer `amount` of fund from the current user to another user. The transfer fee is
+    /// charged to the current user. If the transfer brings the account's balance below ED,
+    /// the account will be reaped. The target is screened against the watch list and the
+    /// transfer is checked by the fraud rules before any money moves.
     ///
     /// Requires both the current and target user to be `Customer` role.
     pub fn transfer(&mut self, user: HashResult, amount: Balance, target: u64) -> BankResult<()> {
-        let id = self.assert_role(user, Role::Customer)?;
-        if id == target {
-            return Ok(());
-        }
-        if amount <= 0f64 {
-            return Err(BankingError::InvalidAmount);
-        }
-        if amount < self.existential_deposit {
-            return Err(BankingError::AmountTooSmall);
-        }
+        self.transfer_with_reference(user, amount, target, PaymentReference::default())
+    }
 
-        // Gets the balance of the `to` user
-        let mut to_user_balance = match self
-            .users
-            .iter()
-            .find(|(_, user)| user.id == target && user.role == Role::Customer)
-        {
-            Some(_) => Ok(self.balances.get(&target).copied().unwrap_or_default()),
-            None => Err(BankingError::InvalidUserId),
-        }?;
-
-        // Calculates the new balance of the current user.
-        let new_balance = match self.balances.get(&id) {
-            Some(balance) => {
-                if *balance >= amount {
-                    Ok(balance - amount)
-                } else {
-                    Err(BankingError::InsufficientBalance)
-                }
+    /// Transfer `amount` of fund from the current user to another user, and stores `reference`
+    /// with the event so the recipient knows what the transfer is for.
+    /// Follows the same rules as `transfer`.
+    pub fn transfer_with_reference(
+        &mut self,
+        user: HashResult,
+        amount: Balance,
+        target: UserId,
+        reference: PaymentReference,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            let reference = Self::validate_reference(reference)?;
+            let id = bank.assert_role(user, Role::Customer)?;
+            if id == target {
+                return Ok(());
             }
-            None => Err(BankingError::InsufficientBalance),
-        }?;
+            if amount <= 0f64 || !amount.is_finite() {
+                return Err(BankingError::InvalidAmount);
+            }
+            if amount < bank.existential_deposit {
+                return Err(BankingError::AmountTooSmall);
+            }
+            bank.check_kyc_policy(id, amount, true)?;
 
-        // Reap the account if below ED, otherwise inser into the hashmap
-        if new_balance >= self.existential_deposit {
-            self.balances.insert(id, new_balance);
-            println!("User: {}, current balance is {}.", id, new_balance);
-        } else {
-            self.balances.remove(&id);
-            self.deposit_event(Event::AccountReaped {
-                id,
-                dust: new_balance,
-            });
-            println!(
-                "User: {}, balance is too low, account is reaped, current balance is 0.",
-                id
-            );
-        }
+            let target_name = bank.find_customer_by_id(target)?.username.clone();
+            let fee = bank.compute_fee(id, FeeType::Transfer, amount);
+            if bank.available_balance(id) < amount + fee {
+                bank.charge_overdraft_fee(id, amount);
+                return Err(BankingError::InsufficientBalance);
+            }
+            bank.screen_name(&target_name, Some(id))?;
+            bank.screen_movement(id, Some(target), amount, fee, reference.clone(), None)?;
+            bank.execute_transfer(id, target, amount, fee, reference);
+            Ok(())
+        })
+    }
 
-        // Inserts the `to` user's balance into the hashmap.
-        to_user_balance += amount;
-        self.balances.insert(target, to_user_balance);
+    /// Checks that the current user could transfer `amount` to `target` now, after making the
+    /// transfers in `earlier`, each given as the target and amount. The amount, the KYC policy,
+    /// the watch list and the fraud rules are checked as `transfer` does, but the balance is
+    /// not, and nothing is logged. Fails with `TransactionHeld` if the transfer would be held.
+    /// Requires both the current and target user to be `Customer` role.
+    pub fn check_transfer(
+        &self,
+        user: HashResult,
+        amount: Balance,
+        target: UserId,
+        earlier: &[(UserId, Balance)],
+    ) -> BankResult<()> {
+        self.read(|bank| {
+            let id = bank.assert_role(user, Role::Customer)?;
+            if id == target {
+                return Err(BankingError::InvalidUserId);
+            }
+            if amount <= 0f64 || !amount.is_finite() {
+                return Err(BankingError::InvalidAmount);
+            }
+            if amount < bank.existential_deposit {
+                return Err(BankingError::AmountTooSmall);
+            }
+            bank.check_kyc_policy(id, amount, true)?;
+            if bank.is_blocked_name(&bank.find_customer_by_id(target)?.username) {
+                return Err(BankingError::WatchListMatch);
+            }
+            let earlier = earlier
+                .iter()
+                .map(|(to, amount)| Movement {
+                    at: bank.now,
+                    from: id,
+                    to: Some(*to),
+                    amount: *amount,
+                })
+                .collect::<Vec<_>>();
+            bank.check_movement(id, Some(target), amount, &earlier)
+        })
+    }
+
+    // Helper function: Transfers `amount` and charges `fee`, keeping a settled record of the
+    // transfer. The caller must ensure the balance is sufficient.
+    fn execute_transfer(
+        &mut self,
+        id: UserId,
+        target: UserId,
+        amount: Balance,
+        fee: Balance,
+        reference: Option<PaymentReference>,
+    ) {
+        // Reap the account if below ED, and credit the `to` user.
+        self.debit(id, amount + fee);
+        self.credit(target, amount);
 
         self.deposit_event(Event::Transfer {
             id,
             to_id: target,
             amount,
+            reference: reference.clone(),
+        });
+        let settled_event = Some(self.event_count() - 1);
+        self.post_fee(id, FeeType::Transfer, fee);
+
+        // Keep a record of the transfer so it can be reversed later.
+        let transfer_id = self.generate_next_transfer_id();
+        self.transfers.insert(
+            transfer_id,
+            TransferRecord {
+                transfer_id,
+                from: id,
+                to: target,
+                amount,
+                fee,
+                reference,
+                status: TransferStatus::Settled,
+                settled_event,
+            },
+        );
+    }
+
+    /// Initiate a two-phase transfer of `amount` from the current user to another user.
+    /// The amount and transfer fee are held from the current user's available balance until
+    /// the transfer is settled by a `Manager` or cancelled. Returns the ID of the pending
+    /// transfer.
+    ///
+    /// The fraud rules are run when the transfer is initiated. A held transfer fails with
+    /// `TransactionHeld`, and is initiated under the ID recorded in its review once approved.
+    ///
+    /// Requires both the current and target user to be `Customer` role.
+    pub fn initiate_transfer(
+        &mut self,
+        user: HashResult,
+        amount: Balance,
+        target: UserId,
+    ) -> BankResult<TransferId> {
+        self.initiate_transfer_with_reference(user, amount, target, PaymentReference::default())
+    }
+
+    /// Initiate a two-phase transfer, and stores `reference` with the transfer. The reference
+    /// is carried over to the `Event::Transfer` posted when the transfer settles.
+    /// Follows the same rules as `initiate_transfer`.
+    pub fn initiate_transfer_with_reference(
+        &mut self,
+        user: HashResult,
+        amount: Balance,
+        target: UserId,
+        reference: PaymentReference,
+    ) -> BankResult<TransferId> {
+        self.transaction(|bank| {
+            let reference = Self::validate_reference(reference)?;
+            let id = bank.assert_role(user, Role::Customer)?;
+            if id == target {
+                return Err(BankingError::InvalidUserId);
+            }
+            if amount <= 0f64 || !amount.is_finite() {
+                return Err(BankingError::InvalidAmount);
+            }
+            if amount < bank.existential_deposit {
+                return Err(BankingError::AmountTooSmall);
+            }
+            bank.check_kyc_policy(id, amount, true)?;
+            let target_name = bank.find_customer_by_id(target)?.username.clone();
+            let fee = bank.compute_fee(id, FeeType::Transfer, amount);
+            if bank.available_balance(id) < amount + fee {
+                bank.charge_overdraft_fee(id, amount);
+                return Err(BankingError::InsufficientBalance);
+            }
+            bank.screen_name(&target_name, Some(id))?;
+            let transfer_id = bank.generate_next_transfer_id();
+            let screened = Some(transfer_id);
+            bank.screen_movement(id, Some(target), amount, fee, reference.clone(), screened)?;
+            bank.record_pending_transfer(transfer_id, id, target, amount, fee, reference);
+            Ok(transfer_id)
+        })
+    }
+
+    // Helper function: Records a pending transfer, holding the amount and fee from the sender's
+    // available balance. The caller must ensure the balance is sufficient.
+    fn record_pending_transfer(
+        &mut self,
+        transfer_id: TransferId,
+        id: UserId,
+        target: UserId,
+        amount: Balance,
+        fee: Balance,
+        reference: Option<PaymentReference>,
+    ) {
+        self.transfers.insert(
+            transfer_id,
+            TransferRecord {
+                transfer_id,
+                from: id,
+                to: target,
+                amount,
+                fee,
+                reference: reference.clone(),
+                status: TransferStatus::Pending,
+                settled_event: None,
+            },
+        );
+        self.deposit_event(Event::TransferInitiated {
+            transfer_id,
+            id,
+            to_id: target,
+            amount,
+            reference,
         });
-        Ok(())
     }
 
-    /// Returns the current balance of the given user.
+    /// Settle a pending transfer: release the hold and move the funds. If this brings the
+    /// sender's balance below ED, the account will be reaped.
+    /// Requires `Manager` role.
+    pub fn settle_transfer(&mut self, user: HashResult, transfer_id: TransferId) -> BankResult<()> {
+        self.transaction(|bank| {
+            bank.assert_role(user, Role::Manager)?;
+            let mut record = bank.pending_transfer(transfer_id)?;
+            if bank.balance(record.from) < record.amount + record.fee {
+                return Err(BankingError::InsufficientBalance);
+            }
+
+            // Release the hold before debiting, so the held amount is spent.
+            record.status = TransferStatus::Settled;
+            bank.transfers.insert(transfer_id, record.clone());
+            bank.debit(record.from, record.amount + record.fee);
+            bank.credit(record.to, record.amount);
+            bank.deposit_event(Event::Transfer {
+                id: record.from,
+                to_id: record.to,
+                amount: record.amount,
+                reference: record.reference.clone(),
+            });
+            record.settled_event = Some(bank.event_count() - 1);
+            bank.post_fee(record.from, FeeType::Transfer, record.fee);
+            let (from, to) = (record.from, record.to);
+            bank.transfers.insert(transfer_id, record);
+            bank.deposit_event(Event::TransferSettled {
+                transfer_id,
+                id: from,
+                to_id: to,
+            });
+            Ok(())
+        })
+    }
+
+    /// Cancel a pending transfer and release the held funds.
+    /// Requires the current user to be the `Customer` who initiated the transfer.
+    pub fn cancel_transfer(&mut self, user: HashResult, transfer_id: TransferId) -> BankResult<()> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Customer)?;
+            let mut record = bank.pending_transfer(transfer_id)?;
+            if record.from != id {
+                return Err(BankingError::Unauthorized);
+            }
+
+            record.status = TransferStatus::Cancelled;
+            let (from, to) = (record.from, record.to);
+            bank.transfers.insert(transfer_id, record);
+            bank.deposit_event(Event::TransferCancelled {
+                transfer_id,
+                id: from,
+                to_id: to,
+            });
+            Ok(())
+        })
+    }
+
+    /// Reverse a settled transfer by posting a compensating entry that moves the funds back
+    /// from the recipient to the sender. The recipient must have enough available balance.
+    /// Requires `Manager` role.
+    pub fn reverse_transfer(
+        &mut self,
+        user: HashResult,
+        transfer_id: TransferId,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            let by = bank.assert_role(user, Role::Manager)?;
+            let mut record = match bank.transfers.get(&transfer_id) {
+                Some(t) => Ok(t.clone()),
+                None => Err(BankingError::NoTransferFound),
+            }?;
+            let original = match (record.status, record.settled_event) {
+                (TransferStatus::Settled, Some(original)) => Ok(original),
+                _ => Err(BankingError::InvalidTransferStatus),
+            }?;
+            if bank.available_balance(record.to) < record.amount {
+                return Err(BankingError::InsufficientBalance);
+            }
+
+            bank.debit(record.to, record.amount);
+            bank.credit(record.from, record.amount);
+            record.status = TransferStatus::Reversed;
+            let (from, to, amount) = (record.from, record.to, record.amount);
+            bank.transfers.insert(transfer_id, record);
+            bank.deposit_event(Event::TransferReversed {
+                transfer_id,
+                id: from,
+                to_id: to,
+                amount,
+                original,
+                by,
+            });
+            Ok(())
+        })
+    }
+
+    // Helper function that returns a clone of the transfer if it is still pending.
+    fn pending_transfer(&self, transfer_id: TransferId) -> BankResult<TransferRecord> {
+        match self.transfers.get(&transfer_id) {
+            Some(t) if t.status == TransferStatus::Pending => Ok(t.clone()),
+            Some(_) => Err(BankingError::InvalidTransferStatus),
+            None => Err(BankingError::NoTransferFound),
+        }
+    }
+
+    /// Returns the transfers visible to the current user, ordered by transfer ID.
+    /// `Customer`s see the transfers they sent or received, other roles see all transfers.
+    pub fn list_transfers(&self, user: HashResult) -> BankResult<Vec<TransferRecord>> {
+        self.read(|bank| {
+            let u = bank.current_user(user)?;
+            let mut transfers = bank
+                .transfers
+                .values()
+                .filter(|t| u.role != Role::Customer || t.from == u.id || t.to == u.id)
+                .cloned()
+                .collect::<Vec<_>>();
+            transfers.sort_by_key(|t| t.transfer_id);
+            Ok(transfers)
+        })
+    }
+
+    /// Transfer `amount` of fund from the current user to the customer with the given username.
+    /// Follows the same rules as `transfer`.
+    pub fn transfer_by_username(
+        &mut self,
+        user: HashResult,
+        amount: Balance,
+        username: &str,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            bank.assert_role(user, Role::Customer)?;
+            let target = bank.find_customer_by_username(username)?.id;
+            bank.transfer(user, amount, target)
+        })
+    }
+
+    /// Transfer `amount` of fund from the current user to one of their saved payees.
+    /// Follows the same rules as `transfer`.
+    pub fn transfer_to_payee(
+        &mut self,
+        user: HashResult,
+        amount: Balance,
+        nickname: &str,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Customer)?;
+            let target = match bank
+                .payees
+                .get(&id)
+                .and_then(|book| book.iter().find(|p| p.nickname == nickname))
+            {
+                Some(payee) => Ok(payee.id),
+                None => Err(BankingError::NoPayeeFound),
+            }?;
+            bank.transfer(user, amount, target)
+        })
+    }
+
+    /// Resolves a transfer target typed in by the current user. The input is matched against
+    /// the user's saved payee nicknames first, then usernames, then numeric user IDs.
+    /// Returns the ID and username of the resolved customer, so it can be confirmed before
+    /// any money is moved.
+    /// Requires `Customer` role.
+    pub fn resolve_payee(&self, user: HashResult, input: &str) -> BankResult<(UserId, String)> {
+        self.read(|bank| {
+            let id = bank.assert_role(user, Role::Customer)?;
+            let target = match bank
+                .payees
+                .get(&id)
+                .and_then(|book| book.iter().find(|p| p.nickname == input))
+            {
+                Some(payee) => bank.find_customer_by_id(payee.id)?,
+                None => match bank.find_customer_by_username(input) {
+                    Ok(u) => u,
+                    Err(_) => match input.parse::<UserId>() {
+                        Ok(target) => bank.find_customer_by_id(target)?,
+                        Err(_) => return Err(BankingError::NoUserFound),
+                    },
+                },
+            };
+            Ok((target.id, target.username.clone()))
+        })
+    }
+
+    /// Saves the customer with the given username into the current user's payee book
+    /// under `nickname`.
+    /// Requires both the current and payee user to be `Customer` role.
+    pub fn add_payee(
+        &mut self,
+        user: HashResult,
+        username: &str,
+        nickname: String,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Customer)?;
+            let nickname = nickname.trim().to_string();
+            if nickname.is_empty() {
+                return Err(BankingError::InvalidNickname);
+            }
+            let payee = bank.find_customer_by_username(username)?.id;
+            if payee == id {
+                return Err(BankingError::InvalidUserId);
+            }
+
+            let book = bank.payees.entry(id).or_default();
+            if book.iter().any(|p| p.id == payee || p.nickname == nickname) {
+                return Err(BankingError::PayeeAlreadyExist);
+            }
+            book.push(Payee {
+                id: payee,
+                nickname,
+            });
+            bank.deposit_event(Event::PayeeAdded { id, payee });
+            Ok(())
+        })
+    }
+
+    /// Removes the payee saved under `nickname` from the current user's payee book.
+    /// Requires `Customer` role.
+    pub fn remove_payee(&mut self, user: HashResult, nickname: &str) -> BankResult<()> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Customer)?;
+            let book = bank.payees.get_mut(&id).ok_or(BankingError::NoPayeeFound)?;
+            let payee = match book.iter().position(|p| p.nickname == nickname) {
+                Some(index) => Ok(book.remove(index).id),
+                None => Err(BankingError::NoPayeeFound),
+            }?;
+            bank.deposit_event(Event::PayeeRemoved { id, payee });
+            Ok(())
+        })
+    }
+
+    /// Returns the saved payees of the current user, together with their usernames.
+    /// Requires `Customer` role.
+    pub fn payees(&self, user: HashResult) -> BankResult<Vec<(Payee, String)>> {
+        self.read(|bank| {
+            let id = bank.assert_role(user, Role::Customer)?;
+            Ok(bank
+                .payees
+                .get(&id)
+                .map(|book| {
+                    book.iter()
+                        .map(|p| {
+                            let username = bank
+                                .find_customer_by_id(p.id)
+                                .map(|u| u.username.clone())
+                                .unwrap_or_default();
+                            (p.clone(), username)
+                        })
+                        .collect()
+                })
+                .unwrap_or_default())
+        })
+    }
+
+    /// Returns the current ledger balance of the given user, including funds held by
+    /// pending transfers.
     pub fn check_balance(&self, user: HashResult) -> BankResult<Balance> {
-        let id = self.assert_role(user, Role::Customer)?;
-        Ok(self.balances.get(&id).copied().unwrap_or_default())
+        self.read(|bank| {
+            let id = bank.assert_role(user, Role::Customer)?;
+            Ok(bank.balance(id))
+        })
+    }
+
+    /// Returns the available balance of the given user: the ledger balance minus funds
+    /// held by pending transfers.
+    pub fn check_available_balance(&self, user: HashResult) -> BankResult<Balance> {
+        self.read(|bank| {
+            let id = bank.assert_role(user, Role::Customer)?;
+            Ok(bank.available_balance(id))
+        })
+    }
+
+    /// Returns the current time of the simulated clock.
+    pub fn now(&self) -> Timestamp {
+        self.now
+    }
+
+    /// Moves the simulated clock forward by `duration` seconds, then draws the loan
+    /// installments that have fallen due and pays out the term deposits.
+    /// Requires `Manager` role.
+    pub fn advance_clock(&mut self, user: HashResult, duration: Timestamp) -> BankResult<()> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Manager)?;
+            bank.now += duration;
+            bank.deposit_event(Event::ClockAdvanced { id, now: bank.now });
+            bank.process_loan_repayments();
+            bank.process_term_deposits();
+            Ok(())
+        })
+    }
+
+    /// Returns the interest rate.
+    pub fn interest_rate(&self) -> f64 {
+        self.interest_rate
     }
 
     /// Set interest rate, which is used to payout interest to all users.
     /// Requires `Manager` role.
     pub fn set_interest_rate(&mut self, user: HashResult, rate: f64) -> BankResult<()> {
-        if rate < 0f64 {
-            return Err(BankingError::InvalidInterestRate);
-        }
-        match self.users.get(&user) {
-            Some(u) => {
-                if u.role == Role::Manager {
-                    self.interest_rate = rate;
-                    self.deposit_event(Event::InterestRate {
-                        id: u.id,
-                        interest_rate: rate,
-                    });
-                    Ok(())
-                } else {
-                    Err(BankingError::Unauthorized)
+        self.transaction(|bank| {
+            if rate < 0f64 {
+                return Err(BankingError::InvalidInterestRate);
+            }
+            match bank.storage.user(user) {
+                Some(u) => {
+                    if u.role == Role::Manager {
+                        bank.interest_rate = rate;
+                        bank.deposit_event(Event::InterestRate {
+                            id: u.id,
+                            interest_rate: rate,
+                        });
+                        Ok(())
+                    } else {
+                        Err(BankingError::Unauthorized)
+                    }
                 }
+                None => Err(BankingError::NoUserFound),
             }
-            None => Err(BankingError::NoUserFound),
-        }
+        })
+    }
+
+    /// Returns the tax rate.
+    pub fn tax_rate(&self) -> f64 {
+        self.tax_rate
     }
 
     /// Sets the tax rate, which is used to take tax from all users.
     /// Requires `Auditor` role.
     pub fn set_tax_rate(&mut self, user: HashResult, rate: f64) -> BankResult<()> {
-        if !(0f64..=1f64).contains(&rate) {
-            return Err(BankingError::InvalidTaxRate);
-        }
-        match self.users.get(&user) {
-            Some(u) => {
-                if u.role == Role::Auditor {
-                    self.tax_rate = rate;
-                    self.deposit_event(Event::TaxRate {
-                        id: u.id,
-                        tax_rate: rate,
-                    });
-                    Ok(())
-                } else {
-                    Err(BankingError::Unauthorized)
+        self.transaction(|bank| {
+            if !(0f64..=1f64).contains(&rate) {
+                return Err(BankingError::InvalidTaxRate);
+            }
+            match bank.storage.user(user) {
+                Some(u) => {
+                    if u.role == Role::Auditor {
+                        bank.tax_rate = rate;
+                        bank.deposit_event(Event::TaxRate {
+                            id: u.id,
+                            tax_rate: rate,
+                        });
+                        Ok(())
+                    } else {
+                        Err(BankingError::Unauthorized)
+                    }
                 }
+                None => Err(BankingError::NoUserFound),
             }
-            None => Err(BankingError::NoUserFound),
-        }
+        })
     }
 
     /// Pay out interest to all the customers. Increase the balances of all users' by
     /// `interest_rate` proportion.
     /// Requires `Manager` role.
     pub fn pay_interest(&mut self, user: HashResult) -> BankResult<()> {
-        match self.users.get(&user) {
-            Some(u) => {
-                if u.role == Role::Manager {
-                    Ok(())
-                } else {
-                    Err(BankingError::Unauthorized)
+        self.transaction(|bank| {
+            match bank.storage.user(user) {
+                Some(u) => {
+                    if u.role == Role::Manager {
+                        Ok(())
+                    } else {
+                        Err(BankingError::Unauthorized)
+                    }
                 }
-            }
-            None => Err(BankingError::NoUserFound),
-        }?;
+                None => Err(BankingError::NoUserFound),
+            }?;
 
-        let rate = self.interest_rate;
+            let rate = bank.interest_rate;
 
-        // Payout interest to all accounts, and deposit event for each account.
-        self.balances
-            .iter_mut()
-            .map(|(id, balance)| {
-                let new_balance = if *balance > Balance::MAX / (1f64 + rate){
-                    Balance::MAX
-                } else {
-                    *balance * (1f64 + rate)
-                };
-                let interest = new_balance - *balance;
-                *balance = new_balance;
-                (*id, interest)
-            })
-            .collect::<Vec<_>>()
-            .into_iter()
-            .for_each(|(id, interest)| self.deposit_event(Event::Interest { id, interest }));
-        Ok(())
+            // Payout interest to all accounts in ID order, and deposit event for each account.
+            bank.storage
+                .balances()
+                .into_iter()
+                .for_each(|(id, balance)| {
+                    let new_balance = if balance > Balance::MAX / (1f64 + rate) {
+                        Balance::MAX
+                    } else {
+                        balance * (1f64 + rate)
+                    };
+                    bank.storage.set_balance(id, new_balance);
+                    bank.deposit_event(Event::Interest {
+                        id,
+                        interest: new_balance - balance,
+                    });
+                });
+            Ok(())
+        })
     }
 
     /// Take tax from all the customers. Reduce the balance of all accounts by `tax_rate` proportion.
     /// Requires `Auditor` role.
     pub fn take_tax(&mut self, user: HashResult) -> BankResult<()> {
-        match self.users.get(&user) {
-            Some(u) => {
-                if u.role == Role::Auditor {
-                    Ok(())
-                } else {
-                    Err(BankingError::Unauthorized)
+        self.transaction(|bank| {
+            match bank.storage.user(user) {
+                Some(u) => {
+                    if u.role == Role::Auditor {
+                        Ok(())
+                    } else {
+                        Err(BankingError::Unauthorized)
+                    }
                 }
-            }
-            None => Err(BankingError::NoUserFound),
-        }?;
-        let rate = self.tax_rate;
-        let ed = self.existential_deposit;
-
-        // Reduce balance of all accounts by `tax_rate`. Reap the account if
-        // the new balance is below ED.
-        self.balances
-            .iter_mut()
-            .map(|(id, balance)| {
-                let tax = *balance * rate;
-                *balance *= 1f64 - rate;
-                (*id, *balance, tax)
-            })
-            .collect::<Vec<_>>()
-            .into_iter()
-            .for_each(|(id, new_balance, tax)| {
-                self.deposit_event(Event::Tax { id, tax });
-                if new_balance < ed {
-                    self.balances.remove(&id);
-                    self.deposit_event(Event::AccountReaped {
+                None => Err(BankingError::NoUserFound),
+            }?;
+            let rate = bank.tax_rate;
+            let ed = bank.existential_deposit;
+
+            // Reduce balance of all accounts by `tax_rate`, in ID order. Reap the account if
+            // the new balance is below ED.
+            bank.storage
+                .balances()
+                .into_iter()
+                .for_each(|(id, balance)| {
+                    let new_balance = balance * (1f64 - rate);
+                    bank.storage.set_balance(id, new_balance);
+                    bank.deposit_event(Event::Tax {
                         id,
-                        dust: new_balance,
+                        tax: balance * rate,
                     });
-                }
-            });
-        Ok(())
-    }
-
-    // Helper function that iterates all events of a given user and prints them to the console.
-    fn iter_event(&self, target_id: UserId) {
-        self.events.iter().for_each(|e| {
-            if match e {
-                Event::Deposit { id: event_id, .. } if *event_id == target_id => true,
-                Event::Withdrawal { id: event_id, .. } if *event_id == target_id => true,
-                Event::AccountReaped { id: event_id, .. } if *event_id == target_id => true,
-                Event::Transfer {
-                    id: event_id,
-                    to_id,
-                    amount: _,
-                } if *event_id == target_id || *to_id == target_id => true,
-                Event::Interest { id: event_id, .. } if *event_id == target_id => true,
-                Event::Tax { id: event_id, .. } if *event_id == target_id => true,
-                _ => false,
-            } {
-                println!("{}", e);
-            }
-        });
+                    if new_balance < ed && bank.held_balance(id) <= 0f64 {
+                        bank.storage.remove_balance(id);
+                        bank.deposit_event(Event::AccountReaped {
+                            id,
+                            dust: new_balance,
+                        });
+                    }
+                });
+            Ok(())
+        })
     }
 
-    /// Prints all events related to the current user.
-    /// Requires `Customer` role.
-    pub fn print_event(&self, user: HashResult) -> BankResult<()> {
-        let id = self.assert_role(user, Role::Customer)?;
-        println!("===== Events for User ID: {} =====", id);
+    /// Returns the event at the given position of the log, with its time.
+    pub(crate) fn event_row(&self, index: usize) -> Option<EventRow> {
+        self.storage.event(index)
+    }
+
+    /// Returns every event of the log, with its position and time.
+    pub(crate) fn events(&self) -> Vec<EventRow> {
+        self.storage.events()
+    }
 
-        // Iterate through the events vector and print matching events
-        self.iter_event(id);
-        Ok(())
+    /// Returns the events related to the user, with their position and time, oldest first.
+    pub(super) fn user_event_rows(&self, id: UserId) -> Vec<EventRow> {
+        self.storage
+            .event_positions(id)
+            .into_iter()
+            .filter_map(|index| self.event_row(index))
+            .collect()
+    }
+
+    /// Returns all events related to the current user.
+    /// Requires `Customer` role.
+    pub fn my_events(&self, user: HashResult) -> BankResult<Vec<EventRow>> {
+        self.read(|bank| {
+            let id = bank.assert_role(user, Role::Customer)?;
+            Ok(bank.user_event_rows(id))
+        })
     }
 
-    /// Prints out all events related to the given user
+    /// Returns all events related to the given user
     /// Requires `Manager` or `Auditor` role.
-    pub fn print_a_user_event(
+    pub fn user_events(
         &self,
         user: HashResult,
         role: Role,
         user_id: UserId,
-    ) -> BankResult<()> {
-        if role == Role::Customer {
-            return Err(BankingError::Unauthorized);
-        }
-        self.assert_role(user, role)?;
-        let user_find = self
-            .users
-            .iter()
-            .find(|(_, u)| u.id == user_id && u.role == Role::Customer);
-        match user_find {
-            Some(_) => Ok(()),
-            None => return Err(BankingError::InvalidUserId),
-        }?;
-        println!("===== Events for User ID: {} =====", user_id);
+    ) -> BankResult<Vec<EventRow>> {
+        self.read(|bank| {
+            if role == Role::Customer {
+                return Err(BankingError::Unauthorized);
+            }
+            bank.assert_role(user, role)?;
+            bank.find_customer_by_id(user_id)?;
+            Ok(bank.user_event_rows(user_id))
+        })
+    }
 
-        // Iterate through the events vector and print matching events
-        self.iter_event(user_id);
-        Ok(())
+    /// Returns the events whose payment reference matches `query`: the memo contains it
+    /// (case insensitive) or the creditor reference equals it.
+    /// `Customer`s search their own events, other roles search all events.
+    pub fn search_events_by_reference(
+        &self,
+        user: HashResult,
+        query: &str,
+    ) -> BankResult<Vec<Event>> {
+        self.read(|bank| {
+            let u = bank.current_user(user)?;
+            let rows = match u.role {
+                Role::Customer => bank.user_event_rows(u.id),
+                _ => bank.events(),
+            };
+            Ok(rows
+                .into_iter()
+                .map(|r| r.event)
+                .filter(|e| e.reference().map(|r| r.matches(query)) == Some(true))
+                .collect())
+        })
     }
 
-    /// Prints all the events logged.
+    /// Returns all the events logged.
     /// Requires `Manager` or `Auditor role.
-    pub fn print_all_events(&self, user: HashResult, role: Role) -> BankResult<()> {
-        if role == Role::Customer {
-            return Err(BankingError::Unauthorized);
-        }
-        self.assert_role(user, role)?;
-        println!("===== Events for all users =====");
-
-        // Iterate through the events vector and print matching events
-        self.events.iter().for_each(|e| println!("{}", e));
-        Ok(())
+    pub fn all_events(&self, user: HashResult, role: Role) -> BankResult<Vec<EventRow>> {
+        self.read(|bank| {
+            if role == Role::Customer {
+                return Err(BankingError::Unauthorized);
+            }
+            bank.assert_role(user, role)?;
+            Ok(bank.events())
+        })
     }
 }
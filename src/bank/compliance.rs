@@ -0,0 +1,299 @@
+//! Large cash transaction reports and structuring alerts for `Auditor`s.
+//!
+//! The detection pass scans the event log for deposits and withdrawals above the cash
+//! threshold, and for several deposits just below it within a short time. Each event is
+//! reported at most once per kind of report, so the pass can be run repeatedly.
+//!
+//! Reports are exported as fixed-layout files, one record per line:
+//!
+//! | Record  | Layout                                                                            |
+//! |---------|-----------------------------------------------------------------------------------|
+//! | Header  | `H`, report ID (10), kind (4), user ID (10), username (20), status (8), day (6)   |
+//! | Detail  | `D`, event index (10), day (6), type (3), amount in cents (15)                    |
+//! | Trailer | `T`, number of detail records (6), total amount in cents (15)                     |
+//!
+//! Numbers are right-aligned and zero-padded, text is left-aligned and space-padded. The kind
+//! is `LCTR` or `STRC`, and the type is `DEP` or `WDL`.
+use super::Bank;
+use crate::primitives::*;
+use std::{collections::HashSet, fs};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComplianceReport {
+    pub report_id: ReportId,
+    pub kind: ReportKind,
+    /// The customer the report is about.
+    pub id: UserId,
+    /// Total amount of the reported events.
+    pub amount: Balance,
+    /// Positions of the reported deposits and withdrawals in the event log.
+    pub events: Vec<usize>,
+    pub detected_at: Timestamp,
+    pub status: ReportStatus,
+}
+
+// Helper function: Formats an amount as zero-padded cents.
+fn cents(amount: Balance) -> String {
+    format!("{:015}", (amount * 100f64).round() as u64)
+}
+
+impl Bank {
+    /// Returns the thresholds used by the compliance detection pass.
+    pub fn compliance_thresholds(&self) -> ComplianceThresholds {
+        self.compliance_thresholds
+    }
+
+    /// Set the thresholds used by the compliance detection pass. Existing reports are kept.
+    /// Requires `Auditor` role.
+    pub fn set_compliance_thresholds(
+        &mut self,
+        user: HashResult,
+        thresholds: ComplianceThresholds,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Auditor)?;
+            if !thresholds.is_valid() {
+                return Err(BankingError::InvalidComplianceThresholds);
+            }
+            bank.compliance_thresholds = thresholds;
+            bank.deposit_event(Event::ComplianceThresholdsSet { id, thresholds });
+            Ok(())
+        })
+    }
+
+    // Helper function: Records a new open report on the given events.
+    fn open_report(&mut self, kind: ReportKind, id: UserId, events: Vec<usize>) -> ReportId {
+        let report_id = self.compliance_reports.len() as ReportId + 1;
+        let amount = events
+            .iter()
+            .filter_map(|i| self.event_row(*i))
+            .map(|row| match row.event {
+                Event::Deposit { amount, .. } | Event::Withdrawal { amount, .. } => amount,
+                _ => 0f64,
+            })
+            .sum();
+        self.compliance_reports.insert(
+            report_id,
+            ComplianceReport {
+                report_id,
+                kind,
+                id,
+                amount,
+                events,
+                detected_at: self.now,
+                status: ReportStatus::Open,
+            },
+        );
+        self.deposit_event(Event::ComplianceReportCreated {
+            report_id,
+            id,
+            kind,
+            amount,
+        });
+        report_id
+    }
+
+    /// Scans the event log and opens a report for every deposit or withdrawal above the cash
+    /// threshold, and a structuring alert for every run of deposits just below it. Events
+    /// already covered by a report of the same kind are skipped. Returns the new report IDs.
+    /// Requires `Auditor` role.
+    pub fn detect_compliance_issues(&mut self, user: HashResult) -> BankResult<Vec<ReportId>> {
+        self.transaction(|bank| {
+            bank.assert_role(user, Role::Auditor)?;
+            let thresholds = bank.compliance_thresholds;
+            let reported = |kind: ReportKind| -> HashSet<usize> {
+                bank.compliance_reports
+                    .values()
+                    .filter(|r| r.kind == kind)
+                    .flat_map(|r| r.events.iter().copied())
+                    .collect()
+            };
+            let reported_cash = reported(ReportKind::LargeCashTransaction);
+            let reported_structuring = reported(ReportKind::Structuring);
+
+            let mut large = vec![];
+            let mut near_threshold: Vec<(UserId, usize)> = vec![];
+            let events = bank.events();
+            events
+                .iter()
+                .for_each(|EventRow { index, event, .. }| match event {
+                    Event::Deposit { id, amount, .. } | Event::Withdrawal { id, amount, .. }
+                        if *amount > thresholds.cash_threshold
+                            && !reported_cash.contains(index) =>
+                    {
+                        large.push((*id, *index))
+                    }
+                    Event::Deposit { id, amount, .. }
+                        if thresholds.is_near_threshold(*amount)
+                            && !reported_structuring.contains(index) =>
+                    {
+                        near_threshold.push((*id, *index))
+                    }
+                    _ => (),
+                });
+
+            // Find runs of at least `structuring_count` deposits within the window, per user.
+            let mut structuring = vec![];
+            let mut users = near_threshold.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+            users.sort_unstable();
+            users.dedup();
+            users.into_iter().for_each(|id| {
+                let indices = near_threshold
+                    .iter()
+                    .filter(|(user, _)| *user == id)
+                    .map(|(_, index)| *index)
+                    .collect::<Vec<_>>();
+                let mut start = 0;
+                while start < indices.len() {
+                    let window_end = events[indices[start]].at + thresholds.structuring_window;
+                    let end = indices[start..]
+                        .iter()
+                        .take_while(|i| events[**i].at < window_end)
+                        .count()
+                        + start;
+                    if end - start >= thresholds.structuring_count {
+                        structuring.push((id, indices[start..end].to_vec()));
+                        start = end;
+                    } else {
+                        start += 1;
+                    }
+                }
+            });
+
+            let mut report_ids = large
+                .into_iter()
+                .map(|(id, index)| {
+                    bank.open_report(ReportKind::LargeCashTransaction, id, vec![index])
+                })
+                .collect::<Vec<_>>();
+            structuring.into_iter().for_each(|(id, events)| {
+                report_ids.push(bank.open_report(ReportKind::Structuring, id, events))
+            });
+            Ok(report_ids)
+        })
+    }
+
+    /// Returns all compliance reports, ordered by report ID.
+    /// Requires `Auditor` role.
+    pub fn list_compliance_reports(&self, user: HashResult) -> BankResult<Vec<ComplianceReport>> {
+        self.read(|bank| {
+            bank.assert_role(user, Role::Auditor)?;
+            let mut reports = bank
+                .compliance_reports
+                .values()
+                .cloned()
+                .collect::<Vec<_>>();
+            reports.sort_by_key(|r| r.report_id);
+            Ok(reports)
+        })
+    }
+
+    /// Moves a report to the next status: `Open` reports can be reviewed, and `Reviewed`
+    /// reports can be filed.
+    /// Requires `Auditor` role.
+    pub fn set_report_status(
+        &mut self,
+        user: HashResult,
+        report_id: ReportId,
+        status: ReportStatus,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            let by = bank.assert_role(user, Role::Auditor)?;
+            let report = bank
+                .compliance_reports
+                .get_mut(&report_id)
+                .ok_or(BankingError::NoReportFound)?;
+            match (report.status, status) {
+                (ReportStatus::Open, ReportStatus::Reviewed)
+                | (ReportStatus::Reviewed, ReportStatus::Filed) => report.status = status,
+                _ => return Err(BankingError::InvalidReportStatus),
+            }
+            let id = report.id;
+            bank.deposit_event(Event::ComplianceReportStatusChanged {
+                report_id,
+                id,
+                status,
+                by,
+            });
+            Ok(())
+        })
+    }
+
+    /// Renders a report in the fixed layout described in the module documentation.
+    /// Requires `Auditor` role.
+    pub fn render_compliance_report(
+        &self,
+        user: HashResult,
+        report_id: ReportId,
+    ) -> BankResult<String> {
+        self.read(|bank| {
+            bank.assert_role(user, Role::Auditor)?;
+            let report = bank
+                .compliance_reports
+                .get(&report_id)
+                .ok_or(BankingError::NoReportFound)?;
+            let username = bank
+                .storage
+                .user_by_id(report.id)
+                .map(|u| u.username.chars().take(20).collect::<String>())
+                .unwrap_or_default();
+            let kind = match report.kind {
+                ReportKind::LargeCashTransaction => "LCTR",
+                ReportKind::Structuring => "STRC",
+            };
+            let status = match report.status {
+                ReportStatus::Open => "OPEN",
+                ReportStatus::Reviewed => "REVIEWED",
+                ReportStatus::Filed => "FILED",
+            };
+
+            let mut out = format!(
+                "H{:010}{}{:010}{:<20}{:<8}{:06}\n",
+                report.report_id,
+                kind,
+                report.id,
+                username,
+                status,
+                report.detected_at / DAY
+            );
+            report
+                .events
+                .iter()
+                .filter_map(|i| bank.event_row(*i))
+                .for_each(|row| {
+                    let (kind, amount) = match row.event {
+                        Event::Withdrawal { amount, .. } => ("WDL", amount),
+                        Event::Deposit { amount, .. } => ("DEP", amount),
+                        _ => ("", 0f64),
+                    };
+                    out.push_str(&format!(
+                        "D{:010}{:06}{}{}\n",
+                        row.index,
+                        row.at / DAY,
+                        kind,
+                        cents(amount)
+                    ));
+                });
+            out.push_str(&format!(
+                "T{:06}{}\n",
+                report.events.len(),
+                cents(report.amount)
+            ));
+            Ok(out)
+        })
+    }
+
+    /// Writes a report to `path` in the fixed layout described in the module documentation.
+    /// Requires `Auditor` role.
+    pub fn export_compliance_report(
+        &self,
+        user: HashResult,
+        report_id: ReportId,
+        path: &str,
+    ) -> BankResult<()> {
+        self.read(|bank| {
+            let content = bank.render_compliance_report(user, report_id)?;
+            fs::write(path, content).map_err(|_| BankingError::FailedToWriteFile)
+        })
+    }
+}
@@ -0,0 +1,197 @@
+//! Fee schedule, fee waivers and the bank income account.
+use super::Bank;
+use crate::primitives::*;
+
+impl Bank {
+    /// Returns the fee of `fee_type` charged to the user on `base`. Returns 0 if no rule is
+    /// set or the user has a waiver for the fee.
+    pub(super) fn compute_fee(&self, id: UserId, fee_type: FeeType, base: Balance) -> Balance {
+        if self.fee_waivers.contains(&(id, None))
+            || self.fee_waivers.contains(&(id, Some(fee_type)))
+        {
+            return 0f64;
+        }
+        self.fee_rules
+            .get(&fee_type)
+            .map(|rule| rule.fee(base))
+            .unwrap_or_default()
+    }
+
+    /// Pays `fee` into the bank income account and logs the fee. The caller must debit the
+    /// fee from the user's balance.
+    pub(super) fn post_fee(&mut self, id: UserId, fee_type: FeeType, fee: Balance) {
+        if fee <= 0f64 {
+            return;
+        }
+        self.fee_income += fee;
+        self.deposit_event(Event::Fee {
+            id,
+            fee_type,
+            amount: fee,
+        });
+    }
+
+    /// Charges the overdraft fee for a withdrawal or transfer of `amount` that is declined
+    /// with `InsufficientBalance`. The fee is capped at the user's available balance, and kept
+    /// although the operation fails (see `is_logged`).
+    pub(super) fn charge_overdraft_fee(&mut self, id: UserId, amount: Balance) {
+        let fee = self
+            .compute_fee(id, FeeType::Overdraft, amount)
+            .min(self.available_balance(id).max(0f64));
+        if fee > 0f64 {
+            self.post_fee(id, FeeType::Overdraft, fee);
+            self.debit(id, fee);
+        }
+    }
+
+    /// Returns the fee of `fee_type` the current user would be charged on `amount`.
+    /// Requires `Customer` role.
+    pub fn quote_fee(
+        &self,
+        user: HashResult,
+        fee_type: FeeType,
+        amount: Balance,
+    ) -> BankResult<Balance> {
+        self.read(|bank| {
+            let id = bank.assert_role(user, Role::Customer)?;
+            Ok(bank.compute_fee(id, fee_type, amount))
+        })
+    }
+
+    /// Returns the fee rule of every fee type, in the order of `FeeType::ALL`.
+    pub fn fee_schedule(&self) -> Vec<(FeeType, FeeRule)> {
+        FeeType::ALL
+            .iter()
+            .map(|t| (*t, self.fee_rules.get(t).copied().unwrap_or_default()))
+            .collect()
+    }
+
+    /// Sets the rule used to calculate fees of `fee_type`.
+    /// Requires `Manager` role.
+    pub fn set_fee_rule(
+        &mut self,
+        user: HashResult,
+        fee_type: FeeType,
+        rule: FeeRule,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            if !rule.is_valid() {
+                return Err(BankingError::InvalidFeeRule);
+            }
+            let id = bank.assert_role(user, Role::Manager)?;
+            bank.fee_rules.insert(fee_type, rule);
+            bank.deposit_event(Event::FeeRuleSet { id, fee_type, rule });
+            Ok(())
+        })
+    }
+
+    /// Sets the minimum balance below which the monthly fee run charges the
+    /// `BelowMinimumBalance` fee.
+    /// Requires `Manager` role.
+    pub fn set_minimum_balance(&mut self, user: HashResult, amount: Balance) -> BankResult<()> {
+        self.transaction(|bank| {
+            if amount < 0f64 {
+                return Err(BankingError::InvalidAmount);
+            }
+            let id = bank.assert_role(user, Role::Manager)?;
+            bank.minimum_balance = amount;
+            bank.deposit_event(Event::MinimumBalanceSet {
+                id,
+                minimum_balance: amount,
+            });
+            Ok(())
+        })
+    }
+
+    /// Waives fees of `fee_type` for the given customer, or all fees if `fee_type` is `None`.
+    /// Requires `Manager` role.
+    pub fn grant_fee_waiver(
+        &mut self,
+        user: HashResult,
+        customer: UserId,
+        fee_type: Option<FeeType>,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            let by = bank.assert_role(user, Role::Manager)?;
+            bank.find_customer_by_id(customer)?;
+            bank.fee_waivers.insert((customer, fee_type));
+            bank.deposit_event(Event::FeeWaiverGranted {
+                id: customer,
+                fee_type,
+                by,
+            });
+            Ok(())
+        })
+    }
+
+    /// Removes a waiver previously granted with `grant_fee_waiver`.
+    /// Requires `Manager` role.
+    pub fn revoke_fee_waiver(
+        &mut self,
+        user: HashResult,
+        customer: UserId,
+        fee_type: Option<FeeType>,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            let by = bank.assert_role(user, Role::Manager)?;
+            if !bank.fee_waivers.remove(&(customer, fee_type)) {
+                return Err(BankingError::NoFeeWaiverFound);
+            }
+            bank.deposit_event(Event::FeeWaiverRevoked {
+                id: customer,
+                fee_type,
+                by,
+            });
+            Ok(())
+        })
+    }
+
+    /// Charge the monthly maintenance fee to every account, and the below minimum balance fee
+    /// to accounts whose balance is below the minimum balance. Fees are capped at the available
+    /// balance, and accounts brought below ED are reaped.
+    /// Requires `Manager` role.
+    pub fn charge_monthly_fees(&mut self, user: HashResult) -> BankResult<()> {
+        self.transaction(|bank| {
+            bank.assert_role(user, Role::Manager)?;
+
+            bank.storage
+                .balances()
+                .into_iter()
+                .for_each(|(id, balance)| {
+                    let mut available = bank.available_balance(id).max(0f64);
+                    let mut fees = vec![(
+                        FeeType::MonthlyMaintenance,
+                        bank.compute_fee(id, FeeType::MonthlyMaintenance, balance),
+                    )];
+                    if balance < bank.minimum_balance {
+                        fees.push((
+                            FeeType::BelowMinimumBalance,
+                            bank.compute_fee(id, FeeType::BelowMinimumBalance, balance),
+                        ));
+                    }
+                    let total = fees
+                        .into_iter()
+                        .map(|(fee_type, fee)| {
+                            let fee = fee.min(available);
+                            available -= fee;
+                            bank.post_fee(id, fee_type, fee);
+                            fee
+                        })
+                        .sum::<Balance>();
+                    if total > 0f64 {
+                        bank.debit(id, total);
+                    }
+                });
+            Ok(())
+        })
+    }
+
+    /// Returns the balance of the bank income account, into which all fees are paid.
+    /// Requires `Manager` or `Auditor` role.
+    pub fn fee_income(&self, user: HashResult) -> BankResult<Balance> {
+        self.read(|bank| match bank.current_user(user)?.role {
+            Role::Customer => Err(BankingError::Unauthorized),
+            _ => Ok(bank.fee_income),
+        })
+    }
+}
@@ -0,0 +1,416 @@
+//! Rule-based fraud checks, evaluated before every withdrawal and transfer, and when a
+//! two-phase transfer is initiated.
+//!
+//! Each rule looks at the proposed movement and the user's movement history, and returns a
+//! `FraudDecision`. The strictest decision wins: denied movements fail, and held movements wait
+//! in a review queue until an `Auditor` approves or rejects them. Held funds are not available
+//! to other movements.
+use super::Bank;
+use crate::primitives::*;
+
+/// A withdrawal or transfer of `amount` from `from`. `to` is `None` for withdrawals.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Movement {
+    pub at: Timestamp,
+    pub from: UserId,
+    pub to: Option<UserId>,
+    pub amount: Balance,
+}
+
+/// A fraud rule that can be plugged into the bank by an `Auditor`. Rules are `Send`, so the
+/// bank can be handed to another thread.
+pub trait FraudRule: Send {
+    /// Name of the rule. Adding a rule replaces any rule with the same name.
+    fn name(&self) -> String;
+
+    /// Decides on the proposed `movement`. `history` holds the completed withdrawals and
+    /// transfers into or out of the user's account, oldest first.
+    fn evaluate(&self, movement: &Movement, history: &[Movement]) -> FraudDecision;
+}
+
+/// Denies a movement if the user already made `max_count` outgoing movements within `window`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VelocityRule {
+    pub max_count: usize,
+    pub window: Timestamp,
+}
+
+impl FraudRule for VelocityRule {
+    fn name(&self) -> String {
+        "velocity".to_string()
+    }
+
+    fn evaluate(&self, movement: &Movement, history: &[Movement]) -> FraudDecision {
+        let recent = history
+            .iter()
+            .filter(|m| m.from == movement.from && m.at + self.window > movement.at)
+            .count();
+        if recent >= self.max_count {
+            FraudDecision::Deny
+        } else {
+            FraudDecision::Allow
+        }
+    }
+}
+
+/// Holds a movement larger than `multiplier` times the average of the user's previous outgoing
+/// movements, once there are at least `min_history` of them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AmountAnomalyRule {
+    pub multiplier: f64,
+    pub min_history: usize,
+}
+
+impl FraudRule for AmountAnomalyRule {
+    fn name(&self) -> String {
+        "amount-anomaly".to_string()
+    }
+
+    fn evaluate(&self, movement: &Movement, history: &[Movement]) -> FraudDecision {
+        let amounts = history
+            .iter()
+            .filter(|m| m.from == movement.from)
+            .map(|m| m.amount)
+            .collect::<Vec<_>>();
+        if amounts.is_empty() || amounts.len() < self.min_history {
+            return FraudDecision::Allow;
+        }
+        let average = amounts.iter().sum::<Balance>() / amounts.len() as f64;
+        if movement.amount > average * self.multiplier {
+            FraudDecision::Hold
+        } else {
+            FraudDecision::Allow
+        }
+    }
+}
+
+/// Holds a transfer above `threshold` to someone the user never transferred to before.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FirstTimePayeeRule {
+    pub threshold: Balance,
+}
+
+impl FraudRule for FirstTimePayeeRule {
+    fn name(&self) -> String {
+        "first-time-payee".to_string()
+    }
+
+    fn evaluate(&self, movement: &Movement, history: &[Movement]) -> FraudDecision {
+        let to = match movement.to {
+            Some(to) => to,
+            None => return FraudDecision::Allow,
+        };
+        let known = history
+            .iter()
+            .any(|m| m.from == movement.from && m.to == Some(to));
+        if !known && movement.amount > self.threshold {
+            FraudDecision::Hold
+        } else {
+            FraudDecision::Allow
+        }
+    }
+}
+
+/// Holds a transfer back to someone who transferred money to the user within `window`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RoundTripRule {
+    pub window: Timestamp,
+}
+
+impl FraudRule for RoundTripRule {
+    fn name(&self) -> String {
+        "round-trip".to_string()
+    }
+
+    fn evaluate(&self, movement: &Movement, history: &[Movement]) -> FraudDecision {
+        let to = match movement.to {
+            Some(to) => to,
+            None => return FraudDecision::Allow,
+        };
+        let round_trip = history.iter().any(|m| {
+            m.from == to && m.to == Some(movement.from) && m.at + self.window > movement.at
+        });
+        if round_trip {
+            FraudDecision::Hold
+        } else {
+            FraudDecision::Allow
+        }
+    }
+}
+
+/// A movement held by the fraud checks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FraudReview {
+    pub review_id: ReviewId,
+    pub movement: Movement,
+    pub fee: Balance,
+    pub reference: Option<PaymentReference>,
+    /// ID reserved for the two-phase transfer the movement initiates, if any.
+    pub transfer_id: Option<TransferId>,
+    /// Name of the rule that held the movement.
+    pub rule: String,
+    pub status: ReviewStatus,
+}
+
+impl Bank {
+    /// Adds a fraud rule, replacing any rule with the same name.
+    /// Requires `Auditor` role.
+    pub fn add_fraud_rule(&mut self, user: HashResult, rule: Box<dyn FraudRule>) -> BankResult<()> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Auditor)?;
+            let name = rule.name();
+            bank.fraud_rules.retain(|r| r.name() != name);
+            bank.fraud_rules.push(rule);
+            bank.deposit_event(Event::FraudRuleAdded { id, rule: name });
+            Ok(())
+        })
+    }
+
+    /// Removes the fraud rule with the given name.
+    /// Requires `Auditor` role.
+    pub fn remove_fraud_rule(&mut self, user: HashResult, name: &str) -> BankResult<()> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Auditor)?;
+            let count = bank.fraud_rules.len();
+            bank.fraud_rules.retain(|r| r.name() != name);
+            if bank.fraud_rules.len() == count {
+                return Err(BankingError::NoFraudRuleFound);
+            }
+            bank.deposit_event(Event::FraudRuleRemoved {
+                id,
+                rule: name.to_string(),
+            });
+            Ok(())
+        })
+    }
+
+    /// Returns the names of the fraud rules, in the order they were added.
+    pub fn fraud_rules(&self) -> Vec<String> {
+        self.fraud_rules.iter().map(|r| r.name()).collect()
+    }
+
+    /// Returns the completed withdrawals and transfers into or out of the user's account,
+    /// together with the two-phase transfers still pending, oldest first.
+    fn movement_history(&self, id: UserId) -> Vec<Movement> {
+        self.user_event_rows(id)
+            .iter()
+            .filter_map(|EventRow { event, at, .. }| match event {
+                Event::Withdrawal {
+                    id: from, amount, ..
+                } => Some((*from, None, *amount, *at)),
+                Event::Transfer {
+                    id: from,
+                    to_id,
+                    amount,
+                    ..
+                } => Some((*from, Some(*to_id), *amount, *at)),
+                // Settled transfers are counted by their `Event::Transfer`.
+                Event::TransferInitiated {
+                    transfer_id,
+                    id: from,
+                    to_id,
+                    amount,
+                    ..
+                } if self.pending_transfer(*transfer_id).is_ok() => {
+                    Some((*from, Some(*to_id), *amount, *at))
+                }
+                _ => None,
+            })
+            .filter(|(from, to, _, _)| *from == id || *to == Some(id))
+            .map(|(from, to, amount, at)| Movement {
+                at,
+                from,
+                to,
+                amount,
+            })
+            .collect()
+    }
+
+    // Helper function: Returns the strictest decision of the fraud rules on `movement`, and the
+    // name of the rule that made it.
+    fn fraud_decision(&self, movement: &Movement, history: &[Movement]) -> (FraudDecision, String) {
+        self.fraud_rules
+            .iter()
+            .map(|r| (r.evaluate(movement, history), r.name()))
+            .max_by_key(|(decision, _)| *decision)
+            .unwrap_or((FraudDecision::Allow, String::new()))
+    }
+
+    /// Returns the error the fraud rules would fail an outgoing movement of `amount` with, made
+    /// now after the movements in `earlier`. Nothing is logged or queued for review.
+    pub(super) fn check_movement(
+        &self,
+        from: UserId,
+        to: Option<UserId>,
+        amount: Balance,
+        earlier: &[Movement],
+    ) -> BankResult<()> {
+        let movement = Movement {
+            at: self.now,
+            from,
+            to,
+            amount,
+        };
+        let mut history = self.movement_history(from);
+        history.extend_from_slice(earlier);
+        match self.fraud_decision(&movement, &history).0 {
+            FraudDecision::Allow => Ok(()),
+            FraudDecision::Hold => Err(BankingError::TransactionHeld),
+            FraudDecision::Deny => Err(BankingError::TransactionDenied),
+        }
+    }
+
+    /// Runs the fraud rules on an outgoing movement, or on the initiation of the two-phase
+    /// transfer `transfer_id`. Denied movements fail with `TransactionDenied`. Held movements are
+    /// queued for review and fail with `TransactionHeld`; the caller must not move or hold any
+    /// money in either case. Nothing is logged if no rules are set.
+    pub(super) fn screen_movement(
+        &mut self,
+        from: UserId,
+        to: Option<UserId>,
+        amount: Balance,
+        fee: Balance,
+        reference: Option<PaymentReference>,
+        transfer_id: Option<TransferId>,
+    ) -> BankResult<()> {
+        if self.fraud_rules.is_empty() {
+            return Ok(());
+        }
+        let movement = Movement {
+            at: self.now,
+            from,
+            to,
+            amount,
+        };
+        let history = self.movement_history(from);
+        let (decision, rule) = self.fraud_decision(&movement, &history);
+
+        let review_id = if decision == FraudDecision::Hold {
+            let review_id = self.fraud_reviews.len() as ReviewId + 1;
+            self.fraud_reviews.insert(
+                review_id,
+                FraudReview {
+                    review_id,
+                    movement,
+                    fee,
+                    reference,
+                    transfer_id,
+                    rule: rule.clone(),
+                    status: ReviewStatus::Pending,
+                },
+            );
+            Some(review_id)
+        } else {
+            None
+        };
+        self.deposit_event(Event::FraudCheck {
+            id: from,
+            to_id: to,
+            amount,
+            rule: Some(rule).filter(|_| decision != FraudDecision::Allow),
+            decision,
+            review_id,
+        });
+
+        match decision {
+            FraudDecision::Allow => Ok(()),
+            FraudDecision::Hold => Err(BankingError::TransactionHeld),
+            FraudDecision::Deny => Err(BankingError::TransactionDenied),
+        }
+    }
+
+    /// Returns the amount and fee of the user's movements waiting for review.
+    pub(super) fn held_for_review(&self, id: UserId) -> Balance {
+        self.fraud_reviews
+            .values()
+            .filter(|r| r.movement.from == id && r.status == ReviewStatus::Pending)
+            .map(|r| r.movement.amount + r.fee)
+            .sum()
+    }
+
+    /// Returns the reviews visible to the current user, ordered by review ID.
+    /// `Customer`s see their own held movements, other roles see all reviews.
+    pub fn list_fraud_reviews(&self, user: HashResult) -> BankResult<Vec<FraudReview>> {
+        self.read(|bank| {
+            let u = bank.current_user(user)?;
+            let mut reviews = bank
+                .fraud_reviews
+                .values()
+                .filter(|r| u.role != Role::Customer || r.movement.from == u.id)
+                .cloned()
+                .collect::<Vec<_>>();
+            reviews.sort_by_key(|r| r.review_id);
+            Ok(reviews)
+        })
+    }
+
+    // Helper function that returns a clone of the review if it is still pending.
+    fn pending_review(&self, review_id: ReviewId) -> BankResult<FraudReview> {
+        match self.fraud_reviews.get(&review_id) {
+            Some(r) if r.status == ReviewStatus::Pending => Ok(r.clone()),
+            Some(_) => Err(BankingError::InvalidReviewStatus),
+            None => Err(BankingError::NoFraudReviewFound),
+        }
+    }
+
+    /// Approves a held movement, which is then carried out without running the fraud rules
+    /// again. A held two-phase transfer is initiated under its reserved ID, and waits to be
+    /// settled. The held amount and fee must still be in the user's balance.
+    /// Requires `Auditor` role.
+    pub fn approve_fraud_review(
+        &mut self,
+        user: HashResult,
+        review_id: ReviewId,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            let by = bank.assert_role(user, Role::Auditor)?;
+            let mut review = bank.pending_review(review_id)?;
+            let Movement {
+                from, to, amount, ..
+            } = review.movement;
+            // The available balance already excludes the held amount and fee.
+            if bank.available_balance(from) < 0f64 {
+                return Err(BankingError::InsufficientBalance);
+            }
+
+            // Release the hold before moving the money.
+            review.status = ReviewStatus::Approved;
+            let (fee, reference, transfer_id) =
+                (review.fee, review.reference.clone(), review.transfer_id);
+            bank.fraud_reviews.insert(review_id, review);
+            bank.deposit_event(Event::FraudReviewed {
+                review_id,
+                id: from,
+                approved: true,
+                by,
+            });
+            match (to, transfer_id) {
+                (Some(to), Some(transfer_id)) => {
+                    bank.record_pending_transfer(transfer_id, from, to, amount, fee, reference)
+                }
+                (Some(to), None) => bank.execute_transfer(from, to, amount, fee, reference),
+                (None, _) => bank.execute_withdrawal(from, amount, fee, reference),
+            }
+            Ok(())
+        })
+    }
+
+    /// Rejects a held movement, releasing the held funds.
+    /// Requires `Auditor` role.
+    pub fn reject_fraud_review(&mut self, user: HashResult, review_id: ReviewId) -> BankResult<()> {
+        self.transaction(|bank| {
+            let by = bank.assert_role(user, Role::Auditor)?;
+            let mut review = bank.pending_review(review_id)?;
+            review.status = ReviewStatus::Rejected;
+            let from = review.movement.from;
+            bank.fraud_reviews.insert(review_id, review);
+            bank.deposit_event(Event::FraudReviewed {
+                review_id,
+                id: from,
+                approved: false,
+                by,
+            });
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,168 @@
+//! Tamper evidence of the event log.
+//!
+//! Every entry is stored with a SHA-256 hash of the hash of the entry before it, the time it was
+//! logged at and its event, so changing, removing or moving an entry breaks the chain. Every
+//! `CHECKPOINT_INTERVAL` entries, the bank signs the hash of the log with its key
+//! (HMAC-SHA256), so a log rewritten with recomputed hashes no longer matches its checkpoints.
+//! Removing entries from the end of the log is only detected up to the last checkpoint.
+//!
+//! Bank files hold no event log, but every line they journal is sealed with a MAC of the line,
+//! the seal of the line before it and the log as it was after the operation, see `bank_file`.
+//!
+//! The key is read from the `BANKING_SIGNING_KEY` environment variable when the bank is
+//! created. Without it, a development key is used, which anyone can sign with, so the binaries
+//! refuse to persist a bank without it (see `require_signing_key`).
+use super::Bank;
+use crate::primitives::*;
+use crate::storage::encode_event;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::env;
+
+/// Number of entries between two checkpoints of the event log.
+pub const CHECKPOINT_INTERVAL: usize = 100;
+
+/// Name of the environment variable holding the key checkpoints are signed with.
+pub const SIGNING_KEY_ENV: &str = "BANKING_SIGNING_KEY";
+
+const DEVELOPMENT_KEY: &[u8] = b"banking_app development signing key";
+
+/// Returns the key from `BANKING_SIGNING_KEY`, or the development key if it is not set.
+pub fn signing_key_from_env() -> Vec<u8> {
+    env::var(SIGNING_KEY_ENV)
+        .map(String::into_bytes)
+        .unwrap_or_else(|_| DEVELOPMENT_KEY.to_vec())
+}
+
+/// Returns the hash of an entry logged at `at`, chained to the entry hashed `prev`.
+pub fn entry_hash(prev: &LogHash, at: Timestamp, event: &Event) -> LogHash {
+    let mut hasher = Sha256::new();
+    hasher.update(prev);
+    hasher.update(at.to_be_bytes());
+    hasher.update(encode_event(event).as_bytes());
+    hasher.finalize().into()
+}
+
+// Helper function: Returns the MAC of a checkpoint, ready to sign or verify.
+fn checkpoint_mac(key: &[u8], count: usize, hash: &LogHash) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(&(count as u64).to_be_bytes());
+    mac.update(hash);
+    mac
+}
+
+/// Signs the checkpoint of the first `count` entries of a log, the last of them hashed `hash`.
+pub fn sign_checkpoint(key: &[u8], count: usize, hash: LogHash) -> Checkpoint {
+    Checkpoint {
+        count,
+        hash,
+        signature: checkpoint_mac(key, count, &hash)
+            .finalize()
+            .into_bytes()
+            .into(),
+    }
+}
+
+impl Bank {
+    /// Set the key checkpoints of the event log are signed and verified with. Checkpoints
+    /// signed with another key no longer verify.
+    pub fn set_signing_key(&mut self, key: &[u8]) {
+        self.signing_key = key.to_vec();
+    }
+
+    // Helper function: Appends the event to the log, chained to the entry before it, and signs
+    // a checkpoint every `CHECKPOINT_INTERVAL` entries.
+    pub(super) fn chain_event(&mut self, event: Event) {
+        let prev = self.log_head();
+        let hash = entry_hash(&prev, self.now, &event);
+        self.storage
+            .push_event(event, self.now, LogLink { prev, hash });
+        let count = self.storage.event_count();
+        if count.is_multiple_of(CHECKPOINT_INTERVAL) {
+            let checkpoint = sign_checkpoint(&self.signing_key, count, hash);
+            self.storage.push_checkpoint(checkpoint);
+        }
+    }
+
+    // Helper function: Returns the hash of the last entry of the event log, read from the
+    // storage, which other processes may have appended to.
+    fn log_head(&self) -> LogHash {
+        self.event_count()
+            .checked_sub(1)
+            .and_then(|last| self.storage.event_link(last))
+            .map(|link| link.hash)
+            .unwrap_or_default()
+    }
+
+    /// Returns the seal of a line of a bank file, chained to the line sealed `prev`: the MAC,
+    /// with the key of the bank, of `prev`, the line, and the checkpoint of the event log as it
+    /// is once the operation of the line was applied.
+    pub(crate) fn journal_seal(&self, prev: &LogHash, line: &str) -> LogHash {
+        let mut mac = checkpoint_mac(
+            &self.signing_key,
+            self.storage.event_count(),
+            &self.log_head(),
+        );
+        mac.update(prev);
+        mac.update(line.as_bytes());
+        mac.finalize().into_bytes().into()
+    }
+
+    /// Verifies that the event log is as it was logged: every entry matches its hash and is
+    /// chained to the entry before it, and a checkpoint signed with the key of the bank matches
+    /// every `CHECKPOINT_INTERVAL` entries. Returns the first problem found.
+    /// Requires `Auditor` role.
+    pub fn verify_log(&self, user: HashResult) -> BankResult<LogVerification> {
+        self.read(|bank| {
+            bank.assert_role(user, Role::Auditor)?;
+            let mut entries = vec![];
+            bank.storage
+                .for_each_entry(&mut |row, link| entries.push((row.at, row.event.clone(), *link)));
+            let checkpoints = bank.storage.checkpoints();
+            let checkpoint_ok = |count: usize, hash: &LogHash| {
+                checkpoints.iter().any(|c| {
+                    c.count == count
+                        && c.hash == *hash
+                        && checkpoint_mac(&bank.signing_key, count, &c.hash)
+                            .verify_slice(&c.signature)
+                            .is_ok()
+                })
+            };
+
+            let mut issue = None;
+            let mut prev = LogHash::default();
+            for (index, (at, event, link)) in entries.iter().enumerate() {
+                if entry_hash(&link.prev, *at, event) != link.hash {
+                    issue = Some(LogIssue::Modified { index });
+                } else if link.prev != prev {
+                    // The entry that belongs here is the one chained to the previous entry.
+                    let moved = entries[index + 1..].iter().any(|(_, _, l)| l.prev == prev);
+                    issue = Some(if moved {
+                        LogIssue::Reordered { index }
+                    } else {
+                        LogIssue::Missing { index }
+                    });
+                } else if (index + 1).is_multiple_of(CHECKPOINT_INTERVAL)
+                    && !checkpoint_ok(index + 1, &link.hash)
+                {
+                    issue = Some(LogIssue::BadCheckpoint { count: index + 1 });
+                }
+                if issue.is_some() {
+                    break;
+                }
+                prev = link.hash;
+            }
+            // A checkpoint beyond the end of the log shows entries were removed from the end.
+            if issue.is_none() && checkpoints.iter().any(|c| c.count > entries.len()) {
+                issue = Some(LogIssue::Missing {
+                    index: entries.len(),
+                });
+            }
+            Ok(LogVerification {
+                entries: entries.len(),
+                checkpoints: checkpoints.len(),
+                issue,
+            })
+        })
+    }
+}
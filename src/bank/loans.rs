@@ -0,0 +1,341 @@
+//! Loan products, loan applications and amortization schedules. Installments are drawn from
+//! the borrower's balance when the simulated clock passes their due date.
+use super::Bank;
+use crate::primitives::*;
+
+/// A loan offered by the bank, defined by a `Manager`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoanProduct {
+    pub product_id: LoanProductId,
+    pub name: String,
+    pub annual_rate: f64,
+    /// Number of installments.
+    pub term: u32,
+    pub frequency: RepaymentFrequency,
+    pub method: AmortizationMethod,
+}
+
+/// One installment of an amortization schedule.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Installment {
+    /// 1-based number of the installment.
+    pub number: u32,
+    pub due: Timestamp,
+    pub principal: Balance,
+    pub interest: Balance,
+    /// Late fee added when the installment could not be repaid on its due date.
+    pub late_fee: Balance,
+    pub paid: bool,
+}
+
+impl Installment {
+    /// Total amount drawn from the borrower to repay the installment.
+    pub fn amount_due(&self) -> Balance {
+        self.principal + self.interest + self.late_fee
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loan {
+    pub loan_id: LoanId,
+    pub product_id: LoanProductId,
+    pub borrower: UserId,
+    pub principal: Balance,
+    pub status: LoanStatus,
+    /// Empty until the loan is approved.
+    pub schedule: Vec<Installment>,
+}
+
+impl Loan {
+    /// Principal not yet repaid.
+    pub fn outstanding_principal(&self) -> Balance {
+        match self.status {
+            LoanStatus::Pending | LoanStatus::Rejected => 0f64,
+            _ => self
+                .schedule
+                .iter()
+                .filter(|i| !i.paid)
+                .map(|i| i.principal)
+                .sum(),
+        }
+    }
+}
+
+// Helper function: Rounds an amount to cents.
+pub(super) fn round_cents(amount: Balance) -> Balance {
+    (amount * 100f64).round() / 100f64
+}
+
+/// Generates the amortization schedule of a loan of `principal` disbursed at `start`.
+/// Amounts are rounded to cents, and the last installment repays whatever principal is left.
+pub fn amortization_schedule(
+    product: &LoanProduct,
+    principal: Balance,
+    start: Timestamp,
+) -> Vec<Installment> {
+    let n = product.term;
+    let rate = product.annual_rate / product.frequency.periods_per_year();
+    let annuity = if rate == 0f64 {
+        principal / n as f64
+    } else {
+        principal * rate / (1f64 - (1f64 + rate).powi(-(n as i32)))
+    };
+
+    let mut remaining = principal;
+    (1..=n)
+        .map(|number| {
+            let interest = round_cents(remaining * rate);
+            let principal_part = if number == n {
+                round_cents(remaining)
+            } else {
+                match product.method {
+                    AmortizationMethod::Annuity => round_cents(annuity - interest),
+                    AmortizationMethod::EqualPrincipal => round_cents(principal / n as f64),
+                }
+            };
+            remaining -= principal_part;
+            Installment {
+                number,
+                due: start + product.frequency.period() * number as Timestamp,
+                principal: principal_part,
+                interest,
+                late_fee: 0f64,
+                paid: false,
+            }
+        })
+        .collect()
+}
+
+impl Bank {
+    /// Defines a new loan product. Returns the ID of the product.
+    /// Requires `Manager` role.
+    pub fn create_loan_product(
+        &mut self,
+        user: HashResult,
+        name: String,
+        annual_rate: f64,
+        term: u32,
+        frequency: RepaymentFrequency,
+        method: AmortizationMethod,
+    ) -> BankResult<LoanProductId> {
+        self.transaction(|bank| {
+            let by = bank.assert_role(user, Role::Manager)?;
+            let name = name.trim().to_string();
+            if name.is_empty() || annual_rate < 0f64 || term == 0 {
+                return Err(BankingError::InvalidLoanProduct);
+            }
+
+            let product_id = bank.generate_next_loan_product_id();
+            bank.loan_products.insert(
+                product_id,
+                LoanProduct {
+                    product_id,
+                    name,
+                    annual_rate,
+                    term,
+                    frequency,
+                    method,
+                },
+            );
+            bank.deposit_event(Event::LoanProductCreated {
+                product_id,
+                annual_rate,
+                term,
+                by,
+            });
+            Ok(product_id)
+        })
+    }
+
+    /// Returns all loan products, ordered by product ID.
+    pub fn loan_products(&self) -> Vec<LoanProduct> {
+        let mut products = self.loan_products.values().cloned().collect::<Vec<_>>();
+        products.sort_by_key(|p| p.product_id);
+        products
+    }
+
+    /// Applies for a loan of `principal` under the given product. Returns the ID of the loan,
+    /// which stays pending until a `Manager` approves or rejects it.
+    /// Requires `Customer` role.
+    pub fn apply_for_loan(
+        &mut self,
+        user: HashResult,
+        product_id: LoanProductId,
+        principal: Balance,
+    ) -> BankResult<LoanId> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Customer)?;
+            if principal <= 0f64 {
+                return Err(BankingError::InvalidAmount);
+            }
+            if principal < bank.existential_deposit {
+                return Err(BankingError::AmountTooSmall);
+            }
+            if !bank.loan_products.contains_key(&product_id) {
+                return Err(BankingError::NoLoanProductFound);
+            }
+
+            let loan_id = bank.generate_next_loan_id();
+            bank.loans.insert(
+                loan_id,
+                Loan {
+                    loan_id,
+                    product_id,
+                    borrower: id,
+                    principal,
+                    status: LoanStatus::Pending,
+                    schedule: vec![],
+                },
+            );
+            bank.deposit_event(Event::LoanApplied {
+                loan_id,
+                id,
+                product_id,
+                principal,
+            });
+            Ok(loan_id)
+        })
+    }
+
+    // Helper function that returns a clone of the loan if it is still pending.
+    fn pending_loan(&self, loan_id: LoanId) -> BankResult<Loan> {
+        match self.loans.get(&loan_id) {
+            Some(l) if l.status == LoanStatus::Pending => Ok(l.clone()),
+            Some(_) => Err(BankingError::InvalidLoanStatus),
+            None => Err(BankingError::NoLoanFound),
+        }
+    }
+
+    /// Approves a pending loan: the principal is paid into the borrower's balance and the
+    /// amortization schedule starts from the current time.
+    /// Requires `Manager` role.
+    pub fn approve_loan(&mut self, user: HashResult, loan_id: LoanId) -> BankResult<()> {
+        self.transaction(|bank| {
+            let by = bank.assert_role(user, Role::Manager)?;
+            let mut loan = bank.pending_loan(loan_id)?;
+            let product = match bank.loan_products.get(&loan.product_id) {
+                Some(p) => Ok(p),
+                None => Err(BankingError::NoLoanProductFound),
+            }?;
+
+            loan.schedule = amortization_schedule(product, loan.principal, bank.now);
+            loan.status = LoanStatus::Active;
+            let (borrower, principal) = (loan.borrower, loan.principal);
+            bank.loans.insert(loan_id, loan);
+            bank.credit(borrower, principal);
+            bank.deposit_event(Event::LoanDisbursed {
+                loan_id,
+                id: borrower,
+                amount: principal,
+                by,
+            });
+            Ok(())
+        })
+    }
+
+    /// Rejects a pending loan.
+    /// Requires `Manager` role.
+    pub fn reject_loan(&mut self, user: HashResult, loan_id: LoanId) -> BankResult<()> {
+        self.transaction(|bank| {
+            let by = bank.assert_role(user, Role::Manager)?;
+            let mut loan = bank.pending_loan(loan_id)?;
+            loan.status = LoanStatus::Rejected;
+            let borrower = loan.borrower;
+            bank.loans.insert(loan_id, loan);
+            bank.deposit_event(Event::LoanRejected {
+                loan_id,
+                id: borrower,
+                by,
+            });
+            Ok(())
+        })
+    }
+
+    /// Returns the loans visible to the current user, ordered by loan ID.
+    /// `Customer`s see their own loans, other roles see all loans.
+    pub fn list_loans(&self, user: HashResult) -> BankResult<Vec<Loan>> {
+        self.read(|bank| {
+            let u = bank.current_user(user)?;
+            let mut loans = bank
+                .loans
+                .values()
+                .filter(|l| u.role != Role::Customer || l.borrower == u.id)
+                .cloned()
+                .collect::<Vec<_>>();
+            loans.sort_by_key(|l| l.loan_id);
+            Ok(loans)
+        })
+    }
+
+    /// Draws every installment that is due from the borrowers' balances, oldest first.
+    /// An installment that cannot be repaid is charged a late fee once, and the loan becomes
+    /// delinquent, or defaulted once the installment is overdue by `LOAN_DEFAULT_DAYS`.
+    pub(super) fn process_loan_repayments(&mut self) {
+        let now = self.now;
+        let mut loan_ids = self
+            .loans
+            .values()
+            .filter(|l| {
+                matches!(
+                    l.status,
+                    LoanStatus::Active | LoanStatus::Delinquent | LoanStatus::Defaulted
+                )
+            })
+            .map(|l| l.loan_id)
+            .collect::<Vec<_>>();
+        loan_ids.sort_unstable();
+
+        loan_ids.into_iter().for_each(|loan_id| {
+            let mut loan = self.loans[&loan_id].clone();
+            let borrower = loan.borrower;
+
+            for installment in loan.schedule.iter_mut().filter(|i| !i.paid && i.due <= now) {
+                let due = installment.amount_due();
+                if self.available_balance(borrower) >= due {
+                    installment.paid = true;
+                    self.deposit_event(Event::LoanRepayment {
+                        loan_id,
+                        id: borrower,
+                        installment: installment.number,
+                        principal: installment.principal,
+                        interest: installment.interest,
+                    });
+                    self.post_fee(borrower, FeeType::LatePayment, installment.late_fee);
+                    self.debit(borrower, due);
+                } else {
+                    if installment.late_fee <= 0f64 {
+                        let fee = self.compute_fee(borrower, FeeType::LatePayment, due);
+                        if fee > 0f64 {
+                            installment.late_fee = fee;
+                            self.deposit_event(Event::LoanLateFee {
+                                loan_id,
+                                id: borrower,
+                                installment: installment.number,
+                                fee,
+                            });
+                        }
+                    }
+                    // Later installments wait until this one is repaid.
+                    break;
+                }
+            }
+
+            let status = match loan.schedule.iter().find(|i| !i.paid) {
+                None => LoanStatus::Repaid,
+                Some(i) if i.due > now => LoanStatus::Active,
+                Some(i) if now - i.due >= LOAN_DEFAULT_DAYS * DAY => LoanStatus::Defaulted,
+                Some(_) => LoanStatus::Delinquent,
+            };
+            let changed = status != loan.status;
+            loan.status = status;
+            self.loans.insert(loan_id, loan);
+            if changed {
+                self.deposit_event(Event::LoanStatusChanged {
+                    loan_id,
+                    id: borrower,
+                    status,
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,212 @@
+//! Customer profiles with KYC data. Every edit of a profile is kept as a new version, so the
+//! history of a profile can be audited.
+use super::Bank;
+use crate::primitives::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct CustomerProfile {
+    pub legal_name: String,
+    /// Date of birth as `YYYY-MM-DD`.
+    pub date_of_birth: String,
+    pub address: String,
+    pub email: String,
+    pub phone: String,
+}
+
+impl CustomerProfile {
+    pub fn is_valid(&self) -> bool {
+        !self.legal_name.trim().is_empty()
+            && is_valid_date(&self.date_of_birth)
+            && (self.email.is_empty() || self.email.contains('@'))
+    }
+}
+
+// Helper function: Returns true if `date` is a valid `YYYY-MM-DD` date.
+fn is_valid_date(date: &str) -> bool {
+    let parts = date.split('-').collect::<Vec<_>>();
+    if parts.len() != 3 || parts[0].len() != 4 || parts[1].len() != 2 || parts[2].len() != 2 {
+        return false;
+    }
+    let (year, month, day) = match (
+        parts[0].parse::<u32>(),
+        parts[1].parse::<u32>(),
+        parts[2].parse::<u32>(),
+    ) {
+        (Ok(y), Ok(m), Ok(d)) => (y, m, d),
+        _ => return false,
+    };
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
+}
+
+/// One version of a customer profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProfileVersion {
+    /// 1-based version number.
+    pub version: u32,
+    pub profile: CustomerProfile,
+    /// The user who made the edit.
+    pub by: UserId,
+    pub at: Timestamp,
+}
+
+impl Bank {
+    /// Saves a new version of the customer's profile. `Customer`s may only edit their own
+    /// profile, which submits it for verification. `Manager`s may edit any customer's profile
+    /// without changing its KYC status. The legal name is screened against the watch list.
+    pub fn update_profile(
+        &mut self,
+        user: HashResult,
+        customer: UserId,
+        profile: CustomerProfile,
+    ) -> BankResult<u32> {
+        self.transaction(|bank| {
+            let u = bank.current_user(user)?;
+            let (by, role) = (u.id, u.role);
+            match role {
+                Role::Customer if by == customer => Ok(()),
+                Role::Manager => bank.find_customer_by_id(customer).map(|_| ()),
+                _ => Err(BankingError::Unauthorized),
+            }?;
+            if !profile.is_valid() {
+                return Err(BankingError::InvalidProfile);
+            }
+            bank.screen_name(&profile.legal_name, Some(customer))?;
+
+            let versions = bank.profiles.entry(customer).or_default();
+            let version = versions.len() as u32 + 1;
+            versions.push(ProfileVersion {
+                version,
+                profile,
+                by,
+                at: bank.now,
+            });
+            bank.deposit_event(Event::ProfileUpdated {
+                id: customer,
+                version,
+                by,
+            });
+            if role == Role::Customer && bank.kyc_status(customer) != KycStatus::Pending {
+                bank.change_kyc_status(customer, KycStatus::Pending, by);
+            }
+            Ok(version)
+        })
+    }
+
+    // Helper function: Returns the profile versions of a customer, if the current user may
+    // see them. `Customer`s may only see their own profile.
+    fn profile_versions(
+        &self,
+        user: HashResult,
+        customer: UserId,
+    ) -> BankResult<&[ProfileVersion]> {
+        let u = self.current_user(user)?;
+        if u.role == Role::Customer && u.id != customer {
+            return Err(BankingError::Unauthorized);
+        }
+        match self.profiles.get(&customer) {
+            Some(versions) => Ok(versions),
+            None => Err(BankingError::NoProfileFound),
+        }
+    }
+
+    /// Returns the latest version of the customer's profile.
+    pub fn profile(&self, user: HashResult, customer: UserId) -> BankResult<ProfileVersion> {
+        self.read(|bank| {
+            let versions = bank.profile_versions(user, customer)?;
+            versions.last().cloned().ok_or(BankingError::NoProfileFound)
+        })
+    }
+
+    /// Returns every version of the customer's profile, oldest first.
+    pub fn profile_history(
+        &self,
+        user: HashResult,
+        customer: UserId,
+    ) -> BankResult<Vec<ProfileVersion>> {
+        self.read(|bank| Ok(bank.profile_versions(user, customer)?.to_vec()))
+    }
+
+    /// Returns the KYC status of a customer.
+    pub fn kyc_status(&self, customer: UserId) -> KycStatus {
+        self.kyc_statuses
+            .get(&customer)
+            .copied()
+            .unwrap_or(KycStatus::Unverified)
+    }
+
+    // Helper function: Changes the KYC status of a customer and logs the change.
+    fn change_kyc_status(&mut self, customer: UserId, status: KycStatus, by: UserId) {
+        self.kyc_statuses.insert(customer, status);
+        self.deposit_event(Event::KycStatusChanged {
+            id: customer,
+            status,
+            by,
+        });
+    }
+
+    /// Set the KYC status of a customer. A customer can only be verified once they have a
+    /// profile.
+    /// Requires `Manager` role.
+    pub fn set_kyc_status(
+        &mut self,
+        user: HashResult,
+        customer: UserId,
+        status: KycStatus,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            let by = bank.assert_role(user, Role::Manager)?;
+            bank.find_customer_by_id(customer)?;
+            if status == KycStatus::Verified && !bank.profiles.contains_key(&customer) {
+                return Err(BankingError::NoProfileFound);
+            }
+            bank.change_kyc_status(customer, status, by);
+            Ok(())
+        })
+    }
+
+    /// Returns the restrictions on customers who are not verified.
+    pub fn kyc_policy(&self) -> KycPolicy {
+        self.kyc_policy
+    }
+
+    /// Set the restrictions on customers who are not verified.
+    /// Requires `Manager` role.
+    pub fn set_kyc_policy(&mut self, user: HashResult, policy: KycPolicy) -> BankResult<()> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Manager)?;
+            if matches!(policy, KycPolicy::Limit(limit) if limit < 0f64) {
+                return Err(BankingError::InvalidKycPolicy);
+            }
+            bank.kyc_policy = policy;
+            bank.deposit_event(Event::KycPolicySet { id, policy });
+            Ok(())
+        })
+    }
+
+    /// Applies the KYC policy to a withdrawal, or a transfer if `transfer` is true.
+    pub(super) fn check_kyc_policy(
+        &self,
+        id: UserId,
+        amount: Balance,
+        transfer: bool,
+    ) -> BankResult<()> {
+        if self.kyc_status(id) == KycStatus::Verified {
+            return Ok(());
+        }
+        match self.kyc_policy {
+            KycPolicy::Unrestricted => Ok(()),
+            KycPolicy::Limit(limit) if amount > limit => Err(BankingError::KycLimitExceeded),
+            KycPolicy::Limit(_) => Ok(()),
+            KycPolicy::NoTransfers if transfer => Err(BankingError::KycRequired),
+            KycPolicy::NoTransfers => Ok(()),
+        }
+    }
+}
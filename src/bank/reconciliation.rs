@@ -0,0 +1,109 @@
+//! Reconciliation of the stored balances with the event log.
+//!
+//! Every user's balance is replayed from the events that change it, and compared with the
+//! stored balance. As balances are floats, a difference within the tolerance is ignored.
+use super::Bank;
+use crate::primitives::*;
+
+impl Bank {
+    /// Returns the difference allowed between a replayed and a stored balance.
+    pub fn reconciliation_tolerance(&self) -> Balance {
+        self.reconciliation_tolerance
+    }
+
+    /// Set the difference allowed between a replayed and a stored balance.
+    /// Requires `Auditor` role.
+    pub fn set_reconciliation_tolerance(
+        &mut self,
+        user: HashResult,
+        tolerance: Balance,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Auditor)?;
+            if tolerance.is_nan() || tolerance < 0f64 {
+                return Err(BankingError::InvalidTolerance);
+            }
+            bank.reconciliation_tolerance = tolerance;
+            bank.deposit_event(Event::ReconciliationToleranceSet { id, tolerance });
+            Ok(())
+        })
+    }
+
+    /// Replays the event log and returns every account whose stored balance differs from the
+    /// replayed balance by more than `tolerance`, ordered by user ID. This does not need a user,
+    /// so tests can use it as a debug assertion.
+    pub fn discrepancies(&self, tolerance: Balance) -> Vec<Discrepancy> {
+        let events = self.events();
+        self.storage
+            .users()
+            .into_iter()
+            .filter_map(|u| {
+                let actual = self.balance(u.id);
+                let matches = |balance: Balance| (balance - actual).abs() <= tolerance;
+
+                let changes = events
+                    .iter()
+                    .map(|row| (row.index, row.event.balance_change(u.id)))
+                    .filter(|(_, amount)| *amount != 0f64)
+                    .collect::<Vec<_>>();
+                let expected = changes.iter().fold(0f64, |total, (_, a)| total + a);
+                if matches(expected) {
+                    return None;
+                }
+
+                // An event that accounts for the whole difference was most likely logged
+                // without being applied. Otherwise, blame the first event since the replayed
+                // balance last matched the stored balance.
+                let difference = expected - actual;
+                let first_diverging = changes
+                    .iter()
+                    .find(|(_, amount)| (amount - difference).abs() <= tolerance)
+                    .map(|(index, _)| *index)
+                    .or_else(|| {
+                        let mut balance = 0f64;
+                        let mut first = None;
+                        changes.iter().for_each(|(index, amount)| {
+                            first.get_or_insert(*index);
+                            balance += amount;
+                            if matches(balance) {
+                                first = None;
+                            }
+                        });
+                        first
+                    });
+                Some(Discrepancy {
+                    id: u.id,
+                    username: u.username,
+                    expected,
+                    actual,
+                    first_diverging: first_diverging.map(|index| events[index].clone()),
+                })
+            })
+            .collect()
+    }
+
+    /// Reconciles the balances with the event log, using the configured tolerance.
+    /// Requires `Auditor` role.
+    pub fn reconcile(&mut self, user: HashResult) -> BankResult<Vec<Discrepancy>> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Auditor)?;
+            let discrepancies = bank.discrepancies(bank.reconciliation_tolerance);
+            bank.deposit_event(Event::Reconciled {
+                id,
+                discrepancies: discrepancies.len(),
+            });
+            Ok(discrepancies)
+        })
+    }
+
+    /// Panics with the discrepancy report if any balance drifted from the event log.
+    #[cfg(test)]
+    pub fn debug_assert_reconciled(&self) {
+        let discrepancies = self.discrepancies(self.reconciliation_tolerance);
+        assert!(
+            discrepancies.is_empty(),
+            "Balances drifted from the event log:\n{}",
+            crate::report::render_discrepancies(&discrepancies, crate::report::ReportFormat::Table)
+        );
+    }
+}
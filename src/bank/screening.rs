@@ -0,0 +1,252 @@
+//! Watch-list screening of new users and transfer counterparties.
+//!
+//! The watch list is a local text file with one entry per line: the listed name followed by
+//! its aliases, separated by `,`. Blank lines and lines starting with `#` are ignored. The
+//! list can be reloaded at any time by an `Auditor`.
+//!
+//! Names are normalized (case, punctuation and word order are ignored) and compared with the
+//! Levenshtein similarity. Matches at or above the block threshold are rejected; matches at or
+//! above the flag threshold are allowed. Both raise a hit for `Auditor`s, who may clear false
+//! positives so the same name is no longer matched against that entry.
+use super::Bank;
+use crate::primitives::*;
+use std::fs;
+
+/// A listed name and its aliases.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchListEntry {
+    pub name: String,
+    pub aliases: Vec<String>,
+}
+
+/// Minimum similarity, between 0 and 1, for a name to be flagged or blocked.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ScreeningThresholds {
+    pub flag: f64,
+    pub block: f64,
+}
+
+impl Default for ScreeningThresholds {
+    fn default() -> Self {
+        Self {
+            flag: 0.8f64,
+            block: 0.95f64,
+        }
+    }
+}
+
+/// A screened name that matched the watch list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScreeningHit {
+    pub hit_id: HitId,
+    /// The name that was screened.
+    pub name: String,
+    /// The user registering, or sending the transfer.
+    pub id: Option<UserId>,
+    /// Listed name of the matching entry.
+    pub entry: String,
+    pub score: f64,
+    pub blocked: bool,
+    pub cleared: bool,
+}
+
+/// Parses the content of a watch list file.
+pub fn parse_watch_list(input: &str) -> Vec<WatchListEntry> {
+    input
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter_map(|l| {
+            let mut names = l
+                .split(',')
+                .map(|n| n.trim().to_string())
+                .filter(|n| !n.is_empty());
+            Some(WatchListEntry {
+                name: names.next()?,
+                aliases: names.collect(),
+            })
+        })
+        .collect()
+}
+
+/// Normalizes a name for matching: lower case, letters and digits only, words sorted.
+pub fn normalize_name(name: &str) -> String {
+    let mut words = name
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    words.sort();
+    words.join(" ")
+}
+
+// Helper function: Levenshtein distance between two strings, by characters.
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    a.chars().enumerate().for_each(|(i, ca)| {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        (0..b.len()).for_each(|j| {
+            let current = row[j + 1];
+            row[j + 1] = if ca == b[j] {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(row[j + 1])
+            };
+            previous = current;
+        });
+    });
+    row[b.len()]
+}
+
+/// Similarity between two names, from 0 (different) to 1 (same after normalization).
+pub fn name_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalize_name(a), normalize_name(b));
+    let len = a.chars().count().max(b.chars().count());
+    if len == 0 {
+        return 0f64;
+    }
+    1f64 - levenshtein(&a, &b) as f64 / len as f64
+}
+
+impl Bank {
+    /// Replaces the watch list with the entries of the file at `path`.
+    /// Requires `Auditor` role.
+    pub fn load_watch_list(&mut self, user: HashResult, path: &str) -> BankResult<usize> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Auditor)?;
+            let input = fs::read_to_string(path).map_err(|_| BankingError::FailedToReadFile)?;
+            bank.watch_list = parse_watch_list(&input);
+            let entries = bank.watch_list.len();
+            bank.deposit_event(Event::WatchListLoaded { id, entries });
+            Ok(entries)
+        })
+    }
+
+    /// Returns the entries of the watch list.
+    pub fn watch_list(&self) -> &[WatchListEntry] {
+        &self.watch_list
+    }
+
+    /// Set the similarity thresholds used to flag and block matches.
+    /// Requires `Auditor` role.
+    pub fn set_screening_thresholds(
+        &mut self,
+        user: HashResult,
+        thresholds: ScreeningThresholds,
+    ) -> BankResult<()> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Auditor)?;
+            let valid = |t: f64| t > 0f64 && t <= 1f64;
+            if !valid(thresholds.flag)
+                || !valid(thresholds.block)
+                || thresholds.flag > thresholds.block
+            {
+                return Err(BankingError::InvalidScreeningThresholds);
+            }
+            bank.screening_thresholds = thresholds;
+            bank.deposit_event(Event::ScreeningThresholdsSet {
+                id,
+                flag: thresholds.flag,
+                block: thresholds.block,
+            });
+            Ok(())
+        })
+    }
+
+    // Helper function: Returns the score and listed name of the best watch list match of
+    // `name` above the flag threshold, skipping entries cleared for that name.
+    fn watch_list_match(&self, name: &str) -> Option<(f64, String)> {
+        let best = self
+            .watch_list
+            .iter()
+            .filter(|e| {
+                !self
+                    .cleared_matches
+                    .contains(&(normalize_name(name), e.name.clone()))
+            })
+            .map(|e| {
+                let score = std::iter::once(&e.name)
+                    .chain(&e.aliases)
+                    .map(|n| name_similarity(name, n))
+                    .fold(0f64, f64::max);
+                (score, e.name.clone())
+            })
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+        best.filter(|(score, _)| *score >= self.screening_thresholds.flag)
+    }
+
+    /// Returns true if screening `name` would fail with `WatchListMatch`. Raises no hit.
+    pub(super) fn is_blocked_name(&self, name: &str) -> bool {
+        self.watch_list_match(name)
+            .is_some_and(|(score, _)| score >= self.screening_thresholds.block)
+    }
+
+    /// Screens `name` against the watch list, skipping entries cleared for that name. A match
+    /// raises a hit, and fails with `WatchListMatch` if it is above the block threshold.
+    pub(super) fn screen_name(&mut self, name: &str, id: Option<UserId>) -> BankResult<()> {
+        let (score, entry) = match self.watch_list_match(name) {
+            Some(best) => best,
+            None => return Ok(()),
+        };
+
+        let blocked = score >= self.screening_thresholds.block;
+        let hit_id = self.screening_hits.len() as HitId + 1;
+        self.screening_hits.insert(
+            hit_id,
+            ScreeningHit {
+                hit_id,
+                name: name.to_string(),
+                id,
+                entry: entry.clone(),
+                score,
+                blocked,
+                cleared: false,
+            },
+        );
+        self.deposit_event(Event::ScreeningHit {
+            hit_id,
+            id,
+            name: name.to_string(),
+            entry,
+            blocked,
+        });
+        if blocked {
+            Err(BankingError::WatchListMatch)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns all screening hits, ordered by hit ID.
+    /// Requires `Auditor` role.
+    pub fn list_screening_hits(&self, user: HashResult) -> BankResult<Vec<ScreeningHit>> {
+        self.read(|bank| {
+            bank.assert_role(user, Role::Auditor)?;
+            let mut hits = bank.screening_hits.values().cloned().collect::<Vec<_>>();
+            hits.sort_by_key(|h| h.hit_id);
+            Ok(hits)
+        })
+    }
+
+    /// Clears a hit as a false positive. The screened name is no longer matched against the
+    /// entry, so a blocked registration or transfer can be retried.
+    /// Requires `Auditor` role.
+    pub fn clear_screening_hit(&mut self, user: HashResult, hit_id: HitId) -> BankResult<()> {
+        self.transaction(|bank| {
+            let by = bank.assert_role(user, Role::Auditor)?;
+            let hit = match bank.screening_hits.get_mut(&hit_id) {
+                Some(h) if !h.cleared => Ok(h),
+                Some(_) => Err(BankingError::InvalidScreeningHitStatus),
+                None => Err(BankingError::NoScreeningHitFound),
+            }?;
+            hit.cleared = true;
+            let key = (normalize_name(&hit.name), hit.entry.clone());
+            bank.cleared_matches.insert(key);
+            bank.deposit_event(Event::ScreeningHitCleared { hit_id, by });
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,122 @@
+//! Monthly account statements, rebuilt from the event log.
+//!
+//! A statement covers month `n` of the simulated clock, from `n * MONTH` up to the start of the
+//! next month. The opening balance is replayed from every earlier event, and each event that
+//! moves money in or out of the main balance becomes a line with the running balance.
+use super::Bank;
+use crate::primitives::*;
+use crate::report::{render_statement_html, render_statement_text};
+use std::{fs, path::Path};
+
+impl Bank {
+    /// Returns the customer's statement for the given month.
+    /// `Customer`s may only see their own statements.
+    pub fn statement(
+        &self,
+        user: HashResult,
+        customer: UserId,
+        month: u64,
+    ) -> BankResult<Statement> {
+        self.read(|bank| {
+            let u = bank.current_user(user)?;
+            if u.role == Role::Customer && u.id != customer {
+                return Err(BankingError::Unauthorized);
+            }
+            let username = bank.find_customer_by_id(customer)?.username.clone();
+            Ok(bank.build_statement(customer, username, month))
+        })
+    }
+
+    // Helper function: Replays the customer's events up to the end of the month.
+    fn build_statement(&self, id: UserId, username: String, month: u64) -> Statement {
+        let (start, end) = (month * MONTH, (month + 1) * MONTH);
+        let mut statement = Statement {
+            id,
+            username,
+            month,
+            opening_balance: 0f64,
+            lines: vec![],
+            interest: 0f64,
+            tax: 0f64,
+            fees: 0f64,
+            closing_balance: 0f64,
+        };
+        let mut balance = 0f64;
+        self.user_event_rows(id)
+            .iter()
+            .take_while(|row| row.at < end)
+            .for_each(|EventRow { index, at, event }| {
+                let amount = event.balance_change(id);
+                if amount == 0f64 {
+                    return;
+                }
+                balance += amount;
+                if *at < start {
+                    return;
+                }
+                match event {
+                    Event::Interest { interest, .. } => statement.interest += interest,
+                    Event::Tax { tax, .. } => statement.tax += tax,
+                    Event::Fee { amount, .. } => statement.fees += amount,
+                    _ => (),
+                }
+                statement.lines.push(StatementLine {
+                    index: *index,
+                    at: *at,
+                    event: event.clone(),
+                    amount,
+                    balance,
+                });
+            });
+        statement.closing_balance = balance;
+        statement.opening_balance = statement
+            .lines
+            .first()
+            .map(|l| l.balance - l.amount)
+            .unwrap_or(balance);
+        statement
+    }
+
+    /// Writes the statements of every customer for the given month into `dir`, as
+    /// `statement_<id>_<month>.txt` and `.html` files. Returns the statements, ordered by
+    /// customer ID.
+    /// Requires `Manager` role.
+    pub fn generate_statements(
+        &self,
+        user: HashResult,
+        month: u64,
+        dir: &str,
+    ) -> BankResult<Vec<Statement>> {
+        self.read(|bank| {
+            bank.assert_role(user, Role::Manager)?;
+            let customers = bank
+                .storage
+                .users()
+                .into_iter()
+                .filter(|u| u.role == Role::Customer)
+                .map(|u| (u.id, u.username))
+                .collect::<Vec<_>>();
+
+            fs::create_dir_all(dir).map_err(|_| BankingError::FailedToWriteFile)?;
+            customers
+                .into_iter()
+                .map(|(id, username)| {
+                    let statement = bank.build_statement(id, username, month);
+                    let path = Path::new(dir).join(format!("statement_{}_{}", id, month));
+                    fs::write(
+                        path.with_extension("txt"),
+                        render_statement_text(&statement),
+                    )
+                    .and_then(|_| {
+                        fs::write(
+                            path.with_extension("html"),
+                            render_statement_html(&statement),
+                        )
+                    })
+                    .map_err(|_| BankingError::FailedToWriteFile)?;
+                    Ok(statement)
+                })
+                .collect()
+        })
+    }
+}
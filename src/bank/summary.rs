@@ -0,0 +1,122 @@
+//! Bank-wide financial summary for `Manager`s, built from the balances and the event log.
+use super::Bank;
+use crate::primitives::*;
+
+/// Percentiles of the active balances included in the summary.
+const SUMMARY_PERCENTILES: [u32; 5] = [10, 25, 50, 75, 90];
+
+/// Number of customers listed as top movers.
+const TOP_MOVERS: usize = 5;
+
+// Helper function: Nearest-rank percentile of sorted, non-empty `values`.
+fn percentile(values: &[Balance], p: u32) -> Balance {
+    let rank = (p as f64 / 100f64 * values.len() as f64).ceil() as usize;
+    values[rank.clamp(1, values.len()) - 1]
+}
+
+// Helper function: Counts the balances in buckets `[0, 10)`, `[10, 100)`, ... up to the
+// bucket holding the largest balance.
+fn histogram(values: &[Balance]) -> Vec<HistogramBucket> {
+    let mut buckets: Vec<HistogramBucket> = vec![];
+    values.iter().for_each(|v| {
+        while buckets.last().is_none_or(|b| *v >= b.to) {
+            let from = buckets.last().map_or(0f64, |b| b.to);
+            buckets.push(HistogramBucket {
+                from,
+                to: if from == 0f64 { 10f64 } else { from * 10f64 },
+                count: 0,
+            });
+        }
+        if let Some(b) = buckets.iter_mut().find(|b| *v < b.to) {
+            b.count += 1;
+        }
+    });
+    buckets
+}
+
+impl Bank {
+    /// Returns the bank-wide financial summary. Periods are months of the simulated clock,
+    /// and top movers are ranked by the size of their net change during the current month.
+    /// Requires `Manager` role.
+    pub fn financial_summary(&self, user: HashResult) -> BankResult<FinancialSummary> {
+        self.read(|bank| {
+            bank.assert_role(user, Role::Manager)?;
+            let customers = bank
+                .storage
+                .users()
+                .into_iter()
+                .filter(|u| u.role == Role::Customer)
+                .collect::<Vec<_>>();
+
+            let mut balances = customers
+                .iter()
+                .filter_map(|u| bank.storage.balance(u.id))
+                .collect::<Vec<_>>();
+            balances.sort_by(|a, b| a.total_cmp(b));
+            let reaped_accounts = customers
+                .iter()
+                .filter(|u| {
+                    bank.storage.balance(u.id).is_none()
+                        && bank
+                            .user_event_rows(u.id)
+                            .iter()
+                            .any(|r| matches!(r.event, Event::AccountReaped { .. }))
+                })
+                .count();
+
+            let current = bank.now / MONTH;
+            let mut periods = (0..=current)
+                .map(|month| PeriodSummary {
+                    month,
+                    interest: 0f64,
+                    tax: 0f64,
+                })
+                .collect::<Vec<_>>();
+            bank.storage.for_each_event(&mut |row| {
+                let period = &mut periods[(row.at / MONTH) as usize];
+                match row.event {
+                    Event::Interest { interest, .. } => period.interest += interest,
+                    Event::Tax { tax, .. } => period.tax += tax,
+                    _ => (),
+                }
+            });
+
+            let mut top_movers = customers
+                .iter()
+                .map(|u| Mover {
+                    id: u.id,
+                    username: u.username.clone(),
+                    change: bank
+                        .user_event_rows(u.id)
+                        .iter()
+                        .filter(|r| r.at >= current * MONTH)
+                        .fold(0f64, |total, r| total + r.event.balance_change(u.id)),
+                })
+                .filter(|m| m.change != 0f64)
+                .collect::<Vec<_>>();
+            top_movers.sort_by(|a, b| b.change.abs().total_cmp(&a.change.abs()));
+            top_movers.truncate(TOP_MOVERS);
+
+            Ok(FinancialSummary {
+                total_deposits: balances.iter().fold(0f64, |total, b| total + b),
+                term_deposits: customers
+                    .iter()
+                    .fold(0f64, |total, u| total + bank.locked_in_term_deposits(u.id)),
+                active_accounts: balances.len(),
+                reaped_accounts,
+                empty_accounts: customers.len() - balances.len() - reaped_accounts,
+                percentiles: if balances.is_empty() {
+                    vec![]
+                } else {
+                    SUMMARY_PERCENTILES
+                        .iter()
+                        .map(|p| (*p, percentile(&balances, *p)))
+                        .collect()
+                },
+                histogram: histogram(&balances),
+                periods,
+                top_movers,
+            })
+        })
+    }
+}
@@ -0,0 +1,272 @@
+//! Term deposits: an amount is locked away from the main balance for a number of months at
+//! a fixed rate. Interest is paid into the main balance monthly or at maturity, when the
+//! simulated clock passes the due dates.
+use super::{loans::round_cents, Bank};
+use crate::primitives::*;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TermDeposit {
+    pub deposit_id: TermDepositId,
+    pub owner: UserId,
+    pub principal: Balance,
+    /// Annual rate, fixed when the term deposit is opened.
+    pub rate: f64,
+    /// Term in months.
+    pub term: u32,
+    pub opened_at: Timestamp,
+    pub matures_at: Timestamp,
+    pub payout: InterestPayout,
+    pub instruction: MaturityInstruction,
+    /// Interest already paid into the main balance.
+    pub interest_paid: Balance,
+    /// Number of monthly interest payments already made.
+    pub periods_paid: u32,
+    pub status: TermDepositStatus,
+}
+
+impl TermDeposit {
+    /// Simple interest earned over the whole term, rounded to cents.
+    pub fn total_interest(&self) -> Balance {
+        round_cents(self.principal * self.rate * self.term as f64 / 12f64)
+    }
+}
+
+impl Bank {
+    /// Set the annual rate given to new term deposits. Open term deposits keep their rate.
+    /// Requires `Manager` role.
+    pub fn set_term_deposit_rate(&mut self, user: HashResult, rate: f64) -> BankResult<()> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Manager)?;
+            if rate < 0f64 {
+                return Err(BankingError::InvalidInterestRate);
+            }
+            bank.term_deposit_rate = rate;
+            bank.deposit_event(Event::TermDepositRate { id, rate });
+            Ok(())
+        })
+    }
+
+    /// Returns the annual rate given to new term deposits.
+    pub fn term_deposit_rate(&self) -> f64 {
+        self.term_deposit_rate
+    }
+
+    // Helper function: Records a new active term deposit starting at `opened_at`, opened from
+    // the main balance or rolled over from a matured term deposit.
+    #[allow(clippy::too_many_arguments)]
+    fn insert_term_deposit(
+        &mut self,
+        deposit_id: TermDepositId,
+        owner: UserId,
+        principal: Balance,
+        term: u32,
+        opened_at: Timestamp,
+        payout: InterestPayout,
+        instruction: MaturityInstruction,
+        rolled_over_from: Option<TermDepositId>,
+    ) {
+        let deposit = TermDeposit {
+            deposit_id,
+            owner,
+            principal,
+            rate: self.term_deposit_rate,
+            term,
+            opened_at,
+            matures_at: opened_at + MONTH * term as Timestamp,
+            payout,
+            instruction,
+            interest_paid: 0f64,
+            periods_paid: 0,
+            status: TermDepositStatus::Active,
+        };
+        self.term_deposits.insert(deposit_id, deposit);
+        self.deposit_event(Event::TermDepositOpened {
+            deposit_id,
+            id: owner,
+            amount: principal,
+            rate: deposit.rate,
+            matures_at: deposit.matures_at,
+            rolled_over_from,
+        });
+    }
+
+    /// Locks `amount` from the main balance in a term deposit of `term` months at the current
+    /// term deposit rate. At least ED must be left in the main balance.
+    /// Returns the ID of the term deposit.
+    /// Requires `Customer` role.
+    pub fn open_term_deposit(
+        &mut self,
+        user: HashResult,
+        amount: Balance,
+        term: u32,
+        payout: InterestPayout,
+        instruction: MaturityInstruction,
+    ) -> BankResult<TermDepositId> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Customer)?;
+            if amount <= 0f64 {
+                return Err(BankingError::InvalidAmount);
+            }
+            if amount < bank.existential_deposit {
+                return Err(BankingError::AmountTooSmall);
+            }
+            if term == 0 {
+                return Err(BankingError::InvalidTerm);
+            }
+            if bank.available_balance(id) - amount < bank.existential_deposit {
+                return Err(BankingError::InsufficientBalance);
+            }
+
+            let deposit_id = bank.generate_next_term_deposit_id();
+            let now = bank.now;
+            bank.insert_term_deposit(deposit_id, id, amount, term, now, payout, instruction, None);
+            bank.debit(id, amount);
+            Ok(deposit_id)
+        })
+    }
+
+    /// Breaks an active term deposit before maturity. The principal is paid into the main
+    /// balance, less the `EarlyWithdrawal` fee. Interest already paid is kept.
+    /// Returns the amount paid out.
+    /// Requires `Customer` role.
+    pub fn break_term_deposit(
+        &mut self,
+        user: HashResult,
+        deposit_id: TermDepositId,
+    ) -> BankResult<Balance> {
+        self.transaction(|bank| {
+            let id = bank.assert_role(user, Role::Customer)?;
+            let mut deposit = match bank.term_deposits.get(&deposit_id) {
+                Some(d) if d.owner == id => Ok(*d),
+                _ => Err(BankingError::NoTermDepositFound),
+            }?;
+            if deposit.status != TermDepositStatus::Active {
+                return Err(BankingError::InvalidTermDepositStatus);
+            }
+
+            let penalty = bank
+                .compute_fee(id, FeeType::EarlyWithdrawal, deposit.principal)
+                .min(deposit.principal);
+            deposit.status = TermDepositStatus::Broken;
+            bank.term_deposits.insert(deposit_id, deposit);
+            bank.credit(id, deposit.principal);
+            bank.deposit_event(Event::TermDepositBroken {
+                deposit_id,
+                id,
+                principal: deposit.principal,
+            });
+            if penalty > 0f64 {
+                bank.post_fee(id, FeeType::EarlyWithdrawal, penalty);
+                bank.debit(id, penalty);
+            }
+            Ok(deposit.principal - penalty)
+        })
+    }
+
+    /// Returns the term deposits visible to the current user, ordered by ID.
+    /// `Customer`s see their own term deposits, other roles see all term deposits.
+    pub fn list_term_deposits(&self, user: HashResult) -> BankResult<Vec<TermDeposit>> {
+        self.read(|bank| {
+            let u = bank.current_user(user)?;
+            let mut deposits = bank
+                .term_deposits
+                .values()
+                .filter(|d| u.role != Role::Customer || d.owner == u.id)
+                .copied()
+                .collect::<Vec<_>>();
+            deposits.sort_by_key(|d| d.deposit_id);
+            Ok(deposits)
+        })
+    }
+
+    /// Returns the principal the user has locked in active term deposits.
+    pub(super) fn locked_in_term_deposits(&self, id: UserId) -> Balance {
+        self.term_deposits
+            .values()
+            .filter(|d| d.owner == id && d.status == TermDepositStatus::Active)
+            .fold(0f64, |total, d| total + d.principal)
+    }
+
+    // Helper function: Pays term deposit interest into the main balance.
+    fn pay_term_interest(&mut self, deposit: &mut TermDeposit, interest: Balance) {
+        if interest <= 0f64 {
+            return;
+        }
+        deposit.interest_paid += interest;
+        self.credit(deposit.owner, interest);
+        self.deposit_event(Event::Interest {
+            id: deposit.owner,
+            interest,
+        });
+    }
+
+    /// Pays the monthly interest that has fallen due, then settles every term deposit that
+    /// has reached maturity: the remaining interest is paid, and the principal is either paid
+    /// into the main balance or rolled over into a new term deposit starting at maturity.
+    pub(super) fn process_term_deposits(&mut self) {
+        let now = self.now;
+        let mut deposit_ids = self
+            .term_deposits
+            .values()
+            .filter(|d| d.status == TermDepositStatus::Active)
+            .map(|d| d.deposit_id)
+            .collect::<Vec<_>>();
+        deposit_ids.sort_unstable();
+        deposit_ids.reverse();
+
+        // Rolled over term deposits are pushed back, as they may have matured already.
+        while let Some(deposit_id) = deposit_ids.pop() {
+            let mut deposit = self.term_deposits[&deposit_id];
+            let total = deposit.total_interest();
+
+            if deposit.payout == InterestPayout::Monthly {
+                let due = ((now.min(deposit.matures_at) - deposit.opened_at) / MONTH) as u32;
+                while deposit.periods_paid < due {
+                    deposit.periods_paid += 1;
+                    let interest = if deposit.periods_paid == deposit.term {
+                        round_cents(total - deposit.interest_paid)
+                    } else {
+                        round_cents(total / deposit.term as f64)
+                    };
+                    self.pay_term_interest(&mut deposit, interest);
+                }
+            }
+
+            if now < deposit.matures_at {
+                self.term_deposits.insert(deposit_id, deposit);
+                continue;
+            }
+
+            let remaining = round_cents(total - deposit.interest_paid);
+            self.pay_term_interest(&mut deposit, remaining);
+            deposit.status = TermDepositStatus::Matured;
+            self.term_deposits.insert(deposit_id, deposit);
+            let rolled_over_into = match deposit.instruction {
+                MaturityInstruction::RollOver => Some(self.generate_next_term_deposit_id()),
+                MaturityInstruction::PayOut => {
+                    self.credit(deposit.owner, deposit.principal);
+                    None
+                }
+            };
+            self.deposit_event(Event::TermDepositMatured {
+                deposit_id,
+                id: deposit.owner,
+                principal: deposit.principal,
+                rolled_over_into,
+            });
+            if let Some(new_id) = rolled_over_into {
+                self.insert_term_deposit(
+                    new_id,
+                    deposit.owner,
+                    deposit.principal,
+                    deposit.term,
+                    deposit.matures_at,
+                    deposit.payout,
+                    deposit.instruction,
+                    Some(deposit_id),
+                );
+                deposit_ids.push(new_id);
+            }
+        }
+    }
+}
@@ -0,0 +1,461 @@
+//! Bank files: a bank persisted as the journal of the operations applied to it.
+//!
+//! The first line holds the format version, and every other line one operation, with its
+//! fields separated by tabs. Users are recorded by the hash of their username and password,
+//! never by their password. Loading a bank file replays the operations on a new bank, in
+//! order; as the bank is deterministic, this rebuilds the balances and the event log. Only
+//! operations that changed the bank are recorded: those that succeeded, and those that failed
+//! after logging why, such as a withdrawal declined with an overdraft fee.
+//!
+//! Since version 2, tabs, line breaks and backslashes in text are escaped as `\t`, `\n`, `\r`
+//! and `\\`. Since version 3, every line ends with a seal, in hex: the MAC, with the signing
+//! key of the bank, of the line, the seal of the line before it and the event log once the
+//! operation was applied (see `Bank::journal_seal`). Loading checks every seal, so a line
+//! changed, added, removed or moved outside the app is refused, unless the lines after it are
+//! removed too: cutting the end off the file is not detected. Files of an older version are
+//! not read: `migrate_bank_file`, run by `banking_app migrate`, upgrades them.
+//!
+//! | Operation           | Fields                                         |
+//! |---------------------|------------------------------------------------|
+//! | `register`          | user, role, username                           |
+//! | `deposit`           | user, amount, reference                        |
+//! | `withdraw`          | user, amount, reference                        |
+//! | `transfer`          | user, target user ID, amount, reference        |
+//! | `set-interest-rate` | user, rate                                     |
+//! | `pay-interest`      | user                                           |
+//! | `set-tax-rate`      | user, rate                                     |
+//! | `take-tax`          | user                                           |
+//! | `advance-clock`     | user, seconds                                  |
+use crate::bank::Bank;
+use crate::migration::{apply_migrations, backup_path, Migration, MigrationReport};
+use crate::primitives::*;
+use crate::storage::{escape, unescape, MemoryStorage, Storage};
+use std::{
+    fs::{self, OpenOptions},
+    io::{ErrorKind, Write},
+};
+
+/// Version of the bank file format written by this build.
+pub const BANK_FILE_VERSION: u32 = 3;
+
+/// The migrations of bank files, in order. They upgrade the lines after the header.
+pub const BANK_FILE_MIGRATIONS: &[Migration<Vec<String>>] = &[
+    Migration {
+        from: 1,
+        description: "Escape backslashes in text",
+        apply: escape_text,
+    },
+    Migration {
+        from: 2,
+        description: "Seal every line",
+        apply: seal_lines,
+    },
+];
+
+/// An operation recorded in a bank file. `user` is the hash of the acting user.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    Register {
+        user: HashResult,
+        role: Role,
+        username: String,
+    },
+    Deposit {
+        user: HashResult,
+        amount: Balance,
+        reference: String,
+    },
+    Withdraw {
+        user: HashResult,
+        amount: Balance,
+        reference: String,
+    },
+    Transfer {
+        user: HashResult,
+        to: UserId,
+        amount: Balance,
+        reference: String,
+    },
+    SetInterestRate {
+        user: HashResult,
+        rate: f64,
+    },
+    PayInterest {
+        user: HashResult,
+    },
+    SetTaxRate {
+        user: HashResult,
+        rate: f64,
+    },
+    TakeTax {
+        user: HashResult,
+    },
+    AdvanceClock {
+        user: HashResult,
+        duration: Timestamp,
+    },
+}
+
+impl Operation {
+    /// Applies the operation to the bank.
+    pub fn apply(&self, bank: &mut Bank) -> BankResult<()> {
+        match self {
+            Operation::Register {
+                user,
+                role,
+                username,
+            } => bank.create_user_with_hash(username.clone(), *user, *role),
+            Operation::Deposit {
+                user,
+                amount,
+                reference,
+            } => bank.deposit_with_reference(*user, *amount, PaymentReference::parse(reference)?),
+            Operation::Withdraw {
+                user,
+                amount,
+                reference,
+            } => bank.withdraw_with_reference(*user, *amount, PaymentReference::parse(reference)?),
+            Operation::Transfer {
+                user,
+                to,
+                amount,
+                reference,
+            } => bank.transfer_with_reference(
+                *user,
+                *amount,
+                *to,
+                PaymentReference::parse(reference)?,
+            ),
+            Operation::SetInterestRate { user, rate } => bank.set_interest_rate(*user, *rate),
+            Operation::PayInterest { user } => bank.pay_interest(*user),
+            Operation::SetTaxRate { user, rate } => bank.set_tax_rate(*user, *rate),
+            Operation::TakeTax { user } => bank.take_tax(*user),
+            Operation::AdvanceClock { user, duration } => bank.advance_clock(*user, *duration),
+        }
+    }
+
+    /// Encodes the operation as a line of a bank file, without its seal or the line break.
+    pub fn encode(&self) -> String {
+        let fields = match self {
+            Operation::Register {
+                user,
+                role,
+                username,
+            } => vec![
+                "register".to_string(),
+                user.to_string(),
+                format!("{:?}", role),
+                escape(username),
+            ],
+            Operation::Deposit {
+                user,
+                amount,
+                reference,
+            } => vec![
+                "deposit".to_string(),
+                user.to_string(),
+                amount.to_string(),
+                escape(reference),
+            ],
+            Operation::Withdraw {
+                user,
+                amount,
+                reference,
+            } => vec![
+                "withdraw".to_string(),
+                user.to_string(),
+                amount.to_string(),
+                escape(reference),
+            ],
+            Operation::Transfer {
+                user,
+                to,
+                amount,
+                reference,
+            } => vec![
+                "transfer".to_string(),
+                user.to_string(),
+                to.to_string(),
+                amount.to_string(),
+                escape(reference),
+            ],
+            Operation::SetInterestRate { user, rate } => vec![
+                "set-interest-rate".to_string(),
+                user.to_string(),
+                rate.to_string(),
+            ],
+            Operation::PayInterest { user } => vec!["pay-interest".to_string(), user.to_string()],
+            Operation::SetTaxRate { user, rate } => vec![
+                "set-tax-rate".to_string(),
+                user.to_string(),
+                rate.to_string(),
+            ],
+            Operation::TakeTax { user } => vec!["take-tax".to_string(), user.to_string()],
+            Operation::AdvanceClock { user, duration } => vec![
+                "advance-clock".to_string(),
+                user.to_string(),
+                duration.to_string(),
+            ],
+        };
+        fields.join("\t")
+    }
+
+    /// Decodes a line of a bank file, without its seal.
+    pub fn decode(line: &str) -> Option<Self> {
+        let fields = line.split('\t').collect::<Vec<_>>();
+        let user = fields.get(1)?.parse().ok()?;
+        let number = |i: usize| fields.get(i)?.parse::<f64>().ok();
+        let text = |i: usize| unescape(fields.get(i)?);
+        let operation = match (fields[0], fields.len()) {
+            ("register", 4) => Operation::Register {
+                user,
+                role: match fields[2] {
+                    "Customer" => Role::Customer,
+                    "Manager" => Role::Manager,
+                    "Auditor" => Role::Auditor,
+                    _ => return None,
+                },
+                username: text(3)?,
+            },
+            ("deposit", 4) => Operation::Deposit {
+                user,
+                amount: number(2)?,
+                reference: text(3)?,
+            },
+            ("withdraw", 4) => Operation::Withdraw {
+                user,
+                amount: number(2)?,
+                reference: text(3)?,
+            },
+            ("transfer", 5) => Operation::Transfer {
+                user,
+                to: fields[2].parse().ok()?,
+                amount: number(3)?,
+                reference: text(4)?,
+            },
+            ("set-interest-rate", 3) => Operation::SetInterestRate {
+                user,
+                rate: number(2)?,
+            },
+            ("pay-interest", 2) => Operation::PayInterest { user },
+            ("set-tax-rate", 3) => Operation::SetTaxRate {
+                user,
+                rate: number(2)?,
+            },
+            ("take-tax", 2) => Operation::TakeTax { user },
+            ("advance-clock", 3) => Operation::AdvanceClock {
+                user,
+                duration: fields[2].parse().ok()?,
+            },
+            _ => return None,
+        };
+        Some(operation)
+    }
+}
+
+// Helper function: Returns the header line of a bank file.
+fn header() -> String {
+    format!("banking_app bank file version {}", BANK_FILE_VERSION)
+}
+
+// Helper function: Returns the version given by the header line of a bank file.
+fn parse_header(line: &str) -> Option<u32> {
+    line.strip_prefix("banking_app bank file version ")?
+        .parse()
+        .ok()
+}
+
+// Helper function: Migrates version 1, whose text could not hold tabs or line breaks but was
+// not escaped, by escaping its backslashes. Text is the last field of the operations with text.
+#[allow(clippy::ptr_arg)] // The signature is fixed by `Migration`.
+fn escape_text(lines: &mut Vec<String>) -> Result<Vec<String>, String> {
+    let mut changes = vec![];
+    for (i, line) in lines.iter_mut().enumerate() {
+        let has_text = matches!(
+            line.split('\t').next(),
+            Some("register" | "deposit" | "withdraw" | "transfer")
+        );
+        match line.rsplit_once('\t') {
+            Some((fields, text)) if has_text && text.contains('\\') => {
+                changes.push(format!("line {}: {} -> {}", i + 2, text, escape(text)));
+                *line = format!("{}\t{}", fields, escape(text));
+            }
+            _ => {}
+        }
+    }
+    Ok(changes)
+}
+
+// Helper function: Migrates version 2 by sealing every line, as the bank the file is replayed on
+// sees it. The key is the one the bank will be loaded with. Nothing vouches for the unsealed
+// lines, so the seals vouch for them as they are: only an operator migrating a file they trust
+// runs this.
+#[allow(clippy::ptr_arg)] // The signature is fixed by `Migration`.
+fn seal_lines(lines: &mut Vec<String>) -> Result<Vec<String>, String> {
+    let mut bank = Bank::default();
+    bank.set_verbose(false);
+    let mut seal = LogHash::default();
+    let mut sealed = 0;
+    for (i, line) in lines.iter_mut().enumerate().filter(|(_, l)| !l.is_empty()) {
+        let operation = Operation::decode(line).ok_or(format!("line {}: invalid", i + 2))?;
+        let _ = operation.apply(&mut bank);
+        seal = bank.journal_seal(&seal, line);
+        *line = format!("{}\t{}", line, encode_seal(&seal));
+        sealed += 1;
+    }
+    Ok(vec![format!("sealed {} lines", sealed)])
+}
+
+// Helper function: Encodes a seal in hex.
+fn encode_seal(seal: &LogHash) -> String {
+    seal.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Helper function: Decodes a seal from hex.
+fn decode_seal(hex: &str) -> Option<LogHash> {
+    let mut seal = LogHash::default();
+    if hex.len() != 2 * seal.len() || !hex.is_ascii() {
+        return None;
+    }
+    for (i, byte) in seal.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(seal)
+}
+
+/// Upgrades the bank file at `path` to `BANK_FILE_VERSION`, after copying the original to
+/// `backup_path(path, version)`. With `dry_run`, nothing is written and the report tells what
+/// would change. A missing file is up to date.
+pub fn migrate_bank_file(path: &str, dry_run: bool) -> BankResult<MigrationReport> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Ok(MigrationReport::current(path, BANK_FILE_VERSION))
+        }
+        Err(_) => return Err(BankingError::FailedToReadFile),
+    };
+    let mut lines = content.lines().map(str::to_string).collect::<Vec<_>>();
+    let version = lines
+        .first()
+        .and_then(|l| parse_header(l))
+        .ok_or(BankingError::InvalidBankFile)?;
+    if version > BANK_FILE_VERSION {
+        return Err(BankingError::UnsupportedBankFileVersion);
+    }
+    if version == BANK_FILE_VERSION {
+        return Ok(MigrationReport::current(path, version));
+    }
+
+    lines.remove(0);
+    let steps = apply_migrations(&mut lines, version, BANK_FILE_MIGRATIONS)
+        .map_err(|_| BankingError::InvalidBankFile)?;
+    let mut report = MigrationReport {
+        target: path.to_string(),
+        from: version,
+        to: BANK_FILE_VERSION,
+        steps,
+        backup: None,
+        dry_run,
+    };
+    if !dry_run {
+        let backup = backup_path(path, version);
+        fs::copy(path, &backup).map_err(|_| BankingError::FailedToWriteFile)?;
+        let mut content = header();
+        lines.iter().for_each(|l| {
+            content.push('\n');
+            content.push_str(l);
+        });
+        content.push('\n');
+        fs::write(path, content).map_err(|_| BankingError::FailedToWriteFile)?;
+        report.backup = Some(backup);
+    }
+    Ok(report)
+}
+
+/// Reads the operations of the bank file at `path`. A missing file holds no operations. The
+/// seals are not checked.
+pub fn read_operations(path: &str) -> BankResult<Vec<Operation>> {
+    Ok(read_sealed_lines(path)?
+        .into_iter()
+        .map(|(_, operation, _)| operation)
+        .collect())
+}
+
+// Helper function: Reads every line of the bank file at `path`, with its decoded operation and
+// its seal. Returns `OutdatedBankFile` for a file of an older version, which is never upgraded
+// here: its lines are not sealed, so nothing vouches for them.
+fn read_sealed_lines(path: &str) -> BankResult<Vec<(String, Operation, LogHash)>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(_) => return Err(BankingError::FailedToReadFile),
+    };
+    let mut lines = content.lines();
+    match lines.next().and_then(parse_header) {
+        Some(BANK_FILE_VERSION) => {}
+        Some(version) if version > BANK_FILE_VERSION => {
+            return Err(BankingError::UnsupportedBankFileVersion)
+        }
+        Some(version) if version > 0 => return Err(BankingError::OutdatedBankFile),
+        _ => return Err(BankingError::InvalidBankFile),
+    }
+    lines
+        .filter(|l| !l.is_empty())
+        .map(|l| {
+            let (line, seal) = l.rsplit_once('\t')?;
+            Some((
+                line.to_string(),
+                Operation::decode(line)?,
+                decode_seal(seal)?,
+            ))
+        })
+        .map(|l| l.ok_or(BankingError::InvalidBankFile))
+        .collect()
+}
+
+/// Loads the bank file at `path` by replaying its operations on a new, quiet bank.
+pub fn load_bank(path: &str) -> BankResult<Bank> {
+    load_bank_with_storage(path, Box::new(MemoryStorage::new()))
+}
+
+/// Loads the bank file at `path` by replaying its operations on a new, quiet bank kept in
+/// `storage`, which must be empty. Returns `TamperedBankFile` if a line does not match its seal.
+pub fn load_bank_with_storage(path: &str, storage: Box<dyn Storage>) -> BankResult<Bank> {
+    let mut bank = Bank::with_storage(storage);
+    bank.set_verbose(false);
+    let mut prev = LogHash::default();
+    for (line, operation, seal) in read_sealed_lines(path)? {
+        // Operations recorded although they failed fail again, and log the same events.
+        let _ = operation.apply(&mut bank);
+        if bank.journal_seal(&prev, &line) != seal {
+            return Err(BankingError::TamperedBankFile);
+        }
+        prev = seal;
+    }
+    Ok(bank)
+}
+
+/// Appends an operation, just applied to `bank`, to the bank file at `path`, creating the file
+/// if needed. The line is sealed with the key of `bank`, which must be the bank loaded from the
+/// file.
+pub fn append_operation(path: &str, bank: &Bank, operation: &Operation) -> BankResult<()> {
+    let prev = read_sealed_lines(path)?
+        .last()
+        .map_or(LogHash::default(), |(_, _, seal)| *seal);
+    let exists = fs::metadata(path).is_ok();
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|_| BankingError::FailedToWriteFile)?;
+    let mut line = String::new();
+    if !exists {
+        line.push_str(&header());
+        line.push('\n');
+    }
+    let encoded = operation.encode();
+    let seal = bank.journal_seal(&prev, &encoded);
+    line.push_str(&format!("{}\t{}\n", encoded, encode_seal(&seal)));
+    file.write_all(line.as_bytes())
+        .map_err(|_| BankingError::FailedToWriteFile)
+}
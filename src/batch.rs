@@ -0,0 +1,289 @@
+//! Bulk payments read from a CSV batch file.
+//!
+//! Each line of the batch file is `payee,amount,reference`, where `payee` is a user ID,
+//! username or saved payee nickname. The whole batch is validated up front, with the same
+//! checks as `Bank::check_transfer`, then executed through `Bank::transfer_with_reference`, so
+//! the usual transfer rules (including ED reaping) apply and the reference is stored with each
+//! transfer as its memo.
+use crate::bank::Bank;
+use crate::primitives::*;
+use std::fs;
+
+/// Maximum number of payment lines accepted in one batch.
+pub const MAX_BATCH_SIZE: usize = 10_000;
+
+/// How a batch is executed when some of its lines are invalid.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BatchMode {
+    /// Nothing is paid unless every line is valid, and every line is paid.
+    AllOrNothing,
+    /// Valid lines are paid, invalid lines are skipped.
+    BestEffort,
+}
+
+/// A single payment parsed from the batch file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchLine {
+    /// 1-based line number in the batch file.
+    pub line: usize,
+    pub payee: String,
+    pub amount: Balance,
+    pub reference: String,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BatchStatus {
+    Paid,
+    Failed(BankingError),
+    /// The line is valid, but was not paid because the batch was rejected.
+    Skipped,
+}
+
+/// Outcome of one line of a batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchLineResult {
+    pub line: usize,
+    pub payee: String,
+    pub amount: Balance,
+    pub reference: String,
+    pub status: BatchStatus,
+}
+
+// Helper function: Splits a CSV row into fields. Fields may be quoted with `"`, and `""`
+// inside a quoted field is an escaped quote.
+fn split_csv_row(row: &str) -> Vec<String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = row.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields.into_iter().map(|f| f.trim().to_string()).collect()
+}
+
+// Helper function: Quotes a CSV field if needed.
+pub(crate) fn escape_csv_field(field: &str) -> String {
+    if field.contains(',') || field.contains('"') || field.contains('\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Parses the content of a batch file. Blank lines and an optional `payee,amount,reference`
+/// header are ignored. Lines that cannot be parsed are returned as errors with their line number.
+pub fn parse_batch(input: &str) -> Vec<Result<BatchLine, (usize, BankingError)>> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, row)| !row.trim().is_empty())
+        .map(|(index, row)| (index + 1, split_csv_row(row)))
+        .filter(|(line, fields)| {
+            !(*line == 1 && fields.first().map(|f| f.eq_ignore_ascii_case("payee")) == Some(true))
+        })
+        .map(|(line, fields)| {
+            if fields.len() < 2 || fields.len() > 3 || fields[0].is_empty() {
+                return Err((line, BankingError::InvalidBatchLine));
+            }
+            let amount = fields[1]
+                .parse::<Balance>()
+                .ok()
+                .filter(|a| a.is_finite())
+                .ok_or((line, BankingError::InvalidAmount))?;
+            Ok(BatchLine {
+                line,
+                payee: fields[0].clone(),
+                amount,
+                reference: fields.get(2).cloned().unwrap_or_default(),
+            })
+        })
+        .collect()
+}
+
+/// Validates every line of the batch against the current user's available balance, the
+/// transfer fees and the transfer rules, including the KYC policy, the watch list and the fraud
+/// rules, as if the valid lines were paid in order. A line the fraud rules would hold is
+/// invalid. Returns the resolved payee ID of each valid line.
+fn validate_batch(
+    bank: &Bank,
+    user: HashResult,
+    lines: &[BatchLine],
+) -> BankResult<Vec<BankResult<UserId>>> {
+    let ledger = bank.check_balance(user)?;
+    let mut available = bank.check_available_balance(user)?;
+    let held = ledger - available;
+    let ed = bank.existential_deposit();
+    let mut paid = vec![];
+
+    Ok(lines
+        .iter()
+        .map(|l| {
+            PaymentReference::memo(&l.reference)?;
+            let (target, _) = bank.resolve_payee(user, &l.payee)?;
+            bank.check_transfer(user, l.amount, target, &paid)?;
+            let fee = bank.quote_fee(user, FeeType::Transfer, l.amount)?;
+            if available < l.amount + fee {
+                return Err(BankingError::InsufficientBalance);
+            }
+            available -= l.amount + fee;
+            // The account is reaped once the balance falls below ED.
+            if available + held < ed && held <= 0f64 {
+                available = 0f64;
+            }
+            paid.push((target, l.amount));
+            Ok(target)
+        })
+        .collect())
+}
+
+/// Pays every line of the batch from the current user's account.
+///
+/// The batch is validated up front. In `AllOrNothing` mode nothing is paid if any line is
+/// invalid, and the batch is paid as a single operation of the bank, so nothing is paid either
+/// if a payment fails; in `BestEffort` mode the invalid lines are skipped and the rest are paid.
+/// Requires `Customer` role.
+pub fn run_batch(
+    bank: &mut Bank,
+    user: HashResult,
+    lines: Vec<Result<BatchLine, (usize, BankingError)>>,
+    mode: BatchMode,
+) -> BankResult<Vec<BatchLineResult>> {
+    if lines.len() > MAX_BATCH_SIZE {
+        return Err(BankingError::BatchTooLarge);
+    }
+    let parsed = lines
+        .iter()
+        .filter_map(|l| l.as_ref().ok().cloned())
+        .collect::<Vec<_>>();
+    let mut validated = validate_batch(bank, user, &parsed)?.into_iter();
+
+    // Pair each line's result with the payee it resolved to, if the line is valid.
+    let (mut results, targets): (Vec<_>, Vec<_>) = lines
+        .into_iter()
+        .map(|l| match l {
+            Ok(l) => {
+                let (status, target) = match validated.next() {
+                    Some(Ok(target)) => (BatchStatus::Skipped, Some(target)),
+                    Some(Err(e)) => (BatchStatus::Failed(e), None),
+                    None => (BatchStatus::Failed(BankingError::InvalidBatchLine), None),
+                };
+                let result = BatchLineResult {
+                    line: l.line,
+                    payee: l.payee,
+                    amount: l.amount,
+                    reference: l.reference,
+                    status,
+                };
+                (result, target)
+            }
+            Err((line, e)) => {
+                let result = BatchLineResult {
+                    line,
+                    payee: String::new(),
+                    amount: 0f64,
+                    reference: String::new(),
+                    status: BatchStatus::Failed(e),
+                };
+                (result, None)
+            }
+        })
+        .unzip();
+
+    if mode == BatchMode::AllOrNothing && targets.iter().any(|t| t.is_none()) {
+        return Ok(results);
+    }
+
+    let mut payments = results
+        .iter_mut()
+        .zip(targets)
+        .filter_map(|(r, target)| target.map(|target| (r, target)))
+        .collect::<Vec<_>>();
+    match mode {
+        BatchMode::BestEffort => payments.iter_mut().for_each(|(r, target)| {
+            let _ = pay_line(bank, user, r, *target);
+        }),
+        BatchMode::AllOrNothing => {
+            // The batch may still fail as it is paid, e.g. if the fraud rules hold a payment
+            // after all. The payments made before are then undone, and reported as skipped.
+            let paid = bank.atomically(|bank| {
+                payments
+                    .iter_mut()
+                    .try_for_each(|(r, target)| pay_line(bank, user, r, *target))
+            });
+            match paid {
+                Err(BankingError::StorageFailure) => return Err(BankingError::StorageFailure),
+                Err(_) => payments
+                    .iter_mut()
+                    .filter(|(r, _)| r.status == BatchStatus::Paid)
+                    .for_each(|(r, _)| r.status = BatchStatus::Skipped),
+                Ok(()) => {}
+            }
+        }
+    }
+    Ok(results)
+}
+
+// Helper function: Pays one line of a batch to `target`, and sets its status.
+fn pay_line(
+    bank: &mut Bank,
+    user: HashResult,
+    r: &mut BatchLineResult,
+    target: UserId,
+) -> BankResult<()> {
+    let res = PaymentReference::memo(&r.reference)
+        .and_then(|reference| bank.transfer_with_reference(user, r.amount, target, reference));
+    r.status = match res {
+        Ok(()) => BatchStatus::Paid,
+        Err(e) => BatchStatus::Failed(e),
+    };
+    res
+}
+
+/// Renders the batch results as CSV: `line,payee,amount,reference,status,error`.
+pub fn render_results(results: &[BatchLineResult]) -> String {
+    let mut out = String::from("line,payee,amount,reference,status,error\n");
+    results.iter().for_each(|r| {
+        let (status, error) = match r.status {
+            BatchStatus::Paid => ("PAID", String::new()),
+            BatchStatus::Failed(e) => ("FAILED", e.to_string()),
+            BatchStatus::Skipped => ("SKIPPED", String::new()),
+        };
+        out.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            r.line,
+            escape_csv_field(&r.payee),
+            r.amount,
+            escape_csv_field(&r.reference),
+            status,
+            escape_csv_field(&error)
+        ));
+    });
+    out
+}
+
+/// Reads a batch file from `input_path`, pays it from the current user's account and writes
+/// the per-line results to `output_path`.
+/// Requires `Customer` role.
+pub fn process_batch_file(
+    bank: &mut Bank,
+    user: HashResult,
+    input_path: &str,
+    output_path: &str,
+    mode: BatchMode,
+) -> BankResult<Vec<BatchLineResult>> {
+    let input = fs::read_to_string(input_path).map_err(|_| BankingError::FailedToReadFile)?;
+    let results = run_batch(bank, user, parse_batch(&input), mode)?;
+    fs::write(output_path, render_results(&results))
+        .map_err(|_| BankingError::FailedToWriteFile)?;
+    Ok(results)
+}
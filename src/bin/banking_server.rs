@@ -0,0 +1,47 @@
+//! HTTP/JSON server in front of a `Bank`. See the `api` module for the endpoints. The storage
+//! backend is selected by the `BANKING_STORAGE` environment variable. If the bank has no
+//! manager yet, the one named by `BANKING_MANAGER`, with the password in
+//! `BANKING_MANAGER_PASSWORD`, is registered.
+//!
+//! Usage: `banking_server [--addr <host:port>]`. The default address is `127.0.0.1:8080`;
+//! port 0 picks a free port. The bound address is printed as `Listening on <addr>`.
+use banking_app::{bank_from_env, ApiServer, MANAGER_ENV, MANAGER_PASSWORD_ENV};
+use std::{env, net::TcpListener, process};
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let addr = match args.as_slice() {
+        [] => "127.0.0.1:8080".to_string(),
+        [flag, addr] if flag == "--addr" => addr.clone(),
+        _ => {
+            eprintln!("Usage: banking_server [--addr <host:port>]");
+            process::exit(2);
+        }
+    };
+    let bank = match bank_from_env() {
+        Ok(bank) => bank,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let mut server = ApiServer::new(bank);
+    if let (Ok(username), Ok(password)) = (env::var(MANAGER_ENV), env::var(MANAGER_PASSWORD_ENV)) {
+        if let Err(e) = server.bootstrap_manager(username, password) {
+            eprintln!("Failed to register the first manager: {}", e);
+            process::exit(1);
+        }
+    }
+    let listener = match TcpListener::bind(&addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("Failed to listen on {}: {}", addr, e);
+            process::exit(1);
+        }
+    };
+    match listener.local_addr() {
+        Ok(addr) => println!("Listening on {}", addr),
+        Err(e) => eprintln!("{}", e),
+    }
+    server.serve(&listener)
+}
@@ -0,0 +1,19 @@
+//! Full-screen terminal UI over a `Bank`. See the `tui` module for the keys. The storage
+//! backend is selected by the `BANKING_STORAGE` environment variable.
+use banking_app::{bank_from_env, TerminalBackend, Tui};
+use std::process;
+
+fn main() {
+    let bank = match bank_from_env() {
+        Ok(bank) => bank,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let result = TerminalBackend::new().and_then(|mut backend| Tui::new(bank).run(&mut backend));
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
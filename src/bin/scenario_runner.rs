@@ -0,0 +1,34 @@
+//! Runs scenario files against a new `Bank` each. See the `scenario` module for the format.
+//!
+//! Usage: `scenario_runner <file>...`. Prints `PASS` or `FAIL` for every scenario, with a
+//! description of each failed step. Exits with 1 if a scenario failed, or 2 if a file could not
+//! be read or parsed.
+use banking_app::Scenario;
+use std::{env, process};
+
+fn main() {
+    let paths = env::args().skip(1).collect::<Vec<_>>();
+    if paths.is_empty() {
+        eprintln!("Usage: scenario_runner <file>...");
+        process::exit(2);
+    }
+    let mut code = 0;
+    for path in &paths {
+        let scenario = match Scenario::load(path) {
+            Ok(scenario) => scenario,
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                process::exit(2);
+            }
+        };
+        let failures = scenario.run();
+        if failures.is_empty() {
+            println!("PASS {} ({} steps)", scenario.name, scenario.steps.len());
+        } else {
+            println!("FAIL {}", scenario.name);
+            failures.iter().for_each(|f| println!("{}", f));
+            code = 1;
+        }
+    }
+    process::exit(code);
+}
@@ -0,0 +1,412 @@
+//! Non-interactive subcommands for scripting, run against a bank file.
+//!
+//! ```text
+//! banking_app [--bank <file>] <command> [--<flag> <value>]...
+//! ```
+//!
+//! The bank file defaults to `bank.txt`. It is replayed on the storage backend selected by
+//! `BANKING_STORAGE`, and the commands that change the bank append to it. Each command holds
+//! an exclusive lock on `<file>.lock` from loading the bank file to appending to it, so
+//! concurrent commands run one after the other. With `BANKING_STORAGE=sqlite:<path>`, the
+//! commands run against that database instead, and there is no bank file. Either way,
+//! `BANKING_SIGNING_KEY` must be set, except for `migrate --dry-run`.
+//!
+//! Every command except `register` and `migrate` needs
+//! `--user` and `--password`; the password may also be given in the `BANKING_APP_PASSWORD` environment
+//! variable.
+//!
+//! Anyone can `register` a customer. Managers and auditors are registered by a manager, who
+//! gives their own credentials in `--manager` and `--manager-password`; only the first manager
+//! of a bank that has none registers themselves.
+//!
+//! | Command             | Flags                                      | Output                 |
+//! |---------------------|--------------------------------------------|------------------------|
+//! | `register`          | `--role customer\|manager\|auditor`,       | `{}`                   |
+//! |                     | `--manager`, `--manager-password`          |                        |
+//! | `deposit`           | `--amount`, `--reference`                  | `{"balance":x}`        |
+//! | `withdraw`          | `--amount`, `--reference`                  | `{"balance":x}`        |
+//! | `transfer`          | `--to <user ID>`, `--amount`, `--reference`| `{"balance":x}`        |
+//! | `balance`           |                                            | `{"balance":x}`        |
+//! | `events`            | `--of <user ID>`, `--format`               | events                 |
+//! | `report`            | `--format`                                 | bank report            |
+//! | `set-interest-rate` | `--rate`                                   | `{}`                   |
+//! | `pay-interest`      |                                            | `{}`                   |
+//! | `set-tax-rate`      | `--rate`                                   | `{}`                   |
+//! | `take-tax`          |                                            | `{}`                   |
+//! | `advance-clock`     | `--days`                                   | `{}`                   |
+//! | `reconcile`         | `--format`                                 | discrepancies          |
+//! | `migrate`           | `--dry-run`, `--sqlite <file>`             | migration report       |
+//!
+//! `migrate` upgrades the bank file, or with `--sqlite` the SQLite database, to the current
+//! schema version; `--dry-run` takes no value, and only reports what would change. The other
+//! commands refuse a bank file or database of an older version.
+//!
+//! `--format` is `json` (default), `csv` or `table`. Output goes to stdout. Errors are printed
+//! to stderr as `{"error":"<BankingError variant>","message":"<text>"}`, and the process
+//! exits with the code given by `exit_code`. Invalid arguments exit with `USAGE_EXIT_CODE`.
+use crate::bank::Bank;
+use crate::bank_file::{append_operation, load_bank_with_storage, migrate_bank_file, Operation};
+use crate::primitives::*;
+use crate::report::{
+    escape_json, render_discrepancies, render_events, render_report, ReportFormat,
+};
+use crate::storage::{
+    bank_from_env, open_storage, require_signing_key, SqliteStorage, StorageError, STORAGE_ENV,
+};
+use std::{
+    collections::HashMap,
+    env,
+    fs::{File, OpenOptions},
+};
+
+/// Default bank file of the subcommands.
+pub const DEFAULT_BANK_FILE: &str = "bank.txt";
+
+/// Exit code for invalid arguments.
+pub const USAGE_EXIT_CODE: i32 = 2;
+
+/// Returns the exit code for a `BankingError`. Codes are stable: new errors get new codes.
+pub fn exit_code(e: &BankingError) -> i32 {
+    match e {
+        BankingError::Unauthorized => 10,
+        BankingError::InsufficientBalance => 11,
+        BankingError::InvalidAmount => 12,
+        BankingError::FailedLogin => 13,
+        BankingError::NoUserFound => 14,
+        BankingError::AmountTooSmall => 15,
+        BankingError::InvalidUserId => 16,
+        BankingError::InvalidTaxRate => 17,
+        BankingError::InvalidInterestRate => 18,
+        BankingError::UserAlreadyExist => 19,
+        BankingError::InvalidNickname => 20,
+        BankingError::PayeeAlreadyExist => 21,
+        BankingError::NoPayeeFound => 22,
+        BankingError::NoTransferFound => 23,
+        BankingError::InvalidTransferStatus => 24,
+        BankingError::InvalidBatchLine => 25,
+        BankingError::BatchTooLarge => 26,
+        BankingError::FailedToReadFile => 27,
+        BankingError::FailedToWriteFile => 28,
+        BankingError::InvalidReference => 29,
+        BankingError::InvalidCreditorReference => 30,
+        BankingError::InvalidFeeRule => 31,
+        BankingError::NoFeeWaiverFound => 32,
+        BankingError::InvalidLoanProduct => 33,
+        BankingError::NoLoanProductFound => 34,
+        BankingError::NoLoanFound => 35,
+        BankingError::InvalidLoanStatus => 36,
+        BankingError::InvalidTerm => 37,
+        BankingError::NoTermDepositFound => 38,
+        BankingError::InvalidTermDepositStatus => 39,
+        BankingError::TransactionDenied => 40,
+        BankingError::TransactionHeld => 41,
+        BankingError::NoFraudRuleFound => 42,
+        BankingError::NoFraudReviewFound => 43,
+        BankingError::InvalidReviewStatus => 44,
+        BankingError::InvalidComplianceThresholds => 45,
+        BankingError::NoReportFound => 46,
+        BankingError::InvalidReportStatus => 47,
+        BankingError::WatchListMatch => 48,
+        BankingError::InvalidScreeningThresholds => 49,
+        BankingError::NoScreeningHitFound => 50,
+        BankingError::InvalidScreeningHitStatus => 51,
+        BankingError::InvalidProfile => 52,
+        BankingError::NoProfileFound => 53,
+        BankingError::InvalidKycPolicy => 54,
+        BankingError::KycLimitExceeded => 55,
+        BankingError::KycRequired => 56,
+        BankingError::InvalidTolerance => 57,
+        BankingError::InvalidBankFile => 58,
+        BankingError::UnsupportedBankFileVersion => 59,
+        BankingError::ServiceStopped => 60,
+        BankingError::TamperedBankFile => 61,
+        BankingError::OutdatedBankFile => 62,
+        BankingError::StorageFailure => 63,
+    }
+}
+
+/// The result of a subcommand: text for stdout, or the error.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    /// Invalid arguments, with a message for the user.
+    Usage(String),
+    Banking(BankingError),
+    Storage(StorageError),
+}
+
+impl From<BankingError> for CommandError {
+    fn from(e: BankingError) -> Self {
+        CommandError::Banking(e)
+    }
+}
+
+impl From<StorageError> for CommandError {
+    fn from(e: StorageError) -> Self {
+        CommandError::Storage(e)
+    }
+}
+
+// Helper function: Splits `--flag value` pairs.
+fn parse_flags(args: &[String]) -> Result<HashMap<String, String>, CommandError> {
+    let mut flags = HashMap::new();
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let name = flag
+            .strip_prefix("--")
+            .ok_or_else(|| CommandError::Usage(format!("unexpected argument `{}`", flag)))?;
+        let value = args
+            .next()
+            .ok_or_else(|| CommandError::Usage(format!("missing value for `{}`", flag)))?;
+        flags.insert(name.to_string(), value.clone());
+    }
+    Ok(flags)
+}
+
+/// Flags of a subcommand.
+struct Flags(HashMap<String, String>);
+
+impl Flags {
+    fn text(&self, name: &str) -> Result<String, CommandError> {
+        self.0
+            .get(name)
+            .cloned()
+            .ok_or_else(|| CommandError::Usage(format!("missing `--{}`", name)))
+    }
+
+    fn number<T: std::str::FromStr>(&self, name: &str) -> Result<T, CommandError> {
+        self.text(name)?
+            .parse()
+            .map_err(|_| CommandError::Usage(format!("`--{}` must be a number", name)))
+    }
+
+    fn format(&self) -> Result<ReportFormat, CommandError> {
+        match self.0.get("format").map(String::as_str) {
+            None | Some("json") => Ok(ReportFormat::Json),
+            Some("csv") => Ok(ReportFormat::Csv),
+            Some("table") => Ok(ReportFormat::Table),
+            Some(f) => Err(CommandError::Usage(format!("unknown format `{}`", f))),
+        }
+    }
+
+    fn password(&self) -> Result<String, CommandError> {
+        self.text("password")
+            .or_else(|e| env::var("BANKING_APP_PASSWORD").map_err(|_| e))
+    }
+}
+
+/// Runs a subcommand against the bank file at `path`, or the database selected by
+/// `BANKING_STORAGE`, and returns its output.
+pub fn execute(path: &str, command: &str, args: &[String]) -> Result<String, CommandError> {
+    let spec = env::var(STORAGE_ENV).unwrap_or_default();
+    let database = spec.starts_with("sqlite:");
+    // SQLite locks the database itself, for each operation, and the bank reads what it derives
+    // from the database anew inside it.
+    let _lock = if database {
+        None
+    } else {
+        Some(lock_bank_file(path)?)
+    };
+    if !(command == "migrate" && args.iter().any(|a| a == "--dry-run")) {
+        require_signing_key(path)?;
+    }
+    if command == "migrate" {
+        return migrate(path, args);
+    }
+    let flags = Flags(parse_flags(args)?);
+    let (mut bank, path) = if database {
+        let mut bank = bank_from_env()?;
+        bank.set_verbose(false);
+        (bank, None)
+    } else {
+        (
+            load_bank_with_storage(path, open_storage(&spec)?)?,
+            Some(path),
+        )
+    };
+    let username = flags.text("user")?;
+    let password = flags.password()?;
+
+    if command == "register" {
+        let role = match flags.0.get("role").map(|r| r.to_lowercase()).as_deref() {
+            None | Some("customer") => Role::Customer,
+            Some("manager") => Role::Manager,
+            Some("auditor") => Role::Auditor,
+            Some(r) => return Err(CommandError::Usage(format!("unknown role `{}`", r))),
+        };
+        if role != Role::Customer && (role != Role::Manager || bank.has_role(Role::Manager)) {
+            let manager = flags.text("manager")?;
+            let (_, by) = bank.login(manager, flags.text("manager-password")?)?;
+            if by != Role::Manager {
+                return Err(BankingError::Unauthorized.into());
+            }
+        }
+        let operation = Operation::Register {
+            user: Bank::hash(&username, &password),
+            role,
+            username,
+        };
+        return record(&mut bank, path, operation).map(|_| "{}".to_string());
+    }
+
+    let (user, role) = bank.login(username, password)?;
+    let balance = |bank: &Bank| -> Result<String, CommandError> {
+        Ok(format!("{{\"balance\":{}}}", bank.check_balance(user)?))
+    };
+    let reference = || flags.0.get("reference").cloned().unwrap_or_default();
+    match command {
+        "deposit" | "withdraw" => {
+            let amount = flags.number("amount")?;
+            let operation = if command == "deposit" {
+                Operation::Deposit {
+                    user,
+                    amount,
+                    reference: reference(),
+                }
+            } else {
+                Operation::Withdraw {
+                    user,
+                    amount,
+                    reference: reference(),
+                }
+            };
+            record(&mut bank, path, operation)?;
+            balance(&bank)
+        }
+        "transfer" => {
+            let operation = Operation::Transfer {
+                user,
+                to: flags.number("to")?,
+                amount: flags.number("amount")?,
+                reference: reference(),
+            };
+            record(&mut bank, path, operation)?;
+            balance(&bank)
+        }
+        "balance" => balance(&bank),
+        "events" => {
+            let format = flags.format()?;
+            let rows = match (role, flags.0.contains_key("of")) {
+                (Role::Customer, _) => bank.my_events(user)?,
+                (_, true) => bank.user_events(user, role, flags.number("of")?)?,
+                (_, false) => bank.all_events(user, role)?,
+            };
+            Ok(render_events(&rows, format).trim_end().to_string())
+        }
+        "report" => {
+            let format = flags.format()?;
+            Ok(render_report(&bank.report(user)?, format)
+                .trim_end()
+                .to_string())
+        }
+        "set-interest-rate" => {
+            let rate = flags.number("rate")?;
+            record(&mut bank, path, Operation::SetInterestRate { user, rate }).map(|_| "{}".into())
+        }
+        "pay-interest" => {
+            record(&mut bank, path, Operation::PayInterest { user }).map(|_| "{}".into())
+        }
+        "set-tax-rate" => {
+            let rate = flags.number("rate")?;
+            record(&mut bank, path, Operation::SetTaxRate { user, rate }).map(|_| "{}".into())
+        }
+        "take-tax" => record(&mut bank, path, Operation::TakeTax { user }).map(|_| "{}".into()),
+        "advance-clock" => {
+            let duration = flags.number::<Timestamp>("days")? * DAY;
+            record(&mut bank, path, Operation::AdvanceClock { user, duration }).map(|_| "{}".into())
+        }
+        "reconcile" => {
+            // Only reports the discrepancies: unlike `Bank::reconcile`, logs nothing.
+            let format = flags.format()?;
+            if role != Role::Auditor {
+                return Err(BankingError::Unauthorized.into());
+            }
+            let discrepancies = bank.discrepancies(bank.reconciliation_tolerance());
+            Ok(render_discrepancies(&discrepancies, format)
+                .trim_end()
+                .to_string())
+        }
+        _ => Err(CommandError::Usage(format!(
+            "unknown command `{}`",
+            command
+        ))),
+    }
+}
+
+// Helper function: Runs `migrate`, whose `--dry-run` flag takes no value.
+fn migrate(path: &str, args: &[String]) -> Result<String, CommandError> {
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let args = args
+        .iter()
+        .filter(|a| *a != "--dry-run")
+        .cloned()
+        .collect::<Vec<_>>();
+    let flags = parse_flags(&args)?;
+    let report = match flags.get("sqlite") {
+        Some(database) => SqliteStorage::migrate(database, dry_run)?,
+        None => migrate_bank_file(path, dry_run)?,
+    };
+    Ok(report.to_string())
+}
+
+// Helper function: Applies the operation and, if it changed the bank, records it in the bank
+// file at `path`, if any. An operation that fails may still have logged why, such as the
+// overdraft fee of a declined withdrawal, so it is recorded if the log grew.
+fn record(bank: &mut Bank, path: Option<&str>, operation: Operation) -> Result<(), CommandError> {
+    let count = bank.event_count();
+    let result = operation.apply(bank);
+    if let (Some(path), true) = (path, result.is_ok() || bank.event_count() != count) {
+        append_operation(path, bank, &operation)?;
+    }
+    Ok(result?)
+}
+
+// Helper function: Takes an exclusive lock on `<path>.lock`, waiting for other commands to
+// release it. The lock is released when the file is closed.
+fn lock_bank_file(path: &str) -> Result<File, CommandError> {
+    let lock_path = format!("{}.lock", path);
+    let error = |e: std::io::Error| StorageError(format!("cannot lock {}: {}", lock_path, e));
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .map_err(error)?;
+    file.lock().map_err(error)?;
+    Ok(file)
+}
+
+/// Runs the subcommand given by the command line arguments, without the program name. Prints
+/// the output or the error, and returns the exit code.
+pub fn run_command(args: &[String]) -> i32 {
+    let (path, args) = match args {
+        [flag, path, rest @ ..] if flag == "--bank" => (path.as_str(), rest),
+        _ => (DEFAULT_BANK_FILE, args),
+    };
+    let result = match args.split_first() {
+        Some((command, args)) => execute(path, command, args),
+        None => Err(CommandError::Usage("missing command".to_string())),
+    };
+    match result {
+        Ok(output) => {
+            println!("{}", output);
+            0
+        }
+        Err(CommandError::Usage(message)) => {
+            eprintln!("Error: {}", message);
+            eprintln!("Usage: banking_app [--bank <file>] <command> [--<flag> <value>]...");
+            USAGE_EXIT_CODE
+        }
+        Err(CommandError::Storage(e)) => {
+            eprintln!("{}", e);
+            1
+        }
+        Err(CommandError::Banking(e)) => {
+            eprintln!(
+                "{{\"error\":{},\"message\":{}}}",
+                escape_json(&format!("{:?}", e)),
+                escape_json(&e.to_string())
+            );
+            exit_code(&e)
+        }
+    }
+}
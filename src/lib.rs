@@ -0,0 +1,64 @@
+//! Banking application library: the `Bank` with its roles, accounts and event log, and the
+//! front ends built on it. The interactive CLI is the `banking_app` binary, and the HTTP API
+//! is the `banking_server` binary. Given arguments, `banking_app` runs one non-interactive
+//! subcommand against a bank file instead. The `scenario_runner` binary runs declarative
+//! end-to-end scenarios, and `banking_tui` is a full-screen terminal UI. `SharedBank` shares
+//! a `Bank` between threads, and `BankService` queues typed commands for a
+//! single writer. The bank keeps its users, balances and event log in a pluggable `Storage`
+//! backend, in memory or in SQLite. Bank files and SQLite databases carry a schema version, and
+//! are migrated when an older version is opened. The event log is hash chained, with signed
+//! checkpoints, so auditors can verify it was not tampered with.
+mod api;
+mod bank;
+mod bank_file;
+mod batch;
+mod command;
+mod migration;
+mod primitives;
+mod report;
+mod scenario;
+mod service;
+mod shared_bank;
+mod storage;
+mod tui;
+
+pub use api::*;
+pub use bank::{
+    amortization_schedule, entry_hash, name_similarity, normalize_name, parse_watch_list,
+    sign_checkpoint, signing_key_from_env, AmountAnomalyRule, Bank, ComplianceReport,
+    CustomerProfile, FirstTimePayeeRule, FraudReview, FraudRule, Installment, Loan, LoanProduct,
+    Movement, ProfileVersion, RoundTripRule, ScreeningHit, ScreeningThresholds, TermDeposit,
+    VelocityRule, WatchListEntry, CHECKPOINT_INTERVAL, SIGNING_KEY_ENV,
+};
+pub use bank_file::*;
+pub use batch::*;
+pub use command::*;
+pub use migration::*;
+pub use primitives::*;
+pub use report::*;
+pub use scenario::*;
+pub use service::*;
+pub use shared_bank::*;
+pub use storage::*;
+pub use tui::*;
+
+/// The unit tests run once against each storage backend.
+#[cfg(test)]
+#[allow(unused_must_use)]
+mod tests {
+    mod memory {
+        fn new_bank() -> crate::Bank {
+            crate::Bank::default()
+        }
+
+        include!("tests.rs");
+    }
+
+    mod sqlite {
+        fn new_bank() -> crate::Bank {
+            crate::Bank::with_storage(Box::new(crate::SqliteStorage::in_memory().unwrap()))
+        }
+
+        include!("tests.rs");
+    }
+}
@@ -1,13 +1,6 @@
 #![allow(unused_must_use)]
 
-mod bank;
-mod primitives;
-
-#[cfg(test)]
-mod tests;
-
-pub use bank::Bank;
-pub use primitives::*;
+use banking_app::*;
 use std::io;
 
 // Helper function: Prints the error message on failure.
@@ -18,6 +11,65 @@ fn parse_result(res: BankResult<()>) {
     }
 }
 
+// Helper function: Prints the bank report in the format chosen by the user.
+fn print_report(bank: &Bank, user: HashResult) {
+    let format = match read_number::<u32>("Please choose the format: 1.Table; 2.CSV; 3.JSON;") {
+        Some(1) => ReportFormat::Table,
+        Some(2) => ReportFormat::Csv,
+        Some(3) => ReportFormat::Json,
+        _ => return,
+    };
+    match bank.report(user) {
+        Ok(report) => print!("{}", render_report(&report, format)),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+// Helper function: Prints the financial summary as text or JSON.
+fn print_summary(bank: &Bank, user: HashResult) {
+    let json = match read_number::<u32>("Please choose the format: 1.Text; 2.JSON;") {
+        Some(1) => false,
+        Some(2) => true,
+        _ => return,
+    };
+    match bank.financial_summary(user) {
+        Ok(summary) if json => print!("{}", render_summary_json(&summary)),
+        Ok(summary) => print!("{}", render_summary_text(&summary)),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+// Helper function: Prints event rows as a table.
+fn print_events(res: BankResult<Vec<EventRow>>) {
+    match res {
+        Ok(rows) => print!("{}", render_events(&rows, ReportFormat::Table)),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+// Helper function: Reads an optional payment reference. Input that is a valid RF creditor
+// reference is stored as structured remittance information, anything else as a memo.
+fn read_reference() -> BankResult<PaymentReference> {
+    println!("Please input a reference (optional):");
+    let mut reference = String::new();
+    io::stdin().read_line(&mut reference);
+    PaymentReference::parse(&reference)
+}
+
+// Helper function: Asks for a reference and prints the matching events.
+fn search_events(bank: &Bank, user: HashResult) {
+    println!("Please input the reference to search for:");
+    let mut query = String::new();
+    io::stdin().read_line(&mut query);
+    match bank.search_events_by_reference(user, query.trim()) {
+        Ok(events) => {
+            println!("===== Events with reference: {} =====", query.trim());
+            events.iter().for_each(|e| println!("{}", e));
+        }
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
 /// Page used to register a new user.
 fn register_page(bank: &mut Bank) {
     let mut username = String::new();
@@ -76,7 +128,7 @@ fn customer_page(bank: &mut Bank, user: HashResult) {
     let mut user_input = String::new();
     println!("=====  Customer page  =====");
     loop {
-        println!("Please choose: 1.Deposit; 2.Withdraw; 3.Transfer; 4.Change Password; 5.Print Events; 6.Check Balance; 7.Quit;");
+        println!("Please choose: 1.Deposit; 2.Withdraw; 3.Transfer; 4.Change Password; 5.Print Events; 6.Check Balance; 7.Manage Payees; 8.Pending Transfers; 9.Batch Payments; 10.Search Events; 11.Loans; 12.Term Deposits; 13.Profile; 14.Quit;");
         user_input.clear();
         io::stdin().read_line(&mut user_input);
         match user_input.trim() {
@@ -87,7 +139,9 @@ fn customer_page(bank: &mut Bank, user: HashResult) {
                 // Delete the \n from the input
                 amount.pop();
                 match amount.parse::<f64>() {
-                    Ok(converted_amount) => parse_result(bank.deposit(user, converted_amount)),
+                    Ok(converted_amount) => parse_result(read_reference().and_then(|reference| {
+                        bank.deposit_with_reference(user, converted_amount, reference)
+                    })),
                     Err(e) => {
                         println!("Please input a number! {}", e);
                     }
@@ -100,7 +154,9 @@ fn customer_page(bank: &mut Bank, user: HashResult) {
                 // Delete the \n from the input
                 amount.pop();
                 match amount.parse() {
-                    Ok(converted_amount) => parse_result(bank.withdraw(user, converted_amount)),
+                    Ok(converted_amount) => parse_result(read_reference().and_then(|reference| {
+                        bank.withdraw_with_reference(user, converted_amount, reference)
+                    })),
                     Err(_) => {
                         println!("Please input a number!");
                     }
@@ -119,19 +175,43 @@ fn customer_page(bank: &mut Bank, user: HashResult) {
                         continue;
                     }
                 };
-                println!("Please input the ID you want to transfer to:");
-                let mut to_id = String::new();
-                io::stdin().read_line(&mut to_id);
-                // Delete the \n from the input
-                to_id.pop();
-                let converted_id: u64 = match to_id.parse() {
-                    Ok(num) => num,
-                    Err(_) => {
-                        println!("Please input a number!");
+                println!(
+                    "Please input the ID, username or payee nickname you want to transfer to:"
+                );
+                let mut to_user = String::new();
+                io::stdin().read_line(&mut to_user);
+                let (to_id, to_name) = match bank.resolve_payee(user, to_user.trim()) {
+                    Ok(payee) => payee,
+                    Err(e) => {
+                        println!("Error: {}", e);
                         continue;
                     }
                 };
-                parse_result(bank.transfer(user, converted_amount, converted_id));
+                let reference = match read_reference() {
+                    Ok(reference) => reference,
+                    Err(e) => {
+                        println!("Error: {}", e);
+                        continue;
+                    }
+                };
+                let fee = bank
+                    .quote_fee(user, FeeType::Transfer, converted_amount)
+                    .unwrap_or_default();
+                println!(
+                    "Transfer {} to {} (ID: {}) with a fee of {}? y/n",
+                    converted_amount, to_name, to_id, fee
+                );
+                let mut confirm = String::new();
+                io::stdin().read_line(&mut confirm);
+                match confirm.trim() {
+                    "y" | "Y" => parse_result(bank.transfer_with_reference(
+                        user,
+                        converted_amount,
+                        to_id,
+                        reference,
+                    )),
+                    _ => println!("Transfer cancelled."),
+                }
             }
             "4" => {
                 println!("Please inter your new password:");
@@ -141,11 +221,28 @@ fn customer_page(bank: &mut Bank, user: HashResult) {
                 parse_result(bank.change_password(user, change_password));
                 return;
             }
-            "5" => parse_result(bank.print_event(user)),
+            "5" => print_events(bank.my_events(user)),
             "6" => {
-                println!("Current balance is {}", bank.check_balance(user).unwrap());
+                println!(
+                    "Current balance is {}, available balance is {}",
+                    bank.check_balance(user).unwrap(),
+                    bank.check_available_balance(user).unwrap()
+                );
             }
-            "7" => {
+            "7" => payee_page(bank, user),
+            "8" => pending_transfer_page(bank, user),
+            "9" => batch_page(bank, user),
+            "10" => search_events(bank, user),
+            "11" => customer_loan_page(bank, user),
+            "12" => customer_term_deposit_page(bank, user),
+            "13" => match bank.current_user(user) {
+                Ok(u) => {
+                    let id = u.id;
+                    profile_page(bank, user, id)
+                }
+                Err(e) => println!("Error: {}", e),
+            },
+            "14" => {
                 println!("Quit...");
                 return;
             }
@@ -154,16 +251,659 @@ fn customer_page(bank: &mut Bank, user: HashResult) {
     }
 }
 
+/// Page used by a `Customer` to manage their saved payees
+fn payee_page(bank: &mut Bank, user: HashResult) {
+    let mut user_input = String::new();
+    println!("=====  Payee page  =====");
+    loop {
+        println!("Please choose: 1.List Payees; 2.Add Payee; 3.Remove Payee; 4.Back;");
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => match bank.payees(user) {
+                Ok(payees) => payees.iter().for_each(|(payee, username)| {
+                    println!("{}: {} (ID: {})", payee.nickname, username, payee.id)
+                }),
+                Err(e) => println!("Error: {}", e),
+            },
+            "2" => {
+                println!("Please input the username of the payee:");
+                let mut username = String::new();
+                io::stdin().read_line(&mut username);
+                println!("Please input a nickname for the payee:");
+                let mut nickname = String::new();
+                io::stdin().read_line(&mut nickname);
+                parse_result(bank.add_payee(user, username.trim(), nickname));
+            }
+            "3" => {
+                println!("Please input the nickname of the payee to remove:");
+                let mut nickname = String::new();
+                io::stdin().read_line(&mut nickname);
+                parse_result(bank.remove_payee(user, nickname.trim()));
+            }
+            "4" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
+// Helper function: Prints the transfers visible to the current user.
+fn print_transfers(bank: &Bank, user: HashResult) {
+    match bank.list_transfers(user) {
+        Ok(transfers) => transfers.iter().for_each(|t| {
+            println!(
+                "Transfer #{}: {:?} - Amount: {}, From ID: {}, To ID: {}",
+                t.transfer_id, t.status, t.amount, t.from, t.to
+            )
+        }),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+/// Page used by a `Customer` to initiate and cancel pending transfers
+fn pending_transfer_page(bank: &mut Bank, user: HashResult) {
+    let mut user_input = String::new();
+    println!("=====  Pending transfer page  =====");
+    loop {
+        println!(
+            "Please choose: 1.List Transfers; 2.Initiate Transfer; 3.Cancel Transfer; 4.Back;"
+        );
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => print_transfers(bank, user),
+            "2" => {
+                println!("Please input how much money you want to transfer:");
+                let mut amount = String::new();
+                io::stdin().read_line(&mut amount);
+                let converted_amount: f64 = match amount.trim().parse() {
+                    Ok(num) => num,
+                    Err(_) => {
+                        println!("Please input a number!");
+                        continue;
+                    }
+                };
+                println!("Please input the ID you want to transfer to:");
+                let mut to_id = String::new();
+                io::stdin().read_line(&mut to_id);
+                match to_id.trim().parse() {
+                    Ok(num) => match bank.initiate_transfer(user, converted_amount, num) {
+                        Ok(transfer_id) => println!("Transfer #{} is pending.", transfer_id),
+                        Err(e) => println!("Error: {}", e),
+                    },
+                    Err(_) => println!("Please input a number!"),
+                };
+            }
+            "3" => {
+                println!("Please input the transfer ID to cancel:");
+                let mut transfer_id = String::new();
+                io::stdin().read_line(&mut transfer_id);
+                match transfer_id.trim().parse() {
+                    Ok(num) => parse_result(bank.cancel_transfer(user, num)),
+                    Err(_) => println!("Please input a number!"),
+                };
+            }
+            "4" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
+/// Page used by a `Customer` to pay a CSV batch file
+fn batch_page(bank: &mut Bank, user: HashResult) {
+    println!("=====  Batch payment page  =====");
+    println!("Please input the path of the batch file (payee,amount,reference):");
+    let mut input_path = String::new();
+    io::stdin().read_line(&mut input_path);
+    println!("Please input the path to write the results to:");
+    let mut output_path = String::new();
+    io::stdin().read_line(&mut output_path);
+    println!("Please choose: 1.All or nothing; 2.Best effort;");
+    let mut mode = String::new();
+    io::stdin().read_line(&mut mode);
+    let mode = match mode.trim() {
+        "1" => BatchMode::AllOrNothing,
+        "2" => BatchMode::BestEffort,
+        _ => {
+            println!("Invalid input.");
+            return;
+        }
+    };
+    match process_batch_file(bank, user, input_path.trim(), output_path.trim(), mode) {
+        Ok(results) => println!(
+            "{} of {} payments paid, results are written to {}.",
+            results
+                .iter()
+                .filter(|r| r.status == BatchStatus::Paid)
+                .count(),
+            results.len(),
+            output_path.trim()
+        ),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+/// Page used by a `Manager` to settle and reverse transfers
+fn manage_transfer_page(bank: &mut Bank, user: HashResult) {
+    let mut user_input = String::new();
+    println!("=====  Transfer page  =====");
+    loop {
+        println!("Please choose: 1.List Transfers; 2.Settle Transfer; 3.Reverse Transfer; 4.Back;");
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => print_transfers(bank, user),
+            "2" | "3" => {
+                println!("Please input the transfer ID:");
+                let mut transfer_id = String::new();
+                io::stdin().read_line(&mut transfer_id);
+                match (user_input.trim(), transfer_id.trim().parse()) {
+                    ("2", Ok(num)) => parse_result(bank.settle_transfer(user, num)),
+                    (_, Ok(num)) => parse_result(bank.reverse_transfer(user, num)),
+                    (_, Err(_)) => println!("Please input a number!"),
+                };
+            }
+            "4" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
+// Helper function: Reads a line and parses it. Prints a message and returns `None` on failure.
+fn read_number<T: std::str::FromStr>(prompt: &str) -> Option<T> {
+    println!("{}", prompt);
+    let mut input = String::new();
+    io::stdin().read_line(&mut input);
+    match input.trim().parse() {
+        Ok(num) => Some(num),
+        Err(_) => {
+            println!("Please input a number!");
+            None
+        }
+    }
+}
+
+// Helper function: Reads a fee type. 0 selects all fee types, if allowed.
+fn read_fee_type(allow_all: bool) -> Option<Option<FeeType>> {
+    let prompt = format!(
+        "Please choose the fee type: {}1.Withdrawal; 2.Transfer; 3.Overdraft; 4.Monthly maintenance; 5.Below minimum balance; 6.Late payment; 7.Early withdrawal;",
+        if allow_all { "0.All; " } else { "" }
+    );
+    match read_number::<usize>(&prompt)? {
+        0 if allow_all => Some(None),
+        n if (1..=FeeType::ALL.len()).contains(&n) => Some(Some(FeeType::ALL[n - 1])),
+        _ => {
+            println!("Invalid input.");
+            None
+        }
+    }
+}
+
+/// Page used by a `Manager` to manage fees
+fn fee_page(bank: &mut Bank, user: HashResult) {
+    let mut user_input = String::new();
+    println!("=====  Fee page  =====");
+    loop {
+        println!("Please choose: 1.Show fee schedule; 2.Set fee rule; 3.Set minimum balance; 4.Grant fee waiver; 5.Revoke fee waiver; 6.Charge monthly fees; 7.Back;");
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => {
+                bank.fee_schedule().iter().for_each(|(fee_type, rule)| {
+                    println!(
+                        "{:?} - Flat: {}, Percentage: {}, Min: {}, Max: {:?}",
+                        fee_type, rule.flat, rule.percentage, rule.min, rule.max
+                    )
+                });
+                match bank.fee_income(user) {
+                    Ok(income) => println!("Bank income account: {}", income),
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+            "2" => {
+                let fee_type = match read_fee_type(false) {
+                    Some(Some(fee_type)) => fee_type,
+                    _ => continue,
+                };
+                let rule = (|| {
+                    let flat = read_number("Please input the flat fee:")?;
+                    let percentage = read_number("Please input the percentage (0 to 1):")?;
+                    let min = read_number("Please input the minimum fee:")?;
+                    println!("Please input the maximum fee (empty for no maximum):");
+                    let mut max = String::new();
+                    io::stdin().read_line(&mut max);
+                    let max = match max.trim() {
+                        "" => None,
+                        m => Some(m.parse().ok()?),
+                    };
+                    Some(FeeRule {
+                        flat,
+                        percentage,
+                        min,
+                        max,
+                    })
+                })();
+                match rule {
+                    Some(rule) => parse_result(bank.set_fee_rule(user, fee_type, rule)),
+                    None => println!("Invalid fee rule."),
+                }
+            }
+            "3" => {
+                if let Some(amount) = read_number("Please input the minimum balance:") {
+                    parse_result(bank.set_minimum_balance(user, amount));
+                }
+            }
+            "4" | "5" => {
+                let customer = match read_number("Please input the customer ID:") {
+                    Some(customer) => customer,
+                    None => continue,
+                };
+                if let Some(fee_type) = read_fee_type(true) {
+                    if user_input.trim() == "4" {
+                        parse_result(bank.grant_fee_waiver(user, customer, fee_type));
+                    } else {
+                        parse_result(bank.revoke_fee_waiver(user, customer, fee_type));
+                    }
+                }
+            }
+            "6" => parse_result(bank.charge_monthly_fees(user)),
+            "7" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
+// Helper function: Prints the loan products.
+fn print_loan_products(bank: &Bank) {
+    bank.loan_products().iter().for_each(|p| {
+        println!(
+            "Product #{}: {} - Annual Rate: {}, Installments: {}, {:?}, {:?}",
+            p.product_id, p.name, p.annual_rate, p.term, p.frequency, p.method
+        )
+    });
+}
+
+// Helper function: Prints the loans visible to the current user with their schedules.
+fn print_loans(bank: &Bank, user: HashResult) {
+    match bank.list_loans(user) {
+        Ok(loans) => loans.iter().for_each(|l| {
+            println!(
+                "Loan #{}: {:?} - Borrower ID: {}, Product: {}, Principal: {}, Outstanding: {}",
+                l.loan_id,
+                l.status,
+                l.borrower,
+                l.product_id,
+                l.principal,
+                l.outstanding_principal()
+            );
+            l.schedule.iter().for_each(|i| {
+                println!(
+                    "  {}. Due Day {} - Principal: {}, Interest: {}, Late Fee: {}, Paid: {}",
+                    i.number,
+                    i.due / DAY,
+                    i.principal,
+                    i.interest,
+                    i.late_fee,
+                    i.paid
+                )
+            });
+        }),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+/// Page used by a `Customer` to apply for loans
+fn customer_loan_page(bank: &mut Bank, user: HashResult) {
+    let mut user_input = String::new();
+    println!("=====  Loan page  =====");
+    loop {
+        println!("Please choose: 1.List Loan Products; 2.Apply for Loan; 3.My Loans; 4.Back;");
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => print_loan_products(bank),
+            "2" => {
+                let product_id = match read_number("Please input the loan product ID:") {
+                    Some(product_id) => product_id,
+                    None => continue,
+                };
+                if let Some(principal) = read_number("Please input how much you want to borrow:") {
+                    match bank.apply_for_loan(user, product_id, principal) {
+                        Ok(loan_id) => println!("Loan #{} is waiting for approval.", loan_id),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                }
+            }
+            "3" => print_loans(bank, user),
+            "4" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
+// Helper function: Prints the term deposits visible to the current user.
+fn print_term_deposits(bank: &Bank, user: HashResult) {
+    match bank.list_term_deposits(user) {
+        Ok(deposits) => deposits.iter().for_each(|d| {
+            println!(
+                "Term Deposit #{}: {:?} - Owner ID: {}, Principal: {}, Rate: {}, Term: {} months, Matures: Day {}, Interest: {:?}, At Maturity: {:?}, Interest Paid: {}",
+                d.deposit_id,
+                d.status,
+                d.owner,
+                d.principal,
+                d.rate,
+                d.term,
+                d.matures_at / DAY,
+                d.payout,
+                d.instruction,
+                d.interest_paid
+            )
+        }),
+        Err(e) => println!("Error: {}", e),
+    }
+}
+
+/// Page used by a `Customer` to open and break term deposits
+fn customer_term_deposit_page(bank: &mut Bank, user: HashResult) {
+    let mut user_input = String::new();
+    println!("=====  Term deposit page  =====");
+    loop {
+        println!(
+            "Please choose: 1.My Term Deposits; 2.Open Term Deposit; 3.Break Term Deposit; 4.Back;"
+        );
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => print_term_deposits(bank, user),
+            "2" => {
+                println!("Current term deposit rate is {}", bank.term_deposit_rate());
+                let deposit = (|| {
+                    let amount = read_number("Please input how much you want to deposit:")?;
+                    let term = read_number("Please input the term in months:")?;
+                    let payout =
+                        match read_number::<u32>("Pay interest: 1.At maturity; 2.Monthly;")? {
+                            1 => InterestPayout::AtMaturity,
+                            2 => InterestPayout::Monthly,
+                            _ => return None,
+                        };
+                    let instruction =
+                        match read_number::<u32>("At maturity: 1.Pay out; 2.Roll over;")? {
+                            1 => MaturityInstruction::PayOut,
+                            2 => MaturityInstruction::RollOver,
+                            _ => return None,
+                        };
+                    Some((amount, term, payout, instruction))
+                })();
+                match deposit {
+                    Some((amount, term, payout, instruction)) => {
+                        match bank.open_term_deposit(user, amount, term, payout, instruction) {
+                            Ok(deposit_id) => println!("Term deposit #{} is opened.", deposit_id),
+                            Err(e) => println!("Error: {}", e),
+                        }
+                    }
+                    None => println!("Invalid term deposit."),
+                }
+            }
+            "3" => {
+                if let Some(deposit_id) = read_number("Please input the term deposit ID:") {
+                    match bank.break_term_deposit(user, deposit_id) {
+                        Ok(amount) => println!("{} is paid into your balance.", amount),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                }
+            }
+            "4" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
+/// Page used by a `Manager` to set the term deposit rate
+fn manager_term_deposit_page(bank: &mut Bank, user: HashResult) {
+    let mut user_input = String::new();
+    println!("=====  Term deposit page  =====");
+    loop {
+        println!("Please choose: 1.List Term Deposits; 2.Set term deposit rate; 3.Back;");
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => print_term_deposits(bank, user),
+            "2" => {
+                if let Some(rate) = read_number("Please input the annual term deposit rate:") {
+                    parse_result(bank.set_term_deposit_rate(user, rate));
+                }
+            }
+            "3" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
+// Helper function: Reads a customer profile from the user.
+fn read_profile() -> CustomerProfile {
+    let read = |prompt: &str| {
+        println!("{}", prompt);
+        let mut input = String::new();
+        io::stdin().read_line(&mut input);
+        input.trim().to_string()
+    };
+    CustomerProfile {
+        legal_name: read("Please input the legal name:"),
+        date_of_birth: read("Please input the date of birth (YYYY-MM-DD):"),
+        address: read("Please input the address:"),
+        email: read("Please input the email:"),
+        phone: read("Please input the phone number:"),
+    }
+}
+
+// Helper function: Prints a version of a customer profile.
+fn print_profile_version(v: &ProfileVersion) {
+    let p = &v.profile;
+    println!(
+        "Version {} (By: {}, Day {}) - Name: {}, Born: {}, Address: {}, Email: {}, Phone: {}",
+        v.version,
+        v.by,
+        v.at / DAY,
+        p.legal_name,
+        p.date_of_birth,
+        p.address,
+        p.email,
+        p.phone
+    );
+}
+
+/// Page used to view and edit a customer's profile
+fn profile_page(bank: &mut Bank, user: HashResult, customer: UserId) {
+    let mut user_input = String::new();
+    println!("=====  Profile page  =====");
+    loop {
+        println!("Please choose: 1.Show Profile; 2.Show History; 3.Edit Profile; 4.Back;");
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => {
+                println!("KYC status: {:?}", bank.kyc_status(customer));
+                match bank.profile(user, customer) {
+                    Ok(v) => print_profile_version(&v),
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+            "2" => match bank.profile_history(user, customer) {
+                Ok(versions) => versions.iter().for_each(print_profile_version),
+                Err(e) => println!("Error: {}", e),
+            },
+            "3" => match bank.update_profile(user, customer, read_profile()) {
+                Ok(version) => println!("Profile version {} is saved.", version),
+                Err(e) => println!("Error: {}", e),
+            },
+            "4" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
+/// Page used by a `Manager` to manage customer profiles and KYC
+fn kyc_page(bank: &mut Bank, user: HashResult) {
+    let mut user_input = String::new();
+    println!("=====  KYC page  =====");
+    loop {
+        println!("Please choose: 1.Customer Profile; 2.Set KYC Status; 3.Set KYC Policy; 4.Back;");
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => {
+                if let Some(customer) = read_number("Please input the customer ID:") {
+                    profile_page(bank, user, customer);
+                }
+            }
+            "2" => {
+                let customer = match read_number("Please input the customer ID:") {
+                    Some(customer) => customer,
+                    None => continue,
+                };
+                let status = match read_number::<u32>(
+                    "Please choose: 1.Unverified; 2.Pending; 3.Verified; 4.Rejected;",
+                ) {
+                    Some(1) => KycStatus::Unverified,
+                    Some(2) => KycStatus::Pending,
+                    Some(3) => KycStatus::Verified,
+                    Some(4) => KycStatus::Rejected,
+                    _ => continue,
+                };
+                parse_result(bank.set_kyc_status(user, customer, status));
+            }
+            "3" => {
+                println!("Current KYC policy is {:?}", bank.kyc_policy());
+                let policy = match read_number::<u32>(
+                    "Please choose: 1.Unrestricted; 2.Limit; 3.No transfers;",
+                ) {
+                    Some(1) => KycPolicy::Unrestricted,
+                    Some(2) => match read_number("Please input the limit:") {
+                        Some(limit) => KycPolicy::Limit(limit),
+                        None => continue,
+                    },
+                    Some(3) => KycPolicy::NoTransfers,
+                    _ => continue,
+                };
+                parse_result(bank.set_kyc_policy(user, policy));
+            }
+            "4" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
+/// Page used by a `Manager` to print and generate monthly statements
+fn statement_page(bank: &Bank, user: HashResult) {
+    let mut user_input = String::new();
+    println!("=====  Statement page  =====");
+    loop {
+        println!("Current month is {}.", bank.now() / MONTH);
+        println!(
+            "Please choose: 1.Print a customer's statement; 2.Generate all statements; 3.Back;"
+        );
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => {
+                let (customer, month) = match (
+                    read_number("Please input the customer ID:"),
+                    read_number("Please input the month:"),
+                ) {
+                    (Some(customer), Some(month)) => (customer, month),
+                    _ => continue,
+                };
+                match bank.statement(user, customer, month) {
+                    Ok(statement) => print!("{}", render_statement_text(&statement)),
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+            "2" => {
+                let month = match read_number("Please input the month:") {
+                    Some(month) => month,
+                    None => continue,
+                };
+                println!("Please input the directory:");
+                let mut dir = String::new();
+                io::stdin().read_line(&mut dir);
+                match bank.generate_statements(user, month, dir.trim()) {
+                    Ok(statements) => println!("{} statements are written.", statements.len()),
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+            "3" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
+/// Page used by a `Manager` to manage loan products and approve loans
+fn manager_loan_page(bank: &mut Bank, user: HashResult) {
+    let mut user_input = String::new();
+    println!("=====  Loan page  =====");
+    loop {
+        println!("Please choose: 1.List Loan Products; 2.Create Loan Product; 3.List Loans; 4.Approve Loan; 5.Reject Loan; 6.Back;");
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => print_loan_products(bank),
+            "2" => {
+                println!("Please input the name of the loan product:");
+                let mut name = String::new();
+                io::stdin().read_line(&mut name);
+                let product = (|| {
+                    let rate = read_number("Please input the annual interest rate:")?;
+                    let term = read_number("Please input the number of installments:")?;
+                    let frequency = match read_number::<u32>("Please choose: 1.Weekly; 2.Monthly;")?
+                    {
+                        1 => RepaymentFrequency::Weekly,
+                        2 => RepaymentFrequency::Monthly,
+                        _ => return None,
+                    };
+                    let method =
+                        match read_number::<u32>("Please choose: 1.Annuity; 2.Equal principal;")? {
+                            1 => AmortizationMethod::Annuity,
+                            2 => AmortizationMethod::EqualPrincipal,
+                            _ => return None,
+                        };
+                    Some((rate, term, frequency, method))
+                })();
+                match product {
+                    Some((rate, term, frequency, method)) => {
+                        match bank.create_loan_product(user, name, rate, term, frequency, method) {
+                            Ok(product_id) => println!("Loan product #{} is created.", product_id),
+                            Err(e) => println!("Error: {}", e),
+                        }
+                    }
+                    None => println!("Invalid loan product."),
+                }
+            }
+            "3" => print_loans(bank, user),
+            "4" | "5" => {
+                if let Some(loan_id) = read_number("Please input the loan ID:") {
+                    if user_input.trim() == "4" {
+                        parse_result(bank.approve_loan(user, loan_id));
+                    } else {
+                        parse_result(bank.reject_loan(user, loan_id));
+                    }
+                }
+            }
+            "6" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
 /// Page used for users of `Manager` role
 fn manager_page(bank: &mut Bank, user: HashResult) {
     let mut user_input = String::new();
     println!("=====  Manager page  =====");
     loop {
-        println!("Please choose: 1.Report; 2.Set interest rate; 3.Pay interest; 4.Change Password; 5.Print a user's events; 6.Print all events; 7.Quit;");
+        println!("Please choose: 1.Report; 2.Set interest rate; 3.Pay interest; 4.Change Password; 5.Print a user's events; 6.Print all events; 7.Transfers; 8.Search Events; 9.Fees; 10.Loans; 11.Advance Clock; 12.Term Deposits; 13.Customer Profiles; 14.Statements; 15.Summary; 16.Quit;");
         user_input.clear();
         io::stdin().read_line(&mut user_input);
         match user_input.trim() {
-            "1" => parse_result(bank.report(user)),
+            "1" => print_report(bank, user),
             "2" => {
                 println!("Please inter the interest rate:");
                 let mut interest_rate = String::new();
@@ -192,14 +932,27 @@ fn manager_page(bank: &mut Bank, user: HashResult) {
                 // Delete the \n from the input
                 user_id.pop();
                 match user_id.parse() {
-                    Ok(num) => parse_result(bank.print_a_user_event(user, Role::Manager, num)),
+                    Ok(num) => print_events(bank.user_events(user, Role::Manager, num)),
                     Err(_) => {
                         println!("Please input a number!");
                     }
                 };
             }
-            "6" => parse_result(bank.print_all_events(user, Role::Manager)),
-            "7" => {
+            "6" => print_events(bank.all_events(user, Role::Manager)),
+            "7" => manage_transfer_page(bank, user),
+            "8" => search_events(bank, user),
+            "9" => fee_page(bank, user),
+            "10" => manager_loan_page(bank, user),
+            "11" => {
+                if let Some(days) = read_number::<u64>("Please input how many days to advance:") {
+                    parse_result(bank.advance_clock(user, days * DAY));
+                }
+            }
+            "12" => manager_term_deposit_page(bank, user),
+            "13" => kyc_page(bank, user),
+            "14" => statement_page(bank, user),
+            "15" => print_summary(bank, user),
+            "16" => {
                 println!("Quit...");
                 return;
             }
@@ -208,16 +961,277 @@ fn manager_page(bank: &mut Bank, user: HashResult) {
     }
 }
 
+// Helper function: Reads the settings of a fraud rule from the user.
+fn read_fraud_rule() -> Option<Box<dyn FraudRule>> {
+    let rule: Box<dyn FraudRule> = match read_number::<u32>(
+        "Please choose: 1.Velocity; 2.Amount anomaly; 3.First-time payee; 4.Round trip;",
+    )? {
+        1 => Box::new(VelocityRule {
+            max_count: read_number("Please input the maximum number of movements:")?,
+            window: read_number::<u64>("Please input the window in minutes:")? * MINUTE,
+        }),
+        2 => Box::new(AmountAnomalyRule {
+            multiplier: read_number("Please input the multiple of the average amount:")?,
+            min_history: read_number("Please input the minimum number of past movements:")?,
+        }),
+        3 => Box::new(FirstTimePayeeRule {
+            threshold: read_number("Please input the threshold:")?,
+        }),
+        4 => Box::new(RoundTripRule {
+            window: read_number::<u64>("Please input the window in minutes:")? * MINUTE,
+        }),
+        _ => {
+            println!("Invalid input.");
+            return None;
+        }
+    };
+    Some(rule)
+}
+
+/// Page used by an `Auditor` to manage fraud rules and review held movements
+fn fraud_page(bank: &mut Bank, user: HashResult) {
+    let mut user_input = String::new();
+    println!("=====  Fraud check page  =====");
+    loop {
+        println!("Please choose: 1.List Rules; 2.Add Rule; 3.Remove Rule; 4.List Reviews; 5.Approve; 6.Reject; 7.Back;");
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => bank.fraud_rules().iter().for_each(|r| println!("{}", r)),
+            "2" => {
+                if let Some(rule) = read_fraud_rule() {
+                    parse_result(bank.add_fraud_rule(user, rule));
+                }
+            }
+            "3" => {
+                println!("Please input the name of the rule:");
+                let mut name = String::new();
+                io::stdin().read_line(&mut name);
+                parse_result(bank.remove_fraud_rule(user, name.trim()));
+            }
+            "4" => match bank.list_fraud_reviews(user) {
+                Ok(reviews) => reviews.iter().for_each(|r| {
+                    let m = r.movement;
+                    print!(
+                        "Review #{}: {:?} - From ID: {}, Amount: {}, Rule: {}",
+                        r.review_id, r.status, m.from, m.amount, r.rule
+                    );
+                    match m.to {
+                        Some(to) => println!(", To ID: {}", to),
+                        None => println!(", Withdrawal"),
+                    }
+                }),
+                Err(e) => println!("Error: {}", e),
+            },
+            "5" | "6" => {
+                if let Some(review_id) = read_number("Please input the review ID:") {
+                    if user_input.trim() == "5" {
+                        parse_result(bank.approve_fraud_review(user, review_id));
+                    } else {
+                        parse_result(bank.reject_fraud_review(user, review_id));
+                    }
+                }
+            }
+            "7" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
+/// Page used by an `Auditor` to reconcile the balances with the event log, and verify the log
+fn reconciliation_page(bank: &mut Bank, user: HashResult) {
+    let mut user_input = String::new();
+    println!("=====  Reconciliation page  =====");
+    loop {
+        println!("Please choose: 1.Reconcile; 2.Set Tolerance; 3.Verify Event Log; 4.Back;");
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => match bank.reconcile(user) {
+                Ok(discrepancies) if discrepancies.is_empty() => {
+                    println!("All balances match the event log.")
+                }
+                Ok(discrepancies) => print!(
+                    "{}",
+                    render_discrepancies(&discrepancies, ReportFormat::Table)
+                ),
+                Err(e) => println!("Error: {}", e),
+            },
+            "2" => {
+                println!("Current tolerance is {}", bank.reconciliation_tolerance());
+                if let Some(tolerance) = read_number("Please input the tolerance:") {
+                    parse_result(bank.set_reconciliation_tolerance(user, tolerance));
+                }
+            }
+            "3" => match bank.verify_log(user) {
+                Ok(LogVerification {
+                    entries,
+                    checkpoints,
+                    issue: None,
+                }) => println!(
+                    "The event log is intact: {} entries, {} checkpoints.",
+                    entries, checkpoints
+                ),
+                Ok(LogVerification {
+                    issue: Some(issue), ..
+                }) => println!("The event log was tampered with. {}", issue),
+                Err(e) => println!("Error: {}", e),
+            },
+            "4" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
+/// Page used by an `Auditor` to detect, review and file compliance reports
+fn compliance_page(bank: &mut Bank, user: HashResult) {
+    let mut user_input = String::new();
+    println!("=====  Compliance page  =====");
+    loop {
+        println!("Please choose: 1.Show Thresholds; 2.Set Thresholds; 3.Run Detection; 4.List Reports; 5.Mark Reviewed; 6.Mark Filed; 7.Export Report; 8.Back;");
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => {
+                let t = bank.compliance_thresholds();
+                println!(
+                    "Cash threshold: {}, Structuring: {} deposits within {} of the threshold in {} days",
+                    t.cash_threshold,
+                    t.structuring_count,
+                    t.structuring_margin,
+                    t.structuring_window / DAY
+                );
+            }
+            "2" => {
+                let thresholds = (|| {
+                    Some(ComplianceThresholds {
+                        cash_threshold: read_number("Please input the cash threshold:")?,
+                        structuring_margin: read_number(
+                            "Please input the structuring margin below the threshold (0-1):",
+                        )?,
+                        structuring_count: read_number(
+                            "Please input the number of deposits that raises an alert:",
+                        )?,
+                        structuring_window: read_number::<u64>("Please input the window in days:")?
+                            * DAY,
+                    })
+                })();
+                if let Some(thresholds) = thresholds {
+                    parse_result(bank.set_compliance_thresholds(user, thresholds));
+                }
+            }
+            "3" => match bank.detect_compliance_issues(user) {
+                Ok(report_ids) => println!("{} new reports.", report_ids.len()),
+                Err(e) => println!("Error: {}", e),
+            },
+            "4" => match bank.list_compliance_reports(user) {
+                Ok(reports) => reports.iter().for_each(|r| {
+                    println!(
+                        "Report #{}: {:?} - {:?}, User ID: {}, Amount: {}, Events: {}",
+                        r.report_id,
+                        r.status,
+                        r.kind,
+                        r.id,
+                        r.amount,
+                        r.events.len()
+                    )
+                }),
+                Err(e) => println!("Error: {}", e),
+            },
+            "5" | "6" => {
+                if let Some(report_id) = read_number("Please input the report ID:") {
+                    let status = if user_input.trim() == "5" {
+                        ReportStatus::Reviewed
+                    } else {
+                        ReportStatus::Filed
+                    };
+                    parse_result(bank.set_report_status(user, report_id, status));
+                }
+            }
+            "7" => {
+                let report_id = match read_number("Please input the report ID:") {
+                    Some(report_id) => report_id,
+                    None => continue,
+                };
+                println!("Please input the path of the file to write:");
+                let mut path = String::new();
+                io::stdin().read_line(&mut path);
+                parse_result(bank.export_compliance_report(user, report_id, path.trim()));
+            }
+            "8" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
+/// Page used by an `Auditor` to load the watch list and clear false positives
+fn watch_list_page(bank: &mut Bank, user: HashResult) {
+    let mut user_input = String::new();
+    println!("=====  Watch list page  =====");
+    loop {
+        println!("Please choose: 1.Show Watch List; 2.Load Watch List; 3.Set Thresholds; 4.List Hits; 5.Clear Hit; 6.Back;");
+        user_input.clear();
+        io::stdin().read_line(&mut user_input);
+        match user_input.trim() {
+            "1" => bank
+                .watch_list()
+                .iter()
+                .for_each(|e| println!("{} (Aliases: {})", e.name, e.aliases.join(", "))),
+            "2" => {
+                println!("Please input the path of the watch list file:");
+                let mut path = String::new();
+                io::stdin().read_line(&mut path);
+                match bank.load_watch_list(user, path.trim()) {
+                    Ok(entries) => println!("{} entries loaded.", entries),
+                    Err(e) => println!("Error: {}", e),
+                }
+            }
+            "3" => {
+                let thresholds = (|| {
+                    Some(ScreeningThresholds {
+                        flag: read_number("Please input the similarity to flag (0-1):")?,
+                        block: read_number("Please input the similarity to block (0-1):")?,
+                    })
+                })();
+                if let Some(thresholds) = thresholds {
+                    parse_result(bank.set_screening_thresholds(user, thresholds));
+                }
+            }
+            "4" => match bank.list_screening_hits(user) {
+                Ok(hits) => hits.iter().for_each(|h| {
+                    println!(
+                        "Hit #{}: {} - Name: {}, Entry: {}, Score: {:.2}, Cleared: {}",
+                        h.hit_id,
+                        if h.blocked { "Blocked" } else { "Flagged" },
+                        h.name,
+                        h.entry,
+                        h.score,
+                        h.cleared
+                    )
+                }),
+                Err(e) => println!("Error: {}", e),
+            },
+            "5" => {
+                if let Some(hit_id) = read_number("Please input the hit ID:") {
+                    parse_result(bank.clear_screening_hit(user, hit_id));
+                }
+            }
+            "6" => return,
+            _ => println!("Invalid input. Please try again."),
+        }
+    }
+}
+
 /// Page used for users of `Auditor` role
 fn auditor_page(bank: &mut Bank, user: HashResult) {
     let mut user_input = String::new();
     println!("=====  Auditor page  =====");
     loop {
-        println!("Please choose: 1.Report; 2.Set tax rate; 3.Take tax; 4.Change Password; 5.Print a user's events; 6.Print all events; 7.Quit;");
+        println!("Please choose: 1.Report; 2.Set tax rate; 3.Take tax; 4.Change Password; 5.Print a user's events; 6.Print all events; 7.Search Events; 8.Fraud Checks; 9.Compliance Reports; 10.Watch List; 11.Reconciliation; 12.Quit;");
         user_input.clear();
         io::stdin().read_line(&mut user_input);
         match user_input.trim() {
-            "1" => parse_result(bank.report(user)),
+            "1" => print_report(bank, user),
             "2" => {
                 println!("Please inter the tax rate:");
                 let mut tax_rate = String::new();
@@ -246,14 +1260,19 @@ fn auditor_page(bank: &mut Bank, user: HashResult) {
                 // Delete the \n from the input
                 user_id.pop();
                 match user_id.parse() {
-                    Ok(num) => parse_result(bank.print_a_user_event(user, Role::Auditor, num)),
+                    Ok(num) => print_events(bank.user_events(user, Role::Auditor, num)),
                     Err(_) => {
                         println!("Please input a number!");
                     }
                 };
             }
-            "6" => parse_result(bank.print_all_events(user, Role::Auditor)),
-            "7" => {
+            "6" => print_events(bank.all_events(user, Role::Auditor)),
+            "7" => search_events(bank, user),
+            "8" => fraud_page(bank, user),
+            "9" => compliance_page(bank, user),
+            "10" => watch_list_page(bank, user),
+            "11" => reconciliation_page(bank, user),
+            "12" => {
                 println!("Quit...");
                 return;
             }
@@ -263,8 +1282,7 @@ fn auditor_page(bank: &mut Bank, user: HashResult) {
 }
 
 /// Main CLI page.
-fn cli() {
-    let mut bank = Bank::default();
+fn cli(mut bank: Bank) {
     let mut user_input = String::new();
     loop {
         println!("Welcome to ANZ bank!");
@@ -293,5 +1311,16 @@ fn cli() {
 }
 
 fn main() {
-    cli();
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.is_empty() {
+        match bank_from_env() {
+            Ok(bank) => cli(bank),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        std::process::exit(run_command(&args));
+    }
 }
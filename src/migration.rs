@@ -0,0 +1,126 @@
+//! Schema versions and migrations of persisted banks.
+//!
+//! Every persisted format carries the version of its schema: bank files in their header line,
+//! SQLite databases in `PRAGMA user_version`. Each format registers its migrations as ordered
+//! steps, the step `from` upgrading data of version `from` to `from + 1`. Data of an older
+//! version is never opened: migrating it, an explicit step the operator takes, applies the
+//! missing steps in order, after copying the original to `<path>.v<version>.bak`. A dry run
+//! applies the steps without saving anything, and reports what would change.
+//!
+//! | Format    | Version | Change                                                |
+//! |-----------|---------|-------------------------------------------------------|
+//! | bank file | 1       | First version                                         |
+//! | bank file | 2       | Tabs, line breaks and backslashes in text are escaped |
+//! | bank file | 3       | Every line is sealed with the signing key             |
+//! | SQLite    | 1       | First version                                         |
+//! | SQLite    | 2       | Events are hash chained, with signed checkpoints      |
+use std::fmt;
+
+/// A step upgrading data of type `T` from version `from` to `from + 1`.
+pub struct Migration<T> {
+    pub from: u32,
+    pub description: &'static str,
+    /// Upgrades the data, and returns a description of every change.
+    pub apply: fn(&mut T) -> Result<Vec<String>, String>,
+}
+
+/// A migration step that was applied, or would be applied by a dry run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStep {
+    pub from: u32,
+    pub description: String,
+    pub changes: Vec<String>,
+}
+
+/// What migrating persisted data did, or would do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationReport {
+    /// The data that was migrated, e.g. its path.
+    pub target: String,
+    pub from: u32,
+    pub to: u32,
+    pub steps: Vec<MigrationStep>,
+    /// Where the original was copied to. `None` if nothing was saved.
+    pub backup: Option<String>,
+    pub dry_run: bool,
+}
+
+impl MigrationReport {
+    /// Returns a report of data that is up to date.
+    pub fn current(target: &str, version: u32) -> Self {
+        Self {
+            target: target.to_string(),
+            from: version,
+            to: version,
+            steps: vec![],
+            backup: None,
+            dry_run: false,
+        }
+    }
+
+    /// Returns true if the data was, or would be, changed.
+    pub fn is_upgrade(&self) -> bool {
+        self.from != self.to
+    }
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.is_upgrade() {
+            return write!(f, "{} is up to date (version {})", self.target, self.to);
+        }
+        write!(
+            f,
+            "{} {} upgraded from version {} to {}",
+            self.target,
+            if self.dry_run { "would be" } else { "was" },
+            self.from,
+            self.to
+        )?;
+        for step in &self.steps {
+            write!(
+                f,
+                "\n  {} -> {}: {}",
+                step.from,
+                step.from + 1,
+                step.description
+            )?;
+            for change in &step.changes {
+                write!(f, "\n    {}", change)?;
+            }
+        }
+        if let Some(backup) = &self.backup {
+            write!(f, "\nThe original was copied to {}", backup)?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the path the original of `path` is copied to before migrating it from `version`.
+pub fn backup_path(path: &str, version: u32) -> String {
+    format!("{}.v{}.bak", path, version)
+}
+
+/// Applies the steps of `migrations` that upgrade `data` from `version` to the version after
+/// the last step, in order. Fails on the first failing step, or if a step is missing.
+pub fn apply_migrations<T>(
+    data: &mut T,
+    version: u32,
+    migrations: &[Migration<T>],
+) -> Result<Vec<MigrationStep>, String> {
+    let mut steps = vec![];
+    let pending = migrations.iter().filter(|m| m.from >= version);
+    for (next, migration) in (version..).zip(pending) {
+        if migration.from != next {
+            return Err(format!("no migration from version {}", next));
+        }
+        let changes = (migration.apply)(data)
+            .map_err(|e| format!("migration from version {} failed: {}", next, e))?;
+        steps.push(MigrationStep {
+            from: next,
+            description: migration.description.to_string(),
+            changes,
+        });
+    }
+    Ok(steps)
+}
@@ -5,10 +5,35 @@ use std::fmt::Display;
 pub const INTEREST_RATE: f64 = 0.01f64;
 pub const TAX_RATE: f64 = 0.02f64;
 pub const ED: f64 = 5f64;
+pub const TERM_DEPOSIT_RATE: f64 = 0.03f64;
+/// Default difference allowed between a replayed and a stored balance.
+pub const RECONCILIATION_TOLERANCE: Balance = 0.000_001f64;
 pub type UserId = u64;
 pub type Balance = f64;
+pub type TransferId = u64;
+pub type LoanProductId = u64;
+pub type LoanId = u64;
+pub type TermDepositId = u64;
+pub type ReviewId = u64;
+pub type ReportId = u64;
+pub type HitId = u64;
 
-// Default hash output of `DefaultHasher`
+/// Seconds since the bank was opened, as kept by the simulated clock.
+pub type Timestamp = u64;
+pub const MINUTE: Timestamp = 60;
+pub const HOUR: Timestamp = 60 * MINUTE;
+pub const DAY: Timestamp = 24 * HOUR;
+pub const WEEK: Timestamp = 7 * DAY;
+/// Months are simplified to 30 days.
+pub const MONTH: Timestamp = 30 * DAY;
+
+/// Number of days an installment may be overdue before the loan is in default.
+pub const LOAN_DEFAULT_DAYS: Timestamp = 90;
+
+/// Maximum length of the free text memo of a payment reference.
+pub const MAX_MEMO_LENGTH: usize = 140;
+
+/// Credentials of a user, see `Bank::hash`.
 pub type HashResult = u64;
 
 pub type BankResult<T> = Result<T, BankingError>;
@@ -27,6 +52,566 @@ pub struct User {
     pub role: Role,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AccountStatus {
+    /// The customer holds a balance.
+    Active,
+    /// The customer has no balance, because it was never funded or was reaped.
+    Empty,
+    /// Managers and auditors have no account.
+    Staff,
+}
+
+/// One row of the bank report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportRow {
+    pub id: UserId,
+    pub username: String,
+    pub role: Role,
+    pub balance: Balance,
+    /// Principal locked in active term deposits.
+    pub term_deposits: Balance,
+    /// `None` for staff.
+    pub kyc: Option<KycStatus>,
+    pub status: AccountStatus,
+}
+
+/// The bank report: one row per user, ordered by user ID.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BankReport {
+    pub rows: Vec<ReportRow>,
+    /// Balance of the bank income account.
+    pub fee_income: Balance,
+}
+
+/// An entry of the event log, with its position and the time it was logged at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventRow {
+    pub index: usize,
+    pub at: Timestamp,
+    pub event: Event,
+}
+
+/// SHA-256 hash of an entry of the event log, chained to the entries before it.
+pub type LogHash = [u8; 32];
+
+/// The hashes stored with an entry of the event log: `prev` is the hash of the entry before
+/// it (zeros for the first entry), and `hash` covers `prev` and the entry.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct LogLink {
+    pub prev: LogHash,
+    pub hash: LogHash,
+}
+
+/// A checkpoint of the event log: the hash of its first `count` entries, signed with the key of
+/// the bank.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub count: usize,
+    pub hash: LogHash,
+    pub signature: LogHash,
+}
+
+/// The first problem found in the event log by `Bank::verify_log`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LogIssue {
+    /// The entry at `index` does not match its hash: it was modified.
+    Modified { index: usize },
+    /// The entry logged at `index` was removed: the entry found there is chained to another.
+    Missing { index: usize },
+    /// The entry found at `index` was logged at another position.
+    Reordered { index: usize },
+    /// The checkpoint of the first `count` entries is missing, is not signed with the key of
+    /// the bank, or does not match the log: the entries before it were rewritten.
+    BadCheckpoint { count: usize },
+}
+
+impl Display for LogIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogIssue::Modified { index } => write!(f, "Entry {} was modified.", index),
+            LogIssue::Missing { index } => write!(f, "Entry {} is missing.", index),
+            LogIssue::Reordered { index } => write!(f, "Entry {} was moved.", index),
+            LogIssue::BadCheckpoint { count } => write!(
+                f,
+                "The checkpoint of the first {} entries does not verify.",
+                count
+            ),
+        }
+    }
+}
+
+/// The result of `Bank::verify_log`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogVerification {
+    /// Number of entries verified.
+    pub entries: usize,
+    /// Number of checkpoints verified.
+    pub checkpoints: usize,
+    /// The first problem found, `None` if the log is intact.
+    pub issue: Option<LogIssue>,
+}
+
+/// Number of customers in a balance range `[from, to)`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HistogramBucket {
+    pub from: Balance,
+    pub to: Balance,
+    pub count: usize,
+}
+
+/// Interest paid and tax collected during month `month` of the simulated clock.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PeriodSummary {
+    pub month: u64,
+    pub interest: Balance,
+    pub tax: Balance,
+}
+
+/// A customer whose main balance changed the most during the current month.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mover {
+    pub id: UserId,
+    pub username: String,
+    pub change: Balance,
+}
+
+/// Bank-wide figures for `Manager`s.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FinancialSummary {
+    /// Sum of all customer balances.
+    pub total_deposits: Balance,
+    /// Principal locked in active term deposits.
+    pub term_deposits: Balance,
+    pub active_accounts: usize,
+    /// Customers whose account was reaped and not funded again.
+    pub reaped_accounts: usize,
+    /// Customers whose account was never funded.
+    pub empty_accounts: usize,
+    /// Balance percentiles of the active accounts, as `(percentile, balance)`.
+    pub percentiles: Vec<(u32, Balance)>,
+    /// Balances of the active accounts, in buckets of powers of ten.
+    pub histogram: Vec<HistogramBucket>,
+    /// One entry per month, up to the current month.
+    pub periods: Vec<PeriodSummary>,
+    /// Largest changes during the current month, by size.
+    pub top_movers: Vec<Mover>,
+}
+
+/// An account whose balance differs from the balance replayed from the event log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discrepancy {
+    pub id: UserId,
+    pub username: String,
+    /// Balance replayed from the event log.
+    pub expected: Balance,
+    /// Balance stored for the account.
+    pub actual: Balance,
+    /// The event most likely to have caused the difference. `None` if the user has no
+    /// events that change the balance.
+    pub first_diverging: Option<EventRow>,
+}
+
+/// One movement on a statement: the event, the signed change to the main balance, and the
+/// balance after it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatementLine {
+    pub index: usize,
+    pub at: Timestamp,
+    pub event: Event,
+    pub amount: Balance,
+    pub balance: Balance,
+}
+
+/// A customer's monthly account statement. Month `n` starts at `n * MONTH`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub id: UserId,
+    pub username: String,
+    pub month: u64,
+    pub opening_balance: Balance,
+    pub lines: Vec<StatementLine>,
+    /// Interest paid into the main balance during the month.
+    pub interest: Balance,
+    /// Tax taken during the month.
+    pub tax: Balance,
+    /// Fees charged during the month.
+    pub fees: Balance,
+    pub closing_balance: Balance,
+}
+
+/// A saved transfer target in a customer's payee book.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Payee {
+    pub id: UserId,
+    pub nickname: String,
+}
+
+/// Reference attached to a deposit, withdrawal or transfer, telling the parties what the
+/// payment is for.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PaymentReference {
+    /// Free text memo, e.g. "Rent March".
+    pub memo: Option<String>,
+    /// Structured ISO 11649 creditor reference, e.g. "RF18539007547034".
+    pub creditor_reference: Option<String>,
+}
+
+impl PaymentReference {
+    /// Creates a validated payment reference. Empty fields are treated as absent.
+    /// The memo may hold up to `MAX_MEMO_LENGTH` letters, digits, spaces and `/-?:().,'+&#`.
+    /// The creditor reference must be a valid ISO 11649 reference; spaces are ignored.
+    pub fn new(memo: Option<String>, creditor_reference: Option<String>) -> BankResult<Self> {
+        let memo = memo.map(|m| m.trim().to_string()).filter(|m| !m.is_empty());
+        if let Some(m) = &memo {
+            if m.chars().count() > MAX_MEMO_LENGTH
+                || !m
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || " /-?:().,'+&#".contains(c))
+            {
+                return Err(BankingError::InvalidReference);
+            }
+        }
+
+        let creditor_reference = creditor_reference
+            .map(|r| r.split_whitespace().collect::<String>().to_uppercase())
+            .filter(|r| !r.is_empty());
+        if let Some(r) = &creditor_reference {
+            if !Self::is_valid_creditor_reference(r) {
+                return Err(BankingError::InvalidCreditorReference);
+            }
+        }
+
+        Ok(Self {
+            memo,
+            creditor_reference,
+        })
+    }
+
+    /// Creates a reference holding only a free text memo.
+    pub fn memo(memo: &str) -> BankResult<Self> {
+        Self::new(Some(memo.to_string()), None)
+    }
+
+    /// Parses user input: a valid RF creditor reference is stored as structured remittance
+    /// information, anything else as a memo.
+    pub fn parse(input: &str) -> BankResult<Self> {
+        Self::new(None, Some(input.to_string())).or_else(|_| Self::memo(input))
+    }
+
+    /// Returns true if the reference has neither a memo nor a creditor reference.
+    pub fn is_empty(&self) -> bool {
+        self.memo.is_none() && self.creditor_reference.is_none()
+    }
+
+    /// Returns true if the memo contains `query` (case insensitive), or the creditor
+    /// reference equals `query` (ignoring spaces and case).
+    pub fn matches(&self, query: &str) -> bool {
+        let query = query.trim();
+        if query.is_empty() {
+            return false;
+        }
+        let memo_match = self
+            .memo
+            .as_ref()
+            .map(|m| m.to_lowercase().contains(&query.to_lowercase()))
+            .unwrap_or_default();
+        let normalized = query.split_whitespace().collect::<String>().to_uppercase();
+        memo_match || self.creditor_reference.as_ref() == Some(&normalized)
+    }
+
+    // Checks an ISO 11649 creditor reference: "RF", two check digits and up to 21
+    // alphanumerics, where the rearranged reference is 1 modulo 97.
+    fn is_valid_creditor_reference(reference: &str) -> bool {
+        if reference.len() < 5
+            || reference.len() > 25
+            || !reference.starts_with("RF")
+            || !reference.chars().all(|c| c.is_ascii_alphanumeric())
+            || !reference[2..4].chars().all(|c| c.is_ascii_digit())
+        {
+            return false;
+        }
+        let rearranged = format!("{}{}", &reference[4..], &reference[..4]);
+        rearranged
+            .chars()
+            .filter_map(|c| c.to_digit(36))
+            .fold(0u32, |acc, d| {
+                if d < 10 {
+                    (acc * 10 + d) % 97
+                } else {
+                    (acc * 100 + d) % 97
+                }
+            })
+            == 1
+    }
+}
+
+impl Display for PaymentReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.memo, &self.creditor_reference) {
+            (Some(memo), Some(r)) => write!(f, "{} ({})", memo, r),
+            (Some(memo), None) => write!(f, "{}", memo),
+            (None, Some(r)) => write!(f, "{}", r),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+// Helper function: Formats the optional reference of an event.
+fn fmt_reference(reference: &Option<PaymentReference>) -> String {
+    match reference {
+        Some(r) => format!(", Reference: {}", r),
+        None => String::new(),
+    }
+}
+
+/// Operations that can be charged a fee.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum FeeType {
+    /// Charged on every withdrawal, based on the amount withdrawn.
+    Withdrawal,
+    /// Charged to the sender of every transfer, based on the amount transferred.
+    Transfer,
+    /// Charged when a withdrawal or transfer is declined because it would overdraw the
+    /// account, based on the amount requested.
+    Overdraft,
+    /// Charged to every account by the monthly fee run, based on the balance.
+    MonthlyMaintenance,
+    /// Charged by the monthly fee run to accounts below the minimum balance, based on the balance.
+    BelowMinimumBalance,
+    /// Added to a loan installment that could not be repaid on its due date, based on the
+    /// amount due.
+    LatePayment,
+    /// Penalty charged when a term deposit is broken before maturity, based on the principal.
+    EarlyWithdrawal,
+}
+
+impl FeeType {
+    pub const ALL: [FeeType; 7] = [
+        FeeType::Withdrawal,
+        FeeType::Transfer,
+        FeeType::Overdraft,
+        FeeType::MonthlyMaintenance,
+        FeeType::BelowMinimumBalance,
+        FeeType::LatePayment,
+        FeeType::EarlyWithdrawal,
+    ];
+}
+
+/// How a fee is calculated: `flat + percentage * base`, clamped between `min` and `max`.
+/// A zero rule charges no fee.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct FeeRule {
+    pub flat: Balance,
+    pub percentage: f64,
+    pub min: Balance,
+    pub max: Option<Balance>,
+}
+
+impl FeeRule {
+    /// Calculates the fee charged on `base`.
+    pub fn fee(&self, base: Balance) -> Balance {
+        let fee = (self.flat + self.percentage * base).max(self.min);
+        match self.max {
+            Some(max) => fee.min(max),
+            None => fee,
+        }
+    }
+
+    /// Returns true if all parts of the rule are within range.
+    pub fn is_valid(&self) -> bool {
+        self.flat >= 0f64
+            && (0f64..=1f64).contains(&self.percentage)
+            && self.min >= 0f64
+            && self.max.map(|max| max >= self.min) != Some(false)
+    }
+}
+
+/// How often loan installments are due.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RepaymentFrequency {
+    Weekly,
+    Monthly,
+}
+
+impl RepaymentFrequency {
+    /// Time between two installments.
+    pub fn period(&self) -> Timestamp {
+        match self {
+            RepaymentFrequency::Weekly => WEEK,
+            RepaymentFrequency::Monthly => MONTH,
+        }
+    }
+
+    /// Number of installments per year, used to convert the annual rate to a periodic rate.
+    pub fn periods_per_year(&self) -> f64 {
+        match self {
+            RepaymentFrequency::Weekly => 52f64,
+            RepaymentFrequency::Monthly => 12f64,
+        }
+    }
+}
+
+/// How the principal of a loan is spread over its installments.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AmortizationMethod {
+    /// Every installment is the same amount; the principal part grows over time.
+    Annuity,
+    /// Every installment repays the same principal; the interest part shrinks over time.
+    EqualPrincipal,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoanStatus {
+    /// Applied for, waiting for a `Manager` to approve it.
+    Pending,
+    Rejected,
+    /// Disbursed, and all installments due so far are repaid.
+    Active,
+    /// An installment is overdue.
+    Delinquent,
+    /// An installment is overdue by more than `LOAN_DEFAULT_DAYS`.
+    Defaulted,
+    Repaid,
+}
+
+/// When the interest of a term deposit is paid into the main balance.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InterestPayout {
+    AtMaturity,
+    Monthly,
+}
+
+/// What happens to the principal of a term deposit at maturity.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MaturityInstruction {
+    /// The principal is locked again for the same term at the current rate.
+    RollOver,
+    /// The principal is paid into the main balance.
+    PayOut,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TermDepositStatus {
+    Active,
+    Matured,
+    Broken,
+}
+
+/// Outcome of the fraud checks run before an outgoing movement. Ordered by severity.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FraudDecision {
+    Allow,
+    /// The movement waits in the review queue until an `Auditor` approves or rejects it.
+    Hold,
+    Deny,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReviewStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+/// Thresholds used by the compliance detection pass.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ComplianceThresholds {
+    /// Deposits and withdrawals above this amount are reported.
+    pub cash_threshold: Balance,
+    /// Deposits within this fraction below `cash_threshold` count towards structuring.
+    pub structuring_margin: f64,
+    /// Number of such deposits within `structuring_window` that raises a structuring alert.
+    pub structuring_count: usize,
+    pub structuring_window: Timestamp,
+}
+
+impl Default for ComplianceThresholds {
+    fn default() -> Self {
+        Self {
+            cash_threshold: 10_000f64,
+            structuring_margin: 0.1f64,
+            structuring_count: 3,
+            structuring_window: WEEK,
+        }
+    }
+}
+
+impl ComplianceThresholds {
+    pub fn is_valid(&self) -> bool {
+        self.cash_threshold > 0f64
+            && (0f64..1f64).contains(&self.structuring_margin)
+            && self.structuring_count >= 2
+            && self.structuring_window > 0
+    }
+
+    /// Returns true if a deposit of `amount` is just below the cash threshold.
+    pub fn is_near_threshold(&self, amount: Balance) -> bool {
+        amount <= self.cash_threshold
+            && amount >= self.cash_threshold * (1f64 - self.structuring_margin)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReportKind {
+    /// A single deposit or withdrawal above the cash threshold.
+    LargeCashTransaction,
+    /// Several deposits just below the cash threshold within a short time.
+    Structuring,
+}
+
+/// Compliance reports move from `Open` to `Reviewed` to `Filed`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReportStatus {
+    Open,
+    Reviewed,
+    Filed,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KycStatus {
+    Unverified,
+    /// The customer submitted their profile, and waits for a `Manager` to verify it.
+    Pending,
+    Verified,
+    Rejected,
+}
+
+/// Restrictions on customers whose KYC status is not `Verified`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum KycPolicy {
+    Unrestricted,
+    /// Each withdrawal and transfer is limited to the given amount.
+    Limit(Balance),
+    /// Transfers are not allowed. Withdrawals are, so customers can still take their money out.
+    NoTransfers,
+}
+
+/// Lifecycle state of a transfer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TransferStatus {
+    Pending,
+    Settled,
+    Cancelled,
+    Reversed,
+}
+
+/// A transfer tracked through its lifecycle. Pending transfers hold `amount` of the
+/// sender's balance until they are settled or cancelled.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransferRecord {
+    pub transfer_id: TransferId,
+    pub from: UserId,
+    pub to: UserId,
+    pub amount: Balance,
+    /// Transfer fee charged to the sender when the transfer settles.
+    pub fee: Balance,
+    pub reference: Option<PaymentReference>,
+    pub status: TransferStatus,
+    /// Index into the event log of the `Event::Transfer` posted when the transfer settled.
+    pub settled_event: Option<usize>,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum BankingError {
     Unauthorized,
@@ -39,6 +624,50 @@ pub enum BankingError {
     InvalidTaxRate,
     InvalidInterestRate,
     UserAlreadyExist,
+    InvalidNickname,
+    PayeeAlreadyExist,
+    NoPayeeFound,
+    NoTransferFound,
+    InvalidTransferStatus,
+    InvalidBatchLine,
+    BatchTooLarge,
+    FailedToReadFile,
+    FailedToWriteFile,
+    InvalidReference,
+    InvalidCreditorReference,
+    InvalidFeeRule,
+    NoFeeWaiverFound,
+    InvalidLoanProduct,
+    NoLoanProductFound,
+    NoLoanFound,
+    InvalidLoanStatus,
+    InvalidTerm,
+    NoTermDepositFound,
+    InvalidTermDepositStatus,
+    TransactionDenied,
+    TransactionHeld,
+    NoFraudRuleFound,
+    NoFraudReviewFound,
+    InvalidReviewStatus,
+    InvalidComplianceThresholds,
+    NoReportFound,
+    InvalidReportStatus,
+    WatchListMatch,
+    InvalidScreeningThresholds,
+    NoScreeningHitFound,
+    InvalidScreeningHitStatus,
+    InvalidProfile,
+    NoProfileFound,
+    InvalidKycPolicy,
+    KycLimitExceeded,
+    KycRequired,
+    InvalidTolerance,
+    InvalidBankFile,
+    UnsupportedBankFileVersion,
+    ServiceStopped,
+    TamperedBankFile,
+    OutdatedBankFile,
+    StorageFailure,
 }
 
 /// Display user facing message for each error
@@ -61,19 +690,132 @@ impl std::fmt::Display for BankingError {
                 write!(f, "Error, interest rate could not be nagitive.")
             }
             BankingError::UserAlreadyExist => write!(f, "Error, this user is already exist."),
+            BankingError::InvalidNickname => write!(f, "Error, payee nickname could not be empty."),
+            BankingError::PayeeAlreadyExist => write!(f, "Error, this payee is already saved."),
+            BankingError::NoPayeeFound => write!(f, "Error, payee does not exist."),
+            BankingError::NoTransferFound => write!(f, "Error, transfer does not exist."),
+            BankingError::InvalidTransferStatus => {
+                write!(
+                    f,
+                    "Error, the transfer is not in a valid state for this operation."
+                )
+            }
+            BankingError::InvalidBatchLine => {
+                write!(f, "Error, expected a line of `payee,amount,reference`.")
+            }
+            BankingError::BatchTooLarge => write!(f, "Error, the batch has too many lines."),
+            BankingError::FailedToReadFile => write!(f, "Error, failed to read the file."),
+            BankingError::FailedToWriteFile => write!(f, "Error, failed to write the file."),
+            BankingError::InvalidReference => write!(
+                f,
+                "Error, reference must be at most 140 letters, digits, spaces or /-?:().,'+&#."
+            ),
+            BankingError::InvalidCreditorReference => {
+                write!(f, "Error, creditor reference is not a valid RF reference.")
+            }
+            BankingError::NoFeeWaiverFound => write!(f, "Error, fee waiver does not exist."),
+            BankingError::InvalidLoanProduct => write!(
+                f,
+                "Error, loan product needs a name, a non-negative rate and at least one installment."
+            ),
+            BankingError::NoLoanProductFound => write!(f, "Error, loan product does not exist."),
+            BankingError::NoLoanFound => write!(f, "Error, loan does not exist."),
+            BankingError::InvalidTerm => write!(f, "Error, term must be at least one month."),
+            BankingError::NoTermDepositFound => write!(f, "Error, term deposit does not exist."),
+            BankingError::InvalidTermDepositStatus => {
+                write!(f, "Error, the term deposit is no longer active.")
+            }
+            BankingError::TransactionDenied => {
+                write!(f, "Error, the transaction is denied by the fraud checks.")
+            }
+            BankingError::TransactionHeld => write!(
+                f,
+                "Error, the transaction is held for review by an auditor."
+            ),
+            BankingError::NoFraudRuleFound => write!(f, "Error, fraud rule does not exist."),
+            BankingError::NoFraudReviewFound => write!(f, "Error, review does not exist."),
+            BankingError::InvalidReviewStatus => {
+                write!(f, "Error, the review has already been decided.")
+            }
+            BankingError::InvalidComplianceThresholds => write!(
+                f,
+                "Error, thresholds need a positive amount, a margin between 0 and 1, at least 2 deposits and a window."
+            ),
+            BankingError::NoReportFound => write!(f, "Error, report does not exist."),
+            BankingError::WatchListMatch => {
+                write!(f, "Error, the name matches the watch list.")
+            }
+            BankingError::InvalidScreeningThresholds => write!(
+                f,
+                "Error, thresholds must be between 0 and 1, and flag must not exceed block."
+            ),
+            BankingError::NoScreeningHitFound => write!(f, "Error, screening hit does not exist."),
+            BankingError::InvalidProfile => write!(
+                f,
+                "Error, profile needs a legal name and a date of birth as YYYY-MM-DD, and the email must contain '@'."
+            ),
+            BankingError::NoProfileFound => write!(f, "Error, the customer has no profile."),
+            BankingError::InvalidKycPolicy => {
+                write!(f, "Error, the limit must not be negative.")
+            }
+            BankingError::KycLimitExceeded => write!(
+                f,
+                "Error, the amount exceeds the limit for customers who are not verified."
+            ),
+            BankingError::InvalidTolerance => write!(f, "Error, tolerance must not be negative."),
+            BankingError::InvalidBankFile => write!(f, "Error, the bank file is not valid."),
+            BankingError::UnsupportedBankFileVersion => write!(
+                f,
+                "Error, the bank file was written by a newer version of the app."
+            ),
+            BankingError::ServiceStopped => write!(
+                f,
+                "Error, the service stopped before the command was applied."
+            ),
+            BankingError::TamperedBankFile => write!(
+                f,
+                "Error, the bank file was changed outside the app, or sealed with another key."
+            ),
+            BankingError::StorageFailure => write!(
+                f,
+                "Error, the storage failed, so the operation was not done; try again later."
+            ),
+            BankingError::OutdatedBankFile => write!(
+                f,
+                "Error, the bank file was written by an older version of the app; run `banking_app migrate` to upgrade it."
+            ),
+            BankingError::KycRequired => {
+                write!(f, "Error, the customer must be verified to transfer.")
+            }
+            BankingError::InvalidScreeningHitStatus => {
+                write!(f, "Error, the screening hit is already cleared.")
+            }
+            BankingError::InvalidReportStatus => write!(
+                f,
+                "Error, reports must be reviewed before they are filed."
+            ),
+            BankingError::InvalidLoanStatus => {
+                write!(f, "Error, the loan is not in a valid state for this operation.")
+            }
+            BankingError::InvalidFeeRule => write!(
+                f,
+                "Error, fees must not be negative, percentage must be between 0 and 1 and max must not be below min."
+            ),
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Event {
     Deposit {
         id: UserId,
         amount: Balance,
+        reference: Option<PaymentReference>,
     },
     Withdrawal {
         id: UserId,
         amount: Balance,
+        reference: Option<PaymentReference>,
     },
     AccountReaped {
         id: UserId,
@@ -83,6 +825,7 @@ pub enum Event {
         id: UserId,
         to_id: UserId,
         amount: Balance,
+        reference: Option<PaymentReference>,
     },
     Interest {
         id: UserId,
@@ -100,23 +843,401 @@ pub enum Event {
         id: UserId,
         tax_rate: f64,
     },
+    PayeeAdded {
+        id: UserId,
+        payee: UserId,
+    },
+    PayeeRemoved {
+        id: UserId,
+        payee: UserId,
+    },
+    TransferInitiated {
+        transfer_id: TransferId,
+        id: UserId,
+        to_id: UserId,
+        amount: Balance,
+        reference: Option<PaymentReference>,
+    },
+    TransferSettled {
+        transfer_id: TransferId,
+        id: UserId,
+        to_id: UserId,
+    },
+    TransferCancelled {
+        transfer_id: TransferId,
+        id: UserId,
+        to_id: UserId,
+    },
+    /// Compensating entry for a settled transfer: `amount` moves back from `to_id` to `id`.
+    /// `original` is the index of the reversed `Event::Transfer` in the event log.
+    TransferReversed {
+        transfer_id: TransferId,
+        id: UserId,
+        to_id: UserId,
+        amount: Balance,
+        original: usize,
+        by: UserId,
+    },
+    /// A fee charged to a customer and paid into the bank income account.
+    Fee {
+        id: UserId,
+        fee_type: FeeType,
+        amount: Balance,
+    },
+    FeeRuleSet {
+        id: UserId,
+        fee_type: FeeType,
+        rule: FeeRule,
+    },
+    MinimumBalanceSet {
+        id: UserId,
+        minimum_balance: Balance,
+    },
+    /// Fees of `fee_type` (or all fees if `None`) are no longer charged to the customer.
+    FeeWaiverGranted {
+        id: UserId,
+        fee_type: Option<FeeType>,
+        by: UserId,
+    },
+    FeeWaiverRevoked {
+        id: UserId,
+        fee_type: Option<FeeType>,
+        by: UserId,
+    },
+    ClockAdvanced {
+        id: UserId,
+        now: Timestamp,
+    },
+    LoanProductCreated {
+        product_id: LoanProductId,
+        annual_rate: f64,
+        term: u32,
+        by: UserId,
+    },
+    LoanApplied {
+        loan_id: LoanId,
+        id: UserId,
+        product_id: LoanProductId,
+        principal: Balance,
+    },
+    LoanRejected {
+        loan_id: LoanId,
+        id: UserId,
+        by: UserId,
+    },
+    /// The loan is approved and `amount` is paid into the borrower's balance.
+    LoanDisbursed {
+        loan_id: LoanId,
+        id: UserId,
+        amount: Balance,
+        by: UserId,
+    },
+    /// An installment is drawn from the borrower's balance.
+    LoanRepayment {
+        loan_id: LoanId,
+        id: UserId,
+        installment: u32,
+        principal: Balance,
+        interest: Balance,
+    },
+    /// A late fee is added to an installment that could not be repaid on its due date.
+    LoanLateFee {
+        loan_id: LoanId,
+        id: UserId,
+        installment: u32,
+        fee: Balance,
+    },
+    LoanStatusChanged {
+        loan_id: LoanId,
+        id: UserId,
+        status: LoanStatus,
+    },
+    TermDepositRate {
+        id: UserId,
+        rate: f64,
+    },
+    /// A term deposit of `amount` is opened. Unless it is rolled over from a matured term
+    /// deposit, `amount` moves from the main balance into the term deposit.
+    TermDepositOpened {
+        deposit_id: TermDepositId,
+        id: UserId,
+        amount: Balance,
+        rate: f64,
+        matures_at: Timestamp,
+        rolled_over_from: Option<TermDepositId>,
+    },
+    /// The term deposit reached maturity. Unless it is rolled over into a new term deposit,
+    /// `principal` moves into the main balance.
+    TermDepositMatured {
+        deposit_id: TermDepositId,
+        id: UserId,
+        principal: Balance,
+        rolled_over_into: Option<TermDepositId>,
+    },
+    /// The term deposit was broken before maturity, and `principal` moves into the main
+    /// balance. The penalty is charged as a separate fee.
+    TermDepositBroken {
+        deposit_id: TermDepositId,
+        id: UserId,
+        principal: Balance,
+    },
+    FraudRuleAdded {
+        id: UserId,
+        rule: String,
+    },
+    FraudRuleRemoved {
+        id: UserId,
+        rule: String,
+    },
+    /// Decision of the fraud checks on an outgoing movement. `to_id` is `None` for withdrawals,
+    /// and `rule` is the strictest rule that did not allow the movement.
+    FraudCheck {
+        id: UserId,
+        to_id: Option<UserId>,
+        amount: Balance,
+        rule: Option<String>,
+        decision: FraudDecision,
+        review_id: Option<ReviewId>,
+    },
+    FraudReviewed {
+        review_id: ReviewId,
+        id: UserId,
+        approved: bool,
+        by: UserId,
+    },
+    ComplianceThresholdsSet {
+        id: UserId,
+        thresholds: ComplianceThresholds,
+    },
+    ComplianceReportCreated {
+        report_id: ReportId,
+        id: UserId,
+        kind: ReportKind,
+        amount: Balance,
+    },
+    ComplianceReportStatusChanged {
+        report_id: ReportId,
+        id: UserId,
+        status: ReportStatus,
+        by: UserId,
+    },
+    WatchListLoaded {
+        id: UserId,
+        entries: usize,
+    },
+    ScreeningThresholdsSet {
+        id: UserId,
+        flag: f64,
+        block: f64,
+    },
+    /// `name` matched the watch list `entry`. `id` is the user registering or sending the
+    /// transfer, if known.
+    ScreeningHit {
+        hit_id: HitId,
+        id: Option<UserId>,
+        name: String,
+        entry: String,
+        blocked: bool,
+    },
+    ScreeningHitCleared {
+        hit_id: HitId,
+        by: UserId,
+    },
+    ProfileUpdated {
+        id: UserId,
+        version: u32,
+        by: UserId,
+    },
+    KycStatusChanged {
+        id: UserId,
+        status: KycStatus,
+        by: UserId,
+    },
+    KycPolicySet {
+        id: UserId,
+        policy: KycPolicy,
+    },
+    ReconciliationToleranceSet {
+        id: UserId,
+        tolerance: Balance,
+    },
+    /// An `Auditor` reconciled the balances with the event log.
+    Reconciled {
+        id: UserId,
+        discrepancies: usize,
+    },
 }
-impl Display for Event {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl Event {
+    /// Returns the payment reference of the event, if any.
+    pub fn reference(&self) -> Option<&PaymentReference> {
         match self {
-            Event::Deposit { id, amount } => {
-                write!(f, "User ID: {}, Deposit - Amount: {}", id, amount)
+            Event::Deposit { reference, .. }
+            | Event::Withdrawal { reference, .. }
+            | Event::Transfer { reference, .. }
+            | Event::TransferInitiated { reference, .. } => reference.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the event is related to the given user.
+    pub fn involves(&self, target_id: UserId) -> bool {
+        self.parties().contains(&target_id)
+    }
+
+    /// Returns the users the event is related to. Transfers between an account and itself list
+    /// it once.
+    pub fn parties(&self) -> Vec<UserId> {
+        match self {
+            Event::Deposit { id, .. }
+            | Event::Withdrawal { id, .. }
+            | Event::AccountReaped { id, .. }
+            | Event::Interest { id, .. }
+            | Event::Tax { id, .. }
+            | Event::PayeeAdded { id, .. }
+            | Event::PayeeRemoved { id, .. }
+            | Event::Fee { id, .. }
+            | Event::FeeWaiverGranted { id, .. }
+            | Event::FeeWaiverRevoked { id, .. }
+            | Event::LoanApplied { id, .. }
+            | Event::LoanRejected { id, .. }
+            | Event::LoanDisbursed { id, .. }
+            | Event::LoanRepayment { id, .. }
+            | Event::LoanLateFee { id, .. }
+            | Event::LoanStatusChanged { id, .. }
+            | Event::TermDepositOpened { id, .. }
+            | Event::TermDepositMatured { id, .. }
+            | Event::TermDepositBroken { id, .. }
+            | Event::FraudCheck { id, .. }
+            | Event::FraudReviewed { id, .. }
+            | Event::ComplianceReportCreated { id, .. }
+            | Event::ComplianceReportStatusChanged { id, .. }
+            | Event::ProfileUpdated { id, .. }
+            | Event::KycStatusChanged { id, .. } => vec![*id],
+            Event::Transfer { id, to_id, .. }
+            | Event::TransferInitiated { id, to_id, .. }
+            | Event::TransferSettled { id, to_id, .. }
+            | Event::TransferCancelled { id, to_id, .. }
+            | Event::TransferReversed { id, to_id, .. }
+                if id == to_id =>
+            {
+                vec![*id]
+            }
+            Event::Transfer { id, to_id, .. }
+            | Event::TransferInitiated { id, to_id, .. }
+            | Event::TransferSettled { id, to_id, .. }
+            | Event::TransferCancelled { id, to_id, .. }
+            | Event::TransferReversed { id, to_id, .. } => vec![*id, *to_id],
+            Event::InterestRate { .. }
+            | Event::TaxRate { .. }
+            | Event::FeeRuleSet { .. }
+            | Event::MinimumBalanceSet { .. }
+            | Event::ClockAdvanced { .. }
+            | Event::LoanProductCreated { .. }
+            | Event::TermDepositRate { .. }
+            | Event::FraudRuleAdded { .. }
+            | Event::FraudRuleRemoved { .. }
+            | Event::ComplianceThresholdsSet { .. }
+            | Event::WatchListLoaded { .. }
+            | Event::ScreeningThresholdsSet { .. }
+            | Event::ScreeningHitCleared { .. }
+            | Event::KycPolicySet { .. }
+            | Event::ReconciliationToleranceSet { .. }
+            | Event::Reconciled { .. } => vec![],
+            Event::ScreeningHit { id, .. } => id.iter().copied().collect(),
+        }
+    }
+
+    /// Returns how much the event changes the main balance of the given user. Money held by
+    /// pending transfers or fraud reviews is still part of the balance, and term deposits are
+    /// not.
+    pub fn balance_change(&self, target_id: UserId) -> Balance {
+        match self {
+            Event::Deposit { id, amount, .. }
+            | Event::LoanDisbursed { id, amount, .. }
+            | Event::TermDepositMatured {
+                id,
+                principal: amount,
+                rolled_over_into: None,
+                ..
             }
-            Event::Withdrawal { id, amount } => {
-                write!(f, "User ID: {}, Withdrawal - Amount: -{}", id, amount)
+            | Event::TermDepositBroken {
+                id,
+                principal: amount,
+                ..
             }
+            | Event::Interest {
+                id,
+                interest: amount,
+            } if *id == target_id => *amount,
+            Event::Withdrawal { id, amount, .. }
+            | Event::Fee { id, amount, .. }
+            | Event::AccountReaped { id, dust: amount }
+            | Event::Tax { id, tax: amount }
+            | Event::TermDepositOpened {
+                id,
+                amount,
+                rolled_over_from: None,
+                ..
+            } if *id == target_id => -*amount,
+            Event::LoanRepayment {
+                id,
+                principal,
+                interest,
+                ..
+            } if *id == target_id => -(principal + interest),
+            Event::Transfer {
+                id, to_id, amount, ..
+            } if *id == target_id && *to_id != target_id => -*amount,
+            Event::Transfer { to_id, amount, .. } if *to_id == target_id => *amount,
+            Event::TransferReversed {
+                id, to_id, amount, ..
+            } if *id == target_id && *to_id != target_id => *amount,
+            Event::TransferReversed { to_id, amount, .. } if *to_id == target_id => -*amount,
+            _ => 0f64,
+        }
+    }
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Event::Deposit {
+                id,
+                amount,
+                reference,
+            } => write!(
+                f,
+                "User ID: {}, Deposit - Amount: {}{}",
+                id,
+                amount,
+                fmt_reference(reference)
+            ),
+            Event::Withdrawal {
+                id,
+                amount,
+                reference,
+            } => write!(
+                f,
+                "User ID: {}, Withdrawal - Amount: -{}{}",
+                id,
+                amount,
+                fmt_reference(reference)
+            ),
             Event::AccountReaped { id, dust } => {
                 write!(f, "User ID: {}, Account Reaped - Dust: {}", id, dust)
             }
-            Event::Transfer { id, to_id, amount } => write!(
+            Event::Transfer {
+                id,
+                to_id,
+                amount,
+                reference,
+            } => write!(
                 f,
-                "Transfer - Amount: {}, From ID: {}, To ID: {}",
-                amount, id, to_id
+                "Transfer - Amount: {}, From ID: {}, To ID: {}{}",
+                amount,
+                id,
+                to_id,
+                fmt_reference(reference)
             ),
             Event::Interest { id, interest } => {
                 write!(f, "User ID: {}, Interest - Amount: {}", id, interest)
@@ -128,6 +1249,328 @@ impl Display for Event {
             Event::TaxRate { id, tax_rate } => {
                 write!(f, "User ID: {}, Tax Rate - Set: {}", id, tax_rate)
             }
+            Event::PayeeAdded { id, payee } => {
+                write!(f, "User ID: {}, Payee Added - Payee ID: {}", id, payee)
+            }
+            Event::PayeeRemoved { id, payee } => {
+                write!(f, "User ID: {}, Payee Removed - Payee ID: {}", id, payee)
+            }
+            Event::TransferInitiated {
+                transfer_id,
+                id,
+                to_id,
+                amount,
+                reference,
+            } => write!(
+                f,
+                "Transfer #{} Initiated - Amount: {}, From ID: {}, To ID: {}{}",
+                transfer_id,
+                amount,
+                id,
+                to_id,
+                fmt_reference(reference)
+            ),
+            Event::TransferSettled {
+                transfer_id,
+                id,
+                to_id,
+            } => write!(
+                f,
+                "Transfer #{} Settled - From ID: {}, To ID: {}",
+                transfer_id, id, to_id
+            ),
+            Event::TransferCancelled {
+                transfer_id,
+                id,
+                to_id,
+            } => write!(
+                f,
+                "Transfer #{} Cancelled - From ID: {}, To ID: {}",
+                transfer_id, id, to_id
+            ),
+            Event::TransferReversed {
+                transfer_id,
+                id,
+                to_id,
+                amount,
+                original,
+                by,
+            } => write!(
+                f,
+                "Transfer #{} Reversed - Amount: {}, From ID: {}, To ID: {}, Original Event: {}, By ID: {}",
+                transfer_id, amount, to_id, id, original, by
+            ),
+            Event::Fee {
+                id,
+                fee_type,
+                amount,
+            } => write!(
+                f,
+                "User ID: {}, Fee - Type: {:?}, Amount: -{}",
+                id, fee_type, amount
+            ),
+            Event::FeeRuleSet { id, fee_type, rule } => write!(
+                f,
+                "User ID: {}, Fee Rule - Type: {:?}, Flat: {}, Percentage: {}, Min: {}, Max: {:?}",
+                id, fee_type, rule.flat, rule.percentage, rule.min, rule.max
+            ),
+            Event::MinimumBalanceSet {
+                id,
+                minimum_balance,
+            } => write!(
+                f,
+                "User ID: {}, Minimum Balance - Set: {}",
+                id, minimum_balance
+            ),
+            Event::FeeWaiverGranted { id, fee_type, by } => write!(
+                f,
+                "User ID: {}, Fee Waiver Granted - Type: {}, By ID: {}",
+                id,
+                fee_type.map_or("All".to_string(), |t| format!("{:?}", t)),
+                by
+            ),
+            Event::FeeWaiverRevoked { id, fee_type, by } => write!(
+                f,
+                "User ID: {}, Fee Waiver Revoked - Type: {}, By ID: {}",
+                id,
+                fee_type.map_or("All".to_string(), |t| format!("{:?}", t)),
+                by
+            ),
+            Event::ClockAdvanced { id, now } => {
+                write!(f, "User ID: {}, Clock Advanced - Now: Day {}", id, now / DAY)
+            }
+            Event::LoanProductCreated {
+                product_id,
+                annual_rate,
+                term,
+                by,
+            } => write!(
+                f,
+                "Loan Product #{} Created - Annual Rate: {}, Term: {}, By ID: {}",
+                product_id, annual_rate, term, by
+            ),
+            Event::LoanApplied {
+                loan_id,
+                id,
+                product_id,
+                principal,
+            } => write!(
+                f,
+                "User ID: {}, Loan #{} Applied - Product: {}, Principal: {}",
+                id, loan_id, product_id, principal
+            ),
+            Event::LoanRejected { loan_id, id, by } => write!(
+                f,
+                "User ID: {}, Loan #{} Rejected - By ID: {}",
+                id, loan_id, by
+            ),
+            Event::LoanDisbursed {
+                loan_id,
+                id,
+                amount,
+                by,
+            } => write!(
+                f,
+                "User ID: {}, Loan #{} Disbursed - Amount: {}, By ID: {}",
+                id, loan_id, amount, by
+            ),
+            Event::LoanRepayment {
+                loan_id,
+                id,
+                installment,
+                principal,
+                interest,
+            } => write!(
+                f,
+                "User ID: {}, Loan #{} Repayment {} - Principal: -{}, Interest: -{}",
+                id, loan_id, installment, principal, interest
+            ),
+            Event::LoanLateFee {
+                loan_id,
+                id,
+                installment,
+                fee,
+            } => write!(
+                f,
+                "User ID: {}, Loan #{} Installment {} Late - Fee: {}",
+                id, loan_id, installment, fee
+            ),
+            Event::LoanStatusChanged {
+                loan_id,
+                id,
+                status,
+            } => write!(
+                f,
+                "User ID: {}, Loan #{} Status - {:?}",
+                id, loan_id, status
+            ),
+            Event::TermDepositRate { id, rate } => {
+                write!(f, "User ID: {}, Term Deposit Rate - Set: {}", id, rate)
+            }
+            Event::TermDepositOpened {
+                deposit_id,
+                id,
+                amount,
+                rate,
+                matures_at,
+                ..
+            } => write!(
+                f,
+                "User ID: {}, Term Deposit #{} Opened - Amount: {}, Rate: {}, Matures: Day {}",
+                id,
+                deposit_id,
+                amount,
+                rate,
+                matures_at / DAY
+            ),
+            Event::TermDepositMatured {
+                deposit_id,
+                id,
+                principal,
+                rolled_over_into,
+            } => match rolled_over_into {
+                Some(new_id) => write!(
+                    f,
+                    "User ID: {}, Term Deposit #{} Matured - Principal: {}, Rolled Over Into: #{}",
+                    id, deposit_id, principal, new_id
+                ),
+                None => write!(
+                    f,
+                    "User ID: {}, Term Deposit #{} Matured - Principal: {} Paid Out",
+                    id, deposit_id, principal
+                ),
+            },
+            Event::TermDepositBroken {
+                deposit_id,
+                id,
+                principal,
+            } => write!(
+                f,
+                "User ID: {}, Term Deposit #{} Broken - Principal: {} Paid Out",
+                id, deposit_id, principal
+            ),
+            Event::FraudRuleAdded { id, rule } => {
+                write!(f, "User ID: {}, Fraud Rule - Added: {}", id, rule)
+            }
+            Event::FraudRuleRemoved { id, rule } => {
+                write!(f, "User ID: {}, Fraud Rule - Removed: {}", id, rule)
+            }
+            Event::FraudCheck {
+                id,
+                to_id,
+                amount,
+                rule,
+                decision,
+                review_id,
+            } => {
+                write!(f, "User ID: {}, Fraud Check - Amount: {}", id, amount)?;
+                if let Some(to_id) = to_id {
+                    write!(f, ", To ID: {}", to_id)?;
+                }
+                write!(f, ", Decision: {:?}", decision)?;
+                if let Some(rule) = rule {
+                    write!(f, ", Rule: {}", rule)?;
+                }
+                match review_id {
+                    Some(review_id) => write!(f, ", Review #{}", review_id),
+                    None => Ok(()),
+                }
+            }
+            Event::FraudReviewed {
+                review_id,
+                id,
+                approved,
+                by,
+            } => write!(
+                f,
+                "User ID: {}, Review #{} {} By: {}",
+                id,
+                review_id,
+                if *approved { "Approved" } else { "Rejected" },
+                by
+            ),
+            Event::ComplianceThresholdsSet { id, thresholds } => write!(
+                f,
+                "User ID: {}, Compliance Thresholds - Cash: {}, Structuring: {} deposits within {} of the threshold in {} days",
+                id,
+                thresholds.cash_threshold,
+                thresholds.structuring_count,
+                thresholds.structuring_margin,
+                thresholds.structuring_window / DAY
+            ),
+            Event::ComplianceReportCreated {
+                report_id,
+                id,
+                kind,
+                amount,
+            } => write!(
+                f,
+                "User ID: {}, Compliance Report #{} - {:?}, Amount: {}",
+                id, report_id, kind, amount
+            ),
+            Event::ComplianceReportStatusChanged {
+                report_id,
+                id,
+                status,
+                by,
+            } => write!(
+                f,
+                "User ID: {}, Compliance Report #{} - {:?} By: {}",
+                id, report_id, status, by
+            ),
+            Event::WatchListLoaded { id, entries } => {
+                write!(f, "User ID: {}, Watch List - Loaded {} entries", id, entries)
+            }
+            Event::ScreeningThresholdsSet { id, flag, block } => write!(
+                f,
+                "User ID: {}, Screening Thresholds - Flag: {}, Block: {}",
+                id, flag, block
+            ),
+            Event::ScreeningHit {
+                hit_id,
+                id,
+                name,
+                entry,
+                blocked,
+            } => {
+                if let Some(id) = id {
+                    write!(f, "User ID: {}, ", id)?;
+                }
+                write!(
+                    f,
+                    "Screening Hit #{} - Name: {}, Entry: {}, {}",
+                    hit_id,
+                    name,
+                    entry,
+                    if *blocked { "Blocked" } else { "Flagged" }
+                )
+            }
+            Event::ScreeningHitCleared { hit_id, by } => {
+                write!(f, "Screening Hit #{} Cleared By: {}", hit_id, by)
+            }
+            Event::ProfileUpdated { id, version, by } => write!(
+                f,
+                "User ID: {}, Profile Updated - Version: {}, By: {}",
+                id, version, by
+            ),
+            Event::KycStatusChanged { id, status, by } => write!(
+                f,
+                "User ID: {}, KYC Status - {:?}, By: {}",
+                id, status, by
+            ),
+            Event::KycPolicySet { id, policy } => {
+                write!(f, "User ID: {}, KYC Policy - Set: {:?}", id, policy)
+            }
+            Event::ReconciliationToleranceSet { id, tolerance } => write!(
+                f,
+                "User ID: {}, Reconciliation Tolerance - Set: {}",
+                id, tolerance
+            ),
+            Event::Reconciled { id, discrepancies } => write!(
+                f,
+                "User ID: {}, Reconciliation - Discrepancies: {}",
+                id, discrepancies
+            ),
         }
     }
 }
@@ -0,0 +1,426 @@
+//! Renderers for the bank report and event rows, as a text table, CSV or JSON.
+use crate::batch::escape_csv_field;
+use crate::primitives::*;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReportFormat {
+    Table,
+    Csv,
+    Json,
+}
+
+// Helper function: Quotes a JSON string.
+pub(crate) fn escape_json(s: &str) -> String {
+    let mut out = String::from("\"");
+    s.chars().for_each(|c| match c {
+        '"' => out.push_str("\\\""),
+        '\\' => out.push_str("\\\\"),
+        '\n' => out.push_str("\\n"),
+        '\r' => out.push_str("\\r"),
+        '\t' => out.push_str("\\t"),
+        c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+        c => out.push(c),
+    });
+    out.push('"');
+    out
+}
+
+// Helper function: Renders rows as a text table, with columns as wide as their content.
+fn render_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let widths = headers
+        .iter()
+        .enumerate()
+        .map(|(i, h)| {
+            rows.iter()
+                .map(|r| r[i].chars().count())
+                .chain(std::iter::once(h.chars().count()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>();
+    let line = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(c, w)| format!("{:<w$}", c, w = w))
+            .collect::<Vec<_>>()
+            .join(" | ")
+            .trim_end()
+            .to_string()
+            + "\n"
+    };
+
+    let mut out = line(headers.to_vec());
+    out.push_str(
+        &(widths
+            .iter()
+            .map(|w| "-".repeat(*w))
+            .collect::<Vec<_>>()
+            .join("-+-")
+            + "\n"),
+    );
+    rows.iter()
+        .for_each(|r| out.push_str(&line(r.iter().map(String::as_str).collect())));
+    out
+}
+
+// Helper function: Renders rows as CSV, with a header line.
+fn render_csv(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut out = headers.join(",") + "\n";
+    rows.iter().for_each(|r| {
+        out.push_str(
+            &(r.iter()
+                .map(|c| escape_csv_field(c))
+                .collect::<Vec<_>>()
+                .join(",")
+                + "\n"),
+        )
+    });
+    out
+}
+
+const REPORT_HEADERS: [&str; 7] = [
+    "id",
+    "username",
+    "role",
+    "balance",
+    "term_deposits",
+    "kyc",
+    "status",
+];
+
+/// Renders the bank report. The table ends with the bank income account; CSV only holds the
+/// user rows.
+pub fn render_report(report: &BankReport, format: ReportFormat) -> String {
+    let cells = |r: &ReportRow| {
+        vec![
+            r.id.to_string(),
+            r.username.clone(),
+            format!("{:?}", r.role),
+            r.balance.to_string(),
+            r.term_deposits.to_string(),
+            r.kyc.map(|k| format!("{:?}", k)).unwrap_or_default(),
+            format!("{:?}", r.status),
+        ]
+    };
+    match format {
+        ReportFormat::Table => {
+            render_table(
+                &REPORT_HEADERS,
+                &report.rows.iter().map(cells).collect::<Vec<_>>(),
+            ) + &format!("Bank income account: {}\n", report.fee_income)
+        }
+        ReportFormat::Csv => render_csv(
+            &REPORT_HEADERS,
+            &report.rows.iter().map(cells).collect::<Vec<_>>(),
+        ),
+        ReportFormat::Json => {
+            let rows = report
+                .rows
+                .iter()
+                .map(|r| {
+                    format!(
+                        "{{\"id\":{},\"username\":{},\"role\":\"{:?}\",\"balance\":{},\"term_deposits\":{},\"kyc\":{},\"status\":\"{:?}\"}}",
+                        r.id,
+                        escape_json(&r.username),
+                        r.role,
+                        r.balance,
+                        r.term_deposits,
+                        r.kyc.map(|k| format!("\"{:?}\"", k)).unwrap_or_else(|| "null".to_string()),
+                        r.status
+                    )
+                })
+                .collect::<Vec<_>>();
+            format!(
+                "{{\"users\":[{}],\"fee_income\":{}}}\n",
+                rows.join(","),
+                report.fee_income
+            )
+        }
+    }
+}
+
+const EVENT_HEADERS: [&str; 3] = ["index", "day", "event"];
+
+/// Renders event rows. The event is rendered with its `Display` text.
+pub fn render_events(rows: &[EventRow], format: ReportFormat) -> String {
+    let cells = |r: &EventRow| {
+        vec![
+            r.index.to_string(),
+            (r.at / DAY).to_string(),
+            r.event.to_string(),
+        ]
+    };
+    match format {
+        ReportFormat::Table => {
+            render_table(&EVENT_HEADERS, &rows.iter().map(cells).collect::<Vec<_>>())
+        }
+        ReportFormat::Csv => {
+            render_csv(&EVENT_HEADERS, &rows.iter().map(cells).collect::<Vec<_>>())
+        }
+        ReportFormat::Json => {
+            let rows = rows
+                .iter()
+                .map(|r| {
+                    format!(
+                        "{{\"index\":{},\"at\":{},\"event\":{}}}",
+                        r.index,
+                        r.at,
+                        escape_json(&r.event.to_string())
+                    )
+                })
+                .collect::<Vec<_>>();
+            format!("[{}]\n", rows.join(","))
+        }
+    }
+}
+
+// Helper function: Escapes text for HTML.
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const STATEMENT_HEADERS: [&str; 4] = ["day", "event", "amount", "balance"];
+
+// Helper function: Returns the cells of the statement lines.
+fn statement_cells(statement: &Statement) -> Vec<Vec<String>> {
+    statement
+        .lines
+        .iter()
+        .map(|l| {
+            vec![
+                (l.at / DAY).to_string(),
+                l.event.to_string(),
+                l.amount.to_string(),
+                l.balance.to_string(),
+            ]
+        })
+        .collect()
+}
+
+// Helper function: Returns the labelled totals of a statement.
+fn statement_totals(statement: &Statement) -> [(&'static str, Balance); 4] {
+    [
+        ("Interest", statement.interest),
+        ("Tax", statement.tax),
+        ("Fees", statement.fees),
+        ("Closing balance", statement.closing_balance),
+    ]
+}
+
+/// Renders a statement as plain text.
+pub fn render_statement_text(statement: &Statement) -> String {
+    let mut out = format!(
+        "Statement for {} (User ID: {}), month {}\nOpening balance: {}\n",
+        statement.username, statement.id, statement.month, statement.opening_balance
+    );
+    out.push_str(&render_table(
+        &STATEMENT_HEADERS,
+        &statement_cells(statement),
+    ));
+    statement_totals(statement)
+        .iter()
+        .for_each(|(label, amount)| out.push_str(&format!("{}: {}\n", label, amount)));
+    out
+}
+
+/// Renders a statement as a standalone HTML page.
+pub fn render_statement_html(statement: &Statement) -> String {
+    let title = format!(
+        "Statement for {} (User ID: {}), month {}",
+        statement.username, statement.id, statement.month
+    );
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{0}</title></head>\n<body>\n<h1>{0}</h1>\n<p>Opening balance: {1}</p>\n<table>\n<tr>",
+        escape_html(&title),
+        statement.opening_balance
+    );
+    STATEMENT_HEADERS
+        .iter()
+        .for_each(|h| out.push_str(&format!("<th>{}</th>", h)));
+    out.push_str("</tr>\n");
+    statement_cells(statement).iter().for_each(|r| {
+        out.push_str("<tr>");
+        r.iter()
+            .for_each(|c| out.push_str(&format!("<td>{}</td>", escape_html(c))));
+        out.push_str("</tr>\n");
+    });
+    out.push_str("</table>\n<ul>\n");
+    statement_totals(statement)
+        .iter()
+        .for_each(|(label, amount)| out.push_str(&format!("<li>{}: {}</li>\n", label, amount)));
+    out.push_str("</ul>\n</body>\n</html>\n");
+    out
+}
+
+/// Renders the financial summary as text, with one table per section.
+pub fn render_summary_text(summary: &FinancialSummary) -> String {
+    let mut out = format!(
+        "Total deposits: {}\nTerm deposits: {}\nAccounts: {} active, {} reaped, {} empty\n",
+        summary.total_deposits,
+        summary.term_deposits,
+        summary.active_accounts,
+        summary.reaped_accounts,
+        summary.empty_accounts
+    );
+    out.push_str("Balance percentiles:\n");
+    out.push_str(&render_table(
+        &["percentile", "balance"],
+        &summary
+            .percentiles
+            .iter()
+            .map(|(p, b)| vec![format!("p{}", p), b.to_string()])
+            .collect::<Vec<_>>(),
+    ));
+    out.push_str("Balance histogram:\n");
+    out.push_str(&render_table(
+        &["from", "to", "count"],
+        &summary
+            .histogram
+            .iter()
+            .map(|b| vec![b.from.to_string(), b.to.to_string(), b.count.to_string()])
+            .collect::<Vec<_>>(),
+    ));
+    out.push_str("Interest and tax per month:\n");
+    out.push_str(&render_table(
+        &["month", "interest", "tax"],
+        &summary
+            .periods
+            .iter()
+            .map(|p| {
+                vec![
+                    p.month.to_string(),
+                    p.interest.to_string(),
+                    p.tax.to_string(),
+                ]
+            })
+            .collect::<Vec<_>>(),
+    ));
+    out.push_str("Top movers this month:\n");
+    out.push_str(&render_table(
+        &["id", "username", "change"],
+        &summary
+            .top_movers
+            .iter()
+            .map(|m| vec![m.id.to_string(), m.username.clone(), m.change.to_string()])
+            .collect::<Vec<_>>(),
+    ));
+    out
+}
+
+/// Renders the financial summary as a JSON object.
+pub fn render_summary_json(summary: &FinancialSummary) -> String {
+    let join = |items: Vec<String>| items.join(",");
+    format!(
+        "{{\"total_deposits\":{},\"term_deposits\":{},\"active_accounts\":{},\"reaped_accounts\":{},\"empty_accounts\":{},\"percentiles\":[{}],\"histogram\":[{}],\"periods\":[{}],\"top_movers\":[{}]}}\n",
+        summary.total_deposits,
+        summary.term_deposits,
+        summary.active_accounts,
+        summary.reaped_accounts,
+        summary.empty_accounts,
+        join(
+            summary
+                .percentiles
+                .iter()
+                .map(|(p, b)| format!("{{\"percentile\":{},\"balance\":{}}}", p, b))
+                .collect()
+        ),
+        join(
+            summary
+                .histogram
+                .iter()
+                .map(|b| format!("{{\"from\":{},\"to\":{},\"count\":{}}}", b.from, b.to, b.count))
+                .collect()
+        ),
+        join(
+            summary
+                .periods
+                .iter()
+                .map(|p| format!(
+                    "{{\"month\":{},\"interest\":{},\"tax\":{}}}",
+                    p.month, p.interest, p.tax
+                ))
+                .collect()
+        ),
+        join(
+            summary
+                .top_movers
+                .iter()
+                .map(|m| format!(
+                    "{{\"id\":{},\"username\":{},\"change\":{}}}",
+                    m.id,
+                    escape_json(&m.username),
+                    m.change
+                ))
+                .collect()
+        ),
+    )
+}
+
+const DISCREPANCY_HEADERS: [&str; 7] = [
+    "id",
+    "username",
+    "expected",
+    "actual",
+    "difference",
+    "index",
+    "first_diverging_event",
+];
+
+/// Renders the reconciliation discrepancies, with the first diverging event of each account.
+pub fn render_discrepancies(discrepancies: &[Discrepancy], format: ReportFormat) -> String {
+    let cells = |d: &Discrepancy| {
+        vec![
+            d.id.to_string(),
+            d.username.clone(),
+            d.expected.to_string(),
+            d.actual.to_string(),
+            (d.actual - d.expected).to_string(),
+            d.first_diverging
+                .as_ref()
+                .map(|r| r.index.to_string())
+                .unwrap_or_default(),
+            d.first_diverging
+                .as_ref()
+                .map(|r| r.event.to_string())
+                .unwrap_or_default(),
+        ]
+    };
+    match format {
+        ReportFormat::Table => render_table(
+            &DISCREPANCY_HEADERS,
+            &discrepancies.iter().map(cells).collect::<Vec<_>>(),
+        ),
+        ReportFormat::Csv => render_csv(
+            &DISCREPANCY_HEADERS,
+            &discrepancies.iter().map(cells).collect::<Vec<_>>(),
+        ),
+        ReportFormat::Json => {
+            let rows = discrepancies
+                .iter()
+                .map(|d| {
+                    format!(
+                        "{{\"id\":{},\"username\":{},\"expected\":{},\"actual\":{},\"first_diverging\":{}}}",
+                        d.id,
+                        escape_json(&d.username),
+                        d.expected,
+                        d.actual,
+                        d.first_diverging
+                            .as_ref()
+                            .map(|r| format!(
+                                "{{\"index\":{},\"at\":{},\"event\":{}}}",
+                                r.index,
+                                r.at,
+                                escape_json(&r.event.to_string())
+                            ))
+                            .unwrap_or_else(|| "null".to_string())
+                    )
+                })
+                .collect::<Vec<_>>();
+            format!("[{}]\n", rows.join(","))
+        }
+    }
+}
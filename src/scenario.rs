@@ -0,0 +1,567 @@
+//! Declarative end-to-end scenarios, written in TOML and run against a `Bank`.
+//!
+//! A scenario has a `name` and a list of `[[step]]` tables, run in order on a new bank. Every
+//! step has an `action`; operations act as a user who has logged in with a `login` step, and
+//! succeed unless the step gives the `error` they fail with (a `BankingError` variant name).
+//!
+//! ```toml
+//! name = "Deposit and withdraw"
+//!
+//! [[step]]
+//! action = "register"
+//! user = "roy"
+//! password = "secret"
+//!
+//! [[step]]
+//! action = "login"
+//! user = "roy"
+//! password = "secret"
+//!
+//! [[step]]
+//! action = "withdraw"
+//! user = "roy"
+//! amount = 50
+//! error = "InsufficientBalance"
+//!
+//! [[step]]
+//! action = "expect-events"
+//! user = "roy"
+//! events = []
+//! ```
+//!
+//! | Action              | Fields                                                       |
+//! |---------------------|--------------------------------------------------------------|
+//! | `register`          | `user`, `password`, `role` (`customer`, `manager`, `auditor`)|
+//! | `login`             | `user`, `password`                                           |
+//! | `logout`            | `user`                                                       |
+//! | `change-password`   | `user`, `password`; logs the user out                        |
+//! | `deposit`           | `user`, `amount`, `reference`                                |
+//! | `withdraw`          | `user`, `amount`, `reference`                                |
+//! | `transfer`          | `user`, `to` (user ID, username or payee), `amount`, `reference` |
+//! | `set-interest-rate` | `user`, `rate`                                               |
+//! | `pay-interest`      | `user`                                                       |
+//! | `set-tax-rate`      | `user`, `rate`                                               |
+//! | `take-tax`          | `user`                                                       |
+//! | `advance-clock`     | `user`, `days`                                               |
+//! | `reconcile`         | `user`                                                       |
+//! | `expect-balance`    | `user`, `balance`                                            |
+//! | `expect-events`     | `user`, `of` (user ID, staff only), `events`                 |
+//!
+//! Every step may also give `error`; any other field is refused. `role` defaults to `customer`
+//! and `reference` to none. `expect-events` compares the events a user sees, as printed by the
+//! CLI: a customer's own events, or for staff the events of user `of`, or every event.
+use crate::bank::Bank;
+use crate::primitives::*;
+use serde::Deserialize;
+use std::{cell::RefCell, collections::HashMap, fmt, fs};
+use toml::{Spanned, Table, Value};
+
+/// Largest difference between balances that still counts as equal.
+const BALANCE_TOLERANCE: Balance = 0.000_001f64;
+
+/// A scenario file, before its steps are parsed. Every step keeps its span in the file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    name: String,
+    #[serde(default)]
+    step: Vec<Spanned<Table>>,
+}
+
+/// An error in a scenario file, with the 1-based line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScenarioError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error, line {}: {}", self.line, self.message)
+    }
+}
+
+/// What a scenario step does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Register {
+        username: String,
+        password: String,
+        role: Role,
+    },
+    Login {
+        username: String,
+        password: String,
+    },
+    Logout {
+        username: String,
+    },
+    ChangePassword {
+        username: String,
+        password: String,
+    },
+    Deposit {
+        username: String,
+        amount: Balance,
+        reference: String,
+    },
+    Withdraw {
+        username: String,
+        amount: Balance,
+        reference: String,
+    },
+    Transfer {
+        username: String,
+        to: String,
+        amount: Balance,
+        reference: String,
+    },
+    SetInterestRate {
+        username: String,
+        rate: f64,
+    },
+    PayInterest {
+        username: String,
+    },
+    SetTaxRate {
+        username: String,
+        rate: f64,
+    },
+    TakeTax {
+        username: String,
+    },
+    AdvanceClock {
+        username: String,
+        days: Timestamp,
+    },
+    Reconcile {
+        username: String,
+    },
+    ExpectBalance {
+        username: String,
+        balance: Balance,
+    },
+    ExpectEvents {
+        username: String,
+        of: Option<UserId>,
+        events: Vec<String>,
+    },
+}
+
+/// A step of a scenario.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// 1-based line of the `[[step]]` header.
+    pub line: usize,
+    pub action: Action,
+    /// The `BankingError` variant the step is expected to fail with.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scenario {
+    pub name: String,
+    pub steps: Vec<Step>,
+}
+
+/// A step whose outcome differed from the scenario.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    /// 1-based index of the step.
+    pub step: usize,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Step {} (line {}): {}",
+            self.step, self.line, self.message
+        )
+    }
+}
+
+// Helper function: Returns the 1-based line of the byte at `offset` of `input`.
+fn line_of(input: &str, offset: usize) -> usize {
+    input[..offset.min(input.len())].matches('\n').count() + 1
+}
+
+/// Fields of a `[[step]]` table.
+struct Fields<'a> {
+    line: usize,
+    table: &'a Table,
+    /// The keys looked up so far, in order. Any other key of the table is unknown.
+    known: RefCell<Vec<&'static str>>,
+}
+
+impl Fields<'_> {
+    fn error(&self, message: String) -> ScenarioError {
+        ScenarioError {
+            line: self.line,
+            message,
+        }
+    }
+
+    fn get(&self, key: &'static str) -> Option<&Value> {
+        self.known.borrow_mut().push(key);
+        self.table.get(key)
+    }
+
+    // Helper function: Rejects a key the step's action has no use for, such as a misspelt
+    // `amount`, which would otherwise be ignored.
+    fn deny_unknown_fields(&self) -> Result<(), ScenarioError> {
+        let known = self.known.borrow();
+        match self.table.keys().find(|key| !known.contains(&key.as_str())) {
+            Some(key) => Err(self.error(format!(
+                "unknown field `{}`, expected one of {}",
+                key,
+                known
+                    .iter()
+                    .map(|k| format!("`{}`", k))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn optional_text(&self, key: &'static str) -> Result<Option<String>, ScenarioError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Value::String(text)) => Ok(Some(text.clone())),
+            Some(Value::Integer(n)) => Ok(Some(n.to_string())),
+            Some(Value::Float(n)) => Ok(Some(n.to_string())),
+            Some(_) => Err(self.error(format!("`{}` must be a string", key))),
+        }
+    }
+
+    fn text(&self, key: &'static str) -> Result<String, ScenarioError> {
+        self.optional_text(key)?
+            .ok_or_else(|| self.error(format!("missing `{}`", key)))
+    }
+
+    fn optional_number(&self, key: &'static str) -> Result<Option<f64>, ScenarioError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(Value::Integer(n)) => Ok(Some(*n as f64)),
+            Some(Value::Float(n)) => Ok(Some(*n)),
+            Some(_) => Err(self.error(format!("`{}` must be a number", key))),
+        }
+    }
+
+    fn number(&self, key: &'static str) -> Result<f64, ScenarioError> {
+        self.optional_number(key)?
+            .ok_or_else(|| self.error(format!("missing `{}`", key)))
+    }
+
+    fn whole_number(&self, key: &'static str) -> Result<Option<u64>, ScenarioError> {
+        match self.optional_number(key)? {
+            Some(n) if n >= 0f64 && n.fract() == 0f64 => Ok(Some(n as u64)),
+            Some(_) => Err(self.error(format!("`{}` must be a whole number", key))),
+            None => Ok(None),
+        }
+    }
+
+    fn texts(&self, key: &'static str) -> Result<Vec<String>, ScenarioError> {
+        match self.get(key) {
+            Some(Value::Array(values)) => values
+                .iter()
+                .map(|v| match v {
+                    Value::String(text) => Ok(text.clone()),
+                    _ => Err(self.error(format!("`{}` must hold strings", key))),
+                })
+                .collect(),
+            Some(_) => Err(self.error(format!("`{}` must be an array", key))),
+            None => Err(self.error(format!("missing `{}`", key))),
+        }
+    }
+
+    fn action(&self) -> Result<Action, ScenarioError> {
+        let username = self.text("user")?;
+        let reference = || Ok(self.optional_text("reference")?.unwrap_or_default());
+        let action = match self.text("action")?.as_str() {
+            "register" => Action::Register {
+                username,
+                password: self.text("password")?,
+                role: match self.optional_text("role")?.as_deref() {
+                    None | Some("customer") => Role::Customer,
+                    Some("manager") => Role::Manager,
+                    Some("auditor") => Role::Auditor,
+                    Some(r) => return Err(self.error(format!("unknown role `{}`", r))),
+                },
+            },
+            "login" => Action::Login {
+                username,
+                password: self.text("password")?,
+            },
+            "logout" => Action::Logout { username },
+            "change-password" => Action::ChangePassword {
+                username,
+                password: self.text("password")?,
+            },
+            "deposit" => Action::Deposit {
+                username,
+                amount: self.number("amount")?,
+                reference: reference()?,
+            },
+            "withdraw" => Action::Withdraw {
+                username,
+                amount: self.number("amount")?,
+                reference: reference()?,
+            },
+            "transfer" => Action::Transfer {
+                username,
+                to: self.text("to")?,
+                amount: self.number("amount")?,
+                reference: reference()?,
+            },
+            "set-interest-rate" => Action::SetInterestRate {
+                username,
+                rate: self.number("rate")?,
+            },
+            "pay-interest" => Action::PayInterest { username },
+            "set-tax-rate" => Action::SetTaxRate {
+                username,
+                rate: self.number("rate")?,
+            },
+            "take-tax" => Action::TakeTax { username },
+            "advance-clock" => Action::AdvanceClock {
+                username,
+                days: self
+                    .whole_number("days")?
+                    .ok_or_else(|| self.error("missing `days`".to_string()))?,
+            },
+            "reconcile" => Action::Reconcile { username },
+            "expect-balance" => Action::ExpectBalance {
+                username,
+                balance: self.number("balance")?,
+            },
+            "expect-events" => Action::ExpectEvents {
+                username,
+                of: self.whole_number("of")?.map(|id| id as UserId),
+                events: self.texts("events")?,
+            },
+            a => return Err(self.error(format!("unknown action `{}`", a))),
+        };
+        Ok(action)
+    }
+}
+
+impl Scenario {
+    /// Parses a scenario from TOML.
+    pub fn parse(input: &str) -> Result<Self, ScenarioError> {
+        let file = toml::from_str::<ScenarioFile>(input).map_err(|e| ScenarioError {
+            line: e.span().map_or(1, |span| line_of(input, span.start)),
+            message: e.message().trim().replace('\n', ", "),
+        })?;
+        let steps = file
+            .step
+            .iter()
+            .map(|table| {
+                let line = line_of(input, table.span().start);
+                let fields = Fields {
+                    line,
+                    table: table.get_ref(),
+                    known: RefCell::new(vec![]),
+                };
+                let step = Step {
+                    line,
+                    action: fields.action()?,
+                    error: fields.optional_text("error")?,
+                };
+                fields.deny_unknown_fields()?;
+                Ok(step)
+            })
+            .collect::<Result<_, ScenarioError>>()?;
+        Ok(Scenario {
+            name: file.name,
+            steps,
+        })
+    }
+
+    /// Reads and parses the scenario file at `path`.
+    pub fn load(path: &str) -> Result<Self, ScenarioError> {
+        let input = fs::read_to_string(path).map_err(|e| ScenarioError {
+            line: 0,
+            message: format!("failed to read `{}`: {}", path, e),
+        })?;
+        Self::parse(&input)
+    }
+
+    /// Runs the scenario on a new bank, and returns every step whose outcome differed.
+    pub fn run(&self) -> Vec<Failure> {
+        let mut bank = Bank::default();
+        bank.set_verbose(false);
+        let mut sessions = HashMap::new();
+        self.steps
+            .iter()
+            .enumerate()
+            .filter_map(|(i, step)| {
+                run_step(&mut bank, &mut sessions, step)
+                    .err()
+                    .map(|message| Failure {
+                        step: i + 1,
+                        line: step.line,
+                        message,
+                    })
+            })
+            .collect()
+    }
+}
+
+// Helper function: Runs a step against the bank, and describes how its outcome differed.
+fn run_step(
+    bank: &mut Bank,
+    sessions: &mut HashMap<String, HashResult>,
+    step: &Step,
+) -> Result<(), String> {
+    let session = |sessions: &HashMap<String, HashResult>, username: &String| {
+        sessions
+            .get(username)
+            .copied()
+            .ok_or_else(|| format!("{} is not logged in", username))
+    };
+    let result = match &step.action {
+        Action::Register {
+            username,
+            password,
+            role,
+        } => bank.create_user(username.clone(), password.clone(), *role),
+        Action::Login { username, password } => {
+            bank.login(username.clone(), password.clone())
+                .map(|(user, _)| {
+                    sessions.insert(username.clone(), user);
+                })
+        }
+        Action::Logout { username } => {
+            session(sessions, username)?;
+            sessions.remove(username);
+            Ok(())
+        }
+        Action::ChangePassword { username, password } => {
+            let user = session(sessions, username)?;
+            sessions.remove(username);
+            bank.change_password(user, password.clone())
+        }
+        Action::Deposit {
+            username,
+            amount,
+            reference,
+        } => {
+            let user = session(sessions, username)?;
+            PaymentReference::parse(reference)
+                .and_then(|reference| bank.deposit_with_reference(user, *amount, reference))
+        }
+        Action::Withdraw {
+            username,
+            amount,
+            reference,
+        } => {
+            let user = session(sessions, username)?;
+            PaymentReference::parse(reference)
+                .and_then(|reference| bank.withdraw_with_reference(user, *amount, reference))
+        }
+        Action::Transfer {
+            username,
+            to,
+            amount,
+            reference,
+        } => {
+            let user = session(sessions, username)?;
+            bank.resolve_payee(user, to).and_then(|(to, _)| {
+                PaymentReference::parse(reference).and_then(|reference| {
+                    bank.transfer_with_reference(user, *amount, to, reference)
+                })
+            })
+        }
+        Action::SetInterestRate { username, rate } => {
+            bank.set_interest_rate(session(sessions, username)?, *rate)
+        }
+        Action::PayInterest { username } => bank.pay_interest(session(sessions, username)?),
+        Action::SetTaxRate { username, rate } => {
+            bank.set_tax_rate(session(sessions, username)?, *rate)
+        }
+        Action::TakeTax { username } => bank.take_tax(session(sessions, username)?),
+        Action::AdvanceClock { username, days } => {
+            bank.advance_clock(session(sessions, username)?, days * DAY)
+        }
+        Action::Reconcile { username } => bank.reconcile(session(sessions, username)?).map(|_| ()),
+        Action::ExpectBalance { username, balance } => {
+            match bank.check_balance(session(sessions, username)?) {
+                Ok(actual) if (actual - balance).abs() > BALANCE_TOLERANCE => {
+                    return Err(format!(
+                        "expected a balance of {}, but got {}",
+                        balance, actual
+                    ))
+                }
+                result => result.map(|_| ()),
+            }
+        }
+        Action::ExpectEvents {
+            username,
+            of,
+            events,
+        } => {
+            let user = session(sessions, username)?;
+            let rows = bank.current_user(user).and_then(|u| match (u.role, of) {
+                (Role::Customer, _) => bank.my_events(user),
+                (role, Some(of)) => bank.user_events(user, role, *of),
+                (role, None) => bank.all_events(user, role),
+            });
+            match rows {
+                Ok(rows) => {
+                    let actual = rows.iter().map(|r| r.event.to_string()).collect::<Vec<_>>();
+                    if actual != *events {
+                        return Err(format!(
+                            "the events differ (- expected, + actual):\n{}",
+                            diff_lines(events, &actual)
+                        ));
+                    }
+                    Ok(())
+                }
+                Err(e) => Err(e),
+            }
+        }
+    };
+    match (result, &step.error) {
+        (Ok(()), None) => Ok(()),
+        (Ok(()), Some(expected)) => Err(format!("expected error {}, but it succeeded", expected)),
+        (Err(e), Some(expected)) if format!("{:?}", e) == *expected => Ok(()),
+        (Err(e), Some(expected)) => Err(format!("expected error {}, but got {:?}", expected, e)),
+        (Err(e), None) => Err(format!("unexpected error {:?}: {}", e, e)),
+    }
+}
+
+/// Returns a line diff of `expected` and `actual`: common lines start with two spaces, lines
+/// only in `expected` with `- `, and lines only in `actual` with `+ `.
+pub fn diff_lines(expected: &[String], actual: &[String]) -> String {
+    // Longest common subsequence of the suffixes.
+    let mut lcs = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            lines.push(format!("  {}", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j == actual.len() || (i < expected.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(format!("- {}", expected[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", actual[j]));
+            j += 1;
+        }
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,662 @@
+//! Asynchronous command service in front of a `Bank`.
+//!
+//! Callers submit typed `Command`s to a bounded queue. A single writer thread owns the bank and
+//! applies the commands one at a time, in the order they were queued, so the bank needs no
+//! locking. Each command's result comes back through a `Reply` future.
+//!
+//! When the queue is full, `try_send` fails with `ServiceError::QueueFull`, and the future
+//! returned by `send` waits until the writer makes room: this is the backpressure. `shutdown`
+//! stops accepting commands, lets the writer apply every command already queued, and returns
+//! the bank. Dropping the `WriterHandle` also stops accepting commands, but does not wait.
+//!
+//! A storage error fails the command with `BankingError::StorageFailure`. If applying a command
+//! panics, for instance in a fraud rule, the bank may be left half updated, so the writer stops:
+//! that command and every command still queued fail with `BankingError::ServiceStopped`, and
+//! `shutdown` resumes the panic.
+//!
+//! The futures work with any executor; `block_on` runs one on the current thread.
+use crate::bank::Bank;
+use crate::primitives::*;
+use std::{
+    collections::HashMap,
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, JoinHandle},
+};
+
+/// A request to the bank.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Register {
+        username: String,
+        password: String,
+        role: Role,
+    },
+    Login {
+        username: String,
+        password: String,
+    },
+    Deposit {
+        user: HashResult,
+        amount: Balance,
+        reference: PaymentReference,
+    },
+    Withdraw {
+        user: HashResult,
+        amount: Balance,
+        reference: PaymentReference,
+    },
+    Transfer {
+        user: HashResult,
+        amount: Balance,
+        to: UserId,
+        reference: PaymentReference,
+    },
+    Balance {
+        user: HashResult,
+    },
+    Events {
+        user: HashResult,
+    },
+    SetInterestRate {
+        user: HashResult,
+        rate: f64,
+    },
+    PayInterest {
+        user: HashResult,
+    },
+    SetTaxRate {
+        user: HashResult,
+        rate: f64,
+    },
+    TakeTax {
+        user: HashResult,
+    },
+}
+
+/// The result of a successful command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandOutput {
+    Done,
+    LoggedIn { user: HashResult, role: Role },
+    Balance(Balance),
+    Events(Vec<EventRow>),
+}
+
+impl Command {
+    /// Applies the command to the bank.
+    pub fn apply(self, bank: &mut Bank) -> BankResult<CommandOutput> {
+        match self {
+            Command::Register {
+                username,
+                password,
+                role,
+            } => bank
+                .create_user(username, password, role)
+                .map(|_| CommandOutput::Done),
+            Command::Login { username, password } => bank
+                .login(username, password)
+                .map(|(user, role)| CommandOutput::LoggedIn { user, role }),
+            Command::Deposit {
+                user,
+                amount,
+                reference,
+            } => bank
+                .deposit_with_reference(user, amount, reference)
+                .map(|_| CommandOutput::Done),
+            Command::Withdraw {
+                user,
+                amount,
+                reference,
+            } => bank
+                .withdraw_with_reference(user, amount, reference)
+                .map(|_| CommandOutput::Done),
+            Command::Transfer {
+                user,
+                amount,
+                to,
+                reference,
+            } => bank
+                .transfer_with_reference(user, amount, to, reference)
+                .map(|_| CommandOutput::Done),
+            Command::Balance { user } => bank.check_balance(user).map(CommandOutput::Balance),
+            Command::Events { user } => {
+                let rows = match bank.current_user(user)?.role {
+                    Role::Customer => bank.my_events(user),
+                    role => bank.all_events(user, role),
+                };
+                rows.map(CommandOutput::Events)
+            }
+            Command::SetInterestRate { user, rate } => bank
+                .set_interest_rate(user, rate)
+                .map(|_| CommandOutput::Done),
+            Command::PayInterest { user } => bank.pay_interest(user).map(|_| CommandOutput::Done),
+            Command::SetTaxRate { user, rate } => {
+                bank.set_tax_rate(user, rate).map(|_| CommandOutput::Done)
+            }
+            Command::TakeTax { user } => bank.take_tax(user).map(|_| CommandOutput::Done),
+        }
+    }
+}
+
+/// Why a command was not queued. The command is handed back.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServiceError {
+    /// The queue is full; try again later.
+    QueueFull(Command),
+    /// The service is shutting down, and takes no more commands.
+    ShutDown(Command),
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceError::QueueFull(_) => write!(f, "Error, the command queue is full."),
+            ServiceError::ShutDown(_) => write!(f, "Error, the service is shutting down."),
+        }
+    }
+}
+
+// Helper function: Locks a mutex. The state is only changed in small, complete steps, so a
+// poisoned lock is still consistent.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Where the writer leaves a command's result for its `Reply`.
+#[derive(Default)]
+struct Slot {
+    result: Option<BankResult<CommandOutput>>,
+    waker: Option<Waker>,
+}
+
+/// The result of a queued command, ready once the writer applied it.
+pub struct Reply {
+    slot: Arc<Mutex<Slot>>,
+}
+
+impl Future for Reply {
+    type Output = BankResult<CommandOutput>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = lock(&self.slot);
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+struct Queue {
+    commands: VecDeque<(Command, Arc<Mutex<Slot>>)>,
+    capacity: usize,
+    closed: bool,
+    /// Senders waiting for room in the queue, by sender ID.
+    senders: HashMap<u64, Waker>,
+    sender_id_counter: u64,
+}
+
+impl Queue {
+    // Helper function: Wakes every sender waiting for room.
+    fn wake_senders(&mut self) {
+        self.senders.drain().for_each(|(_, waker)| waker.wake());
+    }
+}
+
+// Helper function: Leaves the result in the slot, and wakes its `Reply`.
+fn resolve(slot: &Mutex<Slot>, result: BankResult<CommandOutput>) {
+    let mut slot = lock(slot);
+    slot.result = Some(result);
+    if let Some(waker) = slot.waker.take() {
+        waker.wake();
+    }
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    /// Signalled when a command is queued or the service shuts down.
+    ready: Condvar,
+}
+
+impl Shared {
+    // Helper function: Queues the command if there is room.
+    fn push(&self, queue: &mut Queue, command: Command) -> Result<Reply, ServiceError> {
+        if queue.closed {
+            return Err(ServiceError::ShutDown(command));
+        }
+        if queue.commands.len() >= queue.capacity {
+            return Err(ServiceError::QueueFull(command));
+        }
+        let slot = Arc::new(Mutex::new(Slot::default()));
+        queue.commands.push_back((command, slot.clone()));
+        self.ready.notify_one();
+        Ok(Reply { slot })
+    }
+}
+
+/// A handle to submit commands to the service. Handles are cheap to clone and can be used
+/// from any thread.
+#[derive(Clone)]
+pub struct BankService {
+    shared: Arc<Shared>,
+}
+
+/// The future returned by `BankService::send`: waits for room in the queue.
+pub struct Enqueue<'a> {
+    service: &'a BankService,
+    command: Option<Command>,
+    /// ID under which the waker of this sender waits for room, once it had to wait.
+    sender_id: Option<u64>,
+}
+
+impl Future for Enqueue<'_> {
+    type Output = Result<Reply, ServiceError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let command = match self.command.take() {
+            Some(command) => command,
+            None => panic!("`Enqueue` polled after completion."),
+        };
+        let shared = &self.service.shared;
+        let mut queue = lock(&shared.queue);
+        match shared.push(&mut queue, command) {
+            Err(ServiceError::QueueFull(command)) => {
+                // Replace the waker of an earlier poll, rather than waiting twice.
+                let sender_id = self.sender_id.unwrap_or_else(|| {
+                    queue.sender_id_counter += 1;
+                    queue.sender_id_counter
+                });
+                queue.senders.insert(sender_id, cx.waker().clone());
+                drop(queue);
+                self.sender_id = Some(sender_id);
+                self.command = Some(command);
+                Poll::Pending
+            }
+            result => {
+                if let Some(sender_id) = self.sender_id {
+                    queue.senders.remove(&sender_id);
+                }
+                Poll::Ready(result)
+            }
+        }
+    }
+}
+
+impl Drop for Enqueue<'_> {
+    fn drop(&mut self) {
+        if let Some(sender_id) = self.sender_id {
+            lock(&self.service.shared.queue).senders.remove(&sender_id);
+        }
+    }
+}
+
+/// The writer of a `BankService`, holding the bank until it is spawned.
+pub struct ServiceWriter {
+    bank: Bank,
+    shared: Arc<Shared>,
+}
+
+/// The running writer of a `BankService`, used to shut it down. Dropping the handle stops
+/// accepting commands; the writer applies the commands already queued, then exits.
+pub struct WriterHandle {
+    shared: Arc<Shared>,
+    /// `None` once joined by `shutdown`.
+    thread: Option<JoinHandle<Bank>>,
+}
+
+impl BankService {
+    /// Creates the service over the bank, with a queue holding up to `capacity` commands.
+    /// Commands can be queued before the writer is spawned. The bank is made quiet, so the
+    /// writer does not print.
+    pub fn new(mut bank: Bank, capacity: usize) -> (BankService, ServiceWriter) {
+        bank.set_verbose(false);
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                commands: VecDeque::new(),
+                capacity: capacity.max(1),
+                closed: false,
+                senders: HashMap::new(),
+                sender_id_counter: 0,
+            }),
+            ready: Condvar::new(),
+        });
+        let writer = ServiceWriter {
+            bank,
+            shared: shared.clone(),
+        };
+        (BankService { shared }, writer)
+    }
+
+    /// Creates the service and spawns its writer.
+    pub fn start(bank: Bank, capacity: usize) -> (BankService, WriterHandle) {
+        let (service, writer) = Self::new(bank, capacity);
+        (service, writer.spawn())
+    }
+
+    /// Queues the command if there is room, without waiting.
+    pub fn try_send(&self, command: Command) -> Result<Reply, ServiceError> {
+        let mut queue = lock(&self.shared.queue);
+        self.shared.push(&mut queue, command)
+    }
+
+    /// Queues the command, waiting for room if the queue is full.
+    pub fn send(&self, command: Command) -> Enqueue<'_> {
+        Enqueue {
+            service: self,
+            command: Some(command),
+            sender_id: None,
+        }
+    }
+
+    /// Queues the command and waits for its result.
+    pub async fn call(&self, command: Command) -> Result<BankResult<CommandOutput>, ServiceError> {
+        let reply = self.send(command).await?;
+        Ok(reply.await)
+    }
+
+    /// Returns the number of commands waiting in the queue.
+    pub fn pending(&self) -> usize {
+        lock(&self.shared.queue).commands.len()
+    }
+}
+
+impl ServiceWriter {
+    /// Spawns the writer thread, which applies the queued commands in order.
+    pub fn spawn(self) -> WriterHandle {
+        let ServiceWriter { mut bank, shared } = self;
+        WriterHandle {
+            shared: shared.clone(),
+            thread: Some(thread::spawn(move || {
+                loop {
+                    let (command, slot) = {
+                        let mut queue = lock(&shared.queue);
+                        while queue.commands.is_empty() && !queue.closed {
+                            queue = shared
+                                .ready
+                                .wait(queue)
+                                .unwrap_or_else(PoisonError::into_inner);
+                        }
+                        match queue.commands.pop_front() {
+                            Some(next) => {
+                                // A command left, so waiting senders may have room.
+                                queue.wake_senders();
+                                next
+                            }
+                            None => break,
+                        }
+                    };
+                    match panic::catch_unwind(AssertUnwindSafe(|| command.apply(&mut bank))) {
+                        Ok(result) => resolve(&slot, result),
+                        Err(panic) => {
+                            // Nobody waits forever: fail this command and the queued ones.
+                            resolve(&slot, Err(BankingError::ServiceStopped));
+                            let mut queue = lock(&shared.queue);
+                            queue.closed = true;
+                            queue.commands.drain(..).for_each(|(_, slot)| {
+                                resolve(&slot, Err(BankingError::ServiceStopped))
+                            });
+                            queue.wake_senders();
+                            drop(queue);
+                            panic::resume_unwind(panic);
+                        }
+                    }
+                }
+                bank
+            })),
+        }
+    }
+}
+
+impl WriterHandle {
+    // Helper function: Stops accepting commands. Senders waiting for room fail with
+    // `ServiceError::ShutDown`.
+    fn close(&self) {
+        lock(&self.shared.queue).closed = true;
+        lock(&self.shared.queue).wake_senders();
+        self.shared.ready.notify_all();
+    }
+
+    /// Stops accepting commands, waits until every queued command is applied, and returns the
+    /// bank. Senders waiting for room fail with `ServiceError::ShutDown`. Resumes the panic of
+    /// a command that panicked.
+    pub fn shutdown(mut self) -> Bank {
+        self.close();
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(bank)) => bank,
+            Some(Err(panic)) => panic::resume_unwind(panic),
+            None => unreachable!("the writer is only joined by `shutdown`"),
+        }
+    }
+}
+
+impl Drop for WriterHandle {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            self.close();
+        }
+    }
+}
+
+/// Wakes a thread parked in `block_on`.
+struct ThreadWaker(thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs a future to completion on the current thread.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = Box::pin(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{FraudRule, Movement};
+    use crate::storage::SqliteStorage;
+
+    #[test]
+    fn can_queue_commands_with_backpressure() {
+        let register = |username: &str| Command::Register {
+            username: username.to_string(),
+            password: "pw".to_string(),
+            role: Role::Customer,
+        };
+        let (service, writer) = BankService::new(Bank::default(), 2);
+        let first = service.try_send(register("roy")).unwrap();
+        let second = service.try_send(register("roy")).unwrap();
+        assert_eq!(
+            service.try_send(register("sam")).err(),
+            Some(ServiceError::QueueFull(register("sam")))
+        );
+
+        // A sender waits for room until the writer runs.
+        let waiting = {
+            let service = service.clone();
+            std::thread::spawn(move || block_on(service.call(register("sam"))))
+        };
+        let writer = writer.spawn();
+        assert_eq!(block_on(first), Ok(CommandOutput::Done));
+        assert_eq!(block_on(second), Err(BankingError::UserAlreadyExist));
+        assert_eq!(waiting.join().unwrap(), Ok(Ok(CommandOutput::Done)));
+
+        let roy = match block_on(service.call(Command::Login {
+            username: "roy".to_string(),
+            password: "pw".to_string(),
+        })) {
+            Ok(Ok(CommandOutput::LoggedIn { user, role })) => {
+                assert_eq!(role, Role::Customer);
+                user
+            }
+            other => panic!("Expected a login, but got {:?}", other),
+        };
+        let clients = (0..4)
+            .map(|_| {
+                let service = service.clone();
+                std::thread::spawn(move || {
+                    (0..25).for_each(|_| {
+                        let deposit = Command::Deposit {
+                            user: roy,
+                            amount: 10f64,
+                            reference: PaymentReference::default(),
+                        };
+                        assert_eq!(block_on(service.call(deposit)), Ok(Ok(CommandOutput::Done)));
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        clients.into_iter().for_each(|c| c.join().unwrap());
+        assert_eq!(
+            block_on(service.call(Command::Balance { user: roy })),
+            Ok(Ok(CommandOutput::Balance(1_000f64)))
+        );
+
+        // Shutting down applies the queued commands, then refuses new ones.
+        let withdrawals = (0..2)
+            .map(|_| {
+                service
+                    .try_send(Command::Withdraw {
+                        user: roy,
+                        amount: 100f64,
+                        reference: PaymentReference::default(),
+                    })
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let bank = writer.shutdown();
+        withdrawals
+            .into_iter()
+            .for_each(|w| assert_eq!(block_on(w), Ok(CommandOutput::Done)));
+        assert_eq!(bank.check_balance(roy), Ok(800f64));
+        assert_eq!(
+            service.try_send(Command::Balance { user: roy }).err(),
+            Some(ServiceError::ShutDown(Command::Balance { user: roy }))
+        );
+    }
+
+    #[test]
+    fn can_fail_replies_when_a_command_panics() {
+        // A rule failing in the middle of every withdrawal.
+        struct FailingRule;
+        impl FraudRule for FailingRule {
+            fn name(&self) -> String {
+                "failing".to_string()
+            }
+
+            fn evaluate(&self, _: &Movement, _: &[Movement]) -> FraudDecision {
+                panic!("The rule failed.")
+            }
+        }
+
+        let mut bank = Bank::default();
+        bank.set_verbose(false);
+        bank.create_user("roy".to_string(), "pw".to_string(), Role::Customer)
+            .unwrap();
+        bank.create_user("auditor".to_string(), "pw".to_string(), Role::Auditor)
+            .unwrap();
+        let (roy, _) = bank.login("roy".to_string(), "pw".to_string()).unwrap();
+        let (auditor, _) = bank.login("auditor".to_string(), "pw".to_string()).unwrap();
+        bank.deposit(roy, 200f64).unwrap();
+        bank.add_fraud_rule(auditor, Box::new(FailingRule)).unwrap();
+
+        let withdraw = Command::Withdraw {
+            user: roy,
+            amount: 100f64,
+            reference: PaymentReference::default(),
+        };
+        let (service, writer) = BankService::new(bank, 4);
+        let first = service.try_send(withdraw.clone()).unwrap();
+        let second = service.try_send(Command::Balance { user: roy }).unwrap();
+        let writer = writer.spawn();
+        assert_eq!(block_on(first), Err(BankingError::ServiceStopped));
+        assert_eq!(block_on(second), Err(BankingError::ServiceStopped));
+        assert_eq!(
+            service.try_send(withdraw.clone()).err(),
+            Some(ServiceError::ShutDown(withdraw))
+        );
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| writer.shutdown())).is_err());
+    }
+
+    #[test]
+    fn can_reply_with_storage_failures() {
+        let path = std::env::temp_dir().join(format!(
+            "banking_app_can_reply_with_storage_failures_{}.db",
+            std::process::id()
+        ));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        let mut bank = Bank::with_storage(Box::new(SqliteStorage::open(path).unwrap()));
+        bank.set_verbose(false);
+        bank.create_user("roy".to_string(), "pw".to_string(), Role::Customer)
+            .unwrap();
+        let (roy, _) = bank.login("roy".to_string(), "pw".to_string()).unwrap();
+
+        // The storage fails under the writer, which replies with the failure and carries on.
+        rusqlite::Connection::open(path)
+            .unwrap()
+            .execute_batch("DROP TABLE balances")
+            .unwrap();
+        let (service, writer) = BankService::new(bank, 4);
+        let deposit = service
+            .try_send(Command::Deposit {
+                user: roy,
+                amount: 100f64,
+                reference: PaymentReference::default(),
+            })
+            .unwrap();
+        let writer = writer.spawn();
+        assert_eq!(block_on(deposit), Err(BankingError::StorageFailure));
+        let login = service
+            .try_send(Command::Login {
+                username: "roy".to_string(),
+                password: "pw".to_string(),
+            })
+            .unwrap();
+        assert!(block_on(login).is_ok());
+        writer.shutdown();
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn can_keep_one_waker_per_sender() {
+        let balance = Command::Balance { user: 0 };
+        let (service, writer) = BankService::new(Bank::default(), 1);
+        let _queued = service.try_send(balance.clone()).unwrap();
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut sender = Box::pin(service.send(balance.clone()));
+        let senders = || lock(&service.shared.queue).senders.len();
+
+        // Polling again while the queue is full replaces the waker.
+        assert!(sender.as_mut().poll(&mut cx).is_pending());
+        assert!(sender.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(senders(), 1);
+        drop(sender);
+        assert_eq!(senders(), 0);
+
+        // Dropping the handle closes the queue.
+        drop(writer.spawn());
+        assert_eq!(
+            service.try_send(balance.clone()).err(),
+            Some(ServiceError::ShutDown(balance))
+        );
+    }
+}
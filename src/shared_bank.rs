@@ -0,0 +1,332 @@
+//! A bank that many threads can use at once.
+//!
+//! `Bank` needs `&mut self` for every change, so threads sharing one must serialize on a lock.
+//! `SharedBank` owns that lock: it wraps a real `Bank`, so every operation goes through the
+//! same checks as everywhere else (roles, amounts, ED reaping, fees, fraud rules, screening,
+//! KYC and the hash chained event log), whatever storage backend the bank uses.
+//!
+//! Every account also has a lock of its own. Deposits, withdrawals and transfers hold the
+//! locks of the accounts they touch, and only then take the lock of the bank, so operations on
+//! an account are applied in the order they got its lock, and threads working on different
+//! accounts wait only while the bank applies a change. Locks are always taken in this order, so
+//! no two threads wait on each other, e.g. on transfers between two accounts in opposite
+//! directions:
+//!
+//! 1. `accounts`, the map of account locks, only to look them up;
+//! 2. the locks of the accounts, in ascending user ID;
+//! 3. the bank.
+//!
+//! Each operation holds the lock of the bank while it checks, updates and logs, so it takes
+//! effect at a single point: balances are linearizable, and the event log lists the changes to
+//! each account in the order they happened. The core operations have methods of their own;
+//! `with` runs anything else against the bank under the same lock.
+//!
+//! If an operation panics, for instance in a fraud rule, the bank may be left half updated, so
+//! every later operation panics too rather than work on it.
+use crate::bank::Bank;
+use crate::primitives::*;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+pub struct SharedBank {
+    bank: Mutex<Bank>,
+    /// The lock of every account used so far.
+    accounts: Mutex<HashMap<UserId, Arc<Mutex<()>>>>,
+}
+
+impl Default for SharedBank {
+    /// A quiet bank in memory.
+    fn default() -> Self {
+        let mut bank = Bank::default();
+        bank.set_verbose(false);
+        Self::new(bank)
+    }
+}
+
+impl From<Bank> for SharedBank {
+    fn from(bank: Bank) -> Self {
+        Self::new(bank)
+    }
+}
+
+impl SharedBank {
+    /// Shares `bank` between threads.
+    pub fn new(bank: Bank) -> Self {
+        Self {
+            bank: Mutex::new(bank),
+            accounts: Default::default(),
+        }
+    }
+
+    /// Returns the bank, once no other thread uses it.
+    pub fn into_inner(self) -> Bank {
+        self.bank
+            .into_inner()
+            .expect("An operation on the shared bank panicked.")
+    }
+
+    // Helper function: Locks the bank.
+    fn lock(&self) -> MutexGuard<'_, Bank> {
+        self.bank
+            .lock()
+            .expect("An operation on the shared bank panicked.")
+    }
+
+    // Helper function: Returns the locks of the given accounts, in ascending user ID, each once.
+    // The locks guard no data, so a thread panicking while holding one cannot poison them.
+    fn account_locks(&self, mut ids: Vec<UserId>) -> Vec<Arc<Mutex<()>>> {
+        ids.sort_unstable();
+        ids.dedup();
+        let mut accounts = self.accounts.lock().unwrap_or_else(PoisonError::into_inner);
+        ids.into_iter()
+            .map(|id| accounts.entry(id).or_default().clone())
+            .collect()
+    }
+
+    // Helper function: Runs `f` against the bank while holding the locks of the current user's
+    // account and of the `others`.
+    fn with_accounts<T>(
+        &self,
+        user: HashResult,
+        others: &[UserId],
+        f: impl FnOnce(&mut Bank) -> BankResult<T>,
+    ) -> BankResult<T> {
+        let id = self.lock().current_user(user)?.id;
+        let locks = self.account_locks([&[id], others].concat());
+        let _guards = locks
+            .iter()
+            .map(|lock| lock.lock().unwrap_or_else(PoisonError::into_inner))
+            .collect::<Vec<_>>();
+        f(&mut self.lock())
+    }
+
+    /// Runs `f` against the bank, with no other operation in between.
+    pub fn with<T>(&self, f: impl FnOnce(&mut Bank) -> T) -> T {
+        f(&mut self.lock())
+    }
+
+    /// Returns the minimum balance an account must hold.
+    pub fn existential_deposit(&self) -> Balance {
+        self.lock().existential_deposit()
+    }
+
+    /// Add a new user. See `Bank::create_user`.
+    pub fn create_user(&self, username: String, password: String, role: Role) -> BankResult<()> {
+        self.lock().create_user(username, password, role)
+    }
+
+    /// Tries to log in with the given username and password. See `Bank::login`.
+    pub fn login(&self, username: String, password: String) -> BankResult<(HashResult, Role)> {
+        self.lock().login(username, password)
+    }
+
+    /// Returns the user's balance. See `Bank::check_balance`.
+    pub fn check_balance(&self, user: HashResult) -> BankResult<Balance> {
+        self.lock().check_balance(user)
+    }
+
+    /// Deposits the given `amount` into the user's account. See `Bank::deposit`.
+    pub fn deposit(&self, user: HashResult, amount: Balance) -> BankResult<()> {
+        self.with_accounts(user, &[], |bank| bank.deposit(user, amount))
+    }
+
+    /// Withdraw `amount` funds from the user's account. See `Bank::withdraw`.
+    pub fn withdraw(&self, user: HashResult, amount: Balance) -> BankResult<()> {
+        self.with_accounts(user, &[], |bank| bank.withdraw(user, amount))
+    }
+
+    /// Transfer `amount` from the current user to another user. See `Bank::transfer`.
+    pub fn transfer(&self, user: HashResult, amount: Balance, target: UserId) -> BankResult<()> {
+        self.with_accounts(user, &[target], |bank| bank.transfer(user, amount, target))
+    }
+
+    /// Returns a copy of the event log.
+    pub fn events(&self) -> Vec<Event> {
+        self.lock().events().into_iter().map(|e| e.event).collect()
+    }
+
+    /// Returns the balance of every customer at a single point in time, ordered by user ID.
+    pub fn balances(&self) -> Vec<(UserId, Balance)> {
+        self.lock().balances()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::RecvTimeoutError;
+
+    #[test]
+    fn can_share_bank_between_threads() {
+        let bank = SharedBank::default();
+        let register =
+            |username: &str, role| bank.create_user(username.to_string(), "pw".to_string(), role);
+        assert_eq!(register("roy", Role::Customer), Ok(()));
+        assert_eq!(register("sam", Role::Customer), Ok(()));
+        assert_eq!(register("manager", Role::Manager), Ok(()));
+        assert_eq!(
+            register("roy", Role::Customer),
+            Err(BankingError::UserAlreadyExist)
+        );
+        let (roy, _) = bank.login("roy".to_string(), "pw".to_string()).unwrap();
+        let (manager, _) = bank.login("manager".to_string(), "pw".to_string()).unwrap();
+
+        assert_eq!(bank.deposit(roy, 1f64), Err(BankingError::AmountTooSmall));
+        assert_eq!(
+            bank.deposit(roy, f64::NAN),
+            Err(BankingError::InvalidAmount)
+        );
+        assert_eq!(bank.deposit(roy, 100f64), Ok(()));
+        assert_eq!(
+            bank.deposit(manager, 100f64),
+            Err(BankingError::Unauthorized)
+        );
+        assert_eq!(
+            bank.transfer(roy, 10f64, 3),
+            Err(BankingError::InvalidUserId)
+        );
+        assert_eq!(
+            bank.transfer(roy, 1000f64, 2),
+            Err(BankingError::InsufficientBalance)
+        );
+
+        // The rules of the bank apply: here, a transfer fee.
+        let rule = FeeRule {
+            flat: 2f64,
+            percentage: 0f64,
+            min: 0f64,
+            max: None,
+        };
+        assert_eq!(
+            bank.with(|b| b.set_fee_rule(manager, FeeType::Transfer, rule)),
+            Ok(())
+        );
+        assert_eq!(bank.transfer(roy, 95f64, 2), Ok(()));
+        assert_eq!(bank.check_balance(roy), Ok(0f64));
+        assert_eq!(bank.balances(), vec![(1, 0f64), (2, 95f64)]);
+        assert_eq!(bank.with(|b| b.fee_income(manager)), Ok(2f64));
+        assert_eq!(
+            bank.events()[2..],
+            [
+                Event::AccountReaped { id: 1, dust: 3f64 },
+                Event::Transfer {
+                    id: 1,
+                    to_id: 2,
+                    amount: 95f64,
+                    reference: None
+                },
+                Event::Fee {
+                    id: 1,
+                    fee_type: FeeType::Transfer,
+                    amount: 2f64
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn can_transfer_concurrently_without_losing_money() {
+        const CUSTOMERS: u64 = 20;
+        const THREADS: u64 = 16;
+        const TRANSFERS: u64 = 500;
+        const INITIAL: Balance = 1_000f64;
+
+        let bank = std::sync::Arc::new(SharedBank::default());
+        bank.create_user("manager".to_string(), "pw".to_string(), Role::Manager)
+            .unwrap();
+        let (manager, _) = bank.login("manager".to_string(), "pw".to_string()).unwrap();
+        let users = (1..=CUSTOMERS)
+            .map(|i| {
+                let name = format!("customer{}", i);
+                bank.create_user(name.clone(), "pw".to_string(), Role::Customer)
+                    .unwrap();
+                let (user, _) = bank.login(name, "pw".to_string()).unwrap();
+                bank.deposit(user, INITIAL).unwrap();
+                user
+            })
+            .collect::<Vec<_>>();
+        let total = INITIAL * CUSTOMERS as f64;
+
+        // Fees move money out of the accounts, to the income of the bank.
+        let rule = FeeRule {
+            flat: 1f64,
+            percentage: 0f64,
+            min: 0f64,
+            max: None,
+        };
+        bank.with(|b| {
+            b.set_fee_rule(manager, FeeType::Transfer, rule)?;
+            b.set_fee_rule(manager, FeeType::Overdraft, rule)
+        })
+        .unwrap();
+
+        // Threads come in pairs that draw the same accounts and transfer between them in
+        // opposite directions, so they lock the same two accounts at once.
+        let (done, finished) = std::sync::mpsc::channel();
+        let workers = (0..THREADS)
+            .map(|t| {
+                let (bank, users, done) = (bank.clone(), users.clone(), done.clone());
+                std::thread::spawn(move || {
+                    // Xorshift, seeded per pair of threads.
+                    let mut seed = 0x9E37_79B9_7F4A_7C15u64 ^ (t / 2 + 1);
+                    let mut next = move || {
+                        seed ^= seed << 13;
+                        seed ^= seed >> 7;
+                        seed ^= seed << 17;
+                        seed
+                    };
+                    for _ in 0..TRANSFERS {
+                        let (a, b) = (next() % CUSTOMERS, next() % CUSTOMERS);
+                        let (from, to) = if t % 2 == 0 { (a, b) } else { (b, a) };
+                        let amount = (next() % 300 + 5) as Balance;
+                        // The manager, user 1, has no account.
+                        let _ = bank.transfer(users[from as usize], amount, to + 2);
+                        if next() % 10 == 0 {
+                            let snapshot = bank.balances();
+                            assert!(snapshot.iter().map(|(_, b)| b).sum::<Balance>() <= total);
+                        }
+                    }
+                    done.send(()).unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(done);
+        (0..THREADS).for_each(|_| {
+            // A worker that panicked never reports, and joining it below resumes its panic.
+            let timeout = std::time::Duration::from_secs(120);
+            if let Err(RecvTimeoutError::Timeout) = finished.recv_timeout(timeout) {
+                panic!("The transfers deadlocked.");
+            }
+        });
+        workers.into_iter().for_each(|w| w.join().unwrap());
+
+        // Every unit of money is in an account, was charged as a fee, or was dust reaped from
+        // an account.
+        let balances = bank.balances();
+        let events = bank.events();
+        let dust = events
+            .iter()
+            .map(|e| match e {
+                Event::AccountReaped { dust, .. } => *dust,
+                _ => 0f64,
+            })
+            .sum::<Balance>();
+        let fee_income = bank.with(|b| b.fee_income(manager)).unwrap();
+        assert!(fee_income > 0f64);
+        assert_eq!(
+            balances.iter().map(|(_, b)| b).sum::<Balance>() + fee_income + dust,
+            total
+        );
+        assert!(events.len() > (CUSTOMERS + THREADS) as usize);
+
+        // Replaying the log in order gives the same balances.
+        balances.iter().for_each(|(id, balance)| {
+            let replayed = events
+                .iter()
+                .fold(0f64, |total, e| total + e.balance_change(*id));
+            assert_eq!(replayed, *balance, "Balance of user {} drifted.", id);
+        });
+    }
+}
@@ -0,0 +1,190 @@
+//! Where a `Bank` keeps its users, their credentials, the balances and the event log.
+//!
+//! `MemoryStorage` keeps them in maps, and `SqliteStorage` in an embedded SQLite database.
+//! The backend is chosen when the bank is created, see `Bank::with_storage`; the binaries read
+//! it from the `BANKING_STORAGE` environment variable:
+//!
+//! | `BANKING_STORAGE` | Backend                                |
+//! |-------------------|----------------------------------------|
+//! | unset, `memory`   | `MemoryStorage`                        |
+//! | `sqlite`          | `SqliteStorage` in memory              |
+//! | `sqlite:<path>`   | `SqliteStorage` in the file at `path`  |
+//!
+//! Every event is stored with the hashes chaining it to the log, and the log with its signed
+//! checkpoints, see `Bank::verify_log`. The other state of the bank stays in memory, so a bank
+//! reopened on a database file keeps only its users, credentials, balances and event log. Its
+//! payees, pending transfers and holds, fee rules, waivers and income, loans, term deposits,
+//! fraud rules and reviews, compliance reports, profiles and KYC statuses, watch list and
+//! screening hits, and rates start over from the defaults, and its transfer, loan and term
+//! deposit IDs start over too. `bank_from_env` warns about this.
+//!
+//! Every operation of the bank runs in a transaction, so its changes are saved together or not
+//! at all.
+use crate::bank::{Bank, SIGNING_KEY_ENV};
+use crate::primitives::*;
+use std::{env, fmt};
+
+mod codec;
+mod memory;
+mod sqlite;
+
+pub(crate) use codec::{encode_event, escape, unescape};
+pub use memory::MemoryStorage;
+pub use sqlite::SqliteStorage;
+
+/// Name of the environment variable selecting the storage backend.
+pub const STORAGE_ENV: &str = "BANKING_STORAGE";
+
+/// Storage for the data of a `Bank`. Users are stored under the hash of their username and
+/// password, which serves as their credentials.
+///
+/// The bank checks its rules before it changes the storage, so the storage does not validate
+/// anything. Backends must be `Send`, so a bank can move between threads.
+pub trait Storage: Send {
+    /// Starts a transaction, ended by `commit` or `rollback`. Transactions nest: the changes of
+    /// an inner one are saved when the outermost one commits, and discarded when it or one
+    /// around it rolls back. No transaction is started if this fails.
+    fn begin(&mut self) -> Result<(), StorageError>;
+
+    /// Ends the innermost transaction, keeping its changes. If this fails, the transaction is
+    /// rolled back instead.
+    fn commit(&mut self) -> Result<(), StorageError>;
+
+    /// Ends the innermost transaction, discarding its changes.
+    fn rollback(&mut self) -> Result<(), StorageError>;
+
+    /// Returns the first failure of the methods below since the last call, and forgets it.
+    /// These methods do not fail: a backend that cannot do what one asks (e.g. a database
+    /// that is locked for too long, or on a full disk) records the failure here, and the method
+    /// returns the default, such as no user or no events. The default never fails.
+    fn take_failure(&self) -> Option<StorageError> {
+        None
+    }
+
+    /// Adds a user, stored under `hash`.
+    fn insert_user(&mut self, hash: HashResult, user: User);
+
+    /// Returns the user stored under `hash`.
+    fn user(&self, hash: HashResult) -> Option<User>;
+
+    /// Returns the user with the given ID.
+    fn user_by_id(&self, id: UserId) -> Option<User>;
+
+    /// Returns the user with the given username.
+    fn user_by_username(&self, username: &str) -> Option<User>;
+
+    /// Stores the user stored under `old` under `new` instead. Returns false if no user is
+    /// stored under `old`.
+    fn set_credentials(&mut self, old: HashResult, new: HashResult) -> bool;
+
+    /// Returns every user, ordered by ID.
+    fn users(&self) -> Vec<User>;
+
+    /// Returns the highest user ID, zero if there are no users.
+    fn last_user_id(&self) -> UserId;
+
+    /// Returns the balance of the user's account, or `None` if the account holds no funds.
+    fn balance(&self, id: UserId) -> Option<Balance>;
+
+    fn set_balance(&mut self, id: UserId, balance: Balance);
+
+    /// Removes the user's balance, as the account is empty or reaped.
+    fn remove_balance(&mut self, id: UserId);
+
+    /// Returns every balance, ordered by user ID.
+    fn balances(&self) -> Vec<(UserId, Balance)>;
+
+    /// Appends the event to the log, logged at `at` and chained to the log by `link`.
+    fn push_event(&mut self, event: Event, at: Timestamp, link: LogLink);
+
+    /// Returns the number of events logged.
+    fn event_count(&self) -> usize;
+
+    /// Returns the event at the given position of the log.
+    fn event(&self, index: usize) -> Option<EventRow>;
+
+    /// Returns the hashes stored with the event at the given position of the log.
+    fn event_link(&self, index: usize) -> Option<LogLink>;
+
+    /// Calls `f` on every event of the log and its hashes, oldest first.
+    fn for_each_entry(&self, f: &mut dyn FnMut(&EventRow, &LogLink));
+
+    /// Returns the positions of the events related to the user, oldest first.
+    fn event_positions(&self, id: UserId) -> Vec<usize>;
+
+    /// Adds a signed checkpoint of the log.
+    fn push_checkpoint(&mut self, checkpoint: Checkpoint);
+
+    /// Returns every checkpoint of the log, oldest first.
+    fn checkpoints(&self) -> Vec<Checkpoint>;
+
+    /// Calls `f` on every event of the log, oldest first.
+    fn for_each_event(&self, f: &mut dyn FnMut(&EventRow)) {
+        self.for_each_entry(&mut |row, _| f(row));
+    }
+
+    /// Returns every event of the log, oldest first.
+    fn events(&self) -> Vec<EventRow> {
+        let mut events = Vec::with_capacity(self.event_count());
+        self.for_each_event(&mut |row| events.push(row.clone()));
+        events
+    }
+}
+
+/// A storage backend that could not be opened, or failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageError(pub String);
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error, the storage failed: {}", self.0)
+    }
+}
+
+/// Opens the storage backend described by `spec`, in the format of `BANKING_STORAGE`.
+pub fn open_storage(spec: &str) -> Result<Box<dyn Storage>, StorageError> {
+    match spec {
+        "" | "memory" => Ok(Box::new(MemoryStorage::new())),
+        "sqlite" => Ok(Box::new(SqliteStorage::in_memory()?)),
+        _ => match spec.strip_prefix("sqlite:") {
+            Some(path) if !path.is_empty() => Ok(Box::new(SqliteStorage::open(path)?)),
+            _ => Err(StorageError(format!("unknown backend {:?}", spec))),
+        },
+    }
+}
+
+/// Opens the storage backend selected by the `BANKING_STORAGE` environment variable.
+pub fn storage_from_env() -> Result<Box<dyn Storage>, StorageError> {
+    open_storage(&env::var(STORAGE_ENV).unwrap_or_default())
+}
+
+/// Returns an error unless `BANKING_SIGNING_KEY` is set. Without it, the event log of `target`
+/// would be signed with the development key, which anyone can sign with.
+pub fn require_signing_key(target: &str) -> Result<(), StorageError> {
+    match env::var(SIGNING_KEY_ENV) {
+        Ok(key) if !key.is_empty() => Ok(()),
+        _ => Err(StorageError(format!(
+            "{} must be set to keep the event log in {}",
+            SIGNING_KEY_ENV, target
+        ))),
+    }
+}
+
+/// Opens a bank on the storage backend selected by the `BANKING_STORAGE` environment variable.
+/// A database file needs `BANKING_SIGNING_KEY`. If the storage already holds a bank, warns on
+/// stderr that only part of it was kept.
+pub fn bank_from_env() -> Result<Bank, StorageError> {
+    let spec = env::var(STORAGE_ENV).unwrap_or_default();
+    if spec.starts_with("sqlite:") {
+        require_signing_key(&spec)?;
+    }
+    let storage = open_storage(&spec)?;
+    if !storage.users().is_empty() {
+        eprintln!(
+            "Warning: only the users, credentials, balances and event log of the bank are \
+             kept in storage; its payees, pending transfers, fees, loans, term deposits, \
+             fraud reviews, profiles, watch list and rates start over."
+        );
+    }
+    Ok(Bank::with_storage(storage))
+}
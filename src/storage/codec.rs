@@ -0,0 +1,281 @@
+//! Text encoding of events, used by storage backends that persist the event log.
+//!
+//! An event is encoded as one line of tab separated fields: the name of the variant, then its
+//! fields in declaration order. `None` is written as `0`, and `Some(value)` as `1` followed by
+//! the value. Tabs, line breaks and backslashes in text are escaped.
+use crate::primitives::*;
+use std::str::Split;
+
+type Fields<'a> = Split<'a, char>;
+
+/// A value that can be written as, and read back from, one or more fields.
+trait Field: Sized {
+    fn write(&self, fields: &mut Vec<String>);
+    fn read(fields: &mut Fields) -> Option<Self>;
+}
+
+macro_rules! display_field {
+    ($($ty:ty),*) => {
+        $(impl Field for $ty {
+            fn write(&self, fields: &mut Vec<String>) {
+                fields.push(self.to_string());
+            }
+
+            fn read(fields: &mut Fields) -> Option<Self> {
+                fields.next()?.parse().ok()
+            }
+        })*
+    };
+}
+
+display_field!(u32, u64, usize, f64, bool);
+
+macro_rules! unit_enum_field {
+    ($($ty:ident { $($variant:ident),* })*) => {
+        $(impl Field for $ty {
+            fn write(&self, fields: &mut Vec<String>) {
+                fields.push(format!("{:?}", self));
+            }
+
+            fn read(fields: &mut Fields) -> Option<Self> {
+                match fields.next()? {
+                    $(stringify!($variant) => Some($ty::$variant),)*
+                    _ => None,
+                }
+            }
+        })*
+    };
+}
+
+unit_enum_field! {
+    FeeType {
+        Withdrawal,
+        Transfer,
+        Overdraft,
+        MonthlyMaintenance,
+        BelowMinimumBalance,
+        LatePayment,
+        EarlyWithdrawal
+    }
+    LoanStatus { Pending, Rejected, Active, Delinquent, Defaulted, Repaid }
+    FraudDecision { Allow, Hold, Deny }
+    ReportKind { LargeCashTransaction, Structuring }
+    ReportStatus { Open, Reviewed, Filed }
+    KycStatus { Unverified, Pending, Verified, Rejected }
+}
+
+/// Escapes tabs, line breaks and backslashes in `text`, so it fits in one field.
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    text.chars().for_each(|c| match c {
+        '\\' => escaped.push_str("\\\\"),
+        '\t' => escaped.push_str("\\t"),
+        '\n' => escaped.push_str("\\n"),
+        '\r' => escaped.push_str("\\r"),
+        c => escaped.push(c),
+    });
+    escaped
+}
+
+/// Reverses `escape`. Returns `None` for an invalid escape sequence.
+pub(crate) fn unescape(field: &str) -> Option<String> {
+    let mut text = String::new();
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        text.push(match c {
+            '\\' => match chars.next()? {
+                '\\' => '\\',
+                't' => '\t',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            },
+            c => c,
+        });
+    }
+    Some(text)
+}
+
+impl Field for String {
+    fn write(&self, fields: &mut Vec<String>) {
+        fields.push(escape(self));
+    }
+
+    fn read(fields: &mut Fields) -> Option<Self> {
+        unescape(fields.next()?)
+    }
+}
+
+impl<T: Field> Field for Option<T> {
+    fn write(&self, fields: &mut Vec<String>) {
+        match self {
+            Some(value) => {
+                fields.push("1".to_string());
+                value.write(fields);
+            }
+            None => fields.push("0".to_string()),
+        }
+    }
+
+    fn read(fields: &mut Fields) -> Option<Self> {
+        match fields.next()? {
+            "1" => T::read(fields).map(Some),
+            "0" => Some(None),
+            _ => None,
+        }
+    }
+}
+
+impl Field for PaymentReference {
+    fn write(&self, fields: &mut Vec<String>) {
+        self.memo.write(fields);
+        self.creditor_reference.write(fields);
+    }
+
+    fn read(fields: &mut Fields) -> Option<Self> {
+        Some(PaymentReference {
+            memo: Field::read(fields)?,
+            creditor_reference: Field::read(fields)?,
+        })
+    }
+}
+
+impl Field for FeeRule {
+    fn write(&self, fields: &mut Vec<String>) {
+        self.flat.write(fields);
+        self.percentage.write(fields);
+        self.min.write(fields);
+        self.max.write(fields);
+    }
+
+    fn read(fields: &mut Fields) -> Option<Self> {
+        Some(FeeRule {
+            flat: Field::read(fields)?,
+            percentage: Field::read(fields)?,
+            min: Field::read(fields)?,
+            max: Field::read(fields)?,
+        })
+    }
+}
+
+impl Field for ComplianceThresholds {
+    fn write(&self, fields: &mut Vec<String>) {
+        self.cash_threshold.write(fields);
+        self.structuring_margin.write(fields);
+        self.structuring_count.write(fields);
+        self.structuring_window.write(fields);
+    }
+
+    fn read(fields: &mut Fields) -> Option<Self> {
+        Some(ComplianceThresholds {
+            cash_threshold: Field::read(fields)?,
+            structuring_margin: Field::read(fields)?,
+            structuring_count: Field::read(fields)?,
+            structuring_window: Field::read(fields)?,
+        })
+    }
+}
+
+impl Field for KycPolicy {
+    fn write(&self, fields: &mut Vec<String>) {
+        match self {
+            KycPolicy::Unrestricted => fields.push("Unrestricted".to_string()),
+            KycPolicy::Limit(limit) => {
+                fields.push("Limit".to_string());
+                limit.write(fields);
+            }
+            KycPolicy::NoTransfers => fields.push("NoTransfers".to_string()),
+        }
+    }
+
+    fn read(fields: &mut Fields) -> Option<Self> {
+        match fields.next()? {
+            "Unrestricted" => Some(KycPolicy::Unrestricted),
+            "Limit" => Field::read(fields).map(KycPolicy::Limit),
+            "NoTransfers" => Some(KycPolicy::NoTransfers),
+            _ => None,
+        }
+    }
+}
+
+// Every variant must be listed with all of its fields, or the patterns below do not compile.
+macro_rules! event_codec {
+    ($($variant:ident { $($field:ident),* })*) => {
+        /// Encodes the event as a line of tab separated fields, without the line break.
+        pub(crate) fn encode_event(event: &Event) -> String {
+            let mut fields = vec![];
+            match event {
+                $(Event::$variant { $($field),* } => {
+                    fields.push(stringify!($variant).to_string());
+                    $($field.write(&mut fields);)*
+                })*
+            }
+            fields.join("\t")
+        }
+
+        /// Decodes a line written by `encode_event`.
+        pub(crate) fn decode_event(line: &str) -> Option<Event> {
+            let mut fields = line.split('\t');
+            let event = match fields.next()? {
+                $(stringify!($variant) => Event::$variant {
+                    $($field: Field::read(&mut fields)?),*
+                },)*
+                _ => return None,
+            };
+            match fields.next() {
+                Some(_) => None,
+                None => Some(event),
+            }
+        }
+    };
+}
+
+event_codec! {
+    Deposit { id, amount, reference }
+    Withdrawal { id, amount, reference }
+    AccountReaped { id, dust }
+    Transfer { id, to_id, amount, reference }
+    Interest { id, interest }
+    Tax { id, tax }
+    InterestRate { id, interest_rate }
+    TaxRate { id, tax_rate }
+    PayeeAdded { id, payee }
+    PayeeRemoved { id, payee }
+    TransferInitiated { transfer_id, id, to_id, amount, reference }
+    TransferSettled { transfer_id, id, to_id }
+    TransferCancelled { transfer_id, id, to_id }
+    TransferReversed { transfer_id, id, to_id, amount, original, by }
+    Fee { id, fee_type, amount }
+    FeeRuleSet { id, fee_type, rule }
+    MinimumBalanceSet { id, minimum_balance }
+    FeeWaiverGranted { id, fee_type, by }
+    FeeWaiverRevoked { id, fee_type, by }
+    ClockAdvanced { id, now }
+    LoanProductCreated { product_id, annual_rate, term, by }
+    LoanApplied { loan_id, id, product_id, principal }
+    LoanRejected { loan_id, id, by }
+    LoanDisbursed { loan_id, id, amount, by }
+    LoanRepayment { loan_id, id, installment, principal, interest }
+    LoanLateFee { loan_id, id, installment, fee }
+    LoanStatusChanged { loan_id, id, status }
+    TermDepositRate { id, rate }
+    TermDepositOpened { deposit_id, id, amount, rate, matures_at, rolled_over_from }
+    TermDepositMatured { deposit_id, id, principal, rolled_over_into }
+    TermDepositBroken { deposit_id, id, principal }
+    FraudRuleAdded { id, rule }
+    FraudRuleRemoved { id, rule }
+    FraudCheck { id, to_id, amount, rule, decision, review_id }
+    FraudReviewed { review_id, id, approved, by }
+    ComplianceThresholdsSet { id, thresholds }
+    ComplianceReportCreated { report_id, id, kind, amount }
+    ComplianceReportStatusChanged { report_id, id, status, by }
+    WatchListLoaded { id, entries }
+    ScreeningThresholdsSet { id, flag, block }
+    ScreeningHit { hit_id, id, name, entry, blocked }
+    ScreeningHitCleared { hit_id, by }
+    ProfileUpdated { id, version, by }
+    KycStatusChanged { id, status, by }
+    KycPolicySet { id, policy }
+    ReconciliationToleranceSet { id, tolerance }
+    Reconciled { id, discrepancies }
+}
@@ -0,0 +1,371 @@
+//! Storage in `HashMap`s and `Vec`s, lost when the bank is dropped.
+use super::{Storage, StorageError};
+use crate::primitives::*;
+use std::collections::HashMap;
+
+/// A change made in a transaction, with what undoes it.
+enum Undo {
+    InsertUser {
+        hash: HashResult,
+        last_user_id: UserId,
+    },
+    SetCredentials {
+        old: HashResult,
+        new: HashResult,
+    },
+    SetBalance {
+        id: UserId,
+        balance: Option<Balance>,
+    },
+    PushEvent,
+    PushCheckpoint,
+}
+
+#[derive(Default)]
+pub struct MemoryStorage {
+    users: HashMap<HashResult, User>,
+    /// Index of `users`: the ID of every username.
+    user_ids: HashMap<String, UserId>,
+    /// Index of `users`: the hash every user is stored under.
+    user_hashes: HashMap<UserId, HashResult>,
+    last_user_id: UserId,
+    balances: HashMap<UserId, Balance>,
+    events: Vec<EventRow>,
+    links: Vec<LogLink>,
+    checkpoints: Vec<Checkpoint>,
+    /// Index of `events`: the positions of the events related to each user.
+    event_positions: HashMap<UserId, Vec<usize>>,
+    /// The changes made in the open transactions, oldest first.
+    undo: Vec<Undo>,
+    /// Where the changes of each open transaction start in `undo`, outermost first.
+    savepoints: Vec<usize>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Helper function: Records a change, to be undone if its transaction rolls back. Changes
+    // made outside a transaction are final.
+    fn record(&mut self, undo: Undo) {
+        if !self.savepoints.is_empty() {
+            self.undo.push(undo);
+        }
+    }
+
+    // Helper function: Undoes a change.
+    fn revert(&mut self, undo: Undo) {
+        match undo {
+            Undo::InsertUser { hash, last_user_id } => {
+                if let Some(user) = self.users.remove(&hash) {
+                    self.user_ids.remove(&user.username);
+                    self.user_hashes.remove(&user.id);
+                }
+                self.last_user_id = last_user_id;
+            }
+            Undo::SetCredentials { old, new } => {
+                if let Some(user) = self.users.remove(&new) {
+                    self.user_hashes.insert(user.id, old);
+                    self.users.insert(old, user);
+                }
+            }
+            Undo::SetBalance { id, balance } => match balance {
+                Some(balance) => {
+                    self.balances.insert(id, balance);
+                }
+                None => {
+                    self.balances.remove(&id);
+                }
+            },
+            Undo::PushEvent => {
+                if let Some(row) = self.events.pop() {
+                    self.links.pop();
+                    for id in row.event.parties() {
+                        if let Some(positions) = self.event_positions.get_mut(&id) {
+                            positions.pop();
+                        }
+                    }
+                }
+            }
+            Undo::PushCheckpoint => {
+                self.checkpoints.pop();
+            }
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn begin(&mut self) -> Result<(), StorageError> {
+        self.savepoints.push(self.undo.len());
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), StorageError> {
+        self.savepoints.pop();
+        if self.savepoints.is_empty() {
+            self.undo.clear();
+        }
+        Ok(())
+    }
+
+    fn rollback(&mut self) -> Result<(), StorageError> {
+        let start = self.savepoints.pop().unwrap_or_default();
+        while self.undo.len() > start {
+            let undo = self.undo.pop().unwrap();
+            self.revert(undo);
+        }
+        Ok(())
+    }
+
+    fn insert_user(&mut self, hash: HashResult, user: User) {
+        self.record(Undo::InsertUser {
+            hash,
+            last_user_id: self.last_user_id,
+        });
+        self.user_ids.insert(user.username.clone(), user.id);
+        self.user_hashes.insert(user.id, hash);
+        self.last_user_id = self.last_user_id.max(user.id);
+        self.users.insert(hash, user);
+    }
+
+    fn user(&self, hash: HashResult) -> Option<User> {
+        self.users.get(&hash).cloned()
+    }
+
+    fn user_by_id(&self, id: UserId) -> Option<User> {
+        self.user_hashes.get(&id).and_then(|hash| self.user(*hash))
+    }
+
+    fn user_by_username(&self, username: &str) -> Option<User> {
+        self.user_ids
+            .get(username)
+            .and_then(|id| self.user_by_id(*id))
+    }
+
+    fn set_credentials(&mut self, old: HashResult, new: HashResult) -> bool {
+        match self.users.remove(&old) {
+            Some(user) => {
+                self.user_hashes.insert(user.id, new);
+                self.users.insert(new, user);
+                self.record(Undo::SetCredentials { old, new });
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn users(&self) -> Vec<User> {
+        let mut users = self.users.values().cloned().collect::<Vec<_>>();
+        users.sort_by_key(|u| u.id);
+        users
+    }
+
+    fn last_user_id(&self) -> UserId {
+        self.last_user_id
+    }
+
+    fn balance(&self, id: UserId) -> Option<Balance> {
+        self.balances.get(&id).copied()
+    }
+
+    fn set_balance(&mut self, id: UserId, balance: Balance) {
+        let balance = self.balances.insert(id, balance);
+        self.record(Undo::SetBalance { id, balance });
+    }
+
+    fn remove_balance(&mut self, id: UserId) {
+        let balance = self.balances.remove(&id);
+        self.record(Undo::SetBalance { id, balance });
+    }
+
+    fn balances(&self) -> Vec<(UserId, Balance)> {
+        let mut balances = self
+            .balances
+            .iter()
+            .map(|(id, balance)| (*id, *balance))
+            .collect::<Vec<_>>();
+        balances.sort_by_key(|(id, _)| *id);
+        balances
+    }
+
+    fn push_event(&mut self, event: Event, at: Timestamp, link: LogLink) {
+        let index = self.events.len();
+        event
+            .parties()
+            .into_iter()
+            .for_each(|id| self.event_positions.entry(id).or_default().push(index));
+        self.events.push(EventRow { index, at, event });
+        self.links.push(link);
+        self.record(Undo::PushEvent);
+    }
+
+    fn event_count(&self) -> usize {
+        self.events.len()
+    }
+
+    fn event(&self, index: usize) -> Option<EventRow> {
+        self.events.get(index).cloned()
+    }
+
+    fn event_link(&self, index: usize) -> Option<LogLink> {
+        self.links.get(index).copied()
+    }
+
+    fn for_each_entry(&self, f: &mut dyn FnMut(&EventRow, &LogLink)) {
+        self.events
+            .iter()
+            .zip(&self.links)
+            .for_each(|(row, link)| f(row, link));
+    }
+
+    fn event_positions(&self, id: UserId) -> Vec<usize> {
+        self.event_positions.get(&id).cloned().unwrap_or_default()
+    }
+
+    fn push_checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.checkpoints.push(checkpoint);
+        self.record(Undo::PushCheckpoint);
+    }
+
+    fn checkpoints(&self) -> Vec<Checkpoint> {
+        self.checkpoints.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn can_roll_back_transactions() {
+        let mut storage = MemoryStorage::new();
+        let roy = User {
+            id: 1,
+            username: "roy".to_string(),
+            role: Role::Customer,
+        };
+        storage.insert_user(10, roy.clone());
+        storage.set_balance(1, 10f64);
+
+        // Rolling back an inner transaction discards only its changes, and rolling back the
+        // outermost one discards them all.
+        storage.begin().unwrap();
+        storage.set_credentials(10, 11);
+        storage.begin().unwrap();
+        let sam = User {
+            id: 2,
+            username: "sam".to_string(),
+            role: Role::Customer,
+        };
+        storage.insert_user(20, sam);
+        storage.remove_balance(1);
+        let deposit = Event::Deposit {
+            id: 2,
+            amount: 5f64,
+            reference: None,
+        };
+        storage.push_event(deposit, 0, LogLink::default());
+        storage.push_checkpoint(Checkpoint {
+            count: 1,
+            hash: LogHash::default(),
+            signature: LogHash::default(),
+        });
+        storage.rollback().unwrap();
+        assert_eq!(storage.users(), vec![roy.clone()]);
+        assert_eq!(storage.user(11), Some(roy.clone()));
+        assert_eq!(storage.last_user_id(), 1);
+        assert_eq!(storage.balances(), vec![(1, 10f64)]);
+        assert_eq!(storage.event_count(), 0);
+        assert_eq!(storage.event_positions(2), vec![]);
+        assert!(storage.checkpoints().is_empty());
+        storage.rollback().unwrap();
+        assert_eq!(storage.user(10), Some(roy));
+        assert_eq!(storage.user(11), None);
+
+        // Committed changes are final.
+        storage.begin().unwrap();
+        storage.set_balance(1, 20f64);
+        storage.commit().unwrap();
+        assert_eq!(storage.balance(1), Some(20f64));
+        assert!(storage.undo.is_empty());
+    }
+
+    // Helper function: The linear scan replaced by the username index.
+    fn scan_username(storage: &MemoryStorage, username: &str) -> bool {
+        storage.users.values().any(|user| user.username == username)
+    }
+
+    // Helper function: The linear scan replaced by the user ID index.
+    fn scan_user_by_id(storage: &MemoryStorage, id: UserId) -> Option<User> {
+        storage.users.values().find(|user| user.id == id).cloned()
+    }
+
+    // Helper function: The linear scan replaced by the event index.
+    fn scan_user_events(storage: &MemoryStorage, id: UserId) -> Vec<usize> {
+        storage
+            .events
+            .iter()
+            .filter(|row| row.event.involves(id))
+            .map(|row| row.index)
+            .collect()
+    }
+
+    /// Compares the indexed lookups with the linear scans they replaced, over a million users
+    /// and a million events. Run with
+    /// `cargo test --release -- --ignored bench_indexed_lookups --nocapture`.
+    #[test]
+    #[ignore]
+    fn bench_indexed_lookups() {
+        const USERS: u64 = 1_000_000;
+        const QUERIES: u64 = 100;
+
+        let mut storage = MemoryStorage::new();
+        (1..=USERS).for_each(|id| {
+            let user = User {
+                id,
+                username: format!("user{}", id),
+                role: Role::Customer,
+            };
+            storage.insert_user(id, user);
+            let deposit = Event::Deposit {
+                id,
+                amount: 100f64,
+                reference: None,
+            };
+            storage.push_event(deposit, 0, LogLink::default());
+        });
+
+        let time = |f: &dyn Fn(UserId)| {
+            let start = Instant::now();
+            (0..QUERIES).for_each(|q| f(1 + q * (USERS / QUERIES)));
+            start.elapsed() / QUERIES as u32
+        };
+        let report = |name: &str, scan: Duration, index: Duration| {
+            println!(
+                "{:<16} scan {:>12?}  index {:>10?}  ({:.0}x)",
+                name,
+                scan,
+                index,
+                scan.as_secs_f64() / index.as_secs_f64().max(1e-9)
+            )
+        };
+        println!();
+        report(
+            "username",
+            time(&|id| assert!(scan_username(&storage, &format!("user{}", id)))),
+            time(&|id| assert!(storage.user_by_username(&format!("user{}", id)).is_some())),
+        );
+        report(
+            "user by id",
+            time(&|id| assert!(scan_user_by_id(&storage, id).is_some())),
+            time(&|id| assert!(storage.user_by_id(id).is_some())),
+        );
+        report(
+            "events of user",
+            time(&|id| assert_eq!(vec![id as usize - 1], scan_user_events(&storage, id))),
+            time(&|id| assert_eq!(vec![id as usize - 1], storage.event_positions(id))),
+        );
+    }
+}
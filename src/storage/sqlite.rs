@@ -0,0 +1,944 @@
+//! Storage in an embedded SQLite database, either in memory or in a file.
+//!
+//! | Table           | Columns                                       |
+//! |-----------------|-----------------------------------------------|
+//! | `users`         | id, credentials (hash), username, role        |
+//! | `balances`      | id, balance                                   |
+//! | `events`        | position, at, event (see `codec`), prev, hash |
+//! | `event_parties` | id, position: the users each event involves   |
+//! | `checkpoints`   | count, hash, signature                        |
+//!
+//! `u64` values are stored as SQLite's signed 64 bit integers, bit for bit.
+//!
+//! The schema version is kept in `PRAGMA user_version`. Databases of the first version predate
+//! it and have it unset; they are recognized by their tables. A database of an older version is
+//! not opened: `SqliteStorage::migrate`, run by `banking_app migrate --sqlite`, upgrades it.
+use super::{
+    codec::{decode_event, encode_event},
+    Storage, StorageError,
+};
+use crate::bank::{entry_hash, sign_checkpoint, signing_key_from_env, CHECKPOINT_INTERVAL};
+use crate::migration::{apply_migrations, backup_path, Migration, MigrationReport};
+use crate::primitives::*;
+use rusqlite::{params, types::Type, Connection, OpenFlags, OptionalExtension, Row};
+use std::{cell::RefCell, fs, time::Duration};
+
+/// Version of the database schema written by this build.
+pub const SQLITE_SCHEMA_VERSION: u32 = 2;
+
+/// How long a statement waits for the transaction of another connection to end.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The migrations of the database schema, in order.
+const MIGRATIONS: &[Migration<Connection>] = &[Migration {
+    from: 1,
+    description: "Chain the event log with hashes, and sign its checkpoints",
+    apply: chain_events,
+}];
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS users (
+        id INTEGER PRIMARY KEY,
+        credentials INTEGER NOT NULL UNIQUE,
+        username TEXT NOT NULL UNIQUE,
+        role TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS balances (
+        id INTEGER PRIMARY KEY,
+        balance REAL NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS events (
+        position INTEGER PRIMARY KEY,
+        at INTEGER NOT NULL,
+        event TEXT NOT NULL,
+        prev BLOB NOT NULL,
+        hash BLOB NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS event_parties (
+        id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        PRIMARY KEY (id, position)
+    ) WITHOUT ROWID;
+    CREATE TABLE IF NOT EXISTS checkpoints (
+        count INTEGER PRIMARY KEY,
+        hash BLOB NOT NULL,
+        signature BLOB NOT NULL
+    );
+";
+
+pub struct SqliteStorage {
+    connection: Connection,
+    /// Number of open transactions: the outermost one is a SQLite transaction, and the ones
+    /// inside it savepoints.
+    depth: usize,
+    /// The first statement that failed since the bank last asked, see `Storage::take_failure`.
+    failure: RefCell<Option<StorageError>>,
+}
+
+// Helper function: Returns an error for a value of a column that this build cannot read.
+fn invalid(column: usize, message: String) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(column, Type::Text, message.into())
+}
+
+// Helper function: Reads a row of the `users` table.
+fn read_user(row: &Row) -> rusqlite::Result<User> {
+    let role = row.get::<_, String>(2)?;
+    Ok(User {
+        id: row.get::<_, i64>(0)? as UserId,
+        username: row.get(1)?,
+        role: match role.as_str() {
+            "Customer" => Role::Customer,
+            "Manager" => Role::Manager,
+            "Auditor" => Role::Auditor,
+            _ => return Err(invalid(2, format!("invalid role {:?}", role))),
+        },
+    })
+}
+
+// Helper function: Reads the position, time and event of a row of the `events` table.
+fn read_event(row: &Row) -> rusqlite::Result<EventRow> {
+    let encoded = row.get::<_, String>(2)?;
+    Ok(EventRow {
+        index: row.get::<_, i64>(0)? as usize,
+        at: row.get::<_, i64>(1)? as Timestamp,
+        event: decode_event(&encoded)
+            .ok_or_else(|| invalid(2, format!("invalid event {:?}", encoded)))?,
+    })
+}
+
+// Helper function: Migrates version 1, whose events were not chained, by chaining them in
+// order and signing their checkpoints with the key of `signing_key_from_env`. Nothing vouches
+// for the unchained events, so the signatures vouch for them as they are: only an operator
+// migrating a database they trust runs this.
+fn chain_events(connection: &mut Connection) -> Result<Vec<String>, String> {
+    let error = |e: rusqlite::Error| e.to_string();
+    connection
+        .execute_batch(
+            "ALTER TABLE events ADD COLUMN prev BLOB NOT NULL DEFAULT x'';
+             ALTER TABLE events ADD COLUMN hash BLOB NOT NULL DEFAULT x'';
+             CREATE TABLE checkpoints (
+                 count INTEGER PRIMARY KEY,
+                 hash BLOB NOT NULL,
+                 signature BLOB NOT NULL
+             );",
+        )
+        .map_err(error)?;
+    let rows = connection
+        .prepare("SELECT position, at, event FROM events ORDER BY position")
+        .map_err(error)?
+        .query_map([], read_event)
+        .map_err(error)?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(error)?;
+    let key = signing_key_from_env();
+    let mut prev = LogHash::default();
+    let mut checkpoints = 0;
+    for (count, row) in (1usize..).zip(&rows) {
+        let hash = entry_hash(&prev, row.at, &row.event);
+        connection
+            .execute(
+                "UPDATE events SET prev = ?2, hash = ?3 WHERE position = ?1",
+                params![row.index as i64, prev, hash],
+            )
+            .map_err(error)?;
+        if count.is_multiple_of(CHECKPOINT_INTERVAL) {
+            insert_checkpoint(connection, &sign_checkpoint(&key, count, hash)).map_err(error)?;
+            checkpoints += 1;
+        }
+        prev = hash;
+    }
+    Ok(vec![
+        format!("chained {} events", rows.len()),
+        format!("signed {} checkpoints", checkpoints),
+    ])
+}
+
+// Helper function: Adds a row to the `checkpoints` table.
+fn insert_checkpoint(connection: &Connection, checkpoint: &Checkpoint) -> rusqlite::Result<()> {
+    connection
+        .prepare_cached("INSERT INTO checkpoints (count, hash, signature) VALUES (?1, ?2, ?3)")?
+        .execute(params![
+            checkpoint.count as i64,
+            checkpoint.hash,
+            checkpoint.signature
+        ])
+        .map(|_| ())
+}
+
+// Helper function: Returns the schema version of the database, or `None` if it is new.
+fn schema_version(connection: &Connection) -> rusqlite::Result<Option<u32>> {
+    let version = connection.query_row("PRAGMA user_version", [], |row| row.get::<_, u32>(0))?;
+    if version > 0 {
+        return Ok(Some(version));
+    }
+    let tables = connection.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'users'",
+        [],
+        |row| row.get::<_, i64>(0),
+    )?;
+    Ok(if tables > 0 { Some(1) } else { None })
+}
+
+// Helper function: Upgrades the schema of the database at `path` to `SQLITE_SCHEMA_VERSION`,
+// after copying the original to its backup, or creates the schema if the database is new. A dry
+// run applies the steps in a transaction that is rolled back.
+fn upgrade(
+    connection: &mut Connection,
+    path: &str,
+    dry_run: bool,
+) -> Result<MigrationReport, StorageError> {
+    let error = |e: rusqlite::Error| StorageError(e.to_string());
+    let stamp = format!("PRAGMA user_version = {}", SQLITE_SCHEMA_VERSION);
+    let version = match schema_version(connection).map_err(error)? {
+        Some(version) => version,
+        None if dry_run => return Ok(MigrationReport::current(path, SQLITE_SCHEMA_VERSION)),
+        None => {
+            connection.execute_batch(SCHEMA).map_err(error)?;
+            connection.execute_batch(&stamp).map_err(error)?;
+            return Ok(MigrationReport::current(path, SQLITE_SCHEMA_VERSION));
+        }
+    };
+    if version > SQLITE_SCHEMA_VERSION {
+        return Err(StorageError(format!(
+            "the database has schema version {}, newer than {}",
+            version, SQLITE_SCHEMA_VERSION
+        )));
+    }
+
+    let mut report = MigrationReport {
+        target: path.to_string(),
+        from: version,
+        to: SQLITE_SCHEMA_VERSION,
+        steps: vec![],
+        backup: None,
+        dry_run,
+    };
+    if !dry_run && report.is_upgrade() {
+        let backup = backup_path(path, version);
+        let _ = fs::remove_file(&backup);
+        connection
+            .execute("VACUUM INTO ?1", [&backup])
+            .map_err(error)?;
+        report.backup = Some(backup);
+    }
+    connection.execute_batch("BEGIN").map_err(error)?;
+    match apply_migrations(connection, version, MIGRATIONS) {
+        Ok(steps) => report.steps = steps,
+        Err(e) => {
+            connection.execute_batch("ROLLBACK").map_err(error)?;
+            return Err(StorageError(e));
+        }
+    }
+    if dry_run {
+        connection.execute_batch("ROLLBACK").map_err(error)?;
+    } else {
+        // Also stamps databases of the first version, which predate `user_version`.
+        connection
+            .execute_batch(&format!("{}; COMMIT", stamp))
+            .map_err(error)?;
+    }
+    Ok(report)
+}
+
+impl SqliteStorage {
+    /// Opens the database at `path`, creating it if needed. A database of an older schema
+    /// version is not opened, see `migrate`.
+    pub fn open(path: &str) -> Result<Self, StorageError> {
+        Self::with_connection(Connection::open(path), path)
+    }
+
+    /// Opens a new database held in memory.
+    pub fn in_memory() -> Result<Self, StorageError> {
+        Self::with_connection(Connection::open_in_memory(), ":memory:")
+    }
+
+    /// Upgrades the existing database at `path` to `SQLITE_SCHEMA_VERSION`, after copying the
+    /// original to `backup_path(path, version)`. With `dry_run`, nothing is saved and the
+    /// report tells what would change.
+    pub fn migrate(path: &str, dry_run: bool) -> Result<MigrationReport, StorageError> {
+        let mut connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_WRITE)
+            .map_err(|e| StorageError(e.to_string()))?;
+        upgrade(&mut connection, path, dry_run)
+    }
+
+    // Helper function: Creates the schema if the database is new, and checks its version.
+    fn with_connection(
+        connection: rusqlite::Result<Connection>,
+        path: &str,
+    ) -> Result<Self, StorageError> {
+        let error = |e: rusqlite::Error| StorageError(e.to_string());
+        let mut connection = connection.map_err(error)?;
+        match schema_version(&connection).map_err(error)? {
+            Some(SQLITE_SCHEMA_VERSION) => {}
+            Some(version) if version < SQLITE_SCHEMA_VERSION => {
+                return Err(StorageError(format!(
+                    "the database has schema version {}, older than {}; run `banking_app migrate --sqlite {}` to upgrade it",
+                    version, SQLITE_SCHEMA_VERSION, path
+                )))
+            }
+            _ => {
+                upgrade(&mut connection, path, false)?;
+            }
+        }
+        // Other processes may write the database too: wait for their transactions to end.
+        connection.busy_timeout(BUSY_TIMEOUT).map_err(error)?;
+        Ok(Self {
+            connection,
+            depth: 0,
+            failure: RefCell::new(None),
+        })
+    }
+
+    // Helper function: Runs `statements` on the connection. If they fail, records the failure
+    // for `take_failure`, and returns the default: no user, no balance, no events.
+    fn attempt<T: Default>(
+        &self,
+        statements: impl FnOnce(&Connection) -> rusqlite::Result<T>,
+    ) -> T {
+        statements(&self.connection).unwrap_or_else(|e| {
+            self.failure
+                .borrow_mut()
+                .get_or_insert_with(|| StorageError(e.to_string()));
+            T::default()
+        })
+    }
+
+    // Helper function: Runs `sql`, which starts or ends a transaction.
+    fn control(&self, sql: &str) -> Result<(), StorageError> {
+        self.connection
+            .execute_batch(sql)
+            .map_err(|e| StorageError(e.to_string()))
+    }
+
+    // Helper function: Returns the user selected by `sql`, which takes one parameter.
+    fn query_user(&self, sql: &str, param: impl rusqlite::ToSql) -> Option<User> {
+        self.attempt(|c| {
+            c.prepare_cached(sql)?
+                .query_row([param], read_user)
+                .optional()
+        })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn begin(&mut self) -> Result<(), StorageError> {
+        match self.depth {
+            0 => self.control("BEGIN IMMEDIATE")?,
+            depth => self.control(&format!("SAVEPOINT nested_{}", depth))?,
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn commit(&mut self) -> Result<(), StorageError> {
+        let depth = self.depth - 1;
+        let result = match depth {
+            0 => self.control("COMMIT"),
+            depth => self.control(&format!("RELEASE nested_{}", depth)),
+        };
+        // A transaction that failed to commit, e.g. as the disk is full, is still open.
+        match result {
+            Ok(()) => {
+                self.depth = depth;
+                Ok(())
+            }
+            Err(e) => self.rollback().and(Err(e)),
+        }
+    }
+
+    fn rollback(&mut self) -> Result<(), StorageError> {
+        self.depth -= 1;
+        match self.depth {
+            // Failing, the rollback leaves no transaction open: SQLite rolled it back already.
+            0 if self.connection.is_autocommit() => Ok(()),
+            0 => self.control("ROLLBACK"),
+            depth => self.control(&format!(
+                "ROLLBACK TO nested_{0}; RELEASE nested_{0}",
+                depth
+            )),
+        }
+    }
+
+    fn take_failure(&self) -> Option<StorageError> {
+        self.failure.borrow_mut().take()
+    }
+
+    fn insert_user(&mut self, hash: HashResult, user: User) {
+        self.attempt(|c| {
+            c.prepare_cached(
+                "INSERT INTO users (id, credentials, username, role) VALUES (?1, ?2, ?3, ?4)",
+            )?
+            .execute(params![
+                user.id as i64,
+                hash as i64,
+                user.username,
+                format!("{:?}", user.role)
+            ])
+            .map(|_| ())
+        })
+    }
+
+    fn user(&self, hash: HashResult) -> Option<User> {
+        self.query_user(
+            "SELECT id, username, role FROM users WHERE credentials = ?1",
+            hash as i64,
+        )
+    }
+
+    fn user_by_id(&self, id: UserId) -> Option<User> {
+        self.query_user(
+            "SELECT id, username, role FROM users WHERE id = ?1",
+            id as i64,
+        )
+    }
+
+    fn user_by_username(&self, username: &str) -> Option<User> {
+        self.query_user(
+            "SELECT id, username, role FROM users WHERE username = ?1",
+            username,
+        )
+    }
+
+    fn set_credentials(&mut self, old: HashResult, new: HashResult) -> bool {
+        self.attempt(|c| {
+            c.prepare_cached("UPDATE users SET credentials = ?2 WHERE credentials = ?1")?
+                .execute(params![old as i64, new as i64])
+        }) > 0
+    }
+
+    fn users(&self) -> Vec<User> {
+        self.attempt(|c| {
+            c.prepare_cached("SELECT id, username, role FROM users ORDER BY id")?
+                .query_map([], read_user)?
+                .collect()
+        })
+    }
+
+    fn last_user_id(&self) -> UserId {
+        self.attempt(|c| {
+            c.query_row("SELECT COALESCE(MAX(id), 0) FROM users", [], |row| {
+                row.get::<_, i64>(0)
+            })
+        }) as UserId
+    }
+
+    fn balance(&self, id: UserId) -> Option<Balance> {
+        self.attempt(|c| {
+            c.prepare_cached("SELECT balance FROM balances WHERE id = ?1")?
+                .query_row([id as i64], |row| row.get(0))
+                .optional()
+        })
+    }
+
+    fn set_balance(&mut self, id: UserId, balance: Balance) {
+        self.attempt(|c| {
+            c.prepare_cached("INSERT OR REPLACE INTO balances (id, balance) VALUES (?1, ?2)")?
+                .execute(params![id as i64, balance])
+                .map(|_| ())
+        })
+    }
+
+    fn remove_balance(&mut self, id: UserId) {
+        self.attempt(|c| {
+            c.prepare_cached("DELETE FROM balances WHERE id = ?1")?
+                .execute([id as i64])
+                .map(|_| ())
+        })
+    }
+
+    fn balances(&self) -> Vec<(UserId, Balance)> {
+        self.attempt(|c| {
+            c.prepare_cached("SELECT id, balance FROM balances ORDER BY id")?
+                .query_map([], |row| Ok((row.get::<_, i64>(0)? as UserId, row.get(1)?)))?
+                .collect()
+        })
+    }
+
+    // The bank pushes events in its transactions, which roll back an event and its parties
+    // together if either fails to be inserted.
+    fn push_event(&mut self, event: Event, at: Timestamp, link: LogLink) {
+        let position = self.event_count() as i64;
+        self.attempt(|c| {
+            c.prepare_cached(
+                "INSERT INTO events (position, at, event, prev, hash)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?
+            .execute(params![
+                position,
+                at as i64,
+                encode_event(&event),
+                link.prev,
+                link.hash
+            ])?;
+            let mut parties =
+                c.prepare_cached("INSERT INTO event_parties (id, position) VALUES (?1, ?2)")?;
+            for id in event.parties() {
+                parties.execute(params![id as i64, position])?;
+            }
+            Ok(())
+        })
+    }
+
+    fn event_count(&self) -> usize {
+        // Not cached: other connections may have logged events since.
+        self.attempt(|c| {
+            c.query_row(
+                "SELECT COALESCE(MAX(position) + 1, 0) FROM events",
+                [],
+                |row| row.get::<_, i64>(0),
+            )
+        }) as usize
+    }
+
+    fn event(&self, index: usize) -> Option<EventRow> {
+        self.attempt(|c| {
+            c.prepare_cached("SELECT position, at, event FROM events WHERE position = ?1")?
+                .query_row([index as i64], read_event)
+                .optional()
+        })
+    }
+
+    fn event_link(&self, index: usize) -> Option<LogLink> {
+        self.attempt(|c| {
+            c.prepare_cached("SELECT prev, hash FROM events WHERE position = ?1")?
+                .query_row([index as i64], |row| {
+                    Ok(LogLink {
+                        prev: row.get(0)?,
+                        hash: row.get(1)?,
+                    })
+                })
+                .optional()
+        })
+    }
+
+    fn for_each_entry(&self, f: &mut dyn FnMut(&EventRow, &LogLink)) {
+        self.attempt(|c| {
+            let mut statement = c.prepare_cached(
+                "SELECT position, at, event, prev, hash FROM events ORDER BY position",
+            )?;
+            let entries = statement.query_map([], |row| {
+                let link = LogLink {
+                    prev: row.get(3)?,
+                    hash: row.get(4)?,
+                };
+                Ok((read_event(row)?, link))
+            })?;
+            for entry in entries {
+                let (row, link) = entry?;
+                f(&row, &link);
+            }
+            Ok(())
+        })
+    }
+
+    fn event_positions(&self, id: UserId) -> Vec<usize> {
+        self.attempt(|c| {
+            c.prepare_cached("SELECT position FROM event_parties WHERE id = ?1 ORDER BY position")?
+                .query_map([id as i64], |row| Ok(row.get::<_, i64>(0)? as usize))?
+                .collect()
+        })
+    }
+
+    fn push_checkpoint(&mut self, checkpoint: Checkpoint) {
+        self.attempt(|c| insert_checkpoint(c, &checkpoint))
+    }
+
+    fn checkpoints(&self) -> Vec<Checkpoint> {
+        self.attempt(|c| {
+            c.prepare_cached("SELECT count, hash, signature FROM checkpoints ORDER BY count")?
+                .query_map([], |row| {
+                    Ok(Checkpoint {
+                        count: row.get::<_, i64>(0)? as usize,
+                        hash: row.get(1)?,
+                        signature: row.get(2)?,
+                    })
+                })?
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::{Bank, FULL_HASH_ROUNDS};
+
+    // Helper function: Returns a path in the temp directory, unique to the process, as the
+    // tests of concurrent runs must not share files.
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "banking_app_sqlite_{}_{}.db",
+            name,
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn can_reopen_sqlite_storage() {
+        let path = temp_path("reopen");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let mut bank = Bank::with_storage(Box::new(SqliteStorage::open(path).unwrap()));
+        bank.set_verbose(false);
+        bank.create_user("roy".to_string(), "pw".to_string(), Role::Customer)
+            .unwrap();
+        bank.create_user("tom".to_string(), "pw".to_string(), Role::Customer)
+            .unwrap();
+        let (roy, _) = bank.login("roy".to_string(), "pw".to_string()).unwrap();
+        let memo = PaymentReference::memo("Rent March").unwrap();
+        bank.deposit_with_reference(roy, 1_000f64, memo).unwrap();
+        bank.transfer(roy, 100f64, 2).unwrap();
+        bank.change_password(roy, "secret".to_string()).unwrap();
+        let events = bank.events();
+        drop(bank);
+
+        // Users, credentials, balances and events are read back, and new users get new IDs.
+        let mut bank = Bank::with_storage(Box::new(SqliteStorage::open(path).unwrap()));
+        bank.set_verbose(false);
+        assert_eq!(bank.events(), events);
+        assert_eq!(
+            bank.login("roy".to_string(), "pw".to_string()),
+            Err(BankingError::FailedLogin)
+        );
+        let (roy, _) = bank.login("roy".to_string(), "secret".to_string()).unwrap();
+        assert_eq!(bank.check_balance(roy), Ok(900f64));
+        bank.create_user("ann".to_string(), "pw".to_string(), Role::Customer)
+            .unwrap();
+        let (ann, _) = bank.login("ann".to_string(), "pw".to_string()).unwrap();
+        assert_eq!(bank.current_user(ann).map(|u| u.id), Ok(3));
+        drop(bank);
+        std::fs::remove_file(path).unwrap();
+
+        // Every kind of field survives the encoding, including escaped text.
+        [
+            Event::FeeRuleSet {
+                id: 1,
+                fee_type: FeeType::Overdraft,
+                rule: FeeRule {
+                    flat: 1.5f64,
+                    percentage: 0.01f64,
+                    min: 0f64,
+                    max: Some(25f64),
+                },
+            },
+            Event::ScreeningHit {
+                hit_id: 4,
+                id: None,
+                name: "a\tb\\n\n".to_string(),
+                entry: String::new(),
+                blocked: true,
+            },
+            Event::KycPolicySet {
+                id: 2,
+                policy: KycPolicy::Limit(0.1f64 + 0.2f64),
+            },
+            Event::ComplianceThresholdsSet {
+                id: 3,
+                thresholds: ComplianceThresholds::default(),
+            },
+        ]
+        .iter()
+        .for_each(|e| assert_eq!(decode_event(&encode_event(e)).as_ref(), Some(e)));
+        assert_eq!(decode_event("Deposit\t1"), None);
+        assert_eq!(decode_event("Reconciled\t1\t0\textra"), None);
+    }
+
+    #[test]
+    fn can_migrate_sqlite_schema() {
+        let fixtures = [
+            (1, include_str!("../../tests/fixtures/sqlite_v1.sql")),
+            (2, include_str!("../../tests/fixtures/sqlite_v2.sql")),
+        ];
+        // Every version has a fixture, and every version but the last a migration.
+        assert_eq!(fixtures.len() as u32, SQLITE_SCHEMA_VERSION);
+        assert_eq!(
+            MIGRATIONS.iter().map(|m| m.from).collect::<Vec<_>>(),
+            (1..SQLITE_SCHEMA_VERSION).collect::<Vec<_>>()
+        );
+        let path = temp_path("migrate");
+        let path = path.to_str().unwrap();
+        let user_version = || {
+            let connection = Connection::open(path).unwrap();
+            connection
+                .query_row("PRAGMA user_version", [], |row| row.get::<_, u32>(0))
+                .unwrap()
+        };
+
+        let mut heads = vec![];
+        for (version, fixture) in fixtures.iter() {
+            let _ = std::fs::remove_file(path);
+            Connection::open(path)
+                .unwrap()
+                .execute_batch(fixture)
+                .unwrap();
+
+            // A dry run reports the steps, but saves nothing.
+            let report = SqliteStorage::migrate(path, true).unwrap();
+            assert_eq!((report.from, report.to), (*version, SQLITE_SCHEMA_VERSION));
+            assert_eq!(report.steps.len() as u32, SQLITE_SCHEMA_VERSION - version);
+            assert_eq!(report.backup, None);
+            // The first version predates `user_version`.
+            let stamped = if *version == 1 { 0 } else { *version };
+            assert_eq!(user_version(), stamped);
+
+            // An outdated database is not opened, and not upgraded.
+            let backup = backup_path(path, *version);
+            if *version < SQLITE_SCHEMA_VERSION {
+                assert!(SqliteStorage::open(path).is_err());
+                assert_eq!(user_version(), stamped);
+                assert!(std::fs::metadata(&backup).is_err());
+            }
+
+            // Migrating upgrades the database, after copying the original, and the bank reads
+            // it back.
+            let report = SqliteStorage::migrate(path, false).unwrap();
+            assert_eq!(report.steps.len() as u32, SQLITE_SCHEMA_VERSION - version);
+            assert_eq!(user_version(), SQLITE_SCHEMA_VERSION);
+            assert_eq!(
+                std::fs::remove_file(&backup).is_ok(),
+                *version < SQLITE_SCHEMA_VERSION
+            );
+            let storage = SqliteStorage::open(path).unwrap();
+            heads.push(storage.event_link(9));
+            let mut bank = Bank::with_storage(Box::new(storage));
+            bank.set_verbose(false);
+            // The fixtures were written by the binaries, which hash credentials with more
+            // rounds.
+            let r = Bank::hash_with_rounds("r", "r", FULL_HASH_ROUNDS);
+            assert_eq!(bank.check_balance(r), Ok(540f64));
+            assert_eq!(bank.event_count(), 10);
+            assert_eq!(
+                bank.event_row(2).map(|row| row.event),
+                Some(Event::Transfer {
+                    id: 1,
+                    to_id: 2,
+                    amount: 100f64,
+                    reference: Some(PaymentReference::memo("Rent").unwrap()),
+                })
+            );
+
+            // The log is chained as if it had been logged by this version.
+            let a = Bank::hash_with_rounds("a", "a", FULL_HASH_ROUNDS);
+            bank.deposit(r, 1f64).unwrap();
+            assert_eq!(bank.verify_log(a).unwrap().issue, None);
+        }
+        assert!(heads.windows(2).all(|w| w[0] == w[1]));
+
+        // A current database, downgraded to look like the first version and then edited, is
+        // refused as it is: opening it does not chain and sign the edited events.
+        let connection = Connection::open(path).unwrap();
+        connection
+            .execute_batch(
+                "PRAGMA user_version = 0;
+                 UPDATE events SET event = replace(event, '100', '1000000') WHERE position = 2;",
+            )
+            .unwrap();
+        drop(connection);
+        let original = std::fs::read(path).unwrap();
+        assert!(SqliteStorage::open(path).is_err());
+        assert_eq!(std::fs::read(path).unwrap(), original);
+        assert!(std::fs::metadata(backup_path(path, 1)).is_err());
+
+        // A database written by a newer build is refused.
+        Connection::open(path)
+            .unwrap()
+            .execute_batch(&format!(
+                "PRAGMA user_version = {}",
+                SQLITE_SCHEMA_VERSION + 1
+            ))
+            .unwrap();
+        assert!(SqliteStorage::open(path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn can_detect_tampered_log() {
+        let path = temp_path("tampered");
+        let path = path.to_str().unwrap();
+        let copy = temp_path("tampered_copy");
+        let copy = copy.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let mut bank = Bank::with_storage(Box::new(SqliteStorage::open(path).unwrap()));
+        bank.set_verbose(false);
+        bank.create_user("roy".to_string(), "pw".to_string(), Role::Customer)
+            .unwrap();
+        bank.create_user("auditor".to_string(), "pw".to_string(), Role::Auditor)
+            .unwrap();
+        let (roy, _) = bank.login("roy".to_string(), "pw".to_string()).unwrap();
+        (0..250).for_each(|i| bank.deposit(roy, (i + 10) as f64).unwrap());
+        drop(bank);
+
+        // Verifies a copy of the database, after tampering with it.
+        let verify = |tamper: &dyn Fn(&Connection)| {
+            std::fs::copy(path, copy).unwrap();
+            tamper(&Connection::open(copy).unwrap());
+            let bank = Bank::with_storage(Box::new(SqliteStorage::open(copy).unwrap()));
+            let (auditor, _) = bank.login("auditor".to_string(), "pw".to_string()).unwrap();
+            bank.verify_log(auditor).unwrap().issue
+        };
+        let sql = |sql: &'static str| move |c: &Connection| c.execute_batch(sql).unwrap();
+
+        assert_eq!(verify(&|_| {}), None);
+
+        // Modification of an event, or of the time it was logged at.
+        assert_eq!(
+            verify(&sql(
+                "UPDATE events SET event = replace(event, 'Deposit', 'Withdrawal') \
+                 WHERE position = 42"
+            )),
+            Some(LogIssue::Modified { index: 42 })
+        );
+        assert_eq!(
+            verify(&sql("UPDATE events SET at = 60 WHERE position = 7")),
+            Some(LogIssue::Modified { index: 7 })
+        );
+
+        // Deletion, leaving a gap or moving the later entries up.
+        assert_eq!(
+            verify(&sql("DELETE FROM events WHERE position = 42")),
+            Some(LogIssue::Missing { index: 42 })
+        );
+        assert_eq!(
+            verify(&sql(
+                "DELETE FROM events WHERE position = 42;
+                 UPDATE events SET position = position - 1 WHERE position > 42"
+            )),
+            Some(LogIssue::Missing { index: 42 })
+        );
+
+        // Reordering.
+        assert_eq!(
+            verify(&sql("UPDATE events SET position = -1 WHERE position = 42;
+                 UPDATE events SET position = 42 WHERE position = 43;
+                 UPDATE events SET position = 43 WHERE position = -1")),
+            Some(LogIssue::Reordered { index: 42 })
+        );
+
+        // Deletion at the end of the log, up to the last checkpoint.
+        assert_eq!(
+            verify(&sql("DELETE FROM events WHERE position >= 150")),
+            Some(LogIssue::Missing { index: 150 })
+        );
+
+        // Checkpoints removed or forged.
+        assert_eq!(
+            verify(&sql("DELETE FROM checkpoints WHERE count = 100")),
+            Some(LogIssue::BadCheckpoint { count: 100 })
+        );
+        assert_eq!(
+            verify(&sql(
+                "UPDATE checkpoints SET signature = zeroblob(32) WHERE count = 200"
+            )),
+            Some(LogIssue::BadCheckpoint { count: 200 })
+        );
+
+        // Modification with the hashes of the later entries recomputed: only the checkpoint
+        // after it tells.
+        let rewrite = |c: &Connection| {
+            c.execute_batch("UPDATE events SET at = 60 WHERE position = 42")
+                .unwrap();
+            let mut prev = c
+                .query_row("SELECT hash FROM events WHERE position = 41", [], |row| {
+                    row.get::<_, LogHash>(0)
+                })
+                .unwrap();
+            let rows = c
+                .prepare("SELECT position, at, event FROM events WHERE position >= 42")
+                .unwrap()
+                .query_map([], read_event)
+                .unwrap()
+                .map(Result::unwrap)
+                .collect::<Vec<_>>();
+            rows.iter().for_each(|row| {
+                let hash = entry_hash(&prev, row.at, &row.event);
+                c.execute(
+                    "UPDATE events SET prev = ?2, hash = ?3 WHERE position = ?1",
+                    params![row.index as i64, prev, hash],
+                )
+                .unwrap();
+                prev = hash;
+            });
+        };
+        assert_eq!(
+            verify(&rewrite),
+            Some(LogIssue::BadCheckpoint { count: 100 })
+        );
+
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(copy).unwrap();
+    }
+
+    #[test]
+    fn can_report_storage_failures() {
+        let path = temp_path("failure");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        let mut bank = Bank::with_storage(Box::new(SqliteStorage::open(path).unwrap()));
+        bank.set_verbose(false);
+        bank.create_user("roy".to_string(), "pw".to_string(), Role::Customer)
+            .unwrap();
+        let (roy, _) = bank.login("roy".to_string(), "pw".to_string()).unwrap();
+        bank.deposit(roy, 100f64).unwrap();
+        let events = bank.event_count();
+
+        // The deposit fails once its balance is written, and is rolled back.
+        let other = Connection::open(path).unwrap();
+        other.execute_batch("DROP TABLE event_parties").unwrap();
+        assert_eq!(bank.deposit(roy, 50f64), Err(BankingError::StorageFailure));
+        assert_eq!(bank.check_balance(roy), Ok(100f64));
+        assert_eq!(bank.event_count(), events);
+
+        // Reads fail rather than answer without the data.
+        other.execute_batch("DROP TABLE balances").unwrap();
+        assert_eq!(bank.check_balance(roy), Err(BankingError::StorageFailure));
+
+        drop(other);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn can_save_changes_in_transactions() {
+        let path = temp_path("transactions");
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+        let mut storage = SqliteStorage::open(path).unwrap();
+        let other = Connection::open(path).unwrap();
+        let saved = || {
+            other
+                .query_row("SELECT COUNT(*) FROM balances", [], |row| {
+                    row.get::<_, i64>(0)
+                })
+                .unwrap()
+        };
+
+        // Nested transactions are saved when the outermost one commits.
+        storage.begin().unwrap();
+        storage.set_balance(1, 10f64);
+        storage.begin().unwrap();
+        storage.set_balance(2, 20f64);
+        storage.commit().unwrap();
+        assert_eq!(saved(), 0);
+        storage.commit().unwrap();
+        assert_eq!(saved(), 2);
+
+        // Rolling back an inner transaction discards only its changes, and rolling back the
+        // outermost one discards them all.
+        storage.begin().unwrap();
+        storage.set_balance(3, 30f64);
+        storage.begin().unwrap();
+        storage.remove_balance(1);
+        storage.rollback().unwrap();
+        assert_eq!(storage.balance(1), Some(10f64));
+        storage.commit().unwrap();
+        storage.begin().unwrap();
+        storage.set_balance(4, 40f64);
+        storage.rollback().unwrap();
+        assert_eq!(saved(), 3);
+        assert_eq!(storage.balances(), vec![(1, 10f64), (2, 20f64), (3, 30f64)]);
+
+        drop(other);
+        std::fs::remove_file(path).unwrap();
+    }
+}
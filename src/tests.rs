@@ -1,12 +1,12 @@
 use core::panic;
 
-use crate::{Bank, BankResult, BankingError, Event, HashResult, Role};
+use crate::bank::FULL_HASH_ROUNDS;
+use crate::*;
 
 #[track_caller]
 fn assert_ok<T>(res: BankResult<T>) {
-    match res {
-        Err(e) => panic!("{}", e),
-        _ => (),
+    if let Err(e) = res {
+        panic!("{}", e)
     }
 }
 
@@ -18,14 +18,31 @@ fn assert_noop<T>(res: BankResult<T>, e: BankingError) {
     }
 }
 
+// Returns the `n`th event from the end of the log, the last being 1.
+fn nth_last_event(bank: &Bank, n: usize) -> Event {
+    bank.event_row(bank.event_count() - n).unwrap().event
+}
+
 #[track_caller]
 fn assert_last_event(bank: &Bank, e: Event) {
-    let last = bank.events[bank.events.len() - 1];
+    let last = nth_last_event(bank, 1);
     if last != e {
         panic!("Expected Event: {:?}, but got {}", last, e);
     }
 }
 
+// Returns a path in the temp directory, unique to the process and the storage backend, as the
+// tests run once per backend at the same time.
+fn temp_path(name: &str) -> std::path::PathBuf {
+    let backend = module_path!().rsplit("::").next().unwrap();
+    std::env::temp_dir().join(format!(
+        "banking_app_{}_{}_{}",
+        name,
+        backend,
+        std::process::id()
+    ))
+}
+
 fn setup_account(bank: &mut Bank, name: &str, role: Role) -> HashResult {
     assert_ok(bank.create_user(name.to_string(), name.to_string(), role));
     let (hash, _) = bank.login(name.to_string(), name.to_string()).unwrap();
@@ -40,7 +57,7 @@ fn setup_account(bank: &mut Bank, name: &str, role: Role) -> HashResult {
 #[test]
 fn can_deposit() {
     // Setup user
-    let mut bank = Bank::default();
+    let mut bank = new_bank();
     let hash = setup_account(&mut bank, "roy", Role::Customer);
 
     assert_eq!(1_000f64, bank.check_balance(hash).unwrap());
@@ -51,6 +68,7 @@ fn can_deposit() {
         Event::Deposit {
             id: 1,
             amount: 1f64,
+            reference: None,
         },
     );
 
@@ -65,7 +83,7 @@ fn can_deposit() {
 
 #[test]
 fn can_withdraw() {
-    let mut bank = Bank::default();
+    let mut bank = new_bank();
     let customer = setup_account(&mut bank, "customer", Role::Customer);
     assert_ok(bank.withdraw(customer, 500f64));
     assert_eq!(500f64, bank.check_balance(customer).unwrap());
@@ -73,7 +91,7 @@ fn can_withdraw() {
 
 #[test]
 fn can_transfer() {
-    let mut bank = Bank::default();
+    let mut bank = new_bank();
     let hash1 = setup_account(&mut bank, "user1", Role::Customer);
     let hash2 = setup_account(&mut bank, "user2", Role::Customer);
 
@@ -87,6 +105,7 @@ fn can_transfer() {
             id: 1,
             to_id: 2,
             amount: 500f64,
+            reference: None,
         },
     );
 
@@ -116,28 +135,2364 @@ fn can_transfer() {
             id: 1,
             to_id: 2,
             amount: 496f64,
+            reference: None,
         },
     );
+    bank.debug_assert_reconciled();
 }
 
 #[test]
-fn can_report() {
-    let mut bank = Bank::default();
-    // Setup user
+fn can_transfer_by_username() {
+    let mut bank = new_bank();
+    let hash1 = setup_account(&mut bank, "user1", Role::Customer);
+    let hash2 = setup_account(&mut bank, "user2", Role::Customer);
+    setup_account(&mut bank, "manager", Role::Manager);
+
+    assert_ok(bank.transfer_by_username(hash1, 100f64, "user2"));
+    assert_eq!(900f64, bank.check_balance(hash1).unwrap());
+    assert_eq!(1100f64, bank.check_balance(hash2).unwrap());
+    assert_last_event(
+        &bank,
+        Event::Transfer {
+            id: 1,
+            to_id: 2,
+            amount: 100f64,
+            reference: None,
+        },
+    );
+
+    assert_noop(
+        bank.transfer_by_username(hash1, 100f64, "nobody"),
+        BankingError::NoUserFound,
+    );
+    assert_noop(
+        bank.transfer_by_username(hash1, 100f64, "manager"),
+        BankingError::NoUserFound,
+    );
+}
+
+#[test]
+fn can_manage_payees() {
+    let mut bank = new_bank();
+    let hash1 = setup_account(&mut bank, "user1", Role::Customer);
+    let hash2 = setup_account(&mut bank, "user2", Role::Customer);
+
+    assert_ok(bank.add_payee(hash1, "user2", " mum ".to_string()));
+    assert_last_event(&bank, Event::PayeeAdded { id: 1, payee: 2 });
+    assert_eq!(
+        bank.payees(hash1).unwrap(),
+        vec![(
+            Payee {
+                id: 2,
+                nickname: "mum".to_string()
+            },
+            "user2".to_string()
+        )]
+    );
+
+    assert_noop(
+        bank.add_payee(hash1, "user2", "dad".to_string()),
+        BankingError::PayeeAlreadyExist,
+    );
+    assert_noop(
+        bank.add_payee(hash1, "user2", " ".to_string()),
+        BankingError::InvalidNickname,
+    );
+    assert_noop(
+        bank.add_payee(hash1, "user1", "me".to_string()),
+        BankingError::InvalidUserId,
+    );
+
+    // Payee nicknames take precedence, then usernames and IDs.
+    assert_eq!(
+        bank.resolve_payee(hash1, "mum").unwrap(),
+        (2, "user2".to_string())
+    );
+    assert_eq!(
+        bank.resolve_payee(hash1, "user2").unwrap(),
+        (2, "user2".to_string())
+    );
+    assert_eq!(
+        bank.resolve_payee(hash1, "2").unwrap(),
+        (2, "user2".to_string())
+    );
+    assert_noop(bank.resolve_payee(hash1, "7"), BankingError::InvalidUserId);
+
+    assert_ok(bank.transfer_to_payee(hash1, 100f64, "mum"));
+    assert_eq!(1100f64, bank.check_balance(hash2).unwrap());
+
+    assert_ok(bank.remove_payee(hash1, "mum"));
+    assert_last_event(&bank, Event::PayeeRemoved { id: 1, payee: 2 });
+    assert_noop(bank.remove_payee(hash1, "mum"), BankingError::NoPayeeFound);
+    assert_noop(bank.remove_payee(hash2, "mum"), BankingError::NoPayeeFound);
+    assert_noop(
+        bank.transfer_to_payee(hash1, 100f64, "mum"),
+        BankingError::NoPayeeFound,
+    );
+}
+
+#[test]
+fn can_settle_and_cancel_pending_transfer() {
+    let mut bank = new_bank();
+    let hash1 = setup_account(&mut bank, "user1", Role::Customer);
+    let hash2 = setup_account(&mut bank, "user2", Role::Customer);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+
+    let transfer_id = bank.initiate_transfer(hash1, 600f64, 2).unwrap();
+    assert_last_event(
+        &bank,
+        Event::TransferInitiated {
+            transfer_id,
+            id: 1,
+            to_id: 2,
+            amount: 600f64,
+            reference: None,
+        },
+    );
+    // The hold reduces the available balance only.
+    assert_eq!(1000f64, bank.check_balance(hash1).unwrap());
+    assert_eq!(400f64, bank.check_available_balance(hash1).unwrap());
+    assert_noop(
+        bank.withdraw(hash1, 500f64),
+        BankingError::InsufficientBalance,
+    );
+    assert_noop(
+        bank.initiate_transfer(hash1, 500f64, 2),
+        BankingError::InsufficientBalance,
+    );
+    assert_noop(
+        bank.settle_transfer(hash1, transfer_id),
+        BankingError::Unauthorized,
+    );
+
+    assert_ok(bank.settle_transfer(manager, transfer_id));
+    assert_eq!(400f64, bank.check_balance(hash1).unwrap());
+    assert_eq!(400f64, bank.check_available_balance(hash1).unwrap());
+    assert_eq!(1600f64, bank.check_balance(hash2).unwrap());
+    assert_last_event(
+        &bank,
+        Event::TransferSettled {
+            transfer_id,
+            id: 1,
+            to_id: 2,
+        },
+    );
+    assert_noop(
+        bank.cancel_transfer(hash1, transfer_id),
+        BankingError::InvalidTransferStatus,
+    );
+
+    let transfer_id = bank.initiate_transfer(hash1, 100f64, 2).unwrap();
+    assert_noop(
+        bank.cancel_transfer(hash2, transfer_id),
+        BankingError::Unauthorized,
+    );
+    assert_ok(bank.cancel_transfer(hash1, transfer_id));
+    assert_eq!(400f64, bank.check_available_balance(hash1).unwrap());
+    assert_noop(
+        bank.settle_transfer(manager, transfer_id),
+        BankingError::InvalidTransferStatus,
+    );
+    assert_noop(
+        bank.settle_transfer(manager, 99),
+        BankingError::NoTransferFound,
+    );
+    assert_eq!(
+        bank.list_transfers(hash1)
+            .unwrap()
+            .iter()
+            .map(|t| t.status)
+            .collect::<Vec<_>>(),
+        vec![TransferStatus::Settled, TransferStatus::Cancelled]
+    );
+    bank.debug_assert_reconciled();
+}
+
+#[test]
+fn can_reverse_transfer() {
+    let mut bank = new_bank();
+    let hash1 = setup_account(&mut bank, "user1", Role::Customer);
+    let hash2 = setup_account(&mut bank, "user2", Role::Customer);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+
+    // Atomic transfers are recorded and can be reversed too.
+    assert_ok(bank.transfer(hash1, 300f64, 2));
+    let original = bank.event_count() - 1;
+    let transfer_id = bank.list_transfers(hash1).unwrap()[0].transfer_id;
+
+    assert_noop(
+        bank.reverse_transfer(hash1, transfer_id),
+        BankingError::Unauthorized,
+    );
+    assert_ok(bank.reverse_transfer(manager, transfer_id));
+    assert_eq!(1000f64, bank.check_balance(hash1).unwrap());
+    assert_eq!(1000f64, bank.check_balance(hash2).unwrap());
+    assert_last_event(
+        &bank,
+        Event::TransferReversed {
+            transfer_id,
+            id: 1,
+            to_id: 2,
+            amount: 300f64,
+            original,
+            by: 3,
+        },
+    );
+    assert_noop(
+        bank.reverse_transfer(manager, transfer_id),
+        BankingError::InvalidTransferStatus,
+    );
+
+    // Reversal requires the recipient to still hold the funds.
+    assert_ok(bank.transfer(hash1, 300f64, 2));
+    assert_ok(bank.withdraw(hash2, 1200f64));
+    let transfer_id = bank.list_transfers(hash1).unwrap()[1].transfer_id;
+    assert_noop(
+        bank.reverse_transfer(manager, transfer_id),
+        BankingError::InsufficientBalance,
+    );
+    bank.debug_assert_reconciled();
+}
+
+#[test]
+fn can_run_batch_all_or_nothing() {
+    let mut bank = new_bank();
+    let payer = setup_account(&mut bank, "payer", Role::Customer);
+    let alice = setup_account(&mut bank, "alice", Role::Customer);
+    let bob = setup_account(&mut bank, "bob", Role::Customer);
+
+    let batch = "payee,amount,reference\nalice,300,Salary\n3,200,\"Salary, bonus\"\n";
+    let results = run_batch(
+        &mut bank,
+        payer,
+        parse_batch(batch),
+        BatchMode::AllOrNothing,
+    )
+    .unwrap();
+    assert_eq!(
+        results.iter().map(|r| r.status).collect::<Vec<_>>(),
+        vec![BatchStatus::Paid, BatchStatus::Paid]
+    );
+    assert_eq!(results[1].reference, "Salary, bonus");
+    assert_eq!(500f64, bank.check_balance(payer).unwrap());
+    assert_eq!(1300f64, bank.check_balance(alice).unwrap());
+    assert_eq!(1200f64, bank.check_balance(bob).unwrap());
+
+    // One bad line rejects the whole batch.
+    let batch = "alice,100,Salary\nnobody,100,Salary\nbob,abc\n";
+    let results = run_batch(
+        &mut bank,
+        payer,
+        parse_batch(batch),
+        BatchMode::AllOrNothing,
+    )
+    .unwrap();
+    assert_eq!(
+        results.iter().map(|r| r.status).collect::<Vec<_>>(),
+        vec![
+            BatchStatus::Skipped,
+            BatchStatus::Failed(BankingError::NoUserFound),
+            BatchStatus::Failed(BankingError::InvalidAmount),
+        ]
+    );
+    assert_eq!(500f64, bank.check_balance(payer).unwrap());
+    assert_eq!(1300f64, bank.check_balance(alice).unwrap());
+}
+
+#[test]
+fn can_roll_back_batch_failing_as_it_is_paid() {
+    // Allows the movements it sees until `allowed` runs out, then holds them.
+    struct FickleRule {
+        allowed: std::sync::atomic::AtomicUsize,
+    }
+    impl FraudRule for FickleRule {
+        fn name(&self) -> String {
+            "fickle".to_string()
+        }
+
+        fn evaluate(&self, _: &Movement, _: &[Movement]) -> FraudDecision {
+            use std::sync::atomic::Ordering;
+            match self.allowed.load(Ordering::SeqCst) {
+                0 => FraudDecision::Hold,
+                n => {
+                    self.allowed.store(n - 1, Ordering::SeqCst);
+                    FraudDecision::Allow
+                }
+            }
+        }
+    }
+
+    let mut bank = new_bank();
+    let payer = setup_account(&mut bank, "payer", Role::Customer);
+    let alice = setup_account(&mut bank, "alice", Role::Customer);
+    let bob = setup_account(&mut bank, "bob", Role::Customer);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+    let rule = FickleRule {
+        allowed: 3.into(),
+    };
+    assert_ok(bank.add_fraud_rule(auditor, Box::new(rule)));
+
+    // Both lines pass validation and the first is paid, but the second is held: the first
+    // payment, the fraud check and the review are all undone.
+    let events = bank.event_count();
+    let batch = "alice,300,Salary\nbob,200,Salary\n";
+    let results = run_batch(
+        &mut bank,
+        payer,
+        parse_batch(batch),
+        BatchMode::AllOrNothing,
+    )
+    .unwrap();
+    assert_eq!(
+        results.iter().map(|r| r.status).collect::<Vec<_>>(),
+        vec![
+            BatchStatus::Skipped,
+            BatchStatus::Failed(BankingError::TransactionHeld),
+        ]
+    );
+    assert_eq!(bank.event_count(), events);
+    assert_eq!(1000f64, bank.check_balance(payer).unwrap());
+    assert_eq!(1000f64, bank.check_balance(alice).unwrap());
+    assert_eq!(1000f64, bank.check_balance(bob).unwrap());
+    assert!(bank.list_transfers(payer).unwrap().is_empty());
+    assert!(bank.list_fraud_reviews(auditor).unwrap().is_empty());
+    assert_eq!(bank.verify_log(auditor).unwrap().issue, None);
+
+    // The bank carries on.
+    assert_ok(bank.remove_fraud_rule(auditor, "fickle"));
+    assert_ok(bank.transfer(payer, 100f64, 2));
+    assert_eq!(900f64, bank.check_balance(payer).unwrap());
+}
+
+#[test]
+fn can_validate_batch_against_bank_rules() {
+    let mut bank = new_bank();
+    let payer = setup_account(&mut bank, "payer", Role::Customer);
+    setup_account(&mut bank, "alice", Role::Customer);
+    setup_account(&mut bank, "bob", Role::Customer);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+    assert_ok(bank.add_fraud_rule(
+        auditor,
+        Box::new(VelocityRule {
+            max_count: 2,
+            window: DAY,
+        }),
+    ));
+
+    // The fraud rules count the earlier lines of the batch, so nothing is paid.
+    let batch = "alice,10\nbob,10\nalice,10\nbob,10\n";
+    let results = run_batch(
+        &mut bank,
+        payer,
+        parse_batch(batch),
+        BatchMode::AllOrNothing,
+    )
+    .unwrap();
+    assert_eq!(
+        results.iter().map(|r| r.status).collect::<Vec<_>>(),
+        vec![
+            BatchStatus::Skipped,
+            BatchStatus::Skipped,
+            BatchStatus::Failed(BankingError::TransactionDenied),
+            BatchStatus::Failed(BankingError::TransactionDenied),
+        ]
+    );
+    assert_eq!(1000f64, bank.check_balance(payer).unwrap());
+    assert_eq!(0, bank.list_transfers(payer).unwrap().len());
+
+    // Amounts must be finite.
+    assert_eq!(
+        parse_batch("bob,NaN\nbob,inf\n"),
+        vec![
+            Err((1, BankingError::InvalidAmount)),
+            Err((2, BankingError::InvalidAmount)),
+        ]
+    );
+    assert_noop(
+        bank.transfer(payer, f64::NAN, 3),
+        BankingError::InvalidAmount,
+    );
+    assert_eq!(1000f64, bank.check_balance(payer).unwrap());
+}
+
+#[test]
+fn can_run_batch_best_effort() {
+    let mut bank = new_bank();
+    let payer = setup_account(&mut bank, "payer", Role::Customer);
+    let alice = setup_account(&mut bank, "alice", Role::Customer);
+    let bob = setup_account(&mut bank, "bob", Role::Customer);
+
+    // The first two lines bring the payer below ED, so the account is reaped and the
+    // last line can no longer be paid.
+    let batch = "alice,600,a\npayer,10,b\nbob,1,c\nbob,398,d\nalice,100,e\n";
+    let results = run_batch(&mut bank, payer, parse_batch(batch), BatchMode::BestEffort).unwrap();
+    assert_eq!(
+        results.iter().map(|r| r.status).collect::<Vec<_>>(),
+        vec![
+            BatchStatus::Paid,
+            BatchStatus::Failed(BankingError::InvalidUserId),
+            BatchStatus::Failed(BankingError::AmountTooSmall),
+            BatchStatus::Paid,
+            BatchStatus::Failed(BankingError::InsufficientBalance),
+        ]
+    );
+    assert_eq!(0f64, bank.check_balance(payer).unwrap());
+    assert_eq!(1600f64, bank.check_balance(alice).unwrap());
+    assert_eq!(1398f64, bank.check_balance(bob).unwrap());
+    assert_eq!(
+        render_results(&results).lines().nth(5).unwrap(),
+        "5,alice,100,e,FAILED,User does not have enough balance."
+    );
+}
+
+#[test]
+fn can_process_batch_file() {
+    let mut bank = new_bank();
+    let payer = setup_account(&mut bank, "payer", Role::Customer);
+    setup_account(&mut bank, "alice", Role::Customer);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+
+    let dir = temp_path("batch");
+    std::fs::create_dir_all(&dir).unwrap();
+    let input = dir.join("batch.csv");
+    let output = dir.join("results.csv");
+    std::fs::write(&input, "alice,100,Rent\n").unwrap();
+    let (input, output) = (input.to_str().unwrap(), output.to_str().unwrap());
+
+    assert_noop(
+        process_batch_file(&mut bank, manager, input, output, BatchMode::BestEffort),
+        BankingError::Unauthorized,
+    );
+    assert_noop(
+        process_batch_file(
+            &mut bank,
+            payer,
+            "/no/such/file",
+            output,
+            BatchMode::BestEffort,
+        ),
+        BankingError::FailedToReadFile,
+    );
+    assert_ok(process_batch_file(
+        &mut bank,
+        payer,
+        input,
+        output,
+        BatchMode::BestEffort,
+    ));
+    assert_eq!(
+        std::fs::read_to_string(output).unwrap(),
+        "line,payee,amount,reference,status,error\n1,alice,100,Rent,PAID,\n"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn can_validate_payment_reference() {
+    let reference = PaymentReference::new(
+        Some(" Invoice 42 ".to_string()),
+        Some("rf18 5390 0754 7034".to_string()),
+    )
+    .unwrap();
+    assert_eq!(reference.memo, Some("Invoice 42".to_string()));
+    assert_eq!(
+        reference.creditor_reference,
+        Some("RF18539007547034".to_string())
+    );
+    assert_eq!(reference.to_string(), "Invoice 42 (RF18539007547034)");
+    assert!(PaymentReference::memo("  ").unwrap().is_empty());
+
+    assert_noop(
+        PaymentReference::memo(&"a".repeat(141)),
+        BankingError::InvalidReference,
+    );
+    assert_noop(
+        PaymentReference::memo("Rent\n<script>"),
+        BankingError::InvalidReference,
+    );
+    assert_noop(
+        PaymentReference::new(None, Some("RF19539007547034".to_string())),
+        BankingError::InvalidCreditorReference,
+    );
+    assert_noop(
+        PaymentReference::new(None, Some("XX18539007547034".to_string())),
+        BankingError::InvalidCreditorReference,
+    );
+}
+
+#[test]
+fn can_search_events_by_reference() {
+    let mut bank = new_bank();
+    let hash1 = setup_account(&mut bank, "user1", Role::Customer);
+    let hash2 = setup_account(&mut bank, "user2", Role::Customer);
+    let hash3 = setup_account(&mut bank, "user3", Role::Customer);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+
+    let rent = PaymentReference::memo("Rent March").unwrap();
+    assert_ok(bank.transfer_with_reference(hash1, 100f64, 2, rent.clone()));
+    assert_last_event(
+        &bank,
+        Event::Transfer {
+            id: 1,
+            to_id: 2,
+            amount: 100f64,
+            reference: Some(rent.clone()),
+        },
+    );
+    let invoice = PaymentReference::new(None, Some("RF18539007547034".to_string())).unwrap();
+    assert_ok(bank.withdraw_with_reference(hash3, 10f64, invoice.clone()));
+    assert_ok(bank.deposit_with_reference(hash3, 10f64, rent.clone()));
+    assert_noop(
+        bank.deposit_with_reference(
+            hash3,
+            10f64,
+            PaymentReference {
+                memo: Some("\u{7}".to_string()),
+                creditor_reference: None,
+            },
+        ),
+        BankingError::InvalidReference,
+    );
+
+    // The recipient sees the reference of the transfer.
+    assert_eq!(
+        bank.search_events_by_reference(hash2, "rent").unwrap(),
+        vec![Event::Transfer {
+            id: 1,
+            to_id: 2,
+            amount: 100f64,
+            reference: Some(rent.clone()),
+        }]
+    );
+    assert_eq!(
+        bank.search_events_by_reference(hash3, "rent")
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(
+        bank.search_events_by_reference(auditor, "rent")
+            .unwrap()
+            .len(),
+        2
+    );
+    assert_eq!(
+        bank.search_events_by_reference(auditor, "RF18 5390 0754 7034")
+            .unwrap(),
+        vec![Event::Withdrawal {
+            id: 3,
+            amount: 10f64,
+            reference: Some(invoice),
+        }]
+    );
+    assert!(bank
+        .search_events_by_reference(hash1, "")
+        .unwrap()
+        .is_empty());
+
+    // A pending transfer carries its reference to settlement.
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+    let transfer_id = bank
+        .initiate_transfer_with_reference(hash1, 100f64, 2, rent.clone())
+        .unwrap();
+    assert_ok(bank.settle_transfer(manager, transfer_id));
+    assert_eq!(
+        nth_last_event(&bank, 2),
+        Event::Transfer {
+            id: 1,
+            to_id: 2,
+            amount: 100f64,
+            reference: Some(rent),
+        }
+    );
+}
+
+#[test]
+fn can_charge_fees() {
+    let mut bank = new_bank();
+    let hash1 = setup_account(&mut bank, "user1", Role::Customer);
+    let hash2 = setup_account(&mut bank, "user2", Role::Customer);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+
+    let rule = FeeRule {
+        flat: 1f64,
+        percentage: 0.01f64,
+        min: 0f64,
+        max: Some(5f64),
+    };
+    assert_noop(
+        bank.set_fee_rule(hash1, FeeType::Withdrawal, rule),
+        BankingError::Unauthorized,
+    );
+    assert_noop(
+        bank.set_fee_rule(
+            manager,
+            FeeType::Withdrawal,
+            FeeRule {
+                percentage: 2f64,
+                ..rule
+            },
+        ),
+        BankingError::InvalidFeeRule,
+    );
+    assert_ok(bank.set_fee_rule(manager, FeeType::Withdrawal, rule));
+    assert_ok(bank.set_fee_rule(manager, FeeType::Transfer, rule));
+    assert_ok(bank.set_fee_rule(
+        manager,
+        FeeType::Overdraft,
+        FeeRule {
+            flat: 10f64,
+            ..Default::default()
+        },
+    ));
+
+    // 1 + 1% of 100
+    assert_eq!(
+        2f64,
+        bank.quote_fee(hash1, FeeType::Withdrawal, 100f64).unwrap()
+    );
+    assert_ok(bank.withdraw(hash1, 100f64));
+    assert_eq!(898f64, bank.check_balance(hash1).unwrap());
+    assert_last_event(
+        &bank,
+        Event::Fee {
+            id: 1,
+            fee_type: FeeType::Withdrawal,
+            amount: 2f64,
+        },
+    );
+
+    // The fee is capped at 5, and only the sender pays it.
+    assert_ok(bank.transfer(hash1, 800f64, 2));
+    assert_eq!(93f64, bank.check_balance(hash1).unwrap());
+    assert_eq!(1800f64, bank.check_balance(hash2).unwrap());
+
+    // A declined withdrawal is charged the overdraft fee.
+    assert_noop(
+        bank.withdraw(hash1, 92f64),
+        BankingError::InsufficientBalance,
+    );
+    assert_eq!(83f64, bank.check_balance(hash1).unwrap());
+    assert_eq!(17f64, bank.fee_income(auditor).unwrap());
+    assert_noop(bank.fee_income(hash1), BankingError::Unauthorized);
+
+    // So are transfers declined by username or to a payee.
+    assert_noop(
+        bank.transfer_by_username(hash1, 100f64, "user2"),
+        BankingError::InsufficientBalance,
+    );
+    assert_ok(bank.add_payee(hash1, "user2", "mum".to_string()));
+    assert_noop(
+        bank.transfer_to_payee(hash1, 100f64, "mum"),
+        BankingError::InsufficientBalance,
+    );
+    assert_eq!(63f64, bank.check_balance(hash1).unwrap());
+    assert_eq!(37f64, bank.fee_income(auditor).unwrap());
+    assert_eq!(
+        nth_last_event(&bank, 1),
+        Event::Fee {
+            id: 1,
+            fee_type: FeeType::Overdraft,
+            amount: 10f64,
+        }
+    );
+    bank.debug_assert_reconciled();
+}
+
+#[test]
+fn can_waive_fees_and_charge_monthly_fees() {
+    let mut bank = new_bank();
+    let hash1 = setup_account(&mut bank, "user1", Role::Customer);
+    let hash2 = setup_account(&mut bank, "user2", Role::Customer);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+
+    assert_ok(bank.set_fee_rule(
+        manager,
+        FeeType::MonthlyMaintenance,
+        FeeRule {
+            flat: 2f64,
+            ..Default::default()
+        },
+    ));
+    assert_ok(bank.set_fee_rule(
+        manager,
+        FeeType::BelowMinimumBalance,
+        FeeRule {
+            flat: 10f64,
+            ..Default::default()
+        },
+    ));
+    assert_ok(bank.set_minimum_balance(manager, 500f64));
+    assert_ok(bank.withdraw(hash2, 600f64));
+
+    assert_ok(bank.grant_fee_waiver(manager, 1, Some(FeeType::MonthlyMaintenance)));
+    assert_last_event(
+        &bank,
+        Event::FeeWaiverGranted {
+            id: 1,
+            fee_type: Some(FeeType::MonthlyMaintenance),
+            by: 3,
+        },
+    );
+    assert_noop(
+        bank.grant_fee_waiver(manager, 3, None),
+        BankingError::InvalidUserId,
+    );
+
+    assert_ok(bank.charge_monthly_fees(manager));
+    assert_eq!(1000f64, bank.check_balance(hash1).unwrap());
+    assert_eq!(388f64, bank.check_balance(hash2).unwrap());
+    assert_eq!(12f64, bank.fee_income(manager).unwrap());
+
+    assert_ok(bank.revoke_fee_waiver(manager, 1, Some(FeeType::MonthlyMaintenance)));
+    assert_noop(
+        bank.revoke_fee_waiver(manager, 1, Some(FeeType::MonthlyMaintenance)),
+        BankingError::NoFeeWaiverFound,
+    );
+    assert_ok(bank.grant_fee_waiver(manager, 2, None));
+    assert_ok(bank.charge_monthly_fees(manager));
+    assert_eq!(998f64, bank.check_balance(hash1).unwrap());
+    assert_eq!(388f64, bank.check_balance(hash2).unwrap());
+    assert_eq!(14f64, bank.fee_income(manager).unwrap());
+    bank.debug_assert_reconciled();
+}
+
+#[test]
+fn can_generate_amortization_schedules() {
+    let product = LoanProduct {
+        product_id: 1,
+        name: "Personal".to_string(),
+        annual_rate: 0.12f64,
+        term: 12,
+        frequency: RepaymentFrequency::Monthly,
+        method: AmortizationMethod::Annuity,
+    };
+    let schedule = amortization_schedule(&product, 1200f64, 0);
+    assert_eq!(schedule.len(), 12);
+    assert_eq!(schedule[0].due, MONTH);
+    assert_eq!(schedule[11].due, 12 * MONTH);
+    assert_eq!(schedule[0].interest, 12f64);
+    assert_eq!(schedule[0].principal, 94.62f64);
+    // Every installment is the same amount, give or take rounding.
+    schedule
+        .iter()
+        .for_each(|i| assert!((i.amount_due() - 106.62f64).abs() < 0.02f64));
+    let total = schedule.iter().map(|i| i.principal).sum::<f64>();
+    assert!((total - 1200f64).abs() < 1e-9);
+
+    let schedule = amortization_schedule(
+        &LoanProduct {
+            method: AmortizationMethod::EqualPrincipal,
+            frequency: RepaymentFrequency::Weekly,
+            annual_rate: 0.52f64,
+            ..product
+        },
+        1200f64,
+        DAY,
+    );
+    assert_eq!(schedule[1].due, DAY + 2 * WEEK);
+    assert!(schedule.iter().all(|i| i.principal == 100f64));
+    assert_eq!(schedule[0].interest, 12f64);
+    assert_eq!(schedule[1].interest, 11f64);
+    assert_eq!(schedule[11].interest, 1f64);
+}
+
+#[test]
+fn can_lend_and_collect_repayments() {
+    let mut bank = new_bank();
     let hash = setup_account(&mut bank, "roy", Role::Customer);
-    // Setup user manager
-    let hash_manager = setup_account(&mut bank, "manager", Role::Manager);
-    // Setup user manager
-    let hash_auditor = setup_account(&mut bank, "auditor", Role::Auditor);
-    // test pay_interest
-    assert_ok(bank.report(hash_manager));
-    assert_ok(bank.report(hash_auditor));
-    assert_noop(bank.report(hash), BankingError::Unauthorized);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+
+    assert_noop(
+        bank.create_loan_product(
+            manager,
+            "Bad".to_string(),
+            -1f64,
+            2,
+            RepaymentFrequency::Monthly,
+            AmortizationMethod::Annuity,
+        ),
+        BankingError::InvalidLoanProduct,
+    );
+    let product_id = bank
+        .create_loan_product(
+            manager,
+            "Interest free".to_string(),
+            0f64,
+            2,
+            RepaymentFrequency::Monthly,
+            AmortizationMethod::Annuity,
+        )
+        .unwrap();
+    assert_ok(bank.set_fee_rule(
+        manager,
+        FeeType::LatePayment,
+        FeeRule {
+            flat: 10f64,
+            ..Default::default()
+        },
+    ));
+
+    assert_noop(
+        bank.apply_for_loan(hash, 9, 1000f64),
+        BankingError::NoLoanProductFound,
+    );
+    let rejected = bank.apply_for_loan(hash, product_id, 5000f64).unwrap();
+    assert_ok(bank.reject_loan(manager, rejected));
+    let loan_id = bank.apply_for_loan(hash, product_id, 1000f64).unwrap();
+    assert_noop(bank.approve_loan(hash, loan_id), BankingError::Unauthorized);
+    assert_ok(bank.approve_loan(manager, loan_id));
+    assert_noop(
+        bank.approve_loan(manager, rejected),
+        BankingError::InvalidLoanStatus,
+    );
+    assert_eq!(2000f64, bank.check_balance(hash).unwrap());
+
+    // The first installment is drawn on its due date.
+    assert_ok(bank.advance_clock(manager, MONTH));
+    assert_eq!(1500f64, bank.check_balance(hash).unwrap());
+    assert_last_event(
+        &bank,
+        Event::LoanRepayment {
+            loan_id,
+            id: 1,
+            installment: 1,
+            principal: 500f64,
+            interest: 0f64,
+        },
+    );
+
+    // The second installment is late: a late fee is added and the loan is delinquent.
+    assert_ok(bank.withdraw(hash, 1400f64));
+    assert_ok(bank.advance_clock(manager, MONTH));
+    assert_last_event(
+        &bank,
+        Event::LoanStatusChanged {
+            loan_id,
+            id: 1,
+            status: LoanStatus::Delinquent,
+        },
+    );
+    assert_eq!(
+        bank.list_loans(hash).unwrap()[1].schedule[1].late_fee,
+        10f64
+    );
+
+    assert_ok(bank.advance_clock(manager, LOAN_DEFAULT_DAYS * DAY));
+    assert_eq!(
+        bank.list_loans(manager).unwrap()[1].status,
+        LoanStatus::Defaulted
+    );
+
+    // Once the borrower has the funds, the installment and late fee are collected.
+    assert_ok(bank.deposit(hash, 500f64));
+    assert_ok(bank.advance_clock(manager, DAY));
+    assert_eq!(90f64, bank.check_balance(hash).unwrap());
+    assert_eq!(10f64, bank.fee_income(manager).unwrap());
+    let loan = &bank.list_loans(hash).unwrap()[1];
+    assert_eq!(loan.status, LoanStatus::Repaid);
+    assert_eq!(loan.outstanding_principal(), 0f64);
+    bank.debug_assert_reconciled();
+}
+
+#[test]
+fn can_mature_term_deposits() {
+    let mut bank = new_bank();
+    let hash = setup_account(&mut bank, "roy", Role::Customer);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+    assert_ok(bank.set_term_deposit_rate(manager, 0.12f64));
+
+    assert_noop(
+        bank.open_term_deposit(
+            hash,
+            500f64,
+            0,
+            InterestPayout::Monthly,
+            MaturityInstruction::PayOut,
+        ),
+        BankingError::InvalidTerm,
+    );
+    assert_noop(
+        bank.open_term_deposit(
+            hash,
+            2000f64,
+            3,
+            InterestPayout::Monthly,
+            MaturityInstruction::PayOut,
+        ),
+        BankingError::InsufficientBalance,
+    );
+    let monthly = bank
+        .open_term_deposit(
+            hash,
+            500f64,
+            3,
+            InterestPayout::Monthly,
+            MaturityInstruction::PayOut,
+        )
+        .unwrap();
+    let rolled = bank
+        .open_term_deposit(
+            hash,
+            400f64,
+            1,
+            InterestPayout::AtMaturity,
+            MaturityInstruction::RollOver,
+        )
+        .unwrap();
+    assert_eq!(100f64, bank.check_balance(hash).unwrap());
+    // The main account is not reaped to fund a term deposit.
+    assert_noop(
+        bank.open_term_deposit(
+            hash,
+            97f64,
+            3,
+            InterestPayout::Monthly,
+            MaturityInstruction::PayOut,
+        ),
+        BankingError::InsufficientBalance,
+    );
+
+    // The rate is fixed when the term deposit is opened.
+    assert_ok(bank.set_term_deposit_rate(manager, 0f64));
+
+    // Monthly interest is paid, and the other term deposit is rolled over with its interest
+    // paid into the main balance.
+    assert_ok(bank.advance_clock(manager, MONTH));
+    assert_eq!(100f64 + 5f64 + 4f64, bank.check_balance(hash).unwrap());
+    assert_last_event(
+        &bank,
+        Event::TermDepositOpened {
+            deposit_id: 3,
+            id: 1,
+            amount: 400f64,
+            rate: 0f64,
+            matures_at: 2 * MONTH,
+            rolled_over_from: Some(rolled),
+        },
+    );
+
+    // At maturity the principal is paid out.
+    assert_ok(bank.advance_clock(manager, 2 * MONTH));
+    assert_eq!(
+        100f64 + 15f64 + 4f64 + 500f64,
+        bank.check_balance(hash).unwrap()
+    );
+    let deposits = bank.list_term_deposits(hash).unwrap();
+    assert_eq!(deposits[0].deposit_id, monthly);
+    assert_eq!(deposits[0].status, TermDepositStatus::Matured);
+    assert_eq!(deposits[0].interest_paid, 15f64);
+    assert_eq!(deposits[1].status, TermDepositStatus::Matured);
+    // The rolled over term deposit matured after a month, and is rolled over again.
+    assert_eq!(deposits.len(), 5);
+    assert_eq!(deposits[4].status, TermDepositStatus::Active);
+    assert_eq!(deposits[4].matures_at, 4 * MONTH);
+    bank.debug_assert_reconciled();
+}
+
+#[test]
+fn can_break_term_deposit() {
+    let mut bank = new_bank();
+    let hash = setup_account(&mut bank, "roy", Role::Customer);
+    let other = setup_account(&mut bank, "tom", Role::Customer);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+    assert_ok(bank.set_fee_rule(
+        manager,
+        FeeType::EarlyWithdrawal,
+        FeeRule {
+            percentage: 0.02f64,
+            ..Default::default()
+        },
+    ));
+
+    let deposit_id = bank
+        .open_term_deposit(
+            hash,
+            500f64,
+            12,
+            InterestPayout::AtMaturity,
+            MaturityInstruction::PayOut,
+        )
+        .unwrap();
+    assert_noop(
+        bank.break_term_deposit(other, deposit_id),
+        BankingError::NoTermDepositFound,
+    );
+
+    // The penalty is deducted from the principal and paid to the bank.
+    assert_eq!(490f64, bank.break_term_deposit(hash, deposit_id).unwrap());
+    assert_eq!(990f64, bank.check_balance(hash).unwrap());
+    assert_eq!(10f64, bank.fee_income(manager).unwrap());
+    assert_noop(
+        bank.break_term_deposit(hash, deposit_id),
+        BankingError::InvalidTermDepositStatus,
+    );
+
+    // A broken term deposit earns no interest.
+    assert_ok(bank.advance_clock(manager, 12 * MONTH));
+    assert_eq!(990f64, bank.check_balance(hash).unwrap());
+    bank.debug_assert_reconciled();
+}
+
+#[test]
+fn can_hold_and_review_movements() {
+    let mut bank = new_bank();
+    let hash = setup_account(&mut bank, "roy", Role::Customer);
+    let tom = setup_account(&mut bank, "tom", Role::Customer);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+
+    assert_noop(
+        bank.add_fraud_rule(manager, Box::new(FirstTimePayeeRule { threshold: 300f64 })),
+        BankingError::Unauthorized,
+    );
+    assert_ok(bank.add_fraud_rule(auditor, Box::new(FirstTimePayeeRule { threshold: 300f64 })));
+
+    // A large transfer to a new payee is held, and the funds are not available.
+    assert_noop(
+        bank.transfer(hash, 400f64, 2),
+        BankingError::TransactionHeld,
+    );
+    assert_last_event(
+        &bank,
+        Event::FraudCheck {
+            id: 1,
+            to_id: Some(2),
+            amount: 400f64,
+            rule: Some("first-time-payee".to_string()),
+            decision: FraudDecision::Hold,
+            review_id: Some(1),
+        },
+    );
+    assert_eq!(600f64, bank.check_available_balance(hash).unwrap());
+    assert_noop(
+        bank.withdraw(hash, 700f64),
+        BankingError::InsufficientBalance,
+    );
+
+    // Small transfers are allowed, and the decision is logged.
+    assert_ok(bank.transfer(hash, 100f64, 2));
+    assert_eq!(
+        nth_last_event(&bank, 2),
+        Event::FraudCheck {
+            id: 1,
+            to_id: Some(2),
+            amount: 100f64,
+            rule: None,
+            decision: FraudDecision::Allow,
+            review_id: None,
+        }
+    );
+
+    // Once approved, the held transfer is carried out.
+    assert_noop(
+        bank.approve_fraud_review(hash, 1),
+        BankingError::Unauthorized,
+    );
+    assert_ok(bank.approve_fraud_review(auditor, 1));
+    assert_eq!(500f64, bank.check_balance(hash).unwrap());
+    assert_eq!(1500f64, bank.check_balance(tom).unwrap());
+    assert_noop(
+        bank.approve_fraud_review(auditor, 1),
+        BankingError::InvalidReviewStatus,
+    );
+
+    // A rejected withdrawal releases the held funds.
+    assert_ok(bank.add_fraud_rule(
+        auditor,
+        Box::new(AmountAnomalyRule {
+            multiplier: 1.5f64,
+            min_history: 2,
+        }),
+    ));
+    assert_noop(bank.withdraw(hash, 450f64), BankingError::TransactionHeld);
+    assert_eq!(50f64, bank.check_available_balance(hash).unwrap());
+    assert_ok(bank.reject_fraud_review(auditor, 2));
+    assert_eq!(500f64, bank.check_available_balance(hash).unwrap());
+    assert_eq!(bank.list_fraud_reviews(hash).unwrap().len(), 2);
+    assert_noop(
+        bank.remove_fraud_rule(auditor, "velocity"),
+        BankingError::NoFraudRuleFound,
+    );
+    assert_ok(bank.remove_fraud_rule(auditor, "amount-anomaly"));
+    assert_eq!(bank.fraud_rules(), vec!["first-time-payee".to_string()]);
+    bank.debug_assert_reconciled();
+}
+
+#[test]
+fn can_deny_fast_and_round_trip_movements() {
+    let mut bank = new_bank();
+    let hash = setup_account(&mut bank, "roy", Role::Customer);
+    let tom = setup_account(&mut bank, "tom", Role::Customer);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+    assert_ok(bank.add_fraud_rule(
+        auditor,
+        Box::new(VelocityRule {
+            max_count: 2,
+            window: 10 * MINUTE,
+        }),
+    ));
+    assert_ok(bank.add_fraud_rule(auditor, Box::new(RoundTripRule { window: DAY })));
+
+    assert_ok(bank.transfer(hash, 100f64, 2));
+    assert_ok(bank.withdraw(hash, 100f64));
+    assert_noop(
+        bank.transfer(hash, 100f64, 2),
+        BankingError::TransactionDenied,
+    );
+    assert_last_event(
+        &bank,
+        Event::FraudCheck {
+            id: 1,
+            to_id: Some(2),
+            amount: 100f64,
+            rule: Some("velocity".to_string()),
+            decision: FraudDecision::Deny,
+            review_id: None,
+        },
+    );
+    assert_eq!(800f64, bank.check_balance(hash).unwrap());
+
+    // Sending the money straight back is held.
+    assert_noop(bank.transfer(tom, 100f64, 1), BankingError::TransactionHeld);
+
+    // Both rules allow movements once their windows have passed.
+    assert_ok(bank.advance_clock(manager, DAY));
+    assert_ok(bank.transfer(hash, 100f64, 2));
+    assert_noop(bank.transfer(tom, 100f64, 1), BankingError::TransactionHeld);
+    assert_ok(bank.advance_clock(manager, DAY));
+    assert_ok(bank.transfer(tom, 100f64, 1));
+}
+
+#[test]
+fn can_screen_initiated_transfers() {
+    let mut bank = new_bank();
+    let hash = setup_account(&mut bank, "roy", Role::Customer);
+    let tom = setup_account(&mut bank, "tom", Role::Customer);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+    assert_ok(bank.add_fraud_rule(
+        auditor,
+        Box::new(VelocityRule {
+            max_count: 1,
+            window: DAY,
+        }),
+    ));
+
+    // Two-phase transfers count towards the same limits as direct ones, even while pending.
+    assert_ok(bank.transfer(hash, 100f64, 2));
+    assert_noop(
+        bank.initiate_transfer(hash, 100f64, 2),
+        BankingError::TransactionDenied,
+    );
+    assert_ok(bank.advance_clock(manager, DAY));
+    let transfer_id = bank.initiate_transfer(hash, 100f64, 2).unwrap();
+    assert_noop(
+        bank.initiate_transfer(hash, 100f64, 2),
+        BankingError::TransactionDenied,
+    );
+    assert_ok(bank.settle_transfer(manager, transfer_id));
+    assert_noop(
+        bank.initiate_transfer(hash, 100f64, 2),
+        BankingError::TransactionDenied,
+    );
+
+    // A held transfer is initiated under the ID recorded in its review once approved.
+    assert_ok(bank.remove_fraud_rule(auditor, "velocity"));
+    assert_ok(bank.add_fraud_rule(auditor, Box::new(FirstTimePayeeRule { threshold: 300f64 })));
+    assert_noop(
+        bank.initiate_transfer(tom, 400f64, 1),
+        BankingError::TransactionHeld,
+    );
+    let review = bank.list_fraud_reviews(tom).unwrap()[0].clone();
+    assert_eq!(800f64, bank.check_available_balance(tom).unwrap());
+    assert_noop(
+        bank.settle_transfer(manager, review.transfer_id.unwrap()),
+        BankingError::NoTransferFound,
+    );
+    assert_ok(bank.approve_fraud_review(auditor, review.review_id));
+    assert_eq!(800f64, bank.check_available_balance(tom).unwrap());
+    assert_ok(bank.settle_transfer(manager, review.transfer_id.unwrap()));
+    assert_eq!(800f64, bank.check_balance(tom).unwrap());
+    assert_eq!(1200f64, bank.check_balance(hash).unwrap());
+    bank.debug_assert_reconciled();
+}
+
+#[test]
+fn can_detect_large_cash_and_structuring() {
+    let mut bank = new_bank();
+    let hash = setup_account(&mut bank, "roy", Role::Customer);
+    let tom = setup_account(&mut bank, "tom", Role::Customer);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+
+    assert_noop(
+        bank.set_compliance_thresholds(
+            auditor,
+            ComplianceThresholds {
+                structuring_margin: 1.5f64,
+                ..Default::default()
+            },
+        ),
+        BankingError::InvalidComplianceThresholds,
+    );
+    assert_noop(
+        bank.detect_compliance_issues(manager),
+        BankingError::Unauthorized,
+    );
+
+    assert_ok(bank.deposit(hash, 12_000f64));
+    assert_ok(bank.deposit(hash, 9_500f64));
+    assert_ok(bank.deposit(tom, 9_500f64));
+    assert_ok(bank.deposit(hash, 9_600f64));
+    assert_ok(bank.deposit(tom, 9_900f64));
+    assert_ok(bank.advance_clock(manager, DAY));
+    assert_ok(bank.deposit(hash, 9_800f64));
+    // Deposits outside the window are not part of the run.
+    assert_ok(bank.advance_clock(manager, WEEK));
+    assert_ok(bank.deposit(tom, 9_700f64));
+    assert_ok(bank.withdraw(hash, 11_000f64));
+
+    assert_eq!(
+        bank.detect_compliance_issues(auditor).unwrap(),
+        vec![1, 2, 3]
+    );
+    let reports = bank.list_compliance_reports(auditor).unwrap();
+    assert_eq!(reports[0].kind, ReportKind::LargeCashTransaction);
+    assert_eq!(reports[0].amount, 12_000f64);
+    assert_eq!(reports[1].kind, ReportKind::LargeCashTransaction);
+    assert_eq!(reports[1].amount, 11_000f64);
+    assert_eq!(reports[2].kind, ReportKind::Structuring);
+    assert_eq!(reports[2].id, 1);
+    assert_eq!(reports[2].amount, 28_900f64);
+    assert_eq!(reports[2].events.len(), 3);
+    assert_eq!(reports[2].status, ReportStatus::Open);
+
+    // Events are only reported once.
+    assert_eq!(bank.detect_compliance_issues(auditor).unwrap(), vec![]);
+
+    // Lowering the count raises an alert for the other customer.
+    assert_ok(bank.set_compliance_thresholds(
+        auditor,
+        ComplianceThresholds {
+            structuring_count: 2,
+            ..Default::default()
+        },
+    ));
+    assert_eq!(bank.detect_compliance_issues(auditor).unwrap(), vec![4]);
+    assert_last_event(
+        &bank,
+        Event::ComplianceReportCreated {
+            report_id: 4,
+            id: 2,
+            kind: ReportKind::Structuring,
+            amount: 19_400f64,
+        },
+    );
+}
+
+#[test]
+fn can_review_and_export_compliance_reports() {
+    let mut bank = new_bank();
+    let hash = setup_account(&mut bank, "roy", Role::Customer);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+    assert_ok(bank.deposit(hash, 12_000.5f64));
+    assert_eq!(bank.detect_compliance_issues(auditor).unwrap(), vec![1]);
+
+    assert_noop(
+        bank.set_report_status(auditor, 1, ReportStatus::Filed),
+        BankingError::InvalidReportStatus,
+    );
+    assert_ok(bank.set_report_status(auditor, 1, ReportStatus::Reviewed));
+    assert_ok(bank.set_report_status(auditor, 1, ReportStatus::Filed));
+    assert_noop(
+        bank.set_report_status(auditor, 1, ReportStatus::Reviewed),
+        BankingError::InvalidReportStatus,
+    );
+    assert_noop(
+        bank.set_report_status(auditor, 2, ReportStatus::Reviewed),
+        BankingError::NoReportFound,
+    );
+
+    let expected = "H0000000001LCTR0000000001roy                 FILED   000000\n\
+                    D0000000001000000DEP000000001200050\n\
+                    T000001000000001200050\n";
+    assert_eq!(bank.render_compliance_report(auditor, 1).unwrap(), expected);
+
+    let path = temp_path("report");
+    let path = path.to_str().unwrap();
+    assert_ok(bank.export_compliance_report(auditor, 1, path));
+    assert_eq!(std::fs::read_to_string(path).unwrap(), expected);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn can_match_watch_list_names() {
+    let entries =
+        parse_watch_list("# Watch list\n\nIvan Petrov, Ivan Petroff, I. Petrov\nACME Trading\n");
+    assert_eq!(
+        entries,
+        vec![
+            WatchListEntry {
+                name: "Ivan Petrov".to_string(),
+                aliases: vec!["Ivan Petroff".to_string(), "I. Petrov".to_string()],
+            },
+            WatchListEntry {
+                name: "ACME Trading".to_string(),
+                aliases: vec![],
+            },
+        ]
+    );
+    assert_eq!(normalize_name("  PETROV,  Ivan "), "ivan petrov");
+    assert_eq!(name_similarity("Petrov Ivan", "ivan petrov"), 1f64);
+    assert_eq!(name_similarity("ivan_petrov", "Ivan Petrow"), 10f64 / 11f64);
+    assert!(name_similarity("alice", "Ivan Petrov") < 0.5f64);
+}
+
+#[test]
+fn can_screen_users_and_transfers() {
+    let mut bank = new_bank();
+    let hash = setup_account(&mut bank, "roy", Role::Customer);
+    let acme = setup_account(&mut bank, "acme_tradng", Role::Customer);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+
+    let path = temp_path("watch");
+    let path = path.to_str().unwrap();
+    std::fs::write(path, "Ivan Petrov, Ivan Petroff\n").unwrap();
+    assert_noop(bank.load_watch_list(hash, path), BankingError::Unauthorized);
+    assert_eq!(bank.load_watch_list(auditor, path).unwrap(), 1);
+
+    // Registration is blocked for a listed alias.
+    assert_noop(
+        bank.create_user("petroff.ivan".to_string(), "pw".to_string(), Role::Customer),
+        BankingError::WatchListMatch,
+    );
+    assert!(!bank.has_username("petroff.ivan"));
+
+    // Once cleared as a false positive, the same name can register.
+    assert_ok(bank.clear_screening_hit(auditor, 1));
+    assert_noop(
+        bank.clear_screening_hit(auditor, 1),
+        BankingError::InvalidScreeningHitStatus,
+    );
+    assert_ok(bank.create_user("petroff.ivan".to_string(), "pw".to_string(), Role::Customer));
+
+    // The watch list is reloaded without restarting. Close matches are flagged, and exact
+    // matches are blocked.
+    std::fs::write(path, "ACME Trading\nRoy\n").unwrap();
+    assert_eq!(bank.load_watch_list(auditor, path).unwrap(), 2);
+    std::fs::remove_file(path).unwrap();
+    assert_ok(bank.transfer(hash, 100f64, 2));
+    assert_eq!(
+        nth_last_event(&bank, 2),
+        Event::ScreeningHit {
+            hit_id: 2,
+            id: Some(1),
+            name: "acme_tradng".to_string(),
+            entry: "ACME Trading".to_string(),
+            blocked: false,
+        }
+    );
+    assert_noop(bank.transfer(acme, 100f64, 1), BankingError::WatchListMatch);
+    assert_eq!(1100f64, bank.check_balance(acme).unwrap());
+
+    let hits = bank.list_screening_hits(auditor).unwrap();
+    assert_eq!(hits.len(), 3);
+    assert!(hits[0].cleared);
+    assert!(!hits[1].blocked);
+    assert!(hits[2].blocked);
+}
+
+#[test]
+fn can_roll_back_failed_operations() {
+    // A rule failing halfway through a transfer, after the screening hit was logged.
+    struct FailingRule;
+    impl FraudRule for FailingRule {
+        fn name(&self) -> String {
+            "failing".to_string()
+        }
+
+        fn evaluate(&self, _: &Movement, _: &[Movement]) -> FraudDecision {
+            panic!("The rule failed.")
+        }
+    }
+
+    let mut bank = new_bank();
+    let hash = setup_account(&mut bank, "roy", Role::Customer);
+    setup_account(&mut bank, "acme_tradng", Role::Customer);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+    let path = temp_path("rollback");
+    let path = path.to_str().unwrap();
+    std::fs::write(path, "ACME Trading\n").unwrap();
+    assert_eq!(bank.load_watch_list(auditor, path).unwrap(), 1);
+    std::fs::remove_file(path).unwrap();
+    assert_ok(bank.add_fraud_rule(auditor, Box::new(FailingRule)));
+
+    // The hit is rolled back with the transfer, and the bank carries on.
+    let events = bank.event_count();
+    let transfer =
+        std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| bank.transfer(hash, 100f64, 2)));
+    assert!(transfer.is_err());
+    assert_eq!(bank.event_count(), events);
+    assert_eq!(bank.check_balance(hash), Ok(1000f64));
+    assert_ok(bank.remove_fraud_rule(auditor, "failing"));
+    assert_ok(bank.transfer(hash, 100f64, 2));
+    assert_eq!(bank.check_balance(hash), Ok(900f64));
+    assert_eq!(bank.verify_log(auditor).unwrap().issue, None);
+}
+
+#[test]
+fn can_version_customer_profiles() {
+    let mut bank = new_bank();
+    let hash = setup_account(&mut bank, "roy", Role::Customer);
+    let tom = setup_account(&mut bank, "tom", Role::Customer);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+
+    let profile = CustomerProfile {
+        legal_name: "Roy Smith".to_string(),
+        date_of_birth: "1990-02-28".to_string(),
+        address: "1 Main Street".to_string(),
+        email: "roy@example.com".to_string(),
+        phone: "0123".to_string(),
+    };
+    assert_noop(
+        bank.update_profile(
+            hash,
+            1,
+            CustomerProfile {
+                date_of_birth: "1990-02-30".to_string(),
+                ..profile.clone()
+            },
+        ),
+        BankingError::InvalidProfile,
+    );
+    assert_noop(
+        bank.update_profile(tom, 1, profile.clone()),
+        BankingError::Unauthorized,
+    );
+    assert_noop(bank.profile(hash, 1), BankingError::NoProfileFound);
+
+    // Submitting a profile makes the customer pending verification.
+    assert_eq!(bank.kyc_status(1), KycStatus::Unverified);
+    assert_eq!(bank.update_profile(hash, 1, profile.clone()).unwrap(), 1);
+    assert_eq!(bank.kyc_status(1), KycStatus::Pending);
+    assert_ok(bank.set_kyc_status(manager, 1, KycStatus::Verified));
+    assert_noop(
+        bank.set_kyc_status(manager, 2, KycStatus::Verified),
+        BankingError::NoProfileFound,
+    );
+
+    // A manager's edit keeps the status, and every version is kept.
+    let moved = CustomerProfile {
+        address: "2 High Street".to_string(),
+        ..profile.clone()
+    };
+    assert_eq!(bank.update_profile(manager, 1, moved.clone()).unwrap(), 2);
+    assert_last_event(
+        &bank,
+        Event::ProfileUpdated {
+            id: 1,
+            version: 2,
+            by: 3,
+        },
+    );
+    assert_eq!(bank.kyc_status(1), KycStatus::Verified);
+    assert_eq!(bank.profile(hash, 1).unwrap().profile, moved);
+    assert_noop(bank.profile(tom, 1), BankingError::Unauthorized);
+    let history = bank.profile_history(auditor, 1).unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0].profile, profile);
+    assert_eq!(history[0].by, 1);
+    assert_eq!(history[1].by, 3);
+}
+
+#[test]
+fn can_restrict_unverified_customers() {
+    let mut bank = new_bank();
+    let hash = setup_account(&mut bank, "roy", Role::Customer);
+    setup_account(&mut bank, "tom", Role::Customer);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+
+    assert_noop(
+        bank.set_kyc_policy(manager, KycPolicy::Limit(-1f64)),
+        BankingError::InvalidKycPolicy,
+    );
+    assert_ok(bank.set_kyc_policy(manager, KycPolicy::Limit(100f64)));
+    assert_noop(bank.withdraw(hash, 150f64), BankingError::KycLimitExceeded);
+    assert_noop(
+        bank.transfer(hash, 150f64, 2),
+        BankingError::KycLimitExceeded,
+    );
+    assert_ok(bank.transfer(hash, 100f64, 2));
+
+    assert_ok(bank.set_kyc_policy(manager, KycPolicy::NoTransfers));
+    assert_noop(bank.transfer(hash, 50f64, 2), BankingError::KycRequired);
+    assert_noop(
+        bank.initiate_transfer(hash, 50f64, 2),
+        BankingError::KycRequired,
+    );
+    assert_ok(bank.withdraw(hash, 150f64));
+
+    // Verified customers are not restricted.
+    let profile = CustomerProfile {
+        legal_name: "Roy Smith".to_string(),
+        date_of_birth: "2000-02-29".to_string(),
+        ..Default::default()
+    };
+    assert_ok(bank.update_profile(hash, 1, profile).map(|_| ()));
+    assert_ok(bank.set_kyc_status(manager, 1, KycStatus::Verified));
+    assert_ok(bank.transfer(hash, 500f64, 2));
+    assert_eq!(250f64, bank.check_balance(hash).unwrap());
+}
+
+#[test]
+fn can_build_monthly_statement() {
+    let mut bank = new_bank();
+    let roy = setup_account(&mut bank, "roy", Role::Customer);
+    let tom = setup_account(&mut bank, "tom", Role::Customer);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+    let fee = FeeRule {
+        flat: 1f64,
+        percentage: 0f64,
+        min: 0f64,
+        max: None,
+    };
+    assert_ok(bank.set_fee_rule(manager, FeeType::Transfer, fee));
+    assert_ok(bank.transfer(roy, 100f64, 2));
+
+    assert_ok(bank.advance_clock(manager, MONTH));
+    assert_ok(bank.deposit(roy, 101f64));
+    assert_ok(bank.set_interest_rate(manager, 0.5f64));
+    assert_ok(bank.pay_interest(manager));
+    assert_ok(bank.set_tax_rate(auditor, 0.5f64));
+    assert_ok(bank.take_tax(auditor));
+    assert_ok(bank.set_fee_rule(manager, FeeType::Transfer, FeeRule { flat: 0f64, ..fee }));
+    assert_ok(bank.transfer(tom, 50f64, 1));
+
+    // Events of later months are left out.
+    assert_ok(bank.advance_clock(manager, MONTH));
+    assert_ok(bank.deposit(roy, 10f64));
+
+    let first = bank.statement(roy, 1, 0).unwrap();
+    assert_eq!(0f64, first.opening_balance);
+    assert_eq!(
+        vec![1000f64, 900f64, 899f64],
+        first.lines.iter().map(|l| l.balance).collect::<Vec<_>>()
+    );
+    assert_eq!(1f64, first.fees);
+    assert_eq!(899f64, first.closing_balance);
+
+    let second = bank.statement(manager, 1, 1).unwrap();
+    assert_eq!(899f64, second.opening_balance);
+    assert_eq!(
+        vec![101f64, 500f64, -750f64, 50f64],
+        second.lines.iter().map(|l| l.amount).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        (500f64, 750f64, 0f64),
+        (second.interest, second.tax, second.fees)
+    );
+    assert_eq!(800f64, second.closing_balance);
+
+    let third = bank.statement(roy, 1, 2).unwrap();
+    assert_eq!(
+        (800f64, 810f64),
+        (third.opening_balance, third.closing_balance)
+    );
+
+    assert_noop(bank.statement(roy, 2, 1), BankingError::Unauthorized);
+    assert_noop(bank.statement(manager, 3, 1), BankingError::InvalidUserId);
+}
+
+#[test]
+fn can_generate_statements() {
+    let mut bank = new_bank();
+    let roy = setup_account(&mut bank, "roy", Role::Customer);
+    setup_account(&mut bank, "<tom>", Role::Customer);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+    assert_ok(bank.withdraw(roy, 300f64));
+
+    let dir = temp_path("statements");
+    let dir = dir.to_str().unwrap();
+    assert_noop(
+        bank.generate_statements(roy, 0, dir),
+        BankingError::Unauthorized,
+    );
+    let statements = bank.generate_statements(manager, 0, dir).unwrap();
+    assert_eq!(
+        vec![(1, 700f64), (2, 1000f64)],
+        statements
+            .iter()
+            .map(|s| (s.id, s.closing_balance))
+            .collect::<Vec<_>>()
+    );
+
+    let text = std::fs::read_to_string(format!("{}/statement_1_0.txt", dir)).unwrap();
+    assert!(text.starts_with("Statement for roy (User ID: 1), month 0\nOpening balance: 0\n"));
+    assert!(text.contains("| -300   | 700"));
+    assert!(text.ends_with("Closing balance: 700\n"));
+    let html = std::fs::read_to_string(format!("{}/statement_2_0.html", dir)).unwrap();
+    assert!(html.contains("<h1>Statement for &lt;tom&gt; (User ID: 2), month 0</h1>"));
+    assert!(html.contains("<td>1000</td>"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn can_summarize_bank() {
+    let mut bank = new_bank();
+    let a = setup_account(&mut bank, "a", Role::Customer);
+    let b = setup_account(&mut bank, "b", Role::Customer);
+    setup_account(&mut bank, "c", Role::Customer);
+    assert_ok(bank.create_user("d".to_string(), "d".to_string(), Role::Customer));
+    let e = setup_account(&mut bank, "e", Role::Customer);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+    assert_ok(bank.withdraw(e, 996f64));
+
+    assert_ok(bank.advance_clock(manager, MONTH));
+    assert_ok(bank.set_interest_rate(manager, 0.5f64));
+    assert_ok(bank.pay_interest(manager));
+    assert_ok(bank.withdraw(a, 1000f64));
+    assert_ok(bank.deposit(b, 20f64));
+
+    assert_noop(bank.financial_summary(a), BankingError::Unauthorized);
+    let summary = bank.financial_summary(manager).unwrap();
+    assert_eq!(3520f64, summary.total_deposits);
+    assert_eq!(
+        (3, 1, 1),
+        (
+            summary.active_accounts,
+            summary.reaped_accounts,
+            summary.empty_accounts
+        )
+    );
+    assert_eq!(
+        vec![
+            (10, 500f64),
+            (25, 500f64),
+            (50, 1500f64),
+            (75, 1520f64),
+            (90, 1520f64)
+        ],
+        summary.percentiles
+    );
+    assert_eq!(
+        vec![(0f64, 0), (10f64, 0), (100f64, 1), (1000f64, 2)],
+        summary
+            .histogram
+            .iter()
+            .map(|b| (b.from, b.count))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![(0, 0f64, 0f64), (1, 1500f64, 0f64)],
+        summary
+            .periods
+            .iter()
+            .map(|p| (p.month, p.interest, p.tax))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        vec![(2, 520f64), (1, -500f64), (3, 500f64)],
+        summary
+            .top_movers
+            .iter()
+            .map(|m| (m.id, m.change))
+            .collect::<Vec<_>>()
+    );
+
+    let json = render_summary_json(&summary);
+    assert!(json.starts_with("{\"total_deposits\":3520,\"term_deposits\":0,\"active_accounts\":3,"));
+    assert!(json.contains("\"top_movers\":[{\"id\":2,\"username\":\"b\",\"change\":520},"));
+    assert!(render_summary_text(&summary).contains("Accounts: 3 active, 1 reaped, 1 empty\n"));
+}
+
+#[test]
+fn can_reconcile_balances() {
+    let mut bank = new_bank();
+    let roy = setup_account(&mut bank, "roy", Role::Customer);
+    let tom = setup_account(&mut bank, "tom", Role::Customer);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+    assert_ok(bank.transfer(roy, 100f64, 2));
+    assert_ok(bank.withdraw(tom, 1096f64));
+    assert!(bank.reconcile(auditor).unwrap().is_empty());
+    assert_last_event(
+        &bank,
+        Event::Reconciled {
+            id: 3,
+            discrepancies: 0,
+        },
+    );
+
+    // A deposit is logged without being paid into the balance.
+    bank.deposit_event(Event::Deposit {
+        id: 1,
+        amount: 50f64,
+        reference: None,
+    });
+    let drift = bank.event_count() - 1;
+    assert_ok(bank.deposit(roy, 20f64));
+
+    assert_noop(bank.reconcile(roy), BankingError::Unauthorized);
+    let discrepancies = bank.reconcile(auditor).unwrap();
+    assert_eq!(1, discrepancies.len());
+    assert_eq!(
+        (1, 970f64, 920f64),
+        (
+            discrepancies[0].id,
+            discrepancies[0].expected,
+            discrepancies[0].actual
+        )
+    );
+    assert_eq!(
+        Some(drift),
+        discrepancies[0].first_diverging.as_ref().map(|r| r.index)
+    );
+    assert!(render_discrepancies(&discrepancies, ReportFormat::Csv)
+        .ends_with(",-50,6,\"User ID: 1, Deposit - Amount: 50\"\n"));
+
+    assert_noop(
+        bank.set_reconciliation_tolerance(auditor, -1f64),
+        BankingError::InvalidTolerance,
+    );
+    assert_ok(bank.set_reconciliation_tolerance(auditor, 50f64));
+    assert!(bank.reconcile(auditor).unwrap().is_empty());
+}
+
+#[test]
+fn can_replay_bank_file() {
+    let path = temp_path("bank");
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+    let manager = Bank::hash("manager", "password");
+    let roy = Bank::hash("roy", "password");
+    let operations = [
+        Operation::Register {
+            user: manager,
+            role: Role::Manager,
+            username: "manager".to_string(),
+        },
+        Operation::Register {
+            user: roy,
+            role: Role::Customer,
+            username: "roy".to_string(),
+        },
+        Operation::Deposit {
+            user: roy,
+            amount: 100f64,
+            reference: "RF18 5390 0754 7034".to_string(),
+        },
+        Operation::Withdraw {
+            user: roy,
+            amount: 1000f64,
+            reference: String::new(),
+        },
+        Operation::SetInterestRate {
+            user: manager,
+            rate: 0.5f64,
+        },
+        Operation::PayInterest { user: manager },
+        Operation::AdvanceClock {
+            user: manager,
+            duration: DAY,
+        },
+    ];
+    let mut bank = new_bank();
+    operations.iter().for_each(|op| {
+        assert_eq!(Operation::decode(&op.encode()), Some(op.clone()));
+        let _ = op.apply(&mut bank);
+        assert_ok(append_operation(path, &bank, op));
+    });
+
+    let loaded = load_bank(path).unwrap();
+    assert_eq!(loaded.check_balance(roy), Ok(150f64));
+    assert_eq!(loaded.check_balance(roy), bank.check_balance(roy));
+    assert_eq!(loaded.report(manager), bank.report(manager));
+    assert_eq!(
+        loaded.all_events(manager, Role::Manager),
+        bank.all_events(manager, Role::Manager)
+    );
+
+    // Text is escaped, so it always fits on one line.
+    let register = Operation::Register {
+        user: roy,
+        role: Role::Customer,
+        username: "r\toy\\\n".to_string(),
+    };
+    assert_eq!(register.encode().lines().count(), 1);
+    assert_eq!(Operation::decode(&register.encode()), Some(register));
+    std::fs::write(path, "not a bank file\n").unwrap();
+    assert_noop(load_bank(path), BankingError::InvalidBankFile);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn can_verify_log() {
+    let mut bank = new_bank();
+    let roy = setup_account(&mut bank, "roy", Role::Customer);
+    let auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+    assert_noop(bank.verify_log(roy), BankingError::Unauthorized);
+    while bank.event_count() < 2 * CHECKPOINT_INTERVAL + 10 {
+        assert_ok(bank.deposit(roy, 1f64));
+    }
+
+    assert_eq!(
+        bank.verify_log(auditor),
+        Ok(LogVerification {
+            entries: 2 * CHECKPOINT_INTERVAL + 10,
+            checkpoints: 2,
+            issue: None,
+        })
+    );
+
+    // Checkpoints signed with another key do not verify.
+    bank.set_signing_key(b"another key");
+    assert_eq!(
+        bank.verify_log(auditor).unwrap().issue,
+        Some(LogIssue::BadCheckpoint {
+            count: CHECKPOINT_INTERVAL
+        })
+    );
+}
+
+#[test]
+fn can_detect_tampered_bank_file() {
+    let path = temp_path("tampered");
+    let path = path.to_str().unwrap();
+    // The fixture was sealed with the development key.
+    let fixture = include_str!("../tests/fixtures/bank_v3.txt");
+    let roy = Bank::hash_with_rounds("r\\oy", "pw", FULL_HASH_ROUNDS);
+    std::fs::write(path, fixture).unwrap();
+    assert_eq!(load_bank(path).unwrap().check_balance(roy), Ok(90f64));
+
+    // A changed amount, a removed line or a moved line no longer match the seals.
+    let lines = fixture.lines().collect::<Vec<_>>();
+    let changed = fixture.replacen("\t100\t", "\t1000\t", 1);
+    let removed = [&lines[..4], &lines[5..]].concat().join("\n");
+    let mut moved = lines.clone();
+    moved.swap(4, 5);
+    for content in [changed, removed, moved.join("\n")].iter() {
+        std::fs::write(path, content).unwrap();
+        assert_noop(load_bank(path), BankingError::TamperedBankFile);
+    }
+
+    // Appending seals the line, chained to the line before it.
+    std::fs::write(path, fixture).unwrap();
+    let mut bank = load_bank(path).unwrap();
+    let operation = Operation::Deposit {
+        user: roy,
+        amount: 10f64,
+        reference: String::new(),
+    };
+    assert_ok(operation.apply(&mut bank));
+    assert_ok(append_operation(path, &bank, &operation));
+    assert_eq!(load_bank(path).unwrap().check_balance(roy), Ok(100f64));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn can_migrate_bank_file() {
+    let fixtures = [
+        (1, include_str!("../tests/fixtures/bank_v1.txt")),
+        (2, include_str!("../tests/fixtures/bank_v2.txt")),
+        (3, include_str!("../tests/fixtures/bank_v3.txt")),
+    ];
+    // Every version has a fixture, and every version but the last a migration.
+    assert_eq!(fixtures.len() as u32, BANK_FILE_VERSION);
+    assert_eq!(
+        BANK_FILE_MIGRATIONS
+            .iter()
+            .map(|m| m.from)
+            .collect::<Vec<_>>(),
+        (1..BANK_FILE_VERSION).collect::<Vec<_>>()
+    );
+    let path = temp_path("migrate");
+    let path = path.to_str().unwrap();
+    // The fixtures were written by the binaries, which hash credentials with more rounds.
+    let roy = Bank::hash_with_rounds("r\\oy", "pw", FULL_HASH_ROUNDS);
+    let current = fixtures[fixtures.len() - 1].1;
+
+    let mut loaded = vec![];
+    for (version, fixture) in fixtures.iter() {
+        std::fs::write(path, fixture).unwrap();
+        let backup = backup_path(path, *version);
+        let _ = std::fs::remove_file(&backup);
+
+        // A dry run reports the changes, but writes nothing.
+        let report = migrate_bank_file(path, true).unwrap();
+        assert_eq!((report.from, report.to), (*version, BANK_FILE_VERSION));
+        assert_eq!(report.backup, None);
+        assert_eq!(std::fs::read_to_string(path).unwrap(), *fixture);
+        if *version == 1 {
+            assert_eq!(
+                report.steps[0].changes,
+                vec!["line 3: r\\oy -> r\\\\oy", "line 6: C:\\rent -> C:\\\\rent"]
+            );
+        }
+
+        // An outdated file is not loaded, and not upgraded.
+        if *version < BANK_FILE_VERSION {
+            assert_noop(load_bank(path), BankingError::OutdatedBankFile);
+            assert_eq!(std::fs::read_to_string(path).unwrap(), *fixture);
+            assert!(std::fs::metadata(&backup).is_err());
+        }
+
+        // Migrating upgrades the file, after copying the original.
+        migrate_bank_file(path, false).unwrap();
+        let bank = load_bank(path).unwrap();
+        assert_eq!(bank.check_balance(roy), Ok(90f64));
+        assert_eq!(
+            bank.current_user(roy).map(|u| u.username),
+            Ok("r\\oy".to_string())
+        );
+        assert_eq!(std::fs::read_to_string(path).unwrap(), current);
+        match std::fs::read_to_string(&backup) {
+            Ok(original) => assert_eq!(original, *fixture),
+            Err(_) => assert_eq!(*version, BANK_FILE_VERSION),
+        }
+        let _ = std::fs::remove_file(&backup);
+        loaded.push(bank.events());
+    }
+    assert!(loaded.windows(2).all(|w| w[0] == w[1]));
+
+    // A current file, downgraded to look like the version before and then edited, is refused as
+    // it is: reading it does not seal the edited lines.
+    let downgraded = current
+        .replacen("version 3", "version 2", 1)
+        .lines()
+        .map(|l| l.rsplit_once('\t').map_or(l, |(line, _)| line))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .replacen("\t100\t", "\t1000000\t", 1);
+    std::fs::write(path, &downgraded).unwrap();
+    assert_noop(load_bank(path), BankingError::OutdatedBankFile);
+    let operation = Operation::Deposit {
+        user: roy,
+        amount: 10f64,
+        reference: String::new(),
+    };
+    assert_noop(
+        append_operation(path, &Bank::default(), &operation),
+        BankingError::OutdatedBankFile,
+    );
+    assert_eq!(std::fs::read_to_string(path).unwrap(), downgraded);
+    assert!(std::fs::metadata(backup_path(path, 2)).is_err());
+
+    std::fs::write(path, "banking_app bank file version 99\n").unwrap();
+    assert_noop(load_bank(path), BankingError::UnsupportedBankFileVersion);
+    std::fs::write(path, "banking_app bank file version 0\n").unwrap();
+    assert_noop(load_bank(path), BankingError::InvalidBankFile);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn can_run_scenario() {
+    let scenario = Scenario::parse(
+        r#"
+name = "Deposit" # comment
+[[step]]
+action = "register"
+user = "roy"
+password = "pw"
+
+[[step]]
+action = "login"
+user = "roy"
+password = "pw"
+
+[[step]]
+action = "deposit"
+user = "roy"
+amount = 100
+reference = "Pay # 1"
+
+[[step]]
+action = "withdraw"
+user = "roy"
+amount = 1000
+error = "InsufficientBalance"
+
+[[step]]
+action = "expect-balance"
+user = "roy"
+balance = 90
+
+[[step]]
+action = "expect-events"
+user = "roy"
+events = [
+    "User ID: 1, Deposit - Amount: 100, Reference: Pay # 1",
+    "User ID: 1, Withdrawal - Amount: -1000",
+]
+"#,
+    )
+    .unwrap();
+    assert_eq!(scenario.name, "Deposit");
+    assert_eq!(scenario.steps.len(), 6);
+    assert_eq!(
+        scenario.steps[3].error,
+        Some("InsufficientBalance".to_string())
+    );
+
+    let failures = scenario.run();
+    assert_eq!(
+        failures
+            .iter()
+            .map(|f| (f.step, f.line))
+            .collect::<Vec<_>>(),
+        vec![(5, 25), (6, 30)]
+    );
+    assert_eq!(failures[0].message, "expected a balance of 90, but got 100");
+    assert_eq!(
+        failures[1].to_string(),
+        "Step 6 (line 30): the events differ (- expected, + actual):\n  \
+         User ID: 1, Deposit - Amount: 100, Reference: Pay # 1\n- \
+         User ID: 1, Withdrawal - Amount: -1000"
+    );
+
+    assert_eq!(
+        Scenario::parse("name = \"x\"\n[[step]]\naction = \"fly\"\nuser = \"roy\"\n"),
+        Err(ScenarioError {
+            line: 2,
+            message: "unknown action `fly`".to_string()
+        })
+    );
+    // A misspelt field is rejected, rather than ignored.
+    assert_eq!(
+        Scenario::parse(
+            "name = \"x\"\n[[step]]\naction = \"deposit\"\nuser = \"roy\"\namount = 1\n\
+             ammount = 100\n"
+        ),
+        Err(ScenarioError {
+            line: 2,
+            message: "unknown field `ammount`, expected one of `user`, `action`, `amount`, \
+                      `reference`, `error`"
+                .to_string()
+        })
+    );
+    assert_eq!(
+        Scenario::parse("name = \"x\"\nevents = [\"a\",\n").map(|s| s.name),
+        Err(ScenarioError {
+            line: 3,
+            message: "invalid array, expected `]`".to_string()
+        })
+    );
+    assert_eq!(
+        Scenario::parse("name = \"x\"\nevents = []\n").map(|s| s.name),
+        Err(ScenarioError {
+            line: 2,
+            message: "unknown field `events`, expected `name` or `step`".to_string()
+        })
+    );
+}
+
+#[test]
+fn can_bank_in_tui() {
+    let mut bank = new_bank();
+    assert_ok(bank.create_user("eve".to_string(), "pw".to_string(), Role::Customer));
+    let mut tui = Tui::new(bank);
+    let mut backend = HeadlessBackend::new(100, 24);
+
+    // Log in with a wrong password, then register and log in.
+    backend.type_text("roy");
+    backend.press(&[Key::Tab]);
+    backend.type_text("pw");
+    backend.press(&[Key::Enter]);
+    tui.run(&mut backend).unwrap();
+    assert!(backend.screen().contains("Password:      **_"));
+    assert!(backend
+        .screen()
+        .contains(&BankingError::FailedLogin.to_string()));
+    backend.press(&[Key::Tab, Key::Tab, Key::Enter]);
+    tui.run(&mut backend).unwrap();
+    assert!(backend.screen().contains("> [ Register ]"));
+    assert!(backend.screen().contains("Registered. Please log in."));
+    backend.press(&[Key::BackTab, Key::Enter]);
+    tui.run(&mut backend).unwrap();
+    assert!(backend.screen().contains("ANZ bank · roy (Customer)"));
+    assert!(backend.screen().contains("[Dashboard]"));
+    assert!(backend.screen().contains("Balance:           0"));
+
+    // Amounts are validated as they are typed.
+    backend.press(&[Key::Right]);
+    backend.type_text("1x");
+    tui.run(&mut backend).unwrap();
+    assert!(backend
+        .screen()
+        .contains("Amount:        1x_  ! Enter a positive amount."));
+    backend.press(&[Key::Backspace]);
+    backend.type_text("00");
+    backend.press(&[Key::Tab]);
+    backend.type_text("Salary");
+    backend.press(&[Key::Enter]);
+    tui.run(&mut backend).unwrap();
+    assert!(backend.screen().contains("Completed."));
+    assert_eq!(
+        tui.bank().check_balance(Bank::hash("roy", "pw")),
+        Ok(100f64)
+    );
+
+    // A transfer shows the payee and rejects amounts over the available balance.
+    backend.press(&[Key::Right, Key::Right]);
+    backend.type_text("eve");
+    backend.press(&[Key::Tab]);
+    backend.type_text("500");
+    tui.run(&mut backend).unwrap();
+    assert!(backend
+        .screen()
+        .contains("To:            eve_  eve (ID: 1)"));
+    assert!(backend
+        .screen()
+        .contains("! Exceeds the available balance of 100."));
+    backend.press(&[Key::Enter]);
+    tui.run(&mut backend).unwrap();
+    assert!(backend
+        .screen()
+        .contains("Please correct the highlighted fields."));
+    backend.press(&[Key::Backspace, Key::Backspace, Key::Enter]);
+    tui.run(&mut backend).unwrap();
+    assert_eq!(tui.bank().check_balance(Bank::hash("roy", "pw")), Ok(95f64));
+
+    // Events are filtered by typing.
+    backend.press(&[Key::Right]);
+    backend.type_text("sal");
+    tui.run(&mut backend).unwrap();
+    assert!(backend.screen().contains("1 of 2 events"));
+    assert!(backend.screen().contains("Reference: Salary"));
+    assert!(!backend.screen().contains("Transfer - Amount"));
+
+    backend.press(&[Key::Ctrl('q'), Key::Enter]);
+    tui.run(&mut backend).unwrap();
+    assert!(tui.has_quit());
+    assert_eq!(backend.keys, [Key::Enter]);
+}
+
+#[test]
+fn can_manage_bank_in_tui() {
+    let mut bank = new_bank();
+    assert_ok(bank.create_user("manager".to_string(), "pw".to_string(), Role::Manager));
+    assert_ok(bank.create_user("roy".to_string(), "pw".to_string(), Role::Customer));
+    assert_ok(bank.deposit(Bank::hash("roy", "pw"), 100f64));
+    let mut tui = Tui::new(bank);
+    let mut backend = HeadlessBackend::new(100, 24);
+    backend.type_text("manager");
+    backend.press(&[Key::Tab]);
+    backend.type_text("pw");
+    backend.press(&[Key::Enter]);
+    tui.run(&mut backend).unwrap();
+    assert!(backend.screen().contains("[Report]"));
+    assert!(backend.screen().contains("roy"));
+
+    backend.press(&[Key::Left]);
+    backend.type_text("0.5");
+    backend.press(&[Key::Enter, Key::Tab, Key::Tab, Key::Enter]);
+    tui.run(&mut backend).unwrap();
+    assert!(backend.screen().contains("[Interest]"));
+    assert!(backend.screen().contains("Current interest rate: 0.5"));
+    assert_eq!(
+        tui.bank().check_balance(Bank::hash("roy", "pw")),
+        Ok(150f64)
+    );
+
+    backend.press(&[Key::Esc]);
+    tui.run(&mut backend).unwrap();
+    assert!(backend.screen().contains("Logged out."));
+}
+
+#[test]
+fn can_log_in_with_salted_credentials() {
+    let mut bank = new_bank();
+    bank.set_verbose(false);
+
+    // The username salts the hash: equal passwords hash differently.
+    assert_ne!(Bank::hash("roy", "pw"), Bank::hash("tom", "pw"));
+    // The hash is the same in every build, so stored credentials stay valid.
+    assert_eq!(
+        Bank::hash_with_rounds("r\\oy", "pw", FULL_HASH_ROUNDS),
+        14816517086872701555
+    );
+
+    let roy = setup_account(&mut bank, "roy", Role::Customer);
+    assert_eq!(roy, Bank::hash("roy", "roy"));
+    assert_ok(bank.change_password(roy, "secret".to_string()));
+    assert_eq!(
+        bank.login("roy".to_string(), "secret".to_string()),
+        Ok((Bank::hash("roy", "secret"), Role::Customer))
+    );
+    assert_noop(
+        bank.login("roy".to_string(), "roy".to_string()),
+        BankingError::FailedLogin,
+    );
+}
+
+#[test]
+fn can_look_up_users_and_events_by_index() {
+    let mut bank = new_bank();
+    bank.set_verbose(false);
+    let roy = setup_account(&mut bank, "Roy", Role::Customer);
+    let tom = setup_account(&mut bank, "Tom", Role::Customer);
+    let manager = setup_account(&mut bank, "Manager", Role::Manager);
+    assert_ok(bank.transfer(roy, 100f64, 2));
+
+    // The user index follows a password change.
+    assert_ok(bank.change_password(roy, "secret".to_string()));
+    let (roy, _) = bank.login("Roy".to_string(), "secret".to_string()).unwrap();
+    assert!(bank.has_username("Roy"));
+    assert!(!bank.has_username("roy"));
+    assert_ok(bank.transfer_by_username(tom, 200f64, "Roy"));
+    assert_noop(
+        bank.transfer_by_username(roy, 10f64, "Manager"),
+        BankingError::NoUserFound,
+    );
+    assert_noop(bank.transfer(roy, 10f64, 3), BankingError::InvalidUserId);
+    assert_eq!(bank.find_customer_by_id(1), bank.current_user(roy));
+
+    // The event index lists the deposit and both transfers, in order.
+    let roy_events = bank.my_events(roy).unwrap();
+    let scan = |id| {
+        bank.events()
+            .into_iter()
+            .filter(|r| r.event.involves(id))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(roy_events, scan(1));
+    assert_eq!(
+        vec![0, 2, 3],
+        roy_events.iter().map(|r| r.index).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        bank.user_events(manager, Role::Manager, 2),
+        Ok(scan(2))
+    );
+    assert_eq!(scan(3), vec![]);
+}
+
+#[test]
+fn can_report() {
+    let mut bank = new_bank();
+    // Setup user
+    let hash = setup_account(&mut bank, "roy", Role::Customer);
+    // Setup user manager
+    let hash_manager = setup_account(&mut bank, "manager", Role::Manager);
+    // Setup user manager
+    let hash_auditor = setup_account(&mut bank, "auditor", Role::Auditor);
+    assert_ok(bank.create_user("tom".to_string(), "tom".to_string(), Role::Customer));
+
+    let report = bank.report(hash_manager).unwrap();
+    assert_eq!(report, bank.report(hash_auditor).unwrap());
+    assert_eq!(report.fee_income, 0f64);
+    assert_eq!(
+        report.rows,
+        vec![
+            ReportRow {
+                id: 1,
+                username: "roy".to_string(),
+                role: Role::Customer,
+                balance: 1000f64,
+                term_deposits: 0f64,
+                kyc: Some(KycStatus::Unverified),
+                status: AccountStatus::Active,
+            },
+            ReportRow {
+                id: 2,
+                username: "manager".to_string(),
+                role: Role::Manager,
+                balance: 0f64,
+                term_deposits: 0f64,
+                kyc: None,
+                status: AccountStatus::Staff,
+            },
+            ReportRow {
+                id: 3,
+                username: "auditor".to_string(),
+                role: Role::Auditor,
+                balance: 0f64,
+                term_deposits: 0f64,
+                kyc: None,
+                status: AccountStatus::Staff,
+            },
+            ReportRow {
+                id: 4,
+                username: "tom".to_string(),
+                role: Role::Customer,
+                balance: 0f64,
+                term_deposits: 0f64,
+                kyc: Some(KycStatus::Unverified),
+                status: AccountStatus::Empty,
+            },
+        ]
+    );
+    assert_noop(bank.report(hash), BankingError::Unauthorized);
+}
+
+#[test]
+fn can_render_report_and_events() {
+    let mut bank = new_bank();
+    let hash = setup_account(&mut bank, "roy, jr", Role::Customer);
+    let manager = setup_account(&mut bank, "manager", Role::Manager);
+    let report = bank.report(manager).unwrap();
+
+    assert_eq!(
+        render_report(&report, ReportFormat::Table),
+        "id | username | role     | balance | term_deposits | kyc        | status\n\
+         ---+----------+----------+---------+---------------+------------+-------\n\
+         1  | roy, jr  | Customer | 1000    | 0             | Unverified | Active\n\
+         2  | manager  | Manager  | 0       | 0             |            | Staff\n\
+         Bank income account: 0\n"
+    );
+    assert_eq!(
+        render_report(&report, ReportFormat::Csv),
+        "id,username,role,balance,term_deposits,kyc,status\n\
+         1,\"roy, jr\",Customer,1000,0,Unverified,Active\n\
+         2,manager,Manager,0,0,,Staff\n"
+    );
+    assert_eq!(
+        render_report(&report, ReportFormat::Json),
+        "{\"users\":[\
+         {\"id\":1,\"username\":\"roy, jr\",\"role\":\"Customer\",\"balance\":1000,\"term_deposits\":0,\"kyc\":\"Unverified\",\"status\":\"Active\"},\
+         {\"id\":2,\"username\":\"manager\",\"role\":\"Manager\",\"balance\":0,\"term_deposits\":0,\"kyc\":null,\"status\":\"Staff\"}\
+         ],\"fee_income\":0}\n"
+    );
+
+    let rows = bank.my_events(hash).unwrap();
+    assert_eq!(
+        rows,
+        vec![EventRow {
+            index: 0,
+            at: 0,
+            event: Event::Deposit {
+                id: 1,
+                amount: 1000f64,
+                reference: None,
+            },
+        }]
+    );
+    assert_eq!(
+        render_events(&rows, ReportFormat::Csv),
+        "index,day,event\n0,0,\"User ID: 1, Deposit - Amount: 1000\"\n"
+    );
+    assert_eq!(
+        render_events(&rows, ReportFormat::Json),
+        "[{\"index\":0,\"at\":0,\"event\":\"User ID: 1, Deposit - Amount: 1000\"}]\n"
+    );
+    assert_eq!(bank.user_events(manager, Role::Manager, 1).unwrap(), rows);
+    assert_noop(
+        bank.user_events(manager, Role::Manager, 2),
+        BankingError::InvalidUserId,
+    );
+    assert_eq!(bank.all_events(manager, Role::Manager).unwrap(), rows);
+    assert_noop(
+        bank.all_events(manager, Role::Auditor),
+        BankingError::Unauthorized,
+    );
 }
 
 #[test]
 fn can_pay_interest() {
-    let mut bank = Bank::default();
+    let mut bank = new_bank();
     // Setup user
     let hash = setup_account(&mut bank, "roy", Role::Customer);
     // Setup user manager
@@ -149,7 +2504,7 @@ fn can_pay_interest() {
 
 #[test]
 fn can_take_tax() {
-    let mut bank = Bank::default();
+    let mut bank = new_bank();
     // Setup user
     let hash = setup_account(&mut bank, "roy", Role::Customer);
     // Setup user manager
@@ -157,4 +2512,5 @@ fn can_take_tax() {
     // test pay_interest
     assert_ok(bank.take_tax(hash_auditor));
     assert_eq!(980f64, bank.check_balance(hash).unwrap());
+    bank.debug_assert_reconciled();
 }
@@ -0,0 +1,954 @@
+//! Full-screen terminal UI.
+//!
+//! `Tui` is a state machine: it takes `Key`s and renders the screen as lines of text, so it
+//! runs the same on a real terminal (`TerminalBackend`) and headless in tests
+//! (`HeadlessBackend`).
+//!
+//! After logging in, the user moves between panels with Left/Right, and between the fields of
+//! a form with Tab/Shift-Tab. Enter submits the form (or presses the focused button), Up/Down
+//! scroll tables, Esc logs out and Ctrl-Q quits. Form fields are validated as they are typed,
+//! and event tables are filtered by typing.
+//!
+//! | Role       | Panels                                              |
+//! |------------|-----------------------------------------------------|
+//! | `Customer` | Dashboard, Deposit, Withdraw, Transfer, Events      |
+//! | `Manager`  | Report, Events, Interest                            |
+//! | `Auditor`  | Report, Events, Tax, Reconciliation                 |
+use crate::bank::Bank;
+use crate::primitives::*;
+use crate::report::{render_discrepancies, render_events, render_report, ReportFormat};
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Read, Write},
+    process::{Command, Stdio},
+};
+
+/// Number of recent events shown on the customer dashboard.
+const RECENT_EVENTS: usize = 5;
+
+/// A key press.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    /// A letter pressed with Ctrl.
+    Ctrl(char),
+    Enter,
+    Backspace,
+    Tab,
+    BackTab,
+    Up,
+    Down,
+    Left,
+    Right,
+    Esc,
+}
+
+/// Where the UI is drawn and keys are read from.
+pub trait Backend {
+    /// Returns the screen size as (width, height) in characters.
+    fn size(&self) -> (usize, usize);
+    /// Draws the screen, one string per line.
+    fn draw(&mut self, lines: &[String]) -> io::Result<()>;
+    /// Waits for the next key. `None` means no more input.
+    fn read_key(&mut self) -> io::Result<Option<Key>>;
+}
+
+/// A backend that replays scripted keys and records every frame, for tests.
+pub struct HeadlessBackend {
+    pub width: usize,
+    pub height: usize,
+    pub keys: VecDeque<Key>,
+    pub frames: Vec<Vec<String>>,
+}
+
+impl HeadlessBackend {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            keys: VecDeque::new(),
+            frames: vec![],
+        }
+    }
+
+    /// Queues a key for every character of `text`.
+    pub fn type_text(&mut self, text: &str) {
+        self.keys.extend(text.chars().map(Key::Char));
+    }
+
+    /// Queues keys.
+    pub fn press(&mut self, keys: &[Key]) {
+        self.keys.extend(keys);
+    }
+
+    /// Returns the last frame drawn, joined into one string.
+    pub fn screen(&self) -> String {
+        self.frames.last().map(|f| f.join("\n")).unwrap_or_default()
+    }
+}
+
+impl Backend for HeadlessBackend {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn draw(&mut self, lines: &[String]) -> io::Result<()> {
+        self.frames.push(lines.to_vec());
+        Ok(())
+    }
+
+    fn read_key(&mut self) -> io::Result<Option<Key>> {
+        Ok(self.keys.pop_front())
+    }
+}
+
+/// Decodes the first key of `bytes`, as read from a terminal in raw mode. Returns the key, or
+/// `None` for bytes that are not a key, with the number of bytes it took. Returns `None` if
+/// `bytes` only hold the start of a key, which needs more input. An Esc at the end of `bytes`
+/// is a lone Esc, as an escape sequence arrives in one read.
+pub fn decode_key(bytes: &[u8]) -> Option<(Option<Key>, usize)> {
+    let key = match *bytes.first()? {
+        0x1b if bytes.get(1) == Some(&b'[') => {
+            // Control sequence: parameter and intermediate bytes, then one final byte.
+            let params = bytes[2..]
+                .iter()
+                .take_while(|b| (0x20..=0x3f).contains(*b))
+                .count();
+            let key = match (params, *bytes.get(2 + params)?) {
+                (0, b'A') => Some(Key::Up),
+                (0, b'B') => Some(Key::Down),
+                (0, b'C') => Some(Key::Right),
+                (0, b'D') => Some(Key::Left),
+                (0, b'Z') => Some(Key::BackTab),
+                _ => None,
+            };
+            return Some((key, 3 + params));
+        }
+        0x1b => Some(Key::Esc),
+        b'\r' | b'\n' => Some(Key::Enter),
+        b'\t' => Some(Key::Tab),
+        0x7f | 0x08 => Some(Key::Backspace),
+        c @ 1..=26 => Some(Key::Ctrl((b'a' + c - 1) as char)),
+        lead => {
+            let len = match lead {
+                0x20..=0x7e => 1,
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => return Some((None, 1)),
+            };
+            if bytes.len() < len {
+                return None;
+            }
+            let key = std::str::from_utf8(&bytes[..len])
+                .ok()
+                .and_then(|s| s.chars().next())
+                .filter(|c| !c.is_control())
+                .map(Key::Char);
+            return Some(match key {
+                Some(key) => (Some(key), len),
+                None => (None, 1),
+            });
+        }
+    };
+    Some((key, 1))
+}
+
+/// A backend on the controlling terminal, using ANSI escape codes. The terminal is put in raw
+/// mode with `stty` on creation, and restored when dropped.
+pub struct TerminalBackend {
+    width: usize,
+    height: usize,
+    saved: String,
+    /// Bytes read but not decoded yet: the keys after the one returned, or the start of a key.
+    pending: Vec<u8>,
+}
+
+// Helper function: Runs `stty` on the controlling terminal, and returns its output.
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(File::open("/dev/tty")?)
+        .stderr(Stdio::inherit())
+        .output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+impl TerminalBackend {
+    pub fn new() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        let size = stty(&["size"])?;
+        let mut size = size.split_whitespace().filter_map(|n| n.parse().ok());
+        let (height, width) = (size.next().unwrap_or(24), size.next().unwrap_or(80));
+        stty(&["raw", "-echo"])?;
+        // Switch to the alternate screen and hide the cursor.
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(Self {
+            width,
+            height,
+            saved,
+            pending: vec![],
+        })
+    }
+}
+
+impl Drop for TerminalBackend {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        let _ = stty(&[&self.saved]);
+    }
+}
+
+impl Backend for TerminalBackend {
+    fn size(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    fn draw(&mut self, lines: &[String]) -> io::Result<()> {
+        let mut out = String::from("\x1b[H");
+        lines.iter().enumerate().for_each(|(i, line)| {
+            if i > 0 {
+                out.push_str("\r\n");
+            }
+            out.push_str(line);
+        });
+        let mut stdout = io::stdout();
+        stdout.write_all(out.as_bytes())?;
+        stdout.flush()
+    }
+
+    fn read_key(&mut self) -> io::Result<Option<Key>> {
+        let mut buffer = [0u8; 256];
+        loop {
+            // Pasted text or fast typing arrives as several keys in one read.
+            while let Some((key, len)) = decode_key(&self.pending) {
+                self.pending.drain(..len);
+                if key.is_some() {
+                    return Ok(key);
+                }
+            }
+            let read = io::stdin().read(&mut buffer)?;
+            if read == 0 {
+                return Ok(None);
+            }
+            self.pending.extend_from_slice(&buffer[..read]);
+        }
+    }
+}
+
+/// A panel of the UI.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Panel {
+    Dashboard,
+    Deposit,
+    Withdraw,
+    Transfer,
+    Events,
+    Report,
+    Interest,
+    Tax,
+    Reconciliation,
+}
+
+impl Panel {
+    /// Returns the panels available to a role, in order.
+    pub fn for_role(role: Role) -> &'static [Panel] {
+        match role {
+            Role::Customer => &[
+                Panel::Dashboard,
+                Panel::Deposit,
+                Panel::Withdraw,
+                Panel::Transfer,
+                Panel::Events,
+            ],
+            Role::Manager => &[Panel::Report, Panel::Events, Panel::Interest],
+            Role::Auditor => &[
+                Panel::Report,
+                Panel::Events,
+                Panel::Tax,
+                Panel::Reconciliation,
+            ],
+        }
+    }
+
+    // Helper function: Returns the fields of the panel's form.
+    fn fields(&self) -> Vec<Field> {
+        match self {
+            Panel::Deposit | Panel::Withdraw => vec![
+                Field::text("Amount"),
+                Field::text("Reference"),
+                Field::button(if *self == Panel::Deposit {
+                    "Deposit"
+                } else {
+                    "Withdraw"
+                }),
+            ],
+            Panel::Transfer => vec![
+                Field::text("To"),
+                Field::text("Amount"),
+                Field::text("Reference"),
+                Field::button("Transfer"),
+            ],
+            Panel::Interest => vec![
+                Field::text("Interest rate"),
+                Field::button("Set rate"),
+                Field::button("Pay interest"),
+            ],
+            Panel::Tax => vec![
+                Field::text("Tax rate"),
+                Field::button("Set rate"),
+                Field::button("Take tax"),
+            ],
+            Panel::Reconciliation => vec![Field::button("Reconcile")],
+            Panel::Dashboard | Panel::Events | Panel::Report => vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FieldKind {
+    Text,
+    Secret,
+    Button,
+}
+
+/// A field of a form, or a button.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Field {
+    label: &'static str,
+    value: String,
+    kind: FieldKind,
+}
+
+impl Field {
+    fn text(label: &'static str) -> Self {
+        Self {
+            label,
+            value: String::new(),
+            kind: FieldKind::Text,
+        }
+    }
+
+    fn secret(label: &'static str) -> Self {
+        Self {
+            kind: FieldKind::Secret,
+            ..Self::text(label)
+        }
+    }
+
+    fn button(label: &'static str) -> Self {
+        Self {
+            kind: FieldKind::Button,
+            ..Self::text(label)
+        }
+    }
+}
+
+/// A form: its fields, the focused field, and whether it was submitted, after which empty
+/// fields are reported too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Form {
+    fields: Vec<Field>,
+    focus: usize,
+    submitted: bool,
+}
+
+impl Form {
+    fn new(fields: Vec<Field>) -> Self {
+        Self {
+            fields,
+            focus: 0,
+            submitted: false,
+        }
+    }
+
+    fn value(&self, label: &str) -> &str {
+        self.fields
+            .iter()
+            .find(|f| f.label == label)
+            .map_or("", |f| f.value.as_str())
+    }
+
+    // Helper function: Edits the focused field, or moves the focus. Returns the label of the
+    // button pressed, if any.
+    fn handle_key(&mut self, key: Key) -> Option<&'static str> {
+        let count = self.fields.len();
+        if count == 0 {
+            return None;
+        }
+        let field = &mut self.fields[self.focus];
+        match key {
+            Key::Tab | Key::Down => self.focus = (self.focus + 1) % count,
+            Key::BackTab | Key::Up => self.focus = (self.focus + count - 1) % count,
+            Key::Char(c) if field.kind != FieldKind::Button => field.value.push(c),
+            Key::Backspace => {
+                field.value.pop();
+            }
+            Key::Enter if field.kind == FieldKind::Button => return Some(field.label),
+            // Enter in a text field presses the first button.
+            Key::Enter => {
+                return self
+                    .fields
+                    .iter()
+                    .find(|f| f.kind == FieldKind::Button)
+                    .map(|f| f.label)
+            }
+            _ => (),
+        }
+        None
+    }
+}
+
+/// The state of a logged-in user.
+struct Session {
+    user: HashResult,
+    username: String,
+    role: Role,
+    panel: usize,
+    form: Form,
+    /// Filter of the event table.
+    filter: String,
+    /// First row shown of the scrolled table.
+    scroll: usize,
+    /// Output of the last action of the panel, such as a reconciliation.
+    output: Vec<String>,
+}
+
+impl Session {
+    fn panel(&self) -> Panel {
+        Panel::for_role(self.role)[self.panel]
+    }
+
+    fn select_panel(&mut self, panel: usize) {
+        self.panel = panel;
+        self.form = Form::new(self.panel().fields());
+        self.filter.clear();
+        self.scroll = 0;
+        self.output.clear();
+    }
+}
+
+enum Screen {
+    Login(Form),
+    Session(Session),
+}
+
+/// The terminal UI over a bank.
+pub struct Tui {
+    bank: Bank,
+    screen: Screen,
+    status: String,
+    quit: bool,
+}
+
+// Helper function: Returns the login form.
+fn login_form() -> Form {
+    Form::new(vec![
+        Field::text("Username"),
+        Field::secret("Password"),
+        Field::button("Log in"),
+        Field::button("Register"),
+    ])
+}
+
+// Helper function: Fits a line to `width` characters.
+fn fit(line: &str, width: usize) -> String {
+    let mut line = line.chars().take(width).collect::<String>();
+    let len = line.chars().count();
+    line.extend(std::iter::repeat_n(' ', width - len));
+    line
+}
+
+impl Tui {
+    /// Creates the UI over a bank. The bank is made quiet, so it does not print over the UI.
+    pub fn new(mut bank: Bank) -> Self {
+        bank.set_verbose(false);
+        Self {
+            bank,
+            screen: Screen::Login(login_form()),
+            status: "Welcome to ANZ bank!".to_string(),
+            quit: false,
+        }
+    }
+
+    pub fn bank(&self) -> &Bank {
+        &self.bank
+    }
+
+    /// Returns true once the user quit.
+    pub fn has_quit(&self) -> bool {
+        self.quit
+    }
+
+    /// Draws the UI and handles keys until the user quits or the input ends.
+    pub fn run(&mut self, backend: &mut impl Backend) -> io::Result<()> {
+        loop {
+            let (width, height) = backend.size();
+            backend.draw(&self.render(width, height))?;
+            match backend.read_key()? {
+                Some(key) if !self.quit => self.handle_key(key),
+                _ => return Ok(()),
+            }
+            if self.quit {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Handles a key press.
+    pub fn handle_key(&mut self, key: Key) {
+        if key == Key::Ctrl('q') || key == Key::Ctrl('c') {
+            self.quit = true;
+            return;
+        }
+        match &mut self.screen {
+            Screen::Login(form) => {
+                if key == Key::Esc {
+                    self.quit = true;
+                    return;
+                }
+                if let Some(button) = form.handle_key(key) {
+                    form.submitted = true;
+                    let (username, password) = (
+                        form.value("Username").to_string(),
+                        form.value("Password").to_string(),
+                    );
+                    if username.is_empty() || password.is_empty() {
+                        self.status = "Please enter a username and password.".to_string();
+                        return;
+                    }
+                    if button == "Register" {
+                        self.status =
+                            match self.bank.create_user(username, password, Role::Customer) {
+                                Ok(()) => "Registered. Please log in.".to_string(),
+                                Err(e) => e.to_string(),
+                            };
+                        return;
+                    }
+                    match self.bank.login(username.clone(), password) {
+                        Ok((user, role)) => {
+                            let mut session = Session {
+                                user,
+                                username,
+                                role,
+                                panel: 0,
+                                form: Form::new(vec![]),
+                                filter: String::new(),
+                                scroll: 0,
+                                output: vec![],
+                            };
+                            session.select_panel(0);
+                            self.screen = Screen::Session(session);
+                            self.status = "Logged in.".to_string();
+                        }
+                        Err(e) => self.status = e.to_string(),
+                    }
+                }
+            }
+            Screen::Session(session) => {
+                let panels = Panel::for_role(session.role).len();
+                match (session.panel(), key) {
+                    (_, Key::Esc) => {
+                        self.screen = Screen::Login(login_form());
+                        self.status = "Logged out.".to_string();
+                    }
+                    (_, Key::Right) => session.select_panel((session.panel + 1) % panels),
+                    (_, Key::Left) => session.select_panel((session.panel + panels - 1) % panels),
+                    (Panel::Events, Key::Char(c)) => {
+                        session.filter.push(c);
+                        session.scroll = 0;
+                    }
+                    (Panel::Events, Key::Backspace) => {
+                        session.filter.pop();
+                        session.scroll = 0;
+                    }
+                    (Panel::Events | Panel::Report | Panel::Dashboard, Key::Down) => {
+                        session.scroll += 1
+                    }
+                    (Panel::Events | Panel::Report | Panel::Dashboard, Key::Up) => {
+                        session.scroll = session.scroll.saturating_sub(1)
+                    }
+                    (panel, key) => {
+                        if let Some(button) = session.form.handle_key(key) {
+                            session.form.submitted = true;
+                            self.status = Self::submit(&mut self.bank, session, panel, button);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Helper function: Validates a field of a panel's form as it is typed. Returns a hint for a
+    // valid field, or the error for an invalid one.
+    fn validate(
+        bank: &Bank,
+        session: &Session,
+        label: &str,
+        value: &str,
+    ) -> Result<Option<String>, String> {
+        let required = |value: &str| {
+            if value.trim().is_empty() {
+                Err("Required.".to_string())
+            } else {
+                Ok(())
+            }
+        };
+        match (session.panel(), label) {
+            (_, "Amount") => {
+                required(value)?;
+                let amount = value
+                    .trim()
+                    .parse::<Balance>()
+                    .ok()
+                    .filter(|a| *a > 0f64)
+                    .ok_or_else(|| "Enter a positive amount.".to_string())?;
+                let available = bank.check_available_balance(session.user).unwrap_or(0f64);
+                match session.panel() {
+                    Panel::Withdraw | Panel::Transfer if amount > available => {
+                        Err(format!("Exceeds the available balance of {}.", available))
+                    }
+                    Panel::Transfer => Ok(bank
+                        .quote_fee(session.user, FeeType::Transfer, amount)
+                        .ok()
+                        .filter(|fee| *fee > 0f64)
+                        .map(|fee| format!("Fee: {}", fee))),
+                    _ => Ok(None),
+                }
+            }
+            (_, "Reference") => PaymentReference::parse(value)
+                .map(|_| None)
+                .map_err(|e| e.to_string()),
+            (_, "To") => {
+                required(value)?;
+                bank.resolve_payee(session.user, value.trim())
+                    .map(|(id, name)| Some(format!("{} (ID: {})", name, id)))
+                    .map_err(|e| e.to_string())
+            }
+            (_, "Interest rate") | (_, "Tax rate") => {
+                required(value)?;
+                value
+                    .trim()
+                    .parse::<f64>()
+                    .map(|_| None)
+                    .map_err(|_| "Enter a number.".to_string())
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // Helper function: Runs the action of a panel's button, and returns the status message.
+    fn submit(bank: &mut Bank, session: &mut Session, panel: Panel, button: &str) -> String {
+        let invalid = session
+            .form
+            .fields
+            .iter()
+            .filter(|f| f.kind != FieldKind::Button)
+            .any(|f| Self::validate(bank, session, f.label, &f.value).is_err());
+        // Buttons that do not use the fields run even if the fields are invalid.
+        let uses_fields = !matches!(button, "Pay interest" | "Take tax" | "Reconcile");
+        if invalid && uses_fields {
+            return "Please correct the highlighted fields.".to_string();
+        }
+        let form = &session.form;
+        let user = session.user;
+        let amount = || form.value("Amount").trim().parse().unwrap_or_default();
+        let reference = || PaymentReference::parse(form.value("Reference"));
+        let result = match (panel, button) {
+            (Panel::Deposit, _) => reference()
+                .and_then(|reference| bank.deposit_with_reference(user, amount(), reference)),
+            (Panel::Withdraw, _) => reference()
+                .and_then(|reference| bank.withdraw_with_reference(user, amount(), reference)),
+            (Panel::Transfer, _) => {
+                bank.resolve_payee(user, form.value("To").trim())
+                    .and_then(|(to, _)| {
+                        reference().and_then(|reference| {
+                            bank.transfer_with_reference(user, amount(), to, reference)
+                        })
+                    })
+            }
+            (Panel::Interest, "Set rate") => bank.set_interest_rate(
+                user,
+                form.value("Interest rate")
+                    .trim()
+                    .parse()
+                    .unwrap_or_default(),
+            ),
+            (Panel::Interest, _) => bank.pay_interest(user),
+            (Panel::Tax, "Set rate") => bank.set_tax_rate(
+                user,
+                form.value("Tax rate").trim().parse().unwrap_or_default(),
+            ),
+            (Panel::Tax, _) => bank.take_tax(user),
+            (Panel::Reconciliation, _) => bank.reconcile(user).map(|discrepancies| {
+                session.output = render_discrepancies(&discrepancies, ReportFormat::Table)
+                    .lines()
+                    .map(str::to_string)
+                    .collect();
+            }),
+            _ => Ok(()),
+        };
+        match result {
+            Ok(()) => {
+                if uses_fields {
+                    session.select_panel(session.panel);
+                }
+                "Completed.".to_string()
+            }
+            Err(e) => e.to_string(),
+        }
+    }
+
+    // Helper function: Renders the lines of a form, with the inline validation of its fields.
+    fn render_form(&self, form: &Form, session: Option<&Session>) -> Vec<String> {
+        form.fields
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                let cursor = if i == form.focus { ">" } else { " " };
+                if f.kind == FieldKind::Button {
+                    return format!("{} [ {} ]", cursor, f.label);
+                }
+                let value = match f.kind {
+                    FieldKind::Secret => "*".repeat(f.value.chars().count()),
+                    _ => f.value.clone(),
+                };
+                let check = match session {
+                    Some(s) if form.submitted || !f.value.is_empty() => {
+                        Self::validate(&self.bank, s, f.label, &f.value)
+                    }
+                    None if form.submitted && f.value.is_empty() => Err("Required.".to_string()),
+                    _ => Ok(None),
+                };
+                let note = match check {
+                    Ok(Some(hint)) => format!("  {}", hint),
+                    Ok(None) => String::new(),
+                    Err(e) => format!("  ! {}", e),
+                };
+                format!(
+                    "{} {:<14} {}_{}",
+                    cursor,
+                    format!("{}:", f.label),
+                    value,
+                    note
+                )
+            })
+            .collect()
+    }
+
+    // Helper function: Returns the events the session's user may see.
+    fn events(&self, session: &Session) -> BankResult<Vec<EventRow>> {
+        match session.role {
+            Role::Customer => self.bank.my_events(session.user),
+            role => self.bank.all_events(session.user, role),
+        }
+    }
+
+    // Helper function: Renders a table with a fixed header of two lines, scrolled to `scroll`
+    // and cut to `height` lines.
+    fn scrolled(table: &str, scroll: usize, height: usize) -> Vec<String> {
+        let lines = table.lines().map(str::to_string).collect::<Vec<_>>();
+        let header = lines.len().min(2);
+        let body = &lines[header..];
+        let scroll = scroll.min(body.len().saturating_sub(1));
+        lines[..header]
+            .iter()
+            .chain(body.iter().skip(scroll))
+            .take(height)
+            .cloned()
+            .collect()
+    }
+
+    // Helper function: Renders the body of the session's panel.
+    fn render_panel(&self, session: &Session, height: usize) -> Vec<String> {
+        let error = |e: BankingError| vec![format!("Error: {}", e)];
+        match session.panel() {
+            Panel::Dashboard => {
+                let balance = self.bank.check_balance(session.user).unwrap_or_default();
+                let available = self
+                    .bank
+                    .check_available_balance(session.user)
+                    .unwrap_or_default();
+                let mut lines = vec![
+                    format!("Balance:           {}", balance),
+                    format!("Available balance: {}", available),
+                    String::new(),
+                    "Recent events:".to_string(),
+                ];
+                match self.events(session) {
+                    Ok(rows) => {
+                        let recent = &rows[rows.len().saturating_sub(RECENT_EVENTS)..];
+                        lines.extend(
+                            render_events(recent, ReportFormat::Table)
+                                .lines()
+                                .map(str::to_string),
+                        );
+                    }
+                    Err(e) => lines.extend(error(e)),
+                }
+                lines
+            }
+            Panel::Events => {
+                let mut lines = vec![format!("Filter: {}_", session.filter), String::new()];
+                match self.events(session) {
+                    Ok(rows) => {
+                        let filter = session.filter.to_lowercase();
+                        let total = rows.len();
+                        let rows = rows
+                            .into_iter()
+                            .filter(|r| r.event.to_string().to_lowercase().contains(&filter))
+                            .collect::<Vec<_>>();
+                        lines.push(format!("{} of {} events", rows.len(), total));
+                        lines.extend(Self::scrolled(
+                            &render_events(&rows, ReportFormat::Table),
+                            session.scroll,
+                            height.saturating_sub(3),
+                        ));
+                    }
+                    Err(e) => lines.extend(error(e)),
+                }
+                lines
+            }
+            Panel::Report => match self.bank.report(session.user) {
+                Ok(report) => Self::scrolled(
+                    &render_report(&report, ReportFormat::Table),
+                    session.scroll,
+                    height,
+                ),
+                Err(e) => error(e),
+            },
+            Panel::Interest | Panel::Tax => {
+                let (name, rate) = if session.panel() == Panel::Interest {
+                    ("interest", self.bank.interest_rate())
+                } else {
+                    ("tax", self.bank.tax_rate())
+                };
+                let mut lines = vec![format!("Current {} rate: {}", name, rate), String::new()];
+                lines.extend(self.render_form(&session.form, Some(session)));
+                lines
+            }
+            Panel::Deposit | Panel::Withdraw | Panel::Transfer | Panel::Reconciliation => {
+                let mut lines = self.render_form(&session.form, Some(session));
+                if !session.output.is_empty() {
+                    lines.push(String::new());
+                    lines.extend(session.output.iter().cloned());
+                }
+                lines
+            }
+        }
+    }
+
+    /// Renders the screen as `height` lines of `width` characters.
+    pub fn render(&self, width: usize, height: usize) -> Vec<String> {
+        let mut lines = vec![];
+        // Four lines of header, three of footer.
+        let body_height = height.saturating_sub(7);
+        match &self.screen {
+            Screen::Login(form) => {
+                lines.push("ANZ bank".to_string());
+                lines.push("Log in, or register as a customer.".to_string());
+                lines.push("-".repeat(width));
+                lines.push(String::new());
+                lines.extend(self.render_form(form, None));
+            }
+            Screen::Session(session) => {
+                lines.push(format!(
+                    "ANZ bank · {} ({:?})",
+                    session.username, session.role
+                ));
+                lines.push(
+                    Panel::for_role(session.role)
+                        .iter()
+                        .enumerate()
+                        .map(|(i, p)| {
+                            if i == session.panel {
+                                format!("[{:?}]", p)
+                            } else {
+                                format!(" {:?} ", p)
+                            }
+                        })
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                );
+                lines.push("-".repeat(width));
+                lines.push(String::new());
+                lines.extend(self.render_panel(session, body_height));
+            }
+        }
+        lines.truncate(height.saturating_sub(3));
+        lines.resize(height.saturating_sub(3), String::new());
+        lines.push("-".repeat(width));
+        lines.push(
+            match self.screen {
+                Screen::Login(_) => "Tab: next field · Enter: submit · Esc: quit",
+                Screen::Session(_) => {
+                    "←/→: panel · Tab: field · Enter: submit · ↑/↓: scroll · Esc: log out · Ctrl-Q: quit"
+                }
+            }
+            .to_string(),
+        );
+        lines.push(self.status.clone());
+        lines.iter().map(|l| fit(l, width)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Decodes every key of `bytes`, and returns them with the bytes left undecoded.
+    fn decode_all(mut bytes: &[u8]) -> (Vec<Key>, &[u8]) {
+        let mut keys = vec![];
+        while let Some((key, len)) = decode_key(bytes) {
+            keys.extend(key);
+            bytes = &bytes[len..];
+        }
+        (keys, bytes)
+    }
+
+    #[test]
+    fn can_decode_keys_read_together() {
+        // Pasted text keeps every character, and escape sequences follow each other.
+        assert_eq!(
+            decode_all("ab\u{e9}\r".as_bytes()),
+            (
+                vec![
+                    Key::Char('a'),
+                    Key::Char('b'),
+                    Key::Char('\u{e9}'),
+                    Key::Enter
+                ],
+                &[][..]
+            )
+        );
+        assert_eq!(
+            decode_all(b"\x1b[A\x1b[B\x1b[Zq\x11\x1b"),
+            (
+                vec![
+                    Key::Up,
+                    Key::Down,
+                    Key::BackTab,
+                    Key::Char('q'),
+                    Key::Ctrl('q'),
+                    Key::Esc
+                ],
+                &[][..]
+            )
+        );
+        // Unknown sequences are skipped whole.
+        assert_eq!(decode_all(b"\x1b[15~x"), (vec![Key::Char('x')], &[][..]));
+
+        // The start of a sequence or a character waits for the next read.
+        assert_eq!(
+            decode_all(b"a\x1b[1;"),
+            (vec![Key::Char('a')], &b"\x1b[1;"[..])
+        );
+        assert_eq!(
+            decode_all(&[b'a', 0xc3]),
+            (vec![Key::Char('a')], &[0xc3][..])
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Drives the `banking_server` binary over localhost.
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// A running server, killed when dropped. Its first manager is `manager`, with the password
+/// `manager`.
+struct Server {
+    child: Child,
+    addr: String,
+}
+
+impl Server {
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_banking_server"))
+            .args(["--addr", "127.0.0.1:0"])
+            .env("BANKING_MANAGER", "manager")
+            .env("BANKING_MANAGER_PASSWORD", "manager")
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Failed to start the server.");
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        let addr = line
+            .trim()
+            .strip_prefix("Listening on ")
+            .expect("Expected the bound address.")
+            .to_string();
+        // Keep draining the bank's output so the server never blocks on a full pipe.
+        thread::spawn(move || std::io::copy(&mut stdout, &mut std::io::sink()));
+        Self { child, addr }
+    }
+
+    fn request(&self, method: &str, path: &str, token: Option<&str>, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(&self.addr).unwrap();
+        let auth = token
+            .map(|t| format!("Authorization: Bearer {}\r\n", t))
+            .unwrap_or_default();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\n{}Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            auth,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response.split_whitespace().nth(1).unwrap().parse().unwrap();
+        let body = response.split_once("\r\n\r\n").unwrap().1.to_string();
+        (status, body)
+    }
+
+    fn login(&self, username: &str) -> String {
+        let (status, body) = self.request(
+            "POST",
+            "/login",
+            None,
+            &format!("{{\"username\":\"{0}\",\"password\":\"{0}\"}}", username),
+        );
+        assert_eq!(200, status, "{}", body);
+        body.split('"').nth(3).unwrap().to_string()
+    }
+
+    fn register(&self, username: &str, role: &str, token: Option<&str>) {
+        let (status, body) = self.request(
+            "POST",
+            "/register",
+            token,
+            &format!(
+                "{{\"username\":\"{0}\",\"password\":\"{0}\",\"role\":\"{1}\"}}",
+                username, role
+            ),
+        );
+        assert_eq!(201, status, "{}", body);
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn can_bank_over_http() {
+    let server = Server::start();
+    server.register("roy", "customer", None);
+    server.register("tom", "customer", None);
+    let roy = server.login("roy");
+    let tom = server.login("tom");
+
+    assert_eq!(
+        (200, "{\"balance\":1000}".to_string()),
+        server.request(
+            "POST",
+            "/deposit",
+            Some(&roy),
+            "{\"amount\": 1000, \"reference\": \"Salary\"}"
+        )
+    );
+    assert_eq!(
+        (200, "{\"balance\":800}".to_string()),
+        server.request("POST", "/withdraw", Some(&roy), "{\"amount\":200}")
+    );
+    assert_eq!(
+        (200, "{\"balance\":700}".to_string()),
+        server.request("POST", "/transfer", Some(&roy), "{\"to\":3,\"amount\":100}")
+    );
+    assert_eq!(
+        (200, "{\"balance\":100}".to_string()),
+        server.request("GET", "/balance", Some(&tom), "")
+    );
+
+    let (status, events) = server.request("GET", "/events", Some(&tom), "");
+    assert_eq!(200, status);
+    assert!(events.starts_with("[{\"index\":2,\"at\":0,\"event\":\"Transfer - Amount: 100"));
+
+    // Errors are mapped to status codes.
+    let (status, body) = server.request("POST", "/withdraw", Some(&roy), "{\"amount\":5000}");
+    assert_eq!(409, status);
+    assert_eq!(
+        "{\"error\":\"InsufficientBalance\",\"message\":\"User does not have enough balance.\"}",
+        body
+    );
+    assert_eq!(
+        422,
+        server
+            .request("POST", "/deposit", Some(&roy), "{\"amount\":-1}")
+            .0
+    );
+    assert_eq!(
+        404,
+        server
+            .request("POST", "/transfer", Some(&roy), "{\"to\":9,\"amount\":50}")
+            .0
+    );
+    assert_eq!(403, server.request("GET", "/report", Some(&roy), "").0);
+    assert_eq!(
+        400,
+        server.request("POST", "/deposit", Some(&roy), "{amount}").0
+    );
+}
+
+#[test]
+fn can_manage_rates_over_http() {
+    let server = Server::start();
+    server.register("roy", "customer", None);
+    // Nobody registers as a manager without being one.
+    let (status, _) = server.request(
+        "POST",
+        "/register",
+        None,
+        "{\"username\":\"mallory\",\"password\":\"pw\",\"role\":\"manager\"}",
+    );
+    assert_eq!(401, status);
+    let manager = server.login("manager");
+    server.register("auditor", "auditor", Some(&manager));
+    let roy = server.login("roy");
+    let auditor = server.login("auditor");
+    assert_eq!(
+        401,
+        server
+            .request(
+                "POST",
+                "/register",
+                None,
+                "{\"username\":\"eve\",\"password\":\"eve\",\"role\":\"manager\"}"
+            )
+            .0
+    );
+    server.request("POST", "/deposit", Some(&roy), "{\"amount\":1000}");
+
+    assert_eq!(
+        204,
+        server
+            .request("PUT", "/interest-rate", Some(&manager), "{\"rate\":0.5}")
+            .0
+    );
+    assert_eq!(
+        204,
+        server.request("POST", "/interest", Some(&manager), "").0
+    );
+    assert_eq!(
+        422,
+        server
+            .request("PUT", "/tax-rate", Some(&auditor), "{\"rate\":2}")
+            .0
+    );
+    assert_eq!(
+        204,
+        server
+            .request("PUT", "/tax-rate", Some(&auditor), "{\"rate\":0.5}")
+            .0
+    );
+    assert_eq!(403, server.request("POST", "/tax", Some(&manager), "").0);
+    assert_eq!(204, server.request("POST", "/tax", Some(&auditor), "").0);
+
+    let (status, report) = server.request("GET", "/report", Some(&auditor), "");
+    assert_eq!(200, status);
+    assert!(report.starts_with("{\"users\":[{\"id\":1,\"username\":\"manager\","));
+    assert!(
+        report.contains("{\"id\":2,\"username\":\"roy\",\"role\":\"Customer\",\"balance\":750,")
+    );
+
+    // Tokens are required, and are revoked on logout.
+    assert_eq!(401, server.request("GET", "/report", None, "").0);
+    assert_eq!(
+        401,
+        server
+            .request(
+                "POST",
+                "/login",
+                None,
+                "{\"username\":\"roy\",\"password\":\"wrong\"}"
+            )
+            .0
+    );
+    assert_eq!(204, server.request("POST", "/logout", Some(&auditor), "").0);
+    assert_eq!(401, server.request("GET", "/report", Some(&auditor), "").0);
+    assert_eq!(405, server.request("GET", "/tax", Some(&roy), "").0);
+    assert_eq!(404, server.request("GET", "/nowhere", Some(&roy), "").0);
+}
+
+#[test]
+fn can_drop_idle_and_oversized_requests() {
+    let server = Server::start();
+
+    // A client that sends nothing is timed out, and does not block the next client, which is
+    // answered before the timeout.
+    let mut idle = TcpStream::connect(&server.addr).unwrap();
+    let idle = thread::spawn(move || {
+        let mut response = String::new();
+        idle.read_to_string(&mut response).unwrap();
+        response
+    });
+    let start = Instant::now();
+    server.register("roy", "customer", None);
+    assert!(start.elapsed() < Duration::from_secs(4));
+    let response = idle.join().unwrap();
+    assert!(response.starts_with("HTTP/1.1 408 "), "{}", response);
+
+    let mut stream = TcpStream::connect(&server.addr).unwrap();
+    write!(
+        stream,
+        "GET /balance HTTP/1.1\r\nX-Padding: {}\r\n\r\n",
+        "a".repeat(16 * 1024)
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 431 "), "{}", response);
+}
@@ -0,0 +1,345 @@
+//! Drives the `banking_app` subcommands against a bank file.
+use std::process::{Command, Stdio};
+
+/// Runs `banking_app --bank <path>` with `args`, and returns the exit code, stdout and stderr.
+fn run(path: &str, args: &[&str]) -> (i32, String, String) {
+    run_with_storage(path, "", args)
+}
+
+/// Runs `banking_app --bank <path>` with `args` and `BANKING_STORAGE` set to `storage`.
+fn run_with_storage(path: &str, storage: &str, args: &[&str]) -> (i32, String, String) {
+    run_with_env(path, storage, Some("cli test key"), args)
+}
+
+/// Runs `banking_app --bank <path>` with `args`, `BANKING_STORAGE` set to `storage`, and
+/// `BANKING_SIGNING_KEY` set to `key`, if any.
+fn run_with_env(
+    path: &str,
+    storage: &str,
+    key: Option<&str>,
+    args: &[&str],
+) -> (i32, String, String) {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_banking_app"));
+    command
+        .args(["--bank", path])
+        .args(args)
+        .env("BANKING_STORAGE", storage)
+        .env_remove("BANKING_SIGNING_KEY");
+    if let Some(key) = key {
+        command.env("BANKING_SIGNING_KEY", key);
+    }
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .expect("Failed to run banking_app.");
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap().trim().to_string(),
+        String::from_utf8(output.stderr).unwrap().trim().to_string(),
+    )
+}
+
+#[test]
+fn can_bank_with_subcommands() {
+    let path = std::env::temp_dir().join(format!("banking_app_cli_{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+
+    let register = |username: &str, role: &str| {
+        run(
+            path,
+            &[
+                "register",
+                "--user",
+                username,
+                "--password",
+                "pw",
+                "--role",
+                role,
+            ],
+        )
+    };
+    assert_eq!(register("manager", "manager"), (0, "{}".into(), "".into()));
+    assert_eq!(register("roy", "customer"), (0, "{}".into(), "".into()));
+    assert_eq!(register("sam", "customer"), (0, "{}".into(), "".into()));
+
+    // Once the bank has a manager, only a manager registers staff.
+    let staff = |role: &str, by: &[&str]| {
+        let mut args = vec![
+            "register",
+            "--user",
+            "eve",
+            "--password",
+            "pw",
+            "--role",
+            role,
+        ];
+        args.extend_from_slice(by);
+        run(path, &args).0
+    };
+    assert_eq!(staff("manager", &[]), 2);
+    assert_eq!(staff("auditor", &[]), 2);
+    let roy = ["--manager", "roy", "--manager-password", "pw"];
+    assert_eq!(staff("manager", &roy), 10);
+    let wrong = ["--manager", "manager", "--manager-password", "x"];
+    assert_eq!(staff("auditor", &wrong), 13);
+    let manager = ["--manager", "manager", "--manager-password", "pw"];
+    assert_eq!(staff("auditor", &manager), 0);
+    assert_eq!(
+        run(path, &["reconcile", "--user", "eve", "--password", "pw"]).1,
+        "[]"
+    );
+    assert_eq!(
+        register("roy", "customer"),
+        (
+            19,
+            "".into(),
+            "{\"error\":\"UserAlreadyExist\",\"message\":\"Error, this user is already exist.\"}"
+                .into()
+        )
+    );
+
+    let roy = ["--user", "roy", "--password", "pw"];
+    let with = |command: &str, args: &[&str]| {
+        let mut all = vec![command];
+        all.extend_from_slice(&roy);
+        all.extend_from_slice(args);
+        run(path, &all)
+    };
+    assert_eq!(
+        with("deposit", &["--amount", "100"]),
+        (0, "{\"balance\":100}".into(), "".into())
+    );
+    assert_eq!(
+        with("transfer", &["--to", "3", "--amount", "40"]).1,
+        "{\"balance\":60}"
+    );
+    assert_eq!(with("withdraw", &["--amount", "1000"]).0, 11);
+    assert_eq!(with("balance", &[]).1, "{\"balance\":60}");
+    assert_eq!(with("report", &[]).0, 10);
+    assert_eq!(with("deposit", &["--amount", "lots"]).0, 2);
+    assert_eq!(
+        run(path, &["balance", "--user", "roy", "--password", "x"]).0,
+        13
+    );
+
+    let (code, report, _) = run(
+        path,
+        &[
+            "report",
+            "--user",
+            "manager",
+            "--password",
+            "pw",
+            "--format",
+            "csv",
+        ],
+    );
+    assert_eq!(code, 0);
+    assert!(report.contains("\n2,roy,Customer,60,"));
+    assert!(report.contains("\n3,sam,Customer,40,"));
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(format!("{}.lock", path)).unwrap();
+}
+
+#[test]
+fn can_run_subcommands_concurrently() {
+    let path = std::env::temp_dir().join(format!("banking_app_cli_lock_{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+    let roy = ["--user", "roy", "--password", "pw"];
+    let with = |command: &str, args: &[&str]| {
+        let mut all = vec![command];
+        all.extend_from_slice(&roy);
+        all.extend_from_slice(args);
+        run(path, &all)
+    };
+    let manager = ["--user", "manager", "--password", "pw", "--role", "manager"];
+    assert_eq!(run(path, &[&["register"][..], &manager].concat()).0, 0);
+    assert_eq!(with("register", &[]).0, 0);
+    assert_eq!(with("deposit", &["--amount", "100"]).0, 0);
+
+    // Each withdrawal sees the ones before it: exactly five fit in the balance.
+    let codes = std::thread::scope(|s| {
+        let withdrawals = (0..8)
+            .map(|_| s.spawn(|| with("withdraw", &["--amount", "20"]).0))
+            .collect::<Vec<_>>();
+        withdrawals
+            .into_iter()
+            .map(|w| w.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(codes.iter().filter(|c| **c == 0).count(), 5);
+    assert_eq!(codes.iter().filter(|c| **c == 11).count(), 3);
+    assert_eq!(with("balance", &[]).1, "{\"balance\":0}");
+
+    // Reading the bank does not journal anything.
+    let journal = std::fs::read_to_string(path).unwrap();
+    assert_eq!(journal.lines().count(), 9);
+    let auditor = ["--user", "auditor", "--password", "pw"];
+    let by = ["--manager", "manager", "--manager-password", "pw"];
+    let mut register = vec!["register", "--role", "auditor"];
+    register.extend_from_slice(&auditor);
+    register.extend_from_slice(&by);
+    assert_eq!(run(path, &register).0, 0);
+    let mut reconcile = vec!["reconcile"];
+    reconcile.extend_from_slice(&auditor);
+    assert_eq!(run(path, &reconcile), (0, "[]".into(), "".into()));
+    assert_eq!(with("reconcile", &[]).0, 10);
+    assert_eq!(std::fs::read_to_string(path).unwrap().lines().count(), 10);
+
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(format!("{}.lock", path)).unwrap();
+}
+
+#[test]
+fn can_run_subcommands_against_a_database() {
+    let path = std::env::temp_dir().join(format!("banking_app_cli_db_{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    let database = format!("{}.db", path);
+    let _ = std::fs::remove_file(path);
+    let _ = std::fs::remove_file(&database);
+    let storage = format!("sqlite:{}", database);
+    let run = |args: &[&str]| run_with_storage(path, &storage, args);
+    let roy = |command| [command, "--user", "roy", "--password", "pw"];
+
+    assert_eq!(run(&roy("register")), (0, "{}".into(), "".into()));
+    let manager = ["--user", "manager", "--password", "pw", "--role", "manager"];
+    assert_eq!(run(&[&["register"][..], &manager].concat()).0, 0);
+    let mut deposit = roy("deposit").to_vec();
+    deposit.extend_from_slice(&["--amount", "100"]);
+    assert_eq!(run(&deposit).1, "{\"balance\":100}");
+    let (code, balance, warning) = run(&roy("balance"));
+    assert_eq!((code, balance.as_str()), (0, "{\"balance\":100}"));
+    assert!(warning.starts_with("Warning: only the users"));
+    assert!(std::fs::metadata(path).is_err());
+
+    // Each deposit sees the ones before it: their events, and the users registered meanwhile.
+    let codes = std::thread::scope(|s| {
+        let deposits = (0..8)
+            .map(|i| {
+                let (run, deposit) = (&run, &deposit);
+                s.spawn(move || {
+                    let sam = format!("sam{}", i);
+                    let register = ["register", "--user", &sam, "--password", "pw"];
+                    (run(deposit).0, run(&register).0)
+                })
+            })
+            .collect::<Vec<_>>();
+        deposits
+            .into_iter()
+            .map(|d| d.join().unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(codes, vec![(0, 0); 8]);
+    assert_eq!(run(&roy("balance")).1, "{\"balance\":900}");
+    let (code, events, _) = run(&roy("events"));
+    assert_eq!((code, events.matches("Deposit").count()), (0, 9));
+    let auditor = ["--user", "auditor", "--password", "pw"];
+    assert_eq!(
+        run(&[
+            &["register", "--role", "auditor"][..],
+            &auditor,
+            &["--manager", "manager", "--manager-password", "pw"],
+        ]
+        .concat())
+        .0,
+        0
+    );
+    assert_eq!(run(&[&["reconcile"][..], &auditor].concat()).1, "[]");
+
+    assert_eq!(run_with_storage(path, "postgres", &roy("balance")).0, 1);
+    assert_eq!(run_with_env(path, &storage, None, &roy("balance")).0, 1);
+
+    std::fs::remove_file(database).unwrap();
+}
+
+#[test]
+fn cannot_reseal_downgraded_bank_file() {
+    let path = std::env::temp_dir().join(format!("banking_app_cli_reseal_{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+    let roy = ["--user", "roy", "--password", "pw"];
+    run(
+        path,
+        &[&["register"][..], &roy, &["--role", "customer"]].concat(),
+    );
+    run(
+        path,
+        &[&["deposit"][..], &roy, &["--amount", "100"]].concat(),
+    );
+
+    // Downgrading the file to the version before seals, and raising the deposit, does not get
+    // the edit sealed with the key.
+    let downgraded = std::fs::read_to_string(path)
+        .unwrap()
+        .replacen("version 3", "version 2", 1)
+        .lines()
+        .map(|l| l.rsplit_once('\t').map_or(l, |(line, _)| line))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .replacen("\t100\t", "\t1000000\t", 1);
+    std::fs::write(path, &downgraded).unwrap();
+    let (code, _, error) = run(path, &[&["balance"][..], &roy].concat());
+    assert_eq!(code, 62);
+    assert!(error.contains("OutdatedBankFile"));
+    assert_eq!(std::fs::read_to_string(path).unwrap(), downgraded);
+    assert!(std::fs::metadata(format!("{}.v2.bak", path)).is_err());
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(format!("{}.lock", path)).unwrap();
+}
+
+#[test]
+fn can_migrate_bank_file() {
+    let path = std::env::temp_dir().join(format!("banking_app_cli_migrate_{}", std::process::id()));
+    let path = path.to_str().unwrap();
+    let backup = format!("{}.v1.bak", path);
+    let original = include_str!("fixtures/bank_v1.txt");
+    std::fs::write(path, original).unwrap();
+
+    let (code, report, _) = run(path, &["migrate", "--dry-run"]);
+    assert_eq!(code, 0);
+    assert!(report.starts_with(&format!("{} would be upgraded from version 1 to 3", path)));
+    assert_eq!(std::fs::read_to_string(path).unwrap(), original);
+
+    // Other commands refuse the outdated file, and leave it as it is.
+    assert_eq!(
+        run(path, &["balance", "--user", "r\\oy", "--password", "pw"]).0,
+        62
+    );
+    assert_eq!(std::fs::read_to_string(path).unwrap(), original);
+    assert!(std::fs::metadata(&backup).is_err());
+
+    // Migrating upgrades it, after copying the original.
+    let (code, report, _) = run(path, &["migrate"]);
+    assert_eq!(code, 0);
+    assert!(report.starts_with(&format!("{} was upgraded from version 1 to 3", path)));
+    assert_eq!(std::fs::read_to_string(&backup).unwrap(), original);
+    assert_eq!(
+        run(path, &["balance", "--user", "r\\oy", "--password", "pw"]),
+        (0, "{\"balance\":90}".into(), "".into())
+    );
+    assert_eq!(
+        run(path, &["migrate"]).1,
+        format!("{} is up to date (version 3)", path)
+    );
+
+    // The bank file is sealed with the signing key, and needs it.
+    let balance = ["balance", "--user", "r\\oy", "--password", "pw"];
+    let (code, _, error) = run_with_env(path, "", None, &balance);
+    assert_eq!(code, 1);
+    assert!(error.contains("BANKING_SIGNING_KEY must be set"));
+    assert_eq!(run_with_env(path, "", Some("another key"), &balance).0, 61);
+    let journal = std::fs::read_to_string(path).unwrap();
+    std::fs::write(path, journal.replacen("\t100\t", "\t1000\t", 1)).unwrap();
+    assert_eq!(run(path, &balance).0, 61);
+
+    std::fs::write(path, "banking_app bank file version 99\n").unwrap();
+    assert_eq!(
+        run(path, &["balance", "--user", "r", "--password", "pw"]).0,
+        59
+    );
+    std::fs::remove_file(path).unwrap();
+    std::fs::remove_file(backup).unwrap();
+    std::fs::remove_file(format!("{}.lock", path)).unwrap();
+}
@@ -0,0 +1,46 @@
+-- A SQLite database of schema version 1, which predates PRAGMA user_version. Each user's
+-- password is their username. Event fields are separated by tabs, written as | here.
+CREATE TABLE users (
+        id INTEGER PRIMARY KEY,
+        credentials INTEGER NOT NULL UNIQUE,
+        username TEXT NOT NULL UNIQUE,
+        role TEXT NOT NULL
+    );
+INSERT INTO users VALUES(1,-5125937185078307236,'r','Customer');
+INSERT INTO users VALUES(2,3059571050504932537,'e','Customer');
+INSERT INTO users VALUES(3,7272156565747477206,'m','Manager');
+INSERT INTO users VALUES(4,-119402112856929170,'a','Auditor');
+CREATE TABLE balances (
+        id INTEGER PRIMARY KEY,
+        balance REAL NOT NULL
+    );
+INSERT INTO balances VALUES(1,540.0);
+CREATE TABLE events (
+        position INTEGER PRIMARY KEY,
+        at INTEGER NOT NULL,
+        event TEXT NOT NULL
+    );
+INSERT INTO events VALUES(0,0,replace('Deposit|1|1000|1|1|Salary|0', '|', char(9)));
+INSERT INTO events VALUES(1,0,replace('Withdrawal|1|500|0', '|', char(9)));
+INSERT INTO events VALUES(2,0,replace('Transfer|1|2|100|1|1|Rent|0', '|', char(9)));
+INSERT INTO events VALUES(3,0,replace('Withdrawal|2|95|0', '|', char(9)));
+INSERT INTO events VALUES(4,0,replace('Withdrawal|2|1|0', '|', char(9)));
+INSERT INTO events VALUES(5,0,replace('AccountReaped|2|4', '|', char(9)));
+INSERT INTO events VALUES(6,0,replace('InterestRate|3|0.5', '|', char(9)));
+INSERT INTO events VALUES(7,0,replace('Interest|1|200', '|', char(9)));
+INSERT INTO events VALUES(8,0,replace('TaxRate|4|0.1', '|', char(9)));
+INSERT INTO events VALUES(9,0,replace('Tax|1|60', '|', char(9)));
+CREATE TABLE event_parties (
+        id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        PRIMARY KEY (id, position)
+    ) WITHOUT ROWID;
+INSERT INTO event_parties VALUES(1,0);
+INSERT INTO event_parties VALUES(1,1);
+INSERT INTO event_parties VALUES(1,2);
+INSERT INTO event_parties VALUES(1,7);
+INSERT INTO event_parties VALUES(1,9);
+INSERT INTO event_parties VALUES(2,2);
+INSERT INTO event_parties VALUES(2,3);
+INSERT INTO event_parties VALUES(2,4);
+INSERT INTO event_parties VALUES(2,5);
@@ -0,0 +1,54 @@
+-- A SQLite database of schema version 2, with a hash chained event log. Each user's password
+-- is their username. Event fields are separated by tabs, written as | here.
+PRAGMA user_version = 2;
+CREATE TABLE users (
+        id INTEGER PRIMARY KEY,
+        credentials INTEGER NOT NULL UNIQUE,
+        username TEXT NOT NULL UNIQUE,
+        role TEXT NOT NULL
+    );
+INSERT INTO users VALUES(1,-5125937185078307236,'r','Customer');
+INSERT INTO users VALUES(2,3059571050504932537,'e','Customer');
+INSERT INTO users VALUES(3,7272156565747477206,'m','Manager');
+INSERT INTO users VALUES(4,-119402112856929170,'a','Auditor');
+CREATE TABLE balances (
+        id INTEGER PRIMARY KEY,
+        balance REAL NOT NULL
+    );
+INSERT INTO balances VALUES(1,540.0);
+CREATE TABLE events (
+        position INTEGER PRIMARY KEY,
+        at INTEGER NOT NULL,
+        event TEXT NOT NULL,
+        prev BLOB NOT NULL,
+        hash BLOB NOT NULL
+    );
+INSERT INTO events VALUES(0,0,replace('Deposit|1|1000|1|1|Salary|0', '|', char(9)),X'0000000000000000000000000000000000000000000000000000000000000000',X'8915ec66a2bbfba6d5d5981d8e361e1e409899aae826300db2f747a370c23fbd');
+INSERT INTO events VALUES(1,0,replace('Withdrawal|1|500|0', '|', char(9)),X'8915ec66a2bbfba6d5d5981d8e361e1e409899aae826300db2f747a370c23fbd',X'423f7d1422cded04797f1e5b73670cb87a2c6273fb826d1f0f4b261663c77d95');
+INSERT INTO events VALUES(2,0,replace('Transfer|1|2|100|1|1|Rent|0', '|', char(9)),X'423f7d1422cded04797f1e5b73670cb87a2c6273fb826d1f0f4b261663c77d95',X'd71b6754c53a6f3a966e3624f622025b3b76a5ce173f45241f66c6b5e12188a0');
+INSERT INTO events VALUES(3,0,replace('Withdrawal|2|95|0', '|', char(9)),X'd71b6754c53a6f3a966e3624f622025b3b76a5ce173f45241f66c6b5e12188a0',X'ddac74bf8a1c66c91d28ca6f19bf900aead88db4553cdad0590fad31b82391f5');
+INSERT INTO events VALUES(4,0,replace('Withdrawal|2|1|0', '|', char(9)),X'ddac74bf8a1c66c91d28ca6f19bf900aead88db4553cdad0590fad31b82391f5',X'4a52a37b0ea0bce073f35df2bf9ae3ac94efb9b23325550e694e03c036744065');
+INSERT INTO events VALUES(5,0,replace('AccountReaped|2|4', '|', char(9)),X'4a52a37b0ea0bce073f35df2bf9ae3ac94efb9b23325550e694e03c036744065',X'308c64157cd6870b6dc7cd02c0c657cb5afa9e9ee2bf4d9fcb20623f261733fa');
+INSERT INTO events VALUES(6,0,replace('InterestRate|3|0.5', '|', char(9)),X'308c64157cd6870b6dc7cd02c0c657cb5afa9e9ee2bf4d9fcb20623f261733fa',X'79eb192a8eb5f419d67d51b80e0fb982c68b99b227e9b8dce2500ba18f83a84d');
+INSERT INTO events VALUES(7,0,replace('Interest|1|200', '|', char(9)),X'79eb192a8eb5f419d67d51b80e0fb982c68b99b227e9b8dce2500ba18f83a84d',X'878541da07ada031b6c2db2e8d1fb2e69e165d87d9cd898695b1663235c24e96');
+INSERT INTO events VALUES(8,0,replace('TaxRate|4|0.1', '|', char(9)),X'878541da07ada031b6c2db2e8d1fb2e69e165d87d9cd898695b1663235c24e96',X'81de79d0fe64e94f2866f5572a991b8d19dfb0653d0c81f78ff9e44b1af34f5a');
+INSERT INTO events VALUES(9,0,replace('Tax|1|60', '|', char(9)),X'81de79d0fe64e94f2866f5572a991b8d19dfb0653d0c81f78ff9e44b1af34f5a',X'b2810673d557d952c44a04f9ea9e962d73f28c474774eb21db5f8c5b53a3d281');
+CREATE TABLE event_parties (
+        id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        PRIMARY KEY (id, position)
+    ) WITHOUT ROWID;
+INSERT INTO event_parties VALUES(1,0);
+INSERT INTO event_parties VALUES(1,1);
+INSERT INTO event_parties VALUES(1,2);
+INSERT INTO event_parties VALUES(1,7);
+INSERT INTO event_parties VALUES(1,9);
+INSERT INTO event_parties VALUES(2,2);
+INSERT INTO event_parties VALUES(2,3);
+INSERT INTO event_parties VALUES(2,4);
+INSERT INTO event_parties VALUES(2,5);
+CREATE TABLE checkpoints (
+        count INTEGER PRIMARY KEY,
+        hash BLOB NOT NULL,
+        signature BLOB NOT NULL
+    );
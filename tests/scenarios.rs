@@ -0,0 +1,30 @@
+//! Runs every scenario in the `scenarios` directory.
+use banking_app::Scenario;
+use std::fs;
+
+#[test]
+fn scenarios_pass() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/scenarios");
+    let mut paths = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "toml"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "No scenarios found in {}.", dir);
+
+    let failed = paths
+        .iter()
+        .filter_map(|path| {
+            let path = path.to_str().unwrap();
+            let scenario = Scenario::load(path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+            let failures = scenario.run();
+            if failures.is_empty() {
+                return None;
+            }
+            let failures = failures.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+            Some(format!("{}:\n{}", path, failures.join("\n")))
+        })
+        .collect::<Vec<_>>();
+    assert!(failed.is_empty(), "\n{}", failed.join("\n\n"));
+}